@@ -0,0 +1,247 @@
+//! Minimal collaboration server, run with `cargo run --example server --features net [addr]`.
+//!
+//! Peers speak the frame protocol over tcp:
+//! - a `SnapshotRequest` joins the document and is answered with the snapshot chunks
+//! - `Update` frames are applied, persisted and relayed to the other peers of the document
+//! - `Awareness` and `Ephemeral` frames are relayed without being applied
+//! - idle peers get `Keepalive` frames, silent peers are dropped
+//!
+//! Documents are persisted as chunked snapshots in an in-memory chunk store, replace
+//! `MemoryChunkStore` with any `ChunkStore` to keep them across restarts.
+
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use nitro::{
+    encode_frames, ChunkStore, ChunkedSnapshot, Chunker, Client, Doc, DocId, DocMeta, Frame,
+    FrameKind, FrameParser, Keepalive, MemoryChunkStore, SnapshotRequest, SnapshotUpload,
+};
+
+const SNAPSHOT_CHUNK_SIZE: usize = 16 * 1024;
+const KEEPALIVE_INTERVAL: u64 = 10_000;
+const KEEPALIVE_TIMEOUT: u64 = 30_000;
+
+/// Registry keeps the open documents and their last persisted snapshot
+struct Registry<S: ChunkStore> {
+    store: S,
+    chunker: Chunker,
+    docs: HashMap<DocId, Doc>,
+    snapshots: HashMap<DocId, ChunkedSnapshot>,
+}
+
+impl<S: ChunkStore> Registry<S> {
+    fn new(store: S) -> Self {
+        Self {
+            store,
+            chunker: Chunker::new(4 * 1024),
+            docs: HashMap::new(),
+            snapshots: HashMap::new(),
+        }
+    }
+
+    /// Open a loaded document, load it from the store or create it
+    fn open(&mut self, doc_id: &DocId) -> Result<&Doc, String> {
+        if !self.docs.contains_key(doc_id) {
+            let doc = match self.snapshots.get(doc_id) {
+                Some(snapshot) => Doc::load_chunked(snapshot, &self.store)?,
+                None => Doc::new(DocMeta::new(doc_id.clone(), Client::default())),
+            };
+            self.docs.insert(doc_id.clone(), doc);
+            self.persist(doc_id)?;
+        }
+
+        Ok(&self.docs[doc_id])
+    }
+
+    fn persist(&mut self, doc_id: &DocId) -> Result<(), String> {
+        let doc = self.docs.get(doc_id).ok_or("document is not open")?;
+        let snapshot = doc.save_chunked(&mut self.store, &self.chunker)?;
+        self.snapshots.insert(doc_id.clone(), snapshot);
+        Ok(())
+    }
+}
+
+struct Peer {
+    stream: TcpStream,
+    parser: FrameParser,
+    keepalive: Keepalive,
+    doc_id: Option<DocId>,
+}
+
+impl Peer {
+    fn send(&mut self, frames: &[Frame], now: u64) -> std::io::Result<()> {
+        self.keepalive.on_send(now);
+        self.stream.write_all(&encode_frames(frames))
+    }
+
+    /// Frames read since the last call, Err when the peer is gone
+    fn read(&mut self, now: u64) -> Result<Vec<Frame>, String> {
+        let mut buf = [0u8; 8 * 1024];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err("connection closed".to_string()),
+                Ok(n) => {
+                    self.keepalive.on_receive(now);
+                    self.parser.push(&buf[..n]);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+
+        self.parser.frames()
+    }
+}
+
+struct Server<S: ChunkStore> {
+    registry: Registry<S>,
+    peers: HashMap<u32, Peer>,
+    next_peer: u32,
+    started: Instant,
+}
+
+impl<S: ChunkStore> Server<S> {
+    fn now(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    fn accept(&mut self, stream: TcpStream) -> std::io::Result<()> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        let now = self.now();
+        self.next_peer += 1;
+        self.peers.insert(
+            self.next_peer,
+            Peer {
+                stream,
+                parser: FrameParser::new(),
+                keepalive: Keepalive::new(KEEPALIVE_INTERVAL, KEEPALIVE_TIMEOUT, now),
+                doc_id: None,
+            },
+        );
+        println!("peer {} connected", self.next_peer);
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        let now = self.now();
+        let mut gone = vec![];
+
+        let ids: Vec<u32> = self.peers.keys().copied().collect();
+        for id in ids {
+            let frames = match self.peers.get_mut(&id).unwrap().read(now) {
+                Ok(frames) => frames,
+                Err(e) => {
+                    println!("peer {} dropped: {}", id, e);
+                    gone.push(id);
+                    continue;
+                }
+            };
+
+            for frame in frames {
+                if let Err(e) = self.handle(id, frame, now) {
+                    println!("peer {} dropped: {}", id, e);
+                    gone.push(id);
+                    break;
+                }
+            }
+        }
+
+        for (id, peer) in self.peers.iter_mut() {
+            if peer.keepalive.is_timed_out(now) {
+                println!("peer {} timed out", id);
+                gone.push(*id);
+            } else if let Some(frame) = peer.keepalive.poll(now) {
+                let _ = peer.stream.write_all(&frame.to_bytes());
+            }
+        }
+
+        for id in gone {
+            self.peers.remove(&id);
+        }
+    }
+
+    fn handle(&mut self, id: u32, frame: Frame, now: u64) -> Result<(), String> {
+        match frame.kind {
+            FrameKind::SnapshotRequest => {
+                let request: SnapshotRequest = frame.to_snapshot_request()?;
+                let doc = self.registry.open(&request.doc_id)?;
+                let upload = SnapshotUpload::new(doc, SNAPSHOT_CHUNK_SIZE);
+                let frames: Vec<Frame> = upload
+                    .chunks(&request)
+                    .iter()
+                    .map(Frame::snapshot_chunk)
+                    .collect();
+
+                let peer = self.peers.get_mut(&id).unwrap();
+                peer.doc_id = Some(request.doc_id);
+                peer.send(&frames, now).map_err(|e| e.to_string())
+            }
+            FrameKind::Update => {
+                let doc_id = self.joined(id)?;
+                let diff = frame.to_diff()?;
+                self.registry.open(&doc_id)?.apply(&diff);
+                self.registry.persist(&doc_id)?;
+                self.relay(id, &doc_id, &frame, now);
+                Ok(())
+            }
+            FrameKind::Awareness | FrameKind::Ephemeral => {
+                let doc_id = self.joined(id)?;
+                self.relay(id, &doc_id, &frame, now);
+                Ok(())
+            }
+            FrameKind::Keepalive | FrameKind::Control => Ok(()),
+            FrameKind::SnapshotChunk => Err("peers do not upload snapshots".to_string()),
+        }
+    }
+
+    fn joined(&self, id: u32) -> Result<DocId, String> {
+        self.peers[&id]
+            .doc_id
+            .clone()
+            .ok_or_else(|| "request the document snapshot first".to_string())
+    }
+
+    // send the frame to the other peers of the document, failed peers are dropped by the
+    // next read
+    fn relay(&mut self, from: u32, doc_id: &DocId, frame: &Frame, now: u64) {
+        for (id, peer) in self.peers.iter_mut() {
+            if *id != from && peer.doc_id.as_ref() == Some(doc_id) {
+                let _ = peer.send(std::slice::from_ref(frame), now);
+            }
+        }
+    }
+}
+
+fn main() -> std::io::Result<()> {
+    let addr = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:7070".to_string());
+    let listener = TcpListener::bind(&addr)?;
+    listener.set_nonblocking(true)?;
+    println!("listening on {}", addr);
+
+    // documents are not Send, a single thread polls all peers
+    let mut server = Server {
+        registry: Registry::new(MemoryChunkStore::default()),
+        peers: HashMap::new(),
+        next_peer: 0,
+        started: Instant::now(),
+    };
+
+    loop {
+        loop {
+            match listener.accept() {
+                Ok((stream, _)) => server.accept(stream)?,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        server.tick();
+        thread::sleep(Duration::from_millis(5));
+    }
+}
@@ -0,0 +1,47 @@
+use nitro::{
+    sync_docs, ClientState, Doc, NText, SyncDirection, TextChange, TextChangeHook,
+    TextChangeTracker,
+};
+
+// a toy spellchecker that only looks at the changed ranges
+struct Spellcheck {
+    typos: Vec<&'static str>,
+    found: Vec<String>,
+}
+
+impl TextChangeHook for Spellcheck {
+    fn on_text_change(&mut self, _text: &NText, changes: &[TextChange]) {
+        for change in changes {
+            for word in change.content.split_whitespace() {
+                if self.typos.contains(&word) {
+                    println!("typo '{}' at {}..{}", word, change.start, change.end);
+                    self.found.push(word.to_string());
+                }
+            }
+        }
+    }
+}
+
+fn main() {
+    let d1 = Doc::default();
+    let text = d1.text();
+    d1.set("text", text.clone());
+    text.append(d1.string("hello world"));
+    d1.commit();
+
+    let d2 = Doc::from(&d1.diff(ClientState::default())).unwrap();
+
+    let mut checker = Spellcheck {
+        typos: vec!["teh", "wrold"],
+        found: vec![],
+    };
+    let mut tracker = TextChangeTracker::from_doc(&d2);
+
+    // remote edit arrives through sync, only the new range is checked
+    text.insert(5, d1.string(" teh"));
+    d1.commit();
+    sync_docs(&d1, &d2, SyncDirection::LeftToRight);
+    tracker.process(&d2, &mut checker);
+
+    assert_eq!(checker.found, vec!["teh".to_string()]);
+}
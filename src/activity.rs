@@ -0,0 +1,147 @@
+use hashbrown::HashMap;
+use serde::Serialize;
+
+use crate::bimapid::ClientId;
+use crate::diff::Diff;
+use crate::id::ClockTick;
+use crate::item::{Content, ItemKind};
+use crate::state::ClientState;
+use crate::Client;
+
+/// ClientActivity counts what a remote client added to the document through applied diffs.
+/// Servers can use the counters to detect runaway or abusive collaborators.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize)]
+pub struct ClientActivity {
+    /// number of diffs that carried new items or deletes from the client
+    pub applies: u64,
+    /// number of new items
+    pub items: u64,
+    /// content bytes of the new items
+    pub bytes: u64,
+    /// number of delete operations
+    pub deletes: u64,
+    /// number of move operations
+    pub moves: u64,
+}
+
+impl ClientActivity {
+    fn is_empty(&self) -> bool {
+        self.items == 0 && self.deletes == 0
+    }
+
+    fn merge(&mut self, other: &ClientActivity) {
+        self.applies += other.applies;
+        self.items += other.items;
+        self.bytes += other.bytes;
+        self.deletes += other.deletes;
+        self.moves += other.moves;
+    }
+}
+
+/// ActivityTracker accumulates the client activity of the applied diffs
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub(crate) struct ActivityTracker {
+    clients: HashMap<Client, ClientActivity>,
+}
+
+impl ActivityTracker {
+    /// Record the operations of an adjusted diff that are not yet integrated in the local state
+    pub(crate) fn record(&mut self, diff: &Diff, local: &ClientState) {
+        let mut batch: HashMap<Client, ClientActivity> = HashMap::new();
+        let is_new =
+            |client: &ClientId, clock: ClockTick| local.get(client).map_or(true, |c| clock > *c);
+
+        for (client_id, store) in diff.items.iter() {
+            let Some(client) = diff.state.get_client(client_id) else {
+                continue;
+            };
+
+            for (id, data) in store.iter() {
+                if !is_new(&id.client, id.clock) {
+                    continue;
+                }
+
+                let activity = batch.entry(client.clone()).or_default();
+                activity.items += 1;
+                activity.bytes += content_size(&data.content);
+                if data.kind == ItemKind::Move {
+                    activity.moves += 1;
+                }
+            }
+        }
+
+        for (client_id, store) in diff.deletes.iter() {
+            let Some(client) = diff.state.get_client(client_id) else {
+                continue;
+            };
+
+            for (id, _) in store.iter() {
+                if is_new(&id.client, id.clock) {
+                    batch.entry(client.clone()).or_default().deletes += 1;
+                }
+            }
+        }
+
+        for (client, mut activity) in batch {
+            if activity.is_empty() {
+                continue;
+            }
+
+            activity.applies = 1;
+            self.clients.entry(client).or_default().merge(&activity);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn get(&self, client: &Client) -> Option<&ClientActivity> {
+        self.clients.get(client)
+    }
+
+    pub(crate) fn all(&self) -> HashMap<Client, ClientActivity> {
+        self.clients.clone()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.clients.clear();
+    }
+}
+
+fn content_size(content: &Content) -> u64 {
+    match content {
+        Content::String(s) => s.len() as u64,
+        Content::Binary(b) => b.len() as u64,
+        Content::Embed(any) => any.to_json().to_string().len() as u64,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doc::{CloneDeep, Doc};
+    use crate::state::ClientState;
+
+    #[test]
+    fn test_client_activity_on_apply() {
+        let d1 = Doc::default();
+        d1.commit();
+
+        let d2 = d1.clone_deep();
+        let c2 = d2.update_client();
+
+        d2.set("a", d2.atom("hello"));
+        d2.set("b", d2.atom("b"));
+        d2.commit();
+
+        let diff = d2.diff(ClientState::default());
+        d1.apply(&diff);
+
+        let activity = d1.client_activity_of(&c2).unwrap();
+        assert_eq!(activity.items, 2);
+        assert_eq!(activity.bytes, 6);
+        assert_eq!(activity.applies, 1);
+
+        // applying the same diff again does not count twice
+        d1.apply(&diff);
+        assert_eq!(d1.client_activity_of(&c2).unwrap().items, 2);
+    }
+}
@@ -0,0 +1,94 @@
+use hashbrown::HashMap;
+
+use crate::annotation::Annotation;
+use crate::doc::Doc;
+use crate::id::{Id, IdRange};
+
+/// AnchorRefs counts the registered anchors referencing each item id.
+///
+/// Tombstones referenced by a registered anchor must survive compaction, a compaction pass
+/// checks `is_referenced` before purging a deleted item range.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub(crate) struct AnchorRefs {
+    refs: HashMap<Id, u32>,
+}
+
+impl AnchorRefs {
+    pub(crate) fn retain(&mut self, id: Id) {
+        *self.refs.entry(id).or_default() += 1;
+    }
+
+    pub(crate) fn release(&mut self, id: &Id) {
+        if let Some(count) = self.refs.get_mut(id) {
+            *count -= 1;
+            if *count == 0 {
+                self.refs.remove(id);
+            }
+        }
+    }
+
+    /// Some id of the range is referenced by an anchor
+    pub(crate) fn is_referenced(&self, range: &IdRange) -> bool {
+        self.refs.keys().any(|id| range.contains(id))
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.refs.is_empty()
+    }
+}
+
+impl Doc {
+    /// Register the anchors of the annotation, the anchored characters are kept as
+    /// tombstones when deleted until the annotation is released
+    pub fn retain_annotation(&self, annotation: &Annotation) {
+        let mut store = self.store_mut("retain_annotation");
+        store.anchors.retain(annotation.start);
+        store.anchors.retain(annotation.end);
+    }
+
+    /// Drop the anchors registered by `retain_annotation`
+    pub fn release_annotation(&self, annotation: &Annotation) {
+        let mut store = self.store_mut("release_annotation");
+        store.anchors.release(&annotation.start);
+        store.anchors.release(&annotation.end);
+    }
+
+    /// A registered anchor references an id of the range, the range can not be purged
+    pub fn is_anchored(&self, range: &IdRange) -> bool {
+        self.store.borrow().anchors.is_referenced(range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::id::{WithId, WithIdRange};
+
+    use super::*;
+
+    #[test]
+    fn test_anchored_tombstones() {
+        let doc = Doc::default();
+        let text = doc.text();
+        doc.set("text", text.clone());
+        let hello = doc.string("hello");
+        text.append(hello.clone());
+        text.append(doc.string(" world"));
+        doc.commit();
+
+        let a1 = doc.annotate("a1", &text, 1, 3).unwrap();
+        let a2 = doc.annotate("a2", &text, 2, 4).unwrap();
+        doc.retain_annotation(&a1);
+        doc.retain_annotation(&a2);
+
+        hello.delete();
+        doc.commit();
+        assert!(doc.is_anchored(&hello.range()));
+
+        doc.release_annotation(&a1);
+        assert!(doc.is_anchored(&hello.range()));
+        doc.release_annotation(&a2);
+        assert!(!doc.is_anchored(&hello.range()));
+        assert!(doc.store.borrow().anchors.is_empty());
+    }
+}
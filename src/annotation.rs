@@ -0,0 +1,273 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::doc::Doc;
+use crate::id::{Id, IdRange, WithId, WithIdRange};
+use crate::id_set::IdSet;
+use crate::item::{Content, ItemIterator};
+use crate::ntext::NText;
+use crate::state::ClientState;
+use crate::types::Type;
+
+// number of characters kept around the quote of a TextQuoteSelector
+const QUOTE_CONTEXT: usize = 32;
+
+/// Annotation is a stable anchor on a range of text.
+///
+/// The range is kept as the ids of the first and last annotated character,
+/// so it follows the text through concurrent edits.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Annotation {
+    pub id: String,
+    /// Id of the annotated text
+    pub text: Id,
+    /// id of the first annotated character
+    pub start: Id,
+    /// id of the last annotated character
+    pub end: Id,
+    /// annotation body, exported as is
+    pub body: Option<Value>,
+}
+
+impl Annotation {
+    pub fn with_body(mut self, body: impl Into<Value>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+}
+
+impl Doc {
+    /// Anchor an annotation on the text range start..end, offsets are the same as in `NText::insert`
+    pub fn annotate(
+        &self,
+        id: impl Into<String>,
+        text: &NText,
+        start: u32,
+        end: u32,
+    ) -> Option<Annotation> {
+        if start >= end {
+            return None;
+        }
+
+        Some(Annotation {
+            id: id.into(),
+            text: text.id(),
+            start: char_id(text, start)?,
+            end: char_id(text, end - 1)?,
+            body: None,
+        })
+    }
+
+    /// Export the annotations as W3C Web Annotations, the selectors are computed against the
+    /// text content at the given version. Annotations without any visible text at the version
+    /// are skipped.
+    pub fn export_annotations(
+        &self,
+        source: &str,
+        annotations: &[Annotation],
+        version: &ClientState,
+    ) -> Vec<Value> {
+        let deleted = self.deleted_at(version);
+
+        annotations
+            .iter()
+            .filter_map(|annotation| {
+                let Some(Type::Text(text)) = self.find_by_id(&annotation.text) else {
+                    return None;
+                };
+
+                let content = text_at(&text, version, &deleted);
+                let (start, end) = content.resolve(annotation)?;
+                Some(web_annotation(
+                    source,
+                    annotation,
+                    &content.text,
+                    start,
+                    end,
+                ))
+            })
+            .collect()
+    }
+
+    // ids deleted by the delete operations seen at the version
+    fn deleted_at(&self, version: &ClientState) -> IdSet {
+        let store = self.store.borrow();
+        let mut deleted = IdSet::new();
+
+        for (_, items) in store.deletes.iter() {
+            for (id, item) in items.iter() {
+                if is_seen(version, id) {
+                    deleted.insert(*item.range());
+                }
+            }
+        }
+
+        deleted
+    }
+}
+
+// text content at a version with the visible offset of the string items
+struct VersionText {
+    text: String,
+    // every string item with its byte offset in the text and its visibility at the version
+    items: Vec<(usize, IdRange, bool)>,
+}
+
+impl VersionText {
+    // resolve the annotation anchors to the char range of the text, anchors that are not visible
+    // at the version snap to the nearest visible char inside the range
+    fn resolve(&self, annotation: &Annotation) -> Option<(usize, usize)> {
+        let start = self.offset(&annotation.start, false)?;
+        let end = self.offset(&annotation.end, true)?;
+        if start >= end {
+            return None;
+        }
+
+        Some((char_count(&self.text, start), char_count(&self.text, end)))
+    }
+
+    // byte offset of the char with the id, after the char when `after` is set
+    fn offset(&self, id: &Id, after: bool) -> Option<usize> {
+        let (offset, range, visible) =
+            self.items.iter().find(|(_, range, _)| range.contains(id))?;
+        if !visible {
+            return Some(*offset);
+        }
+
+        let offset = offset + (id.clock - range.start) as usize;
+        Some(if after { offset + 1 } else { offset })
+    }
+}
+
+fn text_at(text: &NText, version: &ClientState, deleted: &IdSet) -> VersionText {
+    text.thaw();
+    let mut content = VersionText {
+        text: String::new(),
+        items: vec![],
+    };
+
+    for item in text.borrow().all_items() {
+        let Content::String(s) = item.content() else {
+            continue;
+        };
+
+        let range = item.range();
+        let visible = is_seen(version, &range.start_id()) && !deleted.contains(&range.start_id());
+        content.items.push((content.text.len(), range, visible));
+        if visible {
+            content.text.push_str(&s);
+        }
+    }
+
+    content
+}
+
+fn web_annotation(
+    source: &str,
+    annotation: &Annotation,
+    text: &str,
+    start: usize,
+    end: usize,
+) -> Value {
+    let chars: Vec<char> = text.chars().collect();
+    let exact: String = chars[start..end].iter().collect();
+    let prefix: String = chars[start.saturating_sub(QUOTE_CONTEXT)..start]
+        .iter()
+        .collect();
+    let suffix: String = chars[end..(end + QUOTE_CONTEXT).min(chars.len())]
+        .iter()
+        .collect();
+
+    let mut value = json!({
+        "@context": "http://www.w3.org/ns/anno.jsonld",
+        "id": annotation.id,
+        "type": "Annotation",
+        "target": {
+            "source": source,
+            "selector": [
+                {
+                    "type": "TextPositionSelector",
+                    "start": start,
+                    "end": end,
+                },
+                {
+                    "type": "TextQuoteSelector",
+                    "exact": exact,
+                    "prefix": prefix,
+                    "suffix": suffix,
+                },
+            ],
+        },
+    });
+
+    if let Some(body) = &annotation.body {
+        value["body"] = body.clone();
+    }
+
+    value
+}
+
+// number of chars that start before the byte offset
+fn char_count(text: &str, offset: usize) -> usize {
+    text.char_indices().take_while(|(i, _)| *i < offset).count()
+}
+
+// id of the visible char at the text offset
+pub(crate) fn char_id(text: &NText, offset: u32) -> Option<Id> {
+    text.thaw();
+    let mut start = 0;
+    for item in text.visible_item_iter() {
+        let size = item.size();
+        if offset < start + size {
+            let id = item.id();
+            return Some(Id::new(id.client, id.clock + offset - start));
+        }
+        start += size;
+    }
+
+    None
+}
+
+#[inline]
+fn is_seen(version: &ClientState, id: &Id) -> bool {
+    version
+        .get(&id.client)
+        .is_some_and(|clock| id.clock <= *clock)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_web_annotation() {
+        let doc = Doc::default();
+        let text = doc.text();
+        doc.set("text", text.clone());
+        text.append(doc.string("hello world"));
+        doc.commit();
+
+        let annotation = doc
+            .annotate("a1", &text, 6, 11)
+            .unwrap()
+            .with_body("greeting");
+        let version = doc.version();
+
+        text.insert(6, doc.string("big "));
+        doc.commit();
+
+        let exported = doc.export_annotations("doc.txt", &[annotation.clone()], &version);
+        let selector = &exported[0]["target"]["selector"];
+        assert_eq!(selector[0]["start"], 6);
+        assert_eq!(selector[0]["end"], 11);
+        assert_eq!(selector[1]["exact"], "world");
+        assert_eq!(selector[1]["prefix"], "hello ");
+        assert_eq!(exported[0]["body"], "greeting");
+
+        let exported = doc.export_annotations("doc.txt", &[annotation], &doc.version());
+        let selector = &exported[0]["target"]["selector"];
+        assert_eq!(selector[0]["start"], 10);
+        assert_eq!(selector[0]["end"], 15);
+        assert_eq!(selector[1]["prefix"], "hello big ");
+    }
+}
@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::limits::{DiffLimit, LimitError};
+
+// mover undo chains longer than this make every apply replay a large part of the dag
+const UNDO_CHAIN_WARN: usize = 64;
+// many parked items usually mean a missing diff from another site
+const PENDING_WARN: usize = 1024;
+const SLOW_APPLY_WARN: Duration = Duration::from_millis(100);
+
+/// ApplyStats describes the work done by `Doc::apply`
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ApplyStats {
+    /// time spent in apply
+    pub elapsed: Duration,
+    /// number of items integrated into the document
+    pub integrated: usize,
+    /// number of delete operations applied
+    pub deleted: usize,
+    /// number of items parked in the pending store with unmet dependencies
+    pub pending: usize,
+    /// number of integrated items that are anchored inside an existing string
+    pub splits: usize,
+    /// number of changes undone in the dag to integrate concurrent movers
+    pub undo_steps: usize,
+    /// number of changes redone after the movers are integrated
+    pub redo_steps: usize,
+    /// pathological patterns seen while applying
+    pub warnings: Vec<ApplyWarning>,
+}
+
+/// ApplyWarning flags a slow path taken by `Doc::apply`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum ApplyWarning {
+    /// a long chain of changes was undone to integrate concurrent movers
+    LongUndoChain(usize),
+    /// many items are waiting for missing dependencies
+    ManyPending(usize),
+    /// apply took longer than expected
+    Slow(Duration),
+    /// the diff was not applied
+    Rejected(String),
+    /// the diff was over the document limits, parked items over the limit are dropped
+    /// without rejecting the diff
+    LimitExceeded(LimitError),
+}
+
+impl ApplyStats {
+    pub(crate) fn rejected(reason: impl Into<String>) -> Self {
+        let mut stats = Self::default();
+        stats.warn(ApplyWarning::Rejected(reason.into()));
+        stats
+    }
+
+    pub(crate) fn over_limit(err: LimitError) -> Self {
+        let mut stats = Self::default();
+        stats.warn(ApplyWarning::LimitExceeded(err));
+        stats
+    }
+
+    #[inline]
+    pub fn is_rejected(&self) -> bool {
+        self.warnings.iter().any(|w| match w {
+            ApplyWarning::Rejected(_) => true,
+            ApplyWarning::LimitExceeded(err) => err.limit != DiffLimit::Pending,
+            _ => false,
+        })
+    }
+
+    pub(crate) fn warn(&mut self, warning: ApplyWarning) {
+        log::warn!("apply warning: {:?}", warning);
+        self.warnings.push(warning);
+    }
+
+    // check the counters against the slow path thresholds
+    pub(crate) fn finish(&mut self, elapsed: Duration) {
+        self.elapsed = elapsed;
+
+        if self.undo_steps > UNDO_CHAIN_WARN {
+            self.warn(ApplyWarning::LongUndoChain(self.undo_steps));
+        }
+        if self.pending > PENDING_WARN {
+            self.warn(ApplyWarning::ManyPending(self.pending));
+        }
+        if elapsed > SLOW_APPLY_WARN {
+            self.warn(ApplyWarning::Slow(elapsed));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doc::{CloneDeep, Doc};
+    use crate::state::ClientState;
+
+    #[test]
+    fn test_apply_stats() {
+        let d1 = Doc::default();
+        d1.commit();
+
+        let d2 = d1.clone_deep();
+        d2.update_client();
+
+        let text = d2.text();
+        d2.set("text", text.clone());
+        text.append(d2.string("hello"));
+        d2.commit();
+
+        let stats = d1.apply(&d2.diff(ClientState::default()));
+        assert_eq!(stats.integrated, 2);
+        assert_eq!(stats.pending, 0);
+        assert!(!stats.is_rejected());
+    }
+}
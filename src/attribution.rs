@@ -0,0 +1,225 @@
+use hashbrown::HashMap;
+
+use crate::bimapid::{ClientId, ClientMapper};
+use crate::id::{Client, Id, IdRange, WithId, WithTarget};
+use crate::item::ItemKind;
+use crate::ntext::NText;
+use crate::store::DocStore;
+use crate::types::Type;
+
+/// Author of an edit, the change is `None` while the edit is pending
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Author {
+    pub client: Client,
+    pub change: Option<IdRange>,
+}
+
+/// TextAttribution is a run of characters inserted by one change
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TextAttribution {
+    /// ids of the characters
+    pub id: IdRange,
+    /// offset of the run in the visible text, a deleted run is at the offset it was at
+    pub offset: u32,
+    /// content of the run, empty when the deleted text was collected
+    pub content: String,
+    pub inserted: Author,
+    /// the delete of a deleted run
+    pub deleted: Option<Author>,
+}
+
+impl TextAttribution {
+    #[inline]
+    pub fn is_deleted(&self) -> bool {
+        self.deleted.is_some()
+    }
+}
+
+// authors of the edits by the ids of the edits
+struct Authors<'a> {
+    store: &'a DocStore,
+    // ranges deleted by every delete, by the client of the deleted items
+    deletes: HashMap<ClientId, Vec<(IdRange, Id)>>,
+}
+
+impl<'a> Authors<'a> {
+    fn new(store: &'a DocStore) -> Self {
+        let mut deletes: HashMap<ClientId, Vec<(IdRange, Id)>> = HashMap::new();
+        for (_, store) in store.deletes.iter() {
+            for (id, delete) in store.iter() {
+                let range = *delete.range();
+                deletes.entry(range.client).or_default().push((range, *id));
+            }
+        }
+
+        Self { store, deletes }
+    }
+
+    fn author(&self, id: &Id) -> Option<Author> {
+        Some(Author {
+            client: self.store.state.clients.get_client(&id.client)?.clone(),
+            change: self.store.changes.get(id).map(|change| (*change).into()),
+        })
+    }
+
+    fn deleted(&self, id: &Id) -> Option<Author> {
+        let ranges = self.deletes.get(&id.client)?;
+        let (_, delete) = ranges.iter().find(|(range, _)| range.contains(id))?;
+        self.author(delete)
+    }
+}
+
+impl NText {
+    /// Who inserted every run of the text and who deleted the deleted runs, in text
+    /// order. A run is split where the inserting change or the delete changes.
+    pub fn attribution(&self) -> Vec<TextAttribution> {
+        self.thaw();
+        let store = self.store.upgrade().unwrap();
+        let store = store.borrow();
+        let authors = Authors::new(&store);
+
+        let mut runs: Vec<TextAttribution> = vec![];
+        let mut offset = 0;
+        for child in self.item_ref().borrow().all_items() {
+            // a moved string shows up at its mover
+            let string = match child.kind() {
+                ItemKind::Move if child.is_visible() => child.item_ref().get_target(),
+                ItemKind::String if !child.is_moved() => Some(child),
+                _ => None,
+            };
+            let Some(string) =
+                string.filter(|s| s.kind() == ItemKind::String && s.item_ref().size() > 0)
+            else {
+                continue;
+            };
+
+            for run in string_runs(&authors, &string, offset) {
+                if run.deleted.is_none() {
+                    offset += run.id.size();
+                }
+                match runs.last_mut() {
+                    Some(last) if follows(last, &run) => {
+                        last.id = IdRange::new(last.id.client, last.id.start, run.id.end);
+                        last.content.push_str(&run.content);
+                    }
+                    _ => runs.push(run),
+                }
+            }
+        }
+
+        runs
+    }
+}
+
+// runs of the string item split at the change boundaries
+fn string_runs(authors: &Authors, string: &Type, offset: u32) -> Vec<TextAttribution> {
+    let item = string.item_ref();
+    let range = item.id().range(item.size());
+    let content = item.text_content();
+    let deleted = authors.deleted(&range.start_id());
+
+    let mut runs = vec![];
+    let mut start = range.start;
+    while start <= range.end {
+        let id = Id::new(range.client, start);
+        let end = authors
+            .store
+            .changes
+            .get(&id)
+            .map_or(range.end, |change| change.end.min(range.end));
+        let Some(inserted) = authors.author(&id) else {
+            break;
+        };
+
+        let bytes = content.as_bytes();
+        let (from, to) = (
+            (start - range.start) as usize,
+            (end - range.start + 1) as usize,
+        );
+        let visible = runs
+            .iter()
+            .map(|r: &TextAttribution| r.id.size())
+            .sum::<u32>();
+        runs.push(TextAttribution {
+            id: IdRange::new(range.client, start, end),
+            offset: if deleted.is_some() {
+                offset
+            } else {
+                offset + visible
+            },
+            content: bytes
+                .get(from..to)
+                .map(|b| String::from_utf8_lossy(b).into_owned())
+                .unwrap_or_default(),
+            inserted,
+            deleted: deleted.clone(),
+        });
+        start = end + 1;
+    }
+
+    runs
+}
+
+// the run continues the last run
+fn follows(last: &TextAttribution, run: &TextAttribution) -> bool {
+    let offset = match last.deleted {
+        Some(_) => last.offset,
+        None => last.offset + last.id.size(),
+    };
+
+    last.id.client == run.id.client
+        && last.id.end + 1 == run.id.start
+        && last.inserted == run.inserted
+        && last.deleted == run.deleted
+        && offset == run.offset
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doc::Doc;
+
+    use super::*;
+
+    #[test]
+    fn test_text_attribution() {
+        let d1 = Doc::default();
+        let text = d1.text();
+        d1.set("text", text.clone());
+        let hello = d1.string("hello");
+        text.append(hello.clone());
+        d1.commit();
+
+        let d2 = Doc::new(d1.meta.clone());
+        d2.apply(&d1.diff(d2.version()));
+        d2.update_client();
+        let Some(Type::Text(remote)) = d2.get("text") else {
+            panic!("text not synced");
+        };
+        remote.append(d2.string(" world"));
+        d2.commit();
+
+        d1.apply(&d2.diff(d1.version()));
+        hello.delete();
+        d1.commit();
+        text.append(d1.string("!"));
+
+        let runs = text.attribution();
+        let content: Vec<&str> = runs.iter().map(|r| r.content.as_str()).collect();
+        assert_eq!(content, vec!["hello", " world", "!"]);
+        assert_eq!(
+            runs.iter().map(|r| r.offset).collect::<Vec<_>>(),
+            vec![0, 0, 6]
+        );
+
+        // the deleted run keeps its author next to the author of the delete
+        let deleted = runs[0].deleted.clone().unwrap();
+        assert_eq!(deleted.client, runs[0].inserted.client);
+        assert_ne!(deleted.change, runs[0].inserted.change);
+        let change = runs[0].inserted.change.unwrap();
+        assert!(change.contains(&runs[0].id.start_id()));
+
+        assert_ne!(runs[1].inserted.client, runs[0].inserted.client);
+        assert!(!runs[1].is_deleted());
+        assert!(runs[2].inserted.change.is_none());
+    }
+}
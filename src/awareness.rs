@@ -0,0 +1,601 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use hashbrown::HashMap;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use serde_json::{Map, Value};
+
+use crate::codec_v1::{DecoderV1, EncoderV1};
+use crate::decoder::{Decode, DecodeContext, Decoder};
+use crate::encoder::{Encode, EncodeContext, Encoder};
+use crate::Client;
+
+/// Awareness keeps the ephemeral presence state of the clients connected to a document.
+/// The state is split into named channels (e.g. "cursor", "profile", "viewport"),
+/// every channel has its own clock so that a client can publish only the channels that changed.
+///
+/// Awareness state is not part of the document history and is never persisted.
+#[derive(Debug, Clone, Default)]
+pub struct Awareness {
+    client: Client,
+    states: HashMap<Client, ClientAwareness>,
+    publisher: Publisher,
+}
+
+impl Awareness {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            states: HashMap::new(),
+            publisher: Publisher::default(),
+        }
+    }
+
+    /// Publish the local state at most `hz` times a second through `poll_update`
+    pub fn with_max_hz(mut self, hz: u32) -> Self {
+        self.publisher.interval = Some(Duration::from_secs(1) / hz.max(1));
+        self
+    }
+
+    /// Local client of the awareness instance
+    #[inline]
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Update a channel of the local client state
+    pub fn set_local(&mut self, channel: impl Into<String>, value: impl Into<Value>) {
+        let state = self.states.entry(self.client.clone()).or_default();
+        state.set(channel.into(), value.into());
+    }
+
+    /// Remove a channel from the local client state, the removal is published as a null value
+    pub fn remove_local(&mut self, channel: impl Into<String>) {
+        self.set_local(channel, Value::Null);
+    }
+
+    /// Replace the local state, the keys of the object are the channels and the channels
+    /// missing in the object are removed. A null state removes every channel.
+    pub fn set_local_state(&mut self, state: Value) -> Result<(), String> {
+        let entries = match state {
+            Value::Object(entries) => entries,
+            Value::Null => Map::new(),
+            _ => return Err("awareness state must be an object or null".to_string()),
+        };
+
+        let removed: Vec<String> = self
+            .get_state(&self.client)
+            .into_keys()
+            .filter(|channel| !entries.contains_key(channel))
+            .collect();
+        removed
+            .into_iter()
+            .for_each(|channel| self.remove_local(channel));
+
+        for (channel, value) in entries {
+            // unchanged channels keep their clock, so they are not published again
+            if self.get_local(&channel) != Some(&value) {
+                self.set_local(channel, value);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the local value of a channel
+    pub fn get_local(&self, channel: &str) -> Option<&Value> {
+        self.get(&self.client, channel)
+    }
+
+    /// Get the value of a channel for the given client
+    pub fn get(&self, client: &Client, channel: &str) -> Option<&Value> {
+        self.states
+            .get(client)
+            .and_then(|state| state.channels.get(channel))
+            .map(|channel| &channel.value)
+            .filter(|value| !value.is_null())
+    }
+
+    /// Get all visible channels of a client
+    pub fn get_state(&self, client: &Client) -> BTreeMap<String, Value> {
+        self.states
+            .get(client)
+            .map(|state| state.values())
+            .unwrap_or_default()
+    }
+
+    /// Clients with a known awareness state
+    pub fn clients(&self) -> Vec<Client> {
+        self.states.keys().cloned().collect()
+    }
+
+    /// Forget the state of a disconnected client
+    pub fn remove_client(&mut self, client: &Client) {
+        self.states.remove(client);
+    }
+
+    /// Create an update with all channels of the local client
+    pub fn full_update(&self) -> AwarenessUpdate {
+        let mut update = AwarenessUpdate::default();
+        if let Some(state) = self.states.get(&self.client) {
+            update.add_client(&self.client, state.channels.iter());
+        }
+
+        update
+    }
+
+    /// Encoded full update of the local client, sending it periodically keeps the local
+    /// client from being evicted by the peers
+    pub fn encode_update(&self) -> Vec<u8> {
+        let mut e = EncoderV1::new();
+        self.full_update()
+            .encode(&mut e, &mut EncodeContext::default());
+        e.buffer()
+    }
+
+    /// Apply an encoded update, returns the (client, channel) pairs that changed
+    pub fn apply_update(&mut self, bytes: &[u8]) -> Result<Vec<(Client, String)>, String> {
+        if bytes.is_empty() {
+            return Err("empty awareness update".to_string());
+        }
+
+        let mut d = DecoderV1::try_new(bytes.to_vec())?;
+        let update = AwarenessUpdate::decode(&mut d, &DecodeContext::default())?;
+        Ok(self.apply(&update))
+    }
+
+    /// Forget the remote clients not heard from within the timeout, returns the evicted
+    /// clients. Any update of a client counts, even when its channels did not change.
+    pub fn evict_stale(&mut self, timeout: Duration) -> Vec<Client> {
+        self.evict_stale_at(now(), timeout)
+    }
+
+    pub(crate) fn evict_stale_at(&mut self, now: u64, timeout: Duration) -> Vec<Client> {
+        let stale: Vec<Client> = self
+            .states
+            .iter()
+            .filter(|(client, _)| **client != self.client)
+            .filter(|(_, state)| now.saturating_sub(state.last_seen) >= timeout.as_secs())
+            .map(|(client, _)| client.clone())
+            .collect();
+        stale.iter().for_each(|client| self.remove_client(client));
+
+        stale
+    }
+
+    /// Create an update with only the given channels of the local client
+    pub fn partial_update(&self, channels: &[&str]) -> AwarenessUpdate {
+        let mut update = AwarenessUpdate::default();
+        if let Some(state) = self.states.get(&self.client) {
+            let entries = state
+                .channels
+                .iter()
+                .filter(|(name, _)| channels.contains(&name.as_str()));
+            update.add_client(&self.client, entries);
+        }
+
+        update
+    }
+
+    /// Create an update with the local channels changed since the last poll, None when
+    /// nothing changed or the last update was sent less than the max rate ago.
+    ///
+    /// Unchanged channels are never resent, so rarely changing channels (e.g. the name and
+    /// color) cost nothing between their changes. An object channel is sent as a delta of
+    /// the changed keys, with a full value every few deltas for the peers that missed one.
+    pub fn poll_update(&mut self) -> Option<AwarenessUpdate> {
+        self.poll_update_at(Instant::now())
+    }
+
+    pub fn poll_update_at(&mut self, now: Instant) -> Option<AwarenessUpdate> {
+        if let (Some(interval), Some(last)) = (self.publisher.interval, self.publisher.last) {
+            if now.duration_since(last) < interval {
+                return None;
+            }
+        }
+
+        let state = self.states.get(&self.client)?;
+        let mut update = AwarenessUpdate::default();
+        for (name, channel) in state.channels.iter() {
+            let sent = self.publisher.sent.get(name);
+            if sent.is_some_and(|sent| sent.clock == channel.clock) {
+                continue;
+            }
+
+            let delta = sent
+                .filter(|sent| sent.deltas + 1 < KEYFRAME_EVERY)
+                .and_then(|sent| delta(&sent.value, &channel.value).map(|d| (sent, d)));
+            let (base, value, deltas) = match delta {
+                Some((sent, delta)) => (Some(sent.clock), delta, sent.deltas + 1),
+                None => (None, channel.value.clone(), 0),
+            };
+
+            update.entries.push(AwarenessEntry {
+                client: self.client.clone(),
+                channel: name.clone(),
+                clock: channel.clock,
+                base,
+                value,
+            });
+            let sent = SentChannel {
+                clock: channel.clock,
+                value: channel.value.clone(),
+                deltas,
+            };
+            self.publisher.sent.insert(name.clone(), sent);
+        }
+
+        if update.is_empty() {
+            return None;
+        }
+        self.publisher.last = Some(now);
+
+        Some(update)
+    }
+
+    /// Apply a remote update, returns the (client, channel) pairs that changed
+    pub fn apply(&mut self, update: &AwarenessUpdate) -> Vec<(Client, String)> {
+        let mut changed = vec![];
+        for entry in update.entries.iter() {
+            // local state is owned by this instance
+            if entry.client == self.client {
+                continue;
+            }
+
+            let state = self.states.entry(entry.client.clone()).or_default();
+            state.last_seen = now();
+            if state.merge(entry) {
+                changed.push((entry.client.clone(), entry.channel.clone()));
+            }
+        }
+
+        changed
+    }
+}
+
+/// Named awareness channels of a single client
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ClientAwareness {
+    pub(crate) channels: BTreeMap<String, AwarenessChannel>,
+    // local time in seconds when an update of the client was last applied
+    pub(crate) last_seen: u64,
+}
+
+impl ClientAwareness {
+    fn set(&mut self, channel: String, value: Value) {
+        let entry = self.channels.entry(channel).or_default();
+        entry.clock += 1;
+        entry.value = value;
+        entry.updated_at = now();
+    }
+
+    // last writer wins per channel, the channel clock decides the winner
+    fn merge(&mut self, entry: &AwarenessEntry) -> bool {
+        let clock = self.channels.get(&entry.channel).map_or(0, |c| c.clock);
+        if entry.clock <= clock {
+            return false;
+        }
+        // a delta is applied on top of the value it was made from only
+        if entry.base.is_some_and(|base| base != clock) {
+            return false;
+        }
+
+        let channel = self.channels.entry(entry.channel.clone()).or_default();
+        channel.value = match entry.base {
+            Some(_) => patch(&channel.value, &entry.value),
+            None => entry.value.clone(),
+        };
+        channel.clock = entry.clock;
+        channel.updated_at = now();
+
+        true
+    }
+
+    fn values(&self) -> BTreeMap<String, Value> {
+        self.channels
+            .iter()
+            .filter(|(_, channel)| !channel.value.is_null())
+            .map(|(name, channel)| (name.clone(), channel.value.clone()))
+            .collect()
+    }
+}
+
+/// AwarenessChannel is a single named value with its own clock
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct AwarenessChannel {
+    pub(crate) clock: u32,
+    pub(crate) value: Value,
+    // local time in seconds when the channel was last updated
+    pub(crate) updated_at: u64,
+}
+
+// every this many updates of a channel carry the full value
+const KEYFRAME_EVERY: u32 = 16;
+
+// local channels published by `Awareness::poll_update`
+#[derive(Debug, Clone, Default)]
+struct Publisher {
+    // min time between two updates
+    interval: Option<Duration>,
+    last: Option<Instant>,
+    sent: BTreeMap<String, SentChannel>,
+}
+
+#[derive(Debug, Clone)]
+struct SentChannel {
+    clock: u32,
+    value: Value,
+    // deltas sent since the last full value
+    deltas: u32,
+}
+
+// the changed keys of an object, removed keys are null. None when the full value is
+// smaller or the null of a removed key can not be told from a null value.
+fn delta(old: &Value, new: &Value) -> Option<Value> {
+    let (Value::Object(old), Value::Object(new)) = (old, new) else {
+        return None;
+    };
+    if new.values().any(Value::is_null) {
+        return None;
+    }
+
+    let mut delta: Map<String, Value> = new
+        .iter()
+        .filter(|(key, value)| old.get(*key) != Some(*value))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    for key in old.keys().filter(|key| !new.contains_key(*key)) {
+        delta.insert(key.clone(), Value::Null);
+    }
+
+    (delta.len() < new.len()).then_some(Value::Object(delta))
+}
+
+fn patch(value: &Value, delta: &Value) -> Value {
+    let mut value = match value {
+        Value::Object(entries) => entries.clone(),
+        _ => Map::new(),
+    };
+    if let Value::Object(delta) = delta {
+        for (key, change) in delta {
+            match change {
+                Value::Null => value.remove(key),
+                _ => value.insert(key.clone(), change.clone()),
+            };
+        }
+    }
+
+    Value::Object(value)
+}
+
+/// AwarenessUpdate carries channel values of one or more clients
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AwarenessUpdate {
+    pub(crate) entries: Vec<AwarenessEntry>,
+}
+
+impl AwarenessUpdate {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn add_client<'a>(
+        &mut self,
+        client: &Client,
+        channels: impl Iterator<Item = (&'a String, &'a AwarenessChannel)>,
+    ) {
+        for (name, channel) in channels {
+            self.entries.push(AwarenessEntry {
+                client: client.clone(),
+                channel: name.clone(),
+                clock: channel.clock,
+                base: None,
+                value: channel.value.clone(),
+            });
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct AwarenessEntry {
+    pub(crate) client: Client,
+    pub(crate) channel: String,
+    pub(crate) clock: u32,
+    // clock of the value the delta is made from, None for a full value
+    pub(crate) base: Option<u32>,
+    pub(crate) value: Value,
+}
+
+impl Serialize for AwarenessEntry {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_struct("AwarenessEntry", 5)?;
+        s.serialize_field("client", &self.client)?;
+        s.serialize_field("channel", &self.channel)?;
+        s.serialize_field("clock", &self.clock)?;
+        s.serialize_field("base", &self.base)?;
+        s.serialize_field("value", &self.value)?;
+        s.end()
+    }
+}
+
+impl Serialize for AwarenessUpdate {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.entries.serialize(serializer)
+    }
+}
+
+impl Encode for AwarenessEntry {
+    fn encode<E: Encoder>(&self, e: &mut E, cx: &mut EncodeContext) {
+        self.client.encode(e, cx);
+        e.string(&self.channel);
+        e.u32(self.clock);
+        // channel clocks start at 1
+        e.u32(self.base.unwrap_or(0));
+        e.string(&self.value.to_string());
+    }
+}
+
+impl Decode for AwarenessEntry {
+    fn decode<D: Decoder>(d: &mut D, ctx: &DecodeContext) -> Result<AwarenessEntry, String> {
+        let client = Client::decode(d, ctx)?;
+        let channel = d.string()?;
+        let clock = d.u32()?;
+        let base = Some(d.u32()?).filter(|base| *base > 0);
+        let value = serde_json::from_str(&d.string()?).map_err(|e| e.to_string())?;
+
+        Ok(AwarenessEntry {
+            client,
+            channel,
+            clock,
+            base,
+            value,
+        })
+    }
+}
+
+impl Encode for AwarenessUpdate {
+    fn encode<E: Encoder>(&self, e: &mut E, cx: &mut EncodeContext) {
+        self.entries.encode(e, cx);
+    }
+}
+
+impl Decode for AwarenessUpdate {
+    fn decode<D: Decoder>(d: &mut D, ctx: &DecodeContext) -> Result<AwarenessUpdate, String> {
+        let entries = Vec::<AwarenessEntry>::decode(d, ctx)?;
+        Ok(AwarenessUpdate { entries })
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::codec_v1::EncoderV1;
+
+    use super::*;
+
+    #[test]
+    fn test_partial_awareness_update() {
+        let mut a1 = Awareness::new(Client::default());
+        let mut a2 = Awareness::new(Client::default());
+
+        a1.set_local("profile", json!({"name": "alice"}));
+        a1.set_local("cursor", json!(1));
+
+        a2.apply(&a1.full_update());
+        assert_eq!(a2.get(a1.client(), "cursor"), Some(&json!(1)));
+
+        a1.set_local("cursor", json!(5));
+        let update = a1.partial_update(&["cursor"]);
+        assert_eq!(update.size(), 1);
+
+        let changed = a2.apply(&update);
+        assert_eq!(changed, vec![(a1.client().clone(), "cursor".to_string())]);
+        assert_eq!(a2.get(a1.client(), "cursor"), Some(&json!(5)));
+        assert_eq!(a2.get(a1.client(), "profile"), Some(&json!({"name": "alice"})));
+
+        // stale updates are ignored
+        assert!(a2.apply(&update).is_empty());
+    }
+
+    #[test]
+    fn test_throttled_delta_updates() {
+        let mut a1 = Awareness::new(Client::default()).with_max_hz(10);
+        let mut a2 = Awareness::new(Client::default());
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+
+        a1.set_local("profile", json!({"name": "alice", "color": "red"}));
+        a1.set_local("cursor", json!({"anchor": 1, "head": 1, "path": [0, 2]}));
+        let update = a1.poll_update_at(at(0)).unwrap();
+        assert_eq!(update.size(), 2);
+        a2.apply(&update);
+
+        // throttled until the interval passed, the profile is not resent
+        a1.set_local("cursor", json!({"anchor": 1, "head": 3, "path": [0, 2]}));
+        assert!(a1.poll_update_at(at(50)).is_none());
+        a1.set_local("cursor", json!({"anchor": 1, "head": 5, "path": [0, 2]}));
+        let update = a1.poll_update_at(at(100)).unwrap();
+        assert_eq!(update.size(), 1);
+        assert_eq!(update.entries[0].value, json!({"head": 5}));
+        assert!(a1.poll_update_at(at(300)).is_none());
+
+        a2.apply(&update);
+        assert_eq!(
+            a2.get(a1.client(), "cursor"),
+            Some(&json!({"anchor": 1, "head": 5, "path": [0, 2]}))
+        );
+
+        // a peer without the base of the delta waits for a full value
+        let mut a3 = Awareness::new(Client::default());
+        assert!(a3.apply(&update).is_empty());
+        assert!(a3.get(a1.client(), "cursor").is_none());
+    }
+
+    #[test]
+    fn test_awareness_state_and_eviction() {
+        let mut a1 = Awareness::new(Client::default());
+        let mut a2 = Awareness::new(Client::default());
+
+        a1.set_local_state(json!({"name": "alice", "cursor": 3}))
+            .unwrap();
+        let changed = a2.apply_update(&a1.encode_update()).unwrap();
+        assert_eq!(changed.len(), 2);
+        assert_eq!(a2.get(a1.client(), "name"), Some(&json!("alice")));
+
+        // only the changed and removed channels change on the peer
+        a1.set_local_state(json!({"name": "alice", "selection": [1, 4]}))
+            .unwrap();
+        let mut changed = a2.apply_update(&a1.encode_update()).unwrap();
+        changed.sort();
+        let channels: Vec<&str> = changed.iter().map(|(_, c)| c.as_str()).collect();
+        assert_eq!(channels, vec!["cursor", "selection"]);
+        assert!(a2.get(a1.client(), "cursor").is_none());
+        assert!(a1.set_local_state(json!(1)).is_err());
+
+        // a silent client is evicted, the local client never is
+        a2.set_local("name", json!("bob"));
+        let timeout = Duration::from_secs(30);
+        assert!(a2.evict_stale_at(now(), timeout).is_empty());
+        let evicted = a2.evict_stale_at(now() + 30, timeout);
+        assert_eq!(evicted, vec![a1.client().clone()]);
+        assert_eq!(a2.clients(), vec![a2.client().clone()]);
+    }
+
+    #[test]
+    fn test_encode_decode_awareness_update() {
+        let mut a1 = Awareness::new(Client::default());
+        a1.set_local("cursor", json!({"anchor": 1, "head": 4}));
+        a1.remove_local("viewport");
+
+        let update = a1.full_update();
+
+        let mut e = EncoderV1::new();
+        update.encode(&mut e, &mut EncodeContext::default());
+
+        let mut d = e.decoder();
+        let decoded = AwarenessUpdate::decode(&mut d, &DecodeContext::default()).unwrap();
+
+        assert_eq!(update, decoded);
+    }
+}
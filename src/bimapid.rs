@@ -1,5 +1,6 @@
 use crate::decoder::{Decode, DecodeContext, Decoder};
 use crate::encoder::{Encode, EncodeContext, Encoder};
+use crate::limits::DiffLimit;
 use crate::mark::Mark;
 use crate::Client;
 use bimap::BiMap;
@@ -348,6 +349,7 @@ impl Encode for ClientMap {
 impl Decode for ClientMap {
     fn decode<D: Decoder>(decoder: &mut D, _ctx: &DecodeContext) -> Result<ClientMap, String> {
         let len = decoder.u32()? as usize;
+        _ctx.check_limit(DiffLimit::Clients, len)?;
         let mut map = BiMap::new();
         for _ in 0..len {
             let client = Client::decode(decoder, _ctx)?;
@@ -0,0 +1,250 @@
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+
+use crate::doc::{Doc, DocMeta};
+use crate::nmap::NMap;
+use crate::state::ClientFrontier;
+use crate::store::DocStore;
+
+/// BridgeMode tells when a bridge carries the changes to the other document
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum BridgeMode {
+    /// apply to the other document right after the commit or apply
+    #[default]
+    Sync,
+    /// wait for `LocalBridge::flush`, e.g. to batch the changes of a frame
+    Queued,
+}
+
+/// LocalBridge replicates two documents of the same process without encoding the changes,
+/// e.g. for preview panes or tests. The changes a document receives from elsewhere are
+/// carried over too, so bridges can be chained. The documents stay connected until the
+/// bridge is dropped or disconnected.
+#[must_use = "dropping the bridge disconnects the documents"]
+#[derive(Debug)]
+pub struct LocalBridge {
+    state: Rc<RefCell<BridgeState>>,
+}
+
+#[derive(Debug)]
+struct BridgeState {
+    mode: BridgeMode,
+    docs: [WeakDoc; 2],
+    tokens: [u32; 2],
+    // the document changed since its changes were carried over
+    pending: [bool; 2],
+    connected: bool,
+}
+
+// a document handle that does not keep the store alive
+#[derive(Debug, Clone)]
+struct WeakDoc {
+    meta: DocMeta,
+    root: NMap,
+    store: Weak<RefCell<DocStore>>,
+}
+
+impl WeakDoc {
+    fn new(doc: &Doc) -> Self {
+        Self {
+            meta: doc.meta.clone(),
+            root: doc.root.clone(),
+            store: Rc::downgrade(&doc.store),
+        }
+    }
+
+    fn upgrade(&self) -> Option<Doc> {
+        Some(Doc {
+            meta: self.meta.clone(),
+            root: self.root.clone(),
+            store: self.store.upgrade()?,
+        })
+    }
+}
+
+impl LocalBridge {
+    /// Connect the documents and bring them in sync, changes are carried over right away
+    pub fn connect(a: &Doc, b: &Doc) -> Self {
+        Self::with_mode(a, b, BridgeMode::Sync)
+    }
+
+    /// Connect the documents and bring them in sync, later changes wait for `flush`
+    pub fn queued(a: &Doc, b: &Doc) -> Self {
+        Self::with_mode(a, b, BridgeMode::Queued)
+    }
+
+    pub fn with_mode(a: &Doc, b: &Doc, mode: BridgeMode) -> Self {
+        let state = Rc::new(RefCell::new(BridgeState {
+            mode,
+            docs: [WeakDoc::new(a), WeakDoc::new(b)],
+            tokens: [0, 0],
+            pending: [false, false],
+            connected: true,
+        }));
+
+        for (side, doc) in [a, b].into_iter().enumerate() {
+            let weak = Rc::downgrade(&state);
+            let token = doc.observe_deep(move |_| on_change(&weak, side));
+            state.borrow_mut().tokens[side] = token;
+        }
+
+        forward(&state, 0);
+        forward(&state, 1);
+        state.borrow_mut().pending = [false, false];
+
+        Self { state }
+    }
+
+    #[inline]
+    pub fn mode(&self) -> BridgeMode {
+        self.state.borrow().mode
+    }
+
+    #[inline]
+    pub fn is_connected(&self) -> bool {
+        self.state.borrow().connected
+    }
+
+    /// Documents with changes waiting for a flush
+    pub fn has_pending(&self) -> bool {
+        self.state.borrow().pending.contains(&true)
+    }
+
+    /// Carry the queued changes over in both directions, returns the number of applied diffs
+    pub fn flush(&self) -> usize {
+        let mut applied = 0;
+        loop {
+            let side = self.state.borrow().pending.iter().position(|p| *p);
+            match side {
+                Some(side) => applied += forward(&self.state, side) as usize,
+                None => return applied,
+            }
+        }
+    }
+
+    /// Stop carrying the changes, queued changes are dropped
+    pub fn disconnect(&self) {
+        let mut state = self.state.borrow_mut();
+        if !state.connected {
+            return;
+        }
+
+        state.connected = false;
+        state.pending = [false, false];
+        for (doc, token) in state.docs.iter().zip(state.tokens) {
+            if let Some(store) = doc.store.upgrade() {
+                if let Ok(mut store) = store.try_borrow_mut() {
+                    store.emitter.remove_listener(token);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for LocalBridge {
+    fn drop(&mut self) {
+        self.disconnect();
+    }
+}
+
+// the document on the side changed, a document reports a change once per event
+fn on_change(state: &Weak<RefCell<BridgeState>>, side: usize) {
+    let Some(state) = state.upgrade() else {
+        return;
+    };
+
+    let doc = {
+        let mut state = state.borrow_mut();
+        if !state.connected || state.pending[side] {
+            return;
+        }
+        state.pending[side] = true;
+        if state.mode == BridgeMode::Queued {
+            return;
+        }
+        state.docs[side].upgrade()
+    };
+
+    // carry the change once all the listeners of the change saw it
+    if let Some(doc) = doc {
+        let weak = Rc::downgrade(&state);
+        doc.defer(move |_| {
+            if let Some(state) = weak.upgrade() {
+                forward(&state, side);
+            }
+        });
+    }
+}
+
+// apply the changes of the side missing on the other side, returns true when a diff was applied
+fn forward(state: &Rc<RefCell<BridgeState>>, side: usize) -> bool {
+    let docs = {
+        let mut state = state.borrow_mut();
+        state.pending[side] = false;
+        let from = state.docs[side].upgrade();
+        let to = state.docs[1 - side].upgrade();
+        from.zip(to).filter(|_| state.connected)
+    };
+    let Some((from, to)) = docs else {
+        return false;
+    };
+
+    let version = to.version();
+    if ClientFrontier::from(version.clone()).covers(&from.version().into()) {
+        return false;
+    }
+
+    // the state borrow is released, the apply reports back to the bridge
+    to.apply(&from.diff(version));
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::Type;
+
+    use super::*;
+
+    #[test]
+    fn test_local_bridge() {
+        let d1 = Doc::default();
+        let list = d1.list();
+        d1.set("list", list.clone());
+        list.append(d1.atom("a"));
+        d1.commit();
+
+        let d2 = Doc::new(d1.meta.clone());
+        let bridge = LocalBridge::connect(&d1, &d2);
+        d2.update_client();
+        assert_eq!(d2.get("list").unwrap().to_json(), list.to_json());
+
+        // commits travel both ways
+        list.append(d1.atom("b"));
+        d1.commit();
+        let Some(Type::List(remote)) = d2.get("list") else {
+            panic!("list not synced");
+        };
+        assert_eq!(remote.size(), 2);
+        remote.append(d2.atom("c"));
+        d2.commit();
+        assert_eq!(list.size(), 3);
+
+        // a chained document gets the changes through the middle one
+        let d3 = Doc::new(d1.meta.clone());
+        let queued = LocalBridge::queued(&d2, &d3);
+        d3.update_client();
+        list.append(d1.atom("d"));
+        d1.commit();
+        assert!(queued.has_pending());
+        assert_eq!(d3.get("list").unwrap().size(), 3);
+        assert_eq!(queued.flush(), 1);
+        assert_eq!(d3.get("list").unwrap().size(), 4);
+        assert_eq!(d3.get("list").unwrap().to_json(), list.to_json());
+
+        bridge.disconnect();
+        list.append(d1.atom("e"));
+        d1.commit();
+        assert_eq!(remote.size(), 4);
+        assert_eq!(queued.flush(), 0);
+    }
+}
@@ -0,0 +1,438 @@
+use crate::id::{WithId, WithTarget};
+use crate::item::{Any, Content};
+use crate::packed::PackedArray;
+use crate::types::Type;
+use crate::Doc;
+
+// CBOR major types, see RFC 8949
+const MAJOR_UNSIGNED: u8 = 0;
+const MAJOR_NEGATIVE: u8 = 1;
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+const MAJOR_TAG: u8 = 6;
+const MAJOR_SIMPLE: u8 = 7;
+
+const SIMPLE_FALSE: u8 = 20;
+const SIMPLE_TRUE: u8 = 21;
+const SIMPLE_NULL: u8 = 22;
+const SIMPLE_UNDEFINED: u8 = 23;
+const SIMPLE_F16: u8 = 25;
+const SIMPLE_F32: u8 = 26;
+const SIMPLE_F64: u8 = 27;
+
+/// CborValue is the decoded form of a CBOR data item
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum CborValue {
+    Null,
+    Bool(bool),
+    Unsigned(u64),
+    Negative(i64),
+    Float(f64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<CborValue>),
+    Map(Vec<(String, CborValue)>),
+}
+
+/// CborEncoder writes the visible document tree as CBOR.
+///
+/// map -> map, list -> array, text -> text string, binary -> byte string,
+/// numbers and booleans keep their native CBOR types.
+#[derive(Debug, Default)]
+pub(crate) struct CborEncoder {
+    buf: Vec<u8>,
+}
+
+impl CborEncoder {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+
+    fn head(&mut self, major: u8, value: u64) {
+        let major = major << 5;
+        if value < 24 {
+            self.buf.push(major | value as u8);
+        } else if value <= u8::MAX as u64 {
+            self.buf.push(major | 24);
+            self.buf.push(value as u8);
+        } else if value <= u16::MAX as u64 {
+            self.buf.push(major | 25);
+            self.buf.extend_from_slice(&(value as u16).to_be_bytes());
+        } else if value <= u32::MAX as u64 {
+            self.buf.push(major | 26);
+            self.buf.extend_from_slice(&(value as u32).to_be_bytes());
+        } else {
+            self.buf.push(major | 27);
+            self.buf.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+
+    fn null(&mut self) {
+        self.buf.push(MAJOR_SIMPLE << 5 | SIMPLE_NULL);
+    }
+
+    fn bool(&mut self, value: bool) {
+        let simple = if value { SIMPLE_TRUE } else { SIMPLE_FALSE };
+        self.buf.push(MAJOR_SIMPLE << 5 | simple);
+    }
+
+    fn int(&mut self, value: i64) {
+        if value < 0 {
+            self.head(MAJOR_NEGATIVE, (-1 - value) as u64);
+        } else {
+            self.head(MAJOR_UNSIGNED, value as u64);
+        }
+    }
+
+    fn float(&mut self, value: f64) {
+        self.buf.push(MAJOR_SIMPLE << 5 | SIMPLE_F64);
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn text(&mut self, value: &str) {
+        self.head(MAJOR_TEXT, value.len() as u64);
+        self.buf.extend_from_slice(value.as_bytes());
+    }
+
+    fn bytes(&mut self, value: &[u8]) {
+        self.head(MAJOR_BYTES, value.len() as u64);
+        self.buf.extend_from_slice(value);
+    }
+
+    pub(crate) fn doc(&mut self, doc: &Doc) {
+        self.item(&Type::from(doc.root.clone()));
+    }
+
+    pub(crate) fn item(&mut self, item: &Type) {
+        match item {
+            Type::Map(map) => {
+                let entries = map
+                    .keys()
+                    .into_iter()
+                    .filter_map(|key| map.get(key.clone()).map(|value| (key, value)))
+                    .collect::<Vec<_>>();
+
+                self.head(MAJOR_MAP, entries.len() as u64);
+                for (key, value) in entries {
+                    self.text(&key);
+                    self.item(&value);
+                }
+            }
+            Type::List(list) => {
+                let items = list.borrow().as_list();
+                self.head(MAJOR_ARRAY, items.len() as u64);
+                for item in items {
+                    self.item(&item);
+                }
+            }
+            Type::Text(text) => self.text(&text.text_content()),
+            Type::String(string) => self.content(&string.content()),
+            Type::Atom(atom) => self.content(&atom.content()),
+            Type::Move(mover) => match mover.get_target() {
+                Some(target) => self.item(&target),
+                None => self.null(),
+            },
+            Type::Counter(counter) => self.int(counter.value()),
+            Type::Proxy(proxy) => match proxy.resolve() {
+                Some(target) => self.text(&target.id().to_string()),
+                None => self.null(),
+            },
+            Type::Mark(_) | Type::Identity => self.null(),
+        }
+    }
+
+    fn content(&mut self, content: &Content) {
+        match content {
+            Content::String(s) => self.text(s),
+            Content::Binary(b) => self.bytes(b),
+            Content::Embed(any) => self.any(any),
+            Content::Id(id) => self.text(&id.to_string()),
+            Content::Types(types) => {
+                self.head(MAJOR_ARRAY, types.len() as u64);
+                for item in types {
+                    self.item(item);
+                }
+            }
+            Content::Doc(_) | Content::Mark(_) | Content::Tombstone(_) | Content::Null => {
+                self.null()
+            }
+        }
+    }
+
+    fn any(&mut self, any: &Any) {
+        match any {
+            Any::Null => self.null(),
+            Any::True => self.bool(true),
+            Any::False => self.bool(false),
+            Any::F32(f) => self.float(*f as f64),
+            Any::F64(f) => self.float(*f),
+            Any::I8(i) => self.int(*i as i64),
+            Any::I16(i) => self.int(*i as i64),
+            Any::I32(i) => self.int(*i as i64),
+            Any::I64(i) => self.int(*i),
+            Any::U8(u) => self.head(MAJOR_UNSIGNED, *u as u64),
+            Any::U16(u) => self.head(MAJOR_UNSIGNED, *u as u64),
+            Any::U32(u) => self.head(MAJOR_UNSIGNED, *u as u64),
+            Any::U64(u) => self.head(MAJOR_UNSIGNED, *u),
+            Any::String(s) => self.text(s),
+            Any::Binary(b) => self.bytes(b),
+            Any::Array(a) => {
+                self.head(MAJOR_ARRAY, a.len() as u64);
+                for any in a {
+                    self.any(any);
+                }
+            }
+            Any::Map(m) => {
+                self.head(MAJOR_MAP, m.len() as u64);
+                for (key, any) in m {
+                    self.text(key);
+                    self.any(any);
+                }
+            }
+            Any::KV(kv) => {
+                self.head(MAJOR_MAP, kv.len() as u64);
+                for (key, value) in kv {
+                    self.text(key);
+                    self.text(value);
+                }
+            }
+            Any::Packed(array) => {
+                self.head(MAJOR_ARRAY, array.len() as u64);
+                match array {
+                    PackedArray::F32(values) => values.iter().for_each(|f| self.float(*f as f64)),
+                    PackedArray::F64(values) => values.iter().for_each(|f| self.float(*f)),
+                    PackedArray::I64(values) => values.iter().for_each(|i| self.int(*i)),
+                }
+            }
+        }
+    }
+}
+
+/// CborDecoder reads definite length CBOR data items
+pub(crate) struct CborDecoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> CborDecoder<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.pos + len > self.buf.len() {
+            return Err("cbor: unexpected end of input".to_string());
+        }
+
+        let slice = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+
+        Ok(slice)
+    }
+
+    fn argument(&mut self, info: u8) -> Result<u64, String> {
+        match info {
+            0..=23 => Ok(info as u64),
+            24 => Ok(self.take(1)?[0] as u64),
+            25 => Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()) as u64),
+            26 => Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()) as u64),
+            27 => Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap())),
+            31 => Err("cbor: indefinite length items are not supported".to_string()),
+            _ => Err(format!("cbor: invalid additional info {}", info)),
+        }
+    }
+
+    pub(crate) fn value(&mut self) -> Result<CborValue, String> {
+        let initial = self.take(1)?[0];
+        let major = initial >> 5;
+        let info = initial & 0x1f;
+
+        match major {
+            MAJOR_UNSIGNED => Ok(CborValue::Unsigned(self.argument(info)?)),
+            MAJOR_NEGATIVE => {
+                let value = self.argument(info)?;
+                if value > i64::MAX as u64 {
+                    return Err("cbor: negative integer out of range".to_string());
+                }
+                Ok(CborValue::Negative(-1 - value as i64))
+            }
+            MAJOR_BYTES => {
+                let len = self.argument(info)? as usize;
+                Ok(CborValue::Bytes(self.take(len)?.to_vec()))
+            }
+            MAJOR_TEXT => {
+                let len = self.argument(info)? as usize;
+                let text = String::from_utf8(self.take(len)?.to_vec())
+                    .map_err(|_| "cbor: invalid utf8 string".to_string())?;
+                Ok(CborValue::Text(text))
+            }
+            MAJOR_ARRAY => {
+                let len = self.argument(info)? as usize;
+                let mut items = Vec::new();
+                for _ in 0..len {
+                    items.push(self.value()?);
+                }
+                Ok(CborValue::Array(items))
+            }
+            MAJOR_MAP => {
+                let len = self.argument(info)? as usize;
+                let mut entries = Vec::new();
+                for _ in 0..len {
+                    let key = match self.value()? {
+                        CborValue::Text(key) => key,
+                        key => return Err(format!("cbor: unsupported map key {:?}", key)),
+                    };
+                    entries.push((key, self.value()?));
+                }
+                Ok(CborValue::Map(entries))
+            }
+            MAJOR_TAG => {
+                // tags carry no meaning for the document, use the tagged item as is
+                self.argument(info)?;
+                self.value()
+            }
+            _ => match info {
+                SIMPLE_FALSE => Ok(CborValue::Bool(false)),
+                SIMPLE_TRUE => Ok(CborValue::Bool(true)),
+                SIMPLE_NULL | SIMPLE_UNDEFINED => Ok(CborValue::Null),
+                SIMPLE_F16 => {
+                    let half = u16::from_be_bytes(self.take(2)?.try_into().unwrap());
+                    Ok(CborValue::Float(half_to_f64(half)))
+                }
+                SIMPLE_F32 => {
+                    let bytes = self.take(4)?.try_into().unwrap();
+                    Ok(CborValue::Float(f32::from_be_bytes(bytes) as f64))
+                }
+                SIMPLE_F64 => {
+                    let bytes = self.take(8)?.try_into().unwrap();
+                    Ok(CborValue::Float(f64::from_be_bytes(bytes)))
+                }
+                _ => Err(format!("cbor: unsupported simple value {}", info)),
+            },
+        }
+    }
+}
+
+// half precision float decoding as described in RFC 8949 appendix D
+fn half_to_f64(half: u16) -> f64 {
+    let exp = (half >> 10) & 0x1f;
+    let mant = (half & 0x3ff) as f64;
+    let value = if exp == 0 {
+        mant * 2f64.powi(-24)
+    } else if exp != 31 {
+        (mant + 1024.0) * 2f64.powi(exp as i32 - 25)
+    } else if mant == 0.0 {
+        f64::INFINITY
+    } else {
+        f64::NAN
+    };
+
+    if half & 0x8000 != 0 {
+        -value
+    } else {
+        value
+    }
+}
+
+/// build the document tree from decoded CBOR value, the top level value must be a map
+pub(crate) fn build_doc(value: CborValue) -> Result<Doc, String> {
+    let entries = match value {
+        CborValue::Map(entries) => entries,
+        _ => return Err("cbor: document root must be a map".to_string()),
+    };
+
+    let doc = Doc::default();
+    for (key, value) in entries {
+        let item = build_type(&doc, value);
+        doc.set(key, item);
+    }
+
+    doc.commit();
+
+    Ok(doc)
+}
+
+fn build_type(doc: &Doc, value: CborValue) -> Type {
+    match value {
+        CborValue::Map(entries) => {
+            let map = doc.map();
+            for (key, value) in entries {
+                map.set(key, build_type(doc, value));
+            }
+            map.into()
+        }
+        CborValue::Array(items) => {
+            let list = doc.list();
+            for value in items {
+                list.append(build_type(doc, value));
+            }
+            list.into()
+        }
+        CborValue::Text(s) => doc.atom(s).into(),
+        CborValue::Bytes(b) => doc.atom(b).into(),
+        CborValue::Bool(true) => doc.atom(Any::True).into(),
+        CborValue::Bool(false) => doc.atom(Any::False).into(),
+        CborValue::Unsigned(u) => doc.atom(Any::U64(u)).into(),
+        CborValue::Negative(i) => doc.atom(Any::I64(i)).into(),
+        CborValue::Float(f) => doc.atom(Any::F64(f)).into(),
+        CborValue::Null => doc.atom(Content::Null).into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cbor_round_trip() {
+        let doc = Doc::default();
+        doc.set("title", doc.atom("hello"));
+        doc.set("blob", doc.atom(vec![0u8, 1, 2, 255]));
+        doc.set("count", doc.atom(Any::U64(300)));
+        doc.set("delta", doc.atom(Any::I64(-42)));
+
+        let list = doc.list();
+        doc.set("list", list.clone());
+        list.append(doc.atom("a"));
+        list.append(doc.atom(Any::True));
+
+        let bytes = doc.to_cbor();
+        let copy = Doc::from_cbor(&bytes).unwrap();
+
+        assert_eq!(
+            copy.get("blob").unwrap().content(),
+            Content::Binary(vec![0, 1, 2, 255])
+        );
+        assert_eq!(copy.get("list").unwrap().size(), 2);
+
+        let mut d1 = CborDecoder::new(&bytes);
+        let copy_bytes = copy.to_cbor();
+        let mut d2 = CborDecoder::new(&copy_bytes);
+
+        let left = d1.value().unwrap();
+        let right = d2.value().unwrap();
+
+        // map entry order is not stable, compare the entries as sets
+        match (left, right) {
+            (CborValue::Map(mut l), CborValue::Map(mut r)) => {
+                l.sort_by(|a, b| a.0.cmp(&b.0));
+                r.sort_by(|a, b| a.0.cmp(&b.0));
+                assert_eq!(l, r);
+            }
+            _ => panic!("root must be a map"),
+        }
+    }
+
+    #[test]
+    fn test_cbor_decode_half_float() {
+        // 1.5 encoded as half precision float
+        let mut d = CborDecoder::new(&[0xf9, 0x3e, 0x00]);
+        assert_eq!(d.value().unwrap(), CborValue::Float(1.5));
+    }
+}
@@ -6,6 +6,7 @@ use crate::encoder::{Encode, EncodeContext, Encoder};
 use crate::hash::calculate_hash;
 use crate::id::{IdComp, IdRange, WithId};
 use crate::item::ItemKind;
+use crate::limits::DiffLimit;
 use crate::persist::DocStoreData;
 use crate::store::{
     ClientStore, DeleteItemStore, ItemDataStore, ItemStore, TypeStore, WeakStoreRef,
@@ -454,6 +455,18 @@ impl ChangeStore {
         self.map.iter()
     }
 
+    // map the changes to the client ids of another client map
+    pub(crate) fn adjust(&self, before: &ClientMap, after: &ClientMap) -> ChangeStore {
+        let mut changes = ChangeStore::default();
+        for (_, store) in self.map.iter() {
+            for change in store.iter() {
+                changes.insert(IdRange::from(*change).adjust(before, after).into());
+            }
+        }
+
+        changes
+    }
+
     pub(crate) fn diff(&self, state: &ClientState) -> ChangeStore {
         let mut diff = ChangeStore::default();
 
@@ -492,9 +505,13 @@ impl Decode for ChangeStore {
     {
         let mut map = HashMap::new();
         let size = d.u32()?;
+        ctx.check_limit(DiffLimit::Clients, size as usize)?;
+        let mut changes = 0;
         for i in 0..size {
             let client = ClientId::decode(d, ctx)?;
             let store = ClientChangeStore::decode(d, ctx)?;
+            changes += store.size();
+            ctx.check_limit(DiffLimit::Changes, changes)?;
             map.insert(client, store);
         }
 
@@ -504,7 +521,7 @@ impl Decode for ChangeStore {
 
 impl Encode for ChangeStore {
     fn encode<T: Encoder>(&self, e: &mut T, ctx: &mut EncodeContext) {
-        e.u32(self.size() as u32);
+        e.u32(self.map.len() as u32);
         for (client, store) in self.map.iter() {
             ClientId::encode(client, e, ctx);
             ClientChangeStore::encode(store, e, ctx);
@@ -562,6 +579,7 @@ impl Decode for ClientChangeStore {
         Self: Sized,
     {
         let size = d.u32()?;
+        ctx.check_limit(DiffLimit::Changes, size as usize)?;
         let mut set = BTreeSet::new();
         for i in 0..size {
             let change_id = ChangeId::decode(d, &ctx)?;
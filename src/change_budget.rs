@@ -0,0 +1,140 @@
+use crate::codec_v1::EncoderV1;
+use crate::doc::Doc;
+use crate::encoder::{Encode, EncodeContext, Encoder};
+use crate::id::{ClockTick, IdRange, WithId};
+use crate::store::DocStore;
+
+/// ChangeBudget limits the size of a single local change.
+///
+/// A commit over the budget is split into a chain of changes, every part depends on the previous one.
+/// Items are never split, an item larger than the byte budget becomes a change of its own.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct ChangeBudget {
+    /// maximum number of items and deletes in a change
+    pub max_items: Option<usize>,
+    /// maximum encoded size of the items and deletes of a change
+    pub max_bytes: Option<usize>,
+}
+
+impl ChangeBudget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_items(mut self, max_items: usize) -> Self {
+        self.max_items = Some(max_items.max(1));
+        self
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes.max(1));
+        self
+    }
+
+    #[inline]
+    pub fn is_unlimited(&self) -> bool {
+        self.max_items.is_none() && self.max_bytes.is_none()
+    }
+}
+
+impl DocStore {
+    // end clocks of the changes the uncommited range is split into, the last one is the range end
+    pub(crate) fn change_splits(&self, range: IdRange) -> Vec<ClockTick> {
+        let budget = self.change_budget;
+        if budget.is_unlimited() {
+            return vec![range.end];
+        }
+
+        // sizes are only needed for a byte budget
+        let sized = budget.max_bytes.is_some();
+
+        // (start clock, encoded size) of every item and delete in the range
+        let mut entries = vec![];
+        for item in self.items.get_by_range(range) {
+            let data = item.data();
+            entries.push((data.id.clock, if sized { encoded_size(&data) } else { 0 }));
+        }
+        for item in self.deletes.get_by_range(range) {
+            entries.push((item.id().clock, if sized { encoded_size(&item) } else { 0 }));
+        }
+        entries.sort();
+
+        let mut ends = vec![];
+        let (mut count, mut bytes) = (0, 0);
+        for (start, size) in entries {
+            let over = budget.max_items.is_some_and(|max| count + 1 > max)
+                || budget.max_bytes.is_some_and(|max| bytes + size > max);
+            if over && count > 0 && start > range.start {
+                ends.push(start - 1);
+                count = 0;
+                bytes = 0;
+            }
+
+            count += 1;
+            bytes += size;
+        }
+        ends.push(range.end);
+
+        ends
+    }
+}
+
+fn encoded_size(value: &impl Encode) -> usize {
+    let mut e = EncoderV1::new();
+    value.encode(&mut e, &mut EncodeContext::default());
+    e.buffer().len()
+}
+
+impl Doc {
+    /// Split the local commits larger than the budget into several changes,
+    /// peers with message size limits can then receive large imports in parts.
+    pub fn set_change_budget(&self, budget: ChangeBudget) {
+        self.store_mut("set_change_budget").change_budget = budget;
+    }
+
+    #[inline]
+    pub fn change_budget(&self) -> ChangeBudget {
+        self.store.borrow().change_budget
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doc::CloneDeep;
+    use crate::state::ClientState;
+    use crate::sync::equal_docs;
+
+    use super::*;
+
+    #[test]
+    fn test_split_oversized_commit() {
+        let d1 = Doc::default();
+        d1.commit();
+        let d2 = d1.clone_deep();
+        d2.update_client();
+
+        let client = d1.store.borrow().client;
+        let changes = || {
+            d1.store
+                .borrow()
+                .changes
+                .id_store(&client)
+                .map_or(0, |s| s.size())
+        };
+        let before = changes();
+
+        d1.set_change_budget(ChangeBudget::new().with_max_items(10));
+        let list = d1.list();
+        d1.set("list", list.clone());
+        for i in 0..45 {
+            list.append(d1.atom(i.to_string()));
+        }
+        d1.commit();
+
+        // the list and its 45 atoms in parts of 10 items
+        assert_eq!(changes() - before, 5);
+
+        d2.apply(&d1.diff(ClientState::default()));
+        assert!(equal_docs(&d1, &d2));
+    }
+}
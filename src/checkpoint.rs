@@ -0,0 +1,361 @@
+use std::collections::BTreeMap;
+
+use hashbrown::HashMap;
+use serde_json::Value;
+
+use crate::bimapid::ClientId;
+use crate::doc::{Doc, DocId};
+use crate::id::{Id, IdRange, WithId, WithTarget};
+use crate::item::{Content, ItemKind, Linked};
+use crate::ncounter::delta_of;
+use crate::snapshot::{content_node, DocSnapshot, SnapshotNode};
+use crate::state::ClientState;
+use crate::store::DocStore;
+use crate::types::Type;
+
+/// Checkpoint is a version of a document to read or restore the document at.
+///
+/// It holds the version only and is cheap to keep around and clone. Items are never
+/// removed from the store, so the document at the version is read from the live items
+/// when it is needed. Texts collected by `Doc::gc` after the checkpoint can not be read.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Checkpoint {
+    doc_id: DocId,
+    version: ClientState,
+}
+
+impl Checkpoint {
+    pub(crate) fn new(doc_id: DocId, version: ClientState) -> Self {
+        Self { doc_id, version }
+    }
+
+    #[inline]
+    pub fn id(&self) -> &DocId {
+        &self.doc_id
+    }
+
+    #[inline]
+    pub fn version(&self) -> &ClientState {
+        &self.version
+    }
+}
+
+// visibility of the items at a version
+pub(crate) struct VersionView {
+    version: ClientState,
+    // targets of the deletes seen at the version
+    deleted: HashMap<ClientId, Vec<IdRange>>,
+}
+
+impl VersionView {
+    pub(crate) fn new(store: &DocStore, version: &ClientState) -> Self {
+        let version = version.as_per(&store.state);
+        let mut view = Self {
+            version,
+            deleted: HashMap::new(),
+        };
+
+        for (_, deletes) in store.deletes.iter() {
+            for (id, delete) in deletes.iter() {
+                if view.is_seen(id) {
+                    let range = *delete.range();
+                    view.deleted.entry(range.client).or_default().push(range);
+                }
+            }
+        }
+
+        view
+    }
+
+    #[inline]
+    fn is_seen(&self, id: &Id) -> bool {
+        self.version
+            .get(&id.client)
+            .is_some_and(|clock| id.clock <= *clock)
+    }
+
+    // inserted and not deleted at the version
+    pub(crate) fn is_visible(&self, item: &Type) -> bool {
+        let id = item.id();
+        if !self.is_seen(&id) {
+            return false;
+        }
+
+        !self
+            .deleted
+            .get(&id.client)
+            .is_some_and(|ranges| ranges.iter().any(|range| range.contains(&id)))
+    }
+
+    // children of the container visible at the version in list order
+    pub(crate) fn children(&self, container: &Type) -> Vec<Type> {
+        let children = container.item_ref().borrow().all_items();
+        children
+            .into_iter()
+            .filter(|child| self.is_visible(child))
+            .collect()
+    }
+
+    // entries of the map at the version, the last visible child of a key wins
+    pub(crate) fn entries(&self, map: &Type) -> BTreeMap<String, Type> {
+        let mut entries = BTreeMap::new();
+        for child in self.children(map) {
+            let has_field = child.item_ref().borrow().data.field.is_some();
+            if let Some(field) = has_field.then(|| child.field()).flatten() {
+                entries.insert(field, child);
+            }
+        }
+
+        entries
+    }
+}
+
+impl Doc {
+    /// Checkpoint at the current version, pending edits are part of it
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            doc_id: self.id(),
+            version: self.version(),
+        }
+    }
+
+    /// Snapshot of the visible document tree at the checkpoint
+    pub fn snapshot_at(&self, checkpoint: &Checkpoint) -> Result<DocSnapshot, String> {
+        let view = self.version_view(checkpoint)?;
+        let mut nodes = vec![];
+        push_at(&view, &mut nodes, &Type::from(self.root.clone()))?;
+
+        Ok(DocSnapshot::from_nodes(
+            self.id(),
+            checkpoint.version.clone().into(),
+            nodes,
+        ))
+    }
+
+    /// Roll the document back to the checkpoint. Items inserted since are deleted and
+    /// items deleted since are inserted again as copies, the edits go into the pending
+    /// change so the rollback syncs like any other edit.
+    pub fn restore_checkpoint(&self, checkpoint: &Checkpoint) -> Result<(), String> {
+        let view = self.version_view(checkpoint)?;
+        self.reconcile(&view, &Type::from(self.root.clone()));
+
+        Ok(())
+    }
+
+    fn version_view(&self, checkpoint: &Checkpoint) -> Result<VersionView, String> {
+        if checkpoint.doc_id != self.id() {
+            return Err(format!(
+                "checkpoint of document {} can not be used with {}",
+                checkpoint.doc_id.to_string(),
+                self.id().to_string()
+            ));
+        }
+
+        Ok(VersionView::new(&self.store.borrow(), &checkpoint.version))
+    }
+
+    // bring the children of the container back to the version
+    fn reconcile(&self, view: &VersionView, container: &Type) {
+        match container {
+            Type::Map(map) => {
+                let before = view.entries(container);
+                for key in map.keys() {
+                    let Some(current) = map.get(key.clone()) else {
+                        continue;
+                    };
+                    if before.get(&key).map(|item| item.id()) != Some(current.id()) {
+                        delete_item(&current);
+                    }
+                }
+
+                for (key, item) in before {
+                    match map.get(key.clone()) {
+                        Some(current) if current.id() == item.id() => {
+                            self.reconcile(view, &current)
+                        }
+                        _ => {
+                            if let Some(copy) = self.new_like(&item) {
+                                container.set(key, copy.clone());
+                                self.fill_at(view, &item, &copy);
+                            }
+                        }
+                    }
+                }
+            }
+            Type::List(_) | Type::Text(_) => {
+                if let Type::Text(text) = container {
+                    text.thaw();
+                }
+
+                // the last child visible after the rollback
+                let mut prev: Option<Type> = None;
+                let children = container.item_ref().borrow().all_items();
+                for child in children {
+                    match (view.is_visible(&child), child.is_visible()) {
+                        (true, true) => {
+                            self.reconcile(view, &child);
+                            prev = Some(child);
+                        }
+                        (false, true) => delete_item(&child),
+                        (true, false) => {
+                            let Some(copy) = self.new_like(&child) else {
+                                continue;
+                            };
+                            match &prev {
+                                Some(prev) => prev.insert_after(copy.clone()),
+                                None => container.prepend(copy.clone()),
+                            }
+                            self.fill_at(view, &child, &copy);
+                            prev = Some(copy);
+                        }
+                        (false, false) => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // copy the children of the source at the version into the attached copy
+    fn fill_at(&self, view: &VersionView, source: &Type, copy: &Type) {
+        match source.kind() {
+            ItemKind::Map => {
+                for (key, child) in view.entries(source) {
+                    if let Some(child_copy) = self.new_like(&child) {
+                        copy.set(key, child_copy.clone());
+                        self.fill_at(view, &child, &child_copy);
+                    }
+                }
+            }
+            ItemKind::Counter => {
+                for delta in view.children(source) {
+                    if let Some(copy) = copy.as_counter() {
+                        copy.increment(delta_of(&delta.content()));
+                    }
+                }
+            }
+            ItemKind::List | ItemKind::Text | ItemKind::PlaintText => {
+                if let Type::Text(text) = source {
+                    text.thaw();
+                }
+                for child in view.children(source) {
+                    if let Some(child_copy) = self.new_like(&child) {
+                        copy.append(child_copy.clone());
+                        self.fill_at(view, &child, &child_copy);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// strings delete all their ticks
+pub(crate) fn delete_item(item: &Type) {
+    match item {
+        Type::String(string) => string.delete(),
+        _ => item.delete(),
+    }
+}
+
+// push the type and its children visible at the version into the arena
+fn push_at(
+    view: &VersionView,
+    nodes: &mut Vec<SnapshotNode>,
+    item: &Type,
+) -> Result<usize, String> {
+    let index = nodes.len();
+    nodes.push(SnapshotNode::Value(Value::Null));
+
+    let node = match item {
+        Type::Map(_) => {
+            let mut entries = vec![];
+            for (key, child) in view.entries(item) {
+                entries.push((key, push_at(view, nodes, &child)?));
+            }
+            SnapshotNode::Map(entries)
+        }
+        Type::List(_) => {
+            let mut children = vec![];
+            for child in view.children(item) {
+                children.push(push_at(view, nodes, &child)?);
+            }
+            SnapshotNode::List(children)
+        }
+        Type::Text(text) => {
+            text.thaw();
+            let mut content = String::new();
+            for child in view.children(item) {
+                match child.content() {
+                    Content::String(s) => content.push_str(&s),
+                    Content::Tombstone(_) => {
+                        return Err(format!(
+                            "text {} was collected after the version",
+                            item.id()
+                        ))
+                    }
+                    _ => {}
+                }
+            }
+            SnapshotNode::Text(content)
+        }
+        Type::String(string) => content_node(string.content()),
+        Type::Atom(atom) => content_node(atom.content()),
+        Type::Move(mover) => match mover.get_target() {
+            Some(target) => {
+                nodes.pop();
+                return push_at(view, nodes, &target);
+            }
+            None => SnapshotNode::Value(Value::Null),
+        },
+        Type::Counter(_) => {
+            let children = view.children(item);
+            let deltas = children.iter().map(|delta| delta_of(&delta.content()));
+            SnapshotNode::Value(deltas.fold(0, i64::wrapping_add).into())
+        }
+        Type::Proxy(proxy) => content_node(proxy.content()),
+        Type::Mark(_) | Type::Identity => SnapshotNode::Value(Value::Null),
+    };
+
+    nodes[index] = node;
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_and_restore_checkpoint() {
+        let doc = Doc::default();
+        doc.set("title", doc.atom("draft"));
+        let list = doc.list();
+        doc.set("items", list.clone());
+        list.append(doc.atom("a"));
+        let text = doc.text();
+        doc.set("text", text.clone());
+        let hello = doc.string("hello");
+        text.append(hello.clone());
+        doc.commit();
+
+        let checkpoint = doc.checkpoint();
+        let before = doc.snapshot().to_json();
+
+        doc.set("title", doc.atom("final"));
+        list.append(doc.atom("b"));
+        doc.set("extra", doc.map());
+        text.append(doc.string(" world"));
+        hello.delete();
+        doc.commit();
+        assert_ne!(doc.snapshot().to_json(), before);
+
+        let snapshot = doc.snapshot_at(&checkpoint).unwrap();
+        assert_eq!(snapshot.to_json(), before);
+
+        doc.restore_checkpoint(&checkpoint).unwrap();
+        doc.commit();
+        assert_eq!(doc.snapshot().to_json(), before);
+
+        assert!(Doc::default().snapshot_at(&checkpoint).is_err());
+    }
+}
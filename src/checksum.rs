@@ -0,0 +1,262 @@
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::doc::Doc;
+use crate::id::{Id, WithId};
+use crate::item::ItemKind;
+use crate::store::DocStore;
+use crate::types::Type;
+
+/// ChecksumTree mirrors the containers of a document with a checksum for every container.
+///
+/// Replicas that suspect divergence exchange the trees and compare them with `diverging`
+/// to find the subtrees that differ. The checksums do not depend on the client ids
+/// so that trees of different replicas are comparable.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ChecksumTree {
+    /// checksum of the container and everything below it
+    pub checksum: u64,
+    /// checksum of the non container children, e.g. atoms of a list or the text content
+    pub leaves: u64,
+    /// child containers by map key or list index
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<(String, ChecksumTree)>,
+}
+
+impl ChecksumTree {
+    pub fn child(&self, key: &str) -> Option<&ChecksumTree> {
+        self.children
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, child)| child)
+    }
+
+    /// Slash separated paths of the deepest containers that differ between the trees,
+    /// the root container is the empty path
+    pub fn diverging(&self, other: &ChecksumTree) -> Vec<String> {
+        let mut paths = vec![];
+        self.diverging_at("", other, &mut paths);
+        paths
+    }
+
+    fn diverging_at(&self, path: &str, other: &ChecksumTree, paths: &mut Vec<String>) {
+        if self.checksum == other.checksum {
+            return;
+        }
+
+        let same_keys = self.children.len() == other.children.len()
+            && self
+                .children
+                .iter()
+                .all(|(key, _)| other.child(key).is_some());
+
+        // the container itself differs, no need to look further down
+        if self.leaves != other.leaves || !same_keys {
+            paths.push(path.to_string());
+            return;
+        }
+
+        for (key, child) in &self.children {
+            let child_path = match path {
+                "" => key.clone(),
+                _ => format!("{}/{}", path, key),
+            };
+            child.diverging_at(&child_path, other.child(key).unwrap(), paths);
+        }
+    }
+}
+
+impl DocStore {
+    // drop the cached checksums of the containers holding the changed items
+    pub(crate) fn invalidate_checksums(&mut self, changed: &[Id]) {
+        if self.checksums.is_empty() {
+            return;
+        }
+
+        for id in changed {
+            let mut current = self.find(id);
+            while let Some(item) = current {
+                self.checksums.remove(&item.id());
+                current = item.parent();
+            }
+        }
+    }
+}
+
+impl Doc {
+    /// Checksum tree of the document containers. The checksums are cached per container
+    /// and only the containers changed since the last call are hashed again.
+    pub fn checksum_tree(&self) -> ChecksumTree {
+        let mut cache = std::mem::take(&mut self.store_mut("checksum_tree").checksums);
+        let tree = checksum_of(&Type::Map(self.root.clone()), &mut cache);
+        self.store_mut("checksum_tree").checksums = cache;
+
+        tree
+    }
+
+    /// Checksum of the whole document
+    #[inline]
+    pub fn checksum(&self) -> u64 {
+        self.checksum_tree().checksum
+    }
+
+    /// Stable hex fingerprint of the committed content, usable as a cache key for rendered
+    /// outputs. Documents with the same content have the same fingerprint whatever their
+    /// history, ids or clients, and only the containers changed since the last call are
+    /// hashed again.
+    pub fn fingerprint(&self) -> String {
+        format!("{:016x}", self.checksum())
+    }
+}
+
+fn checksum_of(container: &Type, cache: &mut HashMap<Id, ChecksumTree>) -> ChecksumTree {
+    if let Some(tree) = cache.get(&container.id()) {
+        return tree.clone();
+    }
+
+    let mut leaves = Fnv::new();
+    let mut children = vec![];
+
+    let entries: Vec<(String, Type)> = match container {
+        Type::Map(map) => {
+            let mut keys = map.keys();
+            keys.sort();
+            keys.into_iter()
+                .filter_map(|key| map.get(key.clone()).map(|child| (key, child)))
+                .collect()
+        }
+        Type::List(list) => list
+            .item_ref()
+            .borrow()
+            .as_list()
+            .into_iter()
+            .enumerate()
+            .map(|(index, child)| (index.to_string(), child))
+            .collect(),
+        _ => {
+            leaves.write_json(&container.to_json());
+            vec![]
+        }
+    };
+
+    for (key, child) in entries {
+        match child.kind() {
+            ItemKind::Map | ItemKind::List | ItemKind::Text | ItemKind::PlaintText => {
+                children.push((key, checksum_of(&child, cache)));
+            }
+            kind => {
+                leaves.write(key.as_bytes());
+                leaves.write(&[kind as u8]);
+                leaves.write_json(&child.to_json());
+            }
+        }
+    }
+
+    let mut checksum = Fnv::new();
+    checksum.write(&[container.kind() as u8]);
+    checksum.write(&leaves.finish().to_le_bytes());
+    for (key, child) in &children {
+        checksum.write(key.as_bytes());
+        checksum.write(&child.checksum.to_le_bytes());
+    }
+
+    let tree = ChecksumTree {
+        checksum: checksum.finish(),
+        leaves: leaves.finish(),
+        children,
+    };
+    cache.insert(container.id(), tree.clone());
+
+    tree
+}
+
+// fnv-1a, stable across platforms and builds unlike the std hasher
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Fnv(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+        // separate the fields so that "ab", "c" and "a", "bc" differ
+        self.0 = self.0.wrapping_mul(0x100000001b3);
+    }
+
+    fn write_json(&mut self, value: &serde_json::Value) {
+        self.write(value.to_string().as_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doc::CloneDeep;
+    use crate::state::ClientState;
+
+    use super::*;
+
+    #[test]
+    fn test_checksum_tree_divergence() {
+        let d1 = Doc::default();
+        let todos = d1.list();
+        d1.set("todos", todos.clone());
+        let first = d1.map();
+        todos.append(first.clone());
+        first.set("title", d1.atom("first"));
+        let notes = d1.text();
+        d1.set("notes", notes.clone());
+        notes.append(d1.string("hello"));
+        d1.commit();
+
+        let d2 = d1.clone_deep();
+        d2.update_client();
+        assert_eq!(d1.checksum_tree(), d2.checksum_tree());
+
+        // a local change is picked up by the cached tree
+        first.set("done", d1.atom("yes"));
+        d1.commit();
+
+        let (t1, t2) = (d1.checksum_tree(), d2.checksum_tree());
+        assert_ne!(t1.checksum, t2.checksum);
+        assert_eq!(t1.child("notes"), t2.child("notes"));
+        assert_eq!(t1.diverging(&t2), vec!["todos/0".to_string()]);
+
+        d2.apply(&d1.diff(ClientState::default()));
+        assert_eq!(d1.checksum_tree(), d2.checksum_tree());
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_history() {
+        let d1 = Doc::default();
+        d1.set("title", d1.atom("draft"));
+        d1.set("tags", d1.list());
+        d1.commit();
+        d1.set("title", d1.atom("final"));
+        d1.commit();
+
+        let d2 = Doc::default();
+        d2.set("title", d2.atom("final"));
+        d2.commit();
+        let tags = d2.list();
+        d2.set("tags", tags.clone());
+        tags.append(d2.atom("removed"));
+        d2.commit();
+        tags.get(0usize).unwrap().delete();
+        d2.commit();
+
+        assert_eq!(d1.fingerprint(), d2.fingerprint());
+        assert_eq!(d1.fingerprint().len(), 16);
+
+        d2.set("title", d2.atom("changed"));
+        d2.commit();
+        assert_ne!(d1.fingerprint(), d2.fingerprint());
+    }
+}
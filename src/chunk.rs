@@ -0,0 +1,266 @@
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use crate::codec_v1::{DecoderV1, EncoderV1};
+use crate::decoder::{Decode, DecodeContext};
+use crate::diff::Diff;
+use crate::doc::{Doc, DocId};
+use crate::encoder::{Encode, EncodeContext, Encoder};
+use crate::state::ClientState;
+
+/// ChunkStore keeps the chunks of chunked snapshots by the hex sha1 of their content.
+///
+/// Chunks are immutable, so `put` of a hash that is already stored can be skipped and
+/// snapshots of the same document share the chunks of their unchanged content.
+pub trait ChunkStore {
+    fn get(&self, hash: &str) -> Result<Option<Vec<u8>>, String>;
+
+    fn put(&mut self, hash: &str, chunk: &[u8]) -> Result<(), String>;
+
+    fn contains(&self, hash: &str) -> Result<bool, String> {
+        Ok(self.get(hash)?.is_some())
+    }
+}
+
+/// MemoryChunkStore keeps the chunks in memory, for tests and as a reference implementation
+#[derive(Debug, Clone, Default)]
+pub struct MemoryChunkStore {
+    chunks: HashMap<String, Vec<u8>>,
+}
+
+impl MemoryChunkStore {
+    /// Number of stored chunks
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Total size of the stored chunks in bytes
+    pub fn size(&self) -> usize {
+        self.chunks.values().map(|chunk| chunk.len()).sum()
+    }
+}
+
+impl ChunkStore for MemoryChunkStore {
+    fn get(&self, hash: &str) -> Result<Option<Vec<u8>>, String> {
+        Ok(self.chunks.get(hash).cloned())
+    }
+
+    fn put(&mut self, hash: &str, chunk: &[u8]) -> Result<(), String> {
+        self.chunks.insert(hash.to_string(), chunk.to_vec());
+        Ok(())
+    }
+
+    fn contains(&self, hash: &str) -> Result<bool, String> {
+        Ok(self.chunks.contains_key(hash))
+    }
+}
+
+/// Chunker splits bytes at content defined boundaries with a gear rolling hash.
+///
+/// A boundary depends only on the bytes just before it, so an edit changes the chunks
+/// around it and the chunks before and after the edit stay the same.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Chunker {
+    min_size: usize,
+    max_size: usize,
+    mask: u64,
+}
+
+impl Default for Chunker {
+    fn default() -> Self {
+        Chunker::new(4096)
+    }
+}
+
+impl Chunker {
+    /// Chunker with the average chunk size rounded to a power of two,
+    /// chunks are between a quarter and four times the average
+    pub fn new(avg_size: usize) -> Self {
+        let avg_size = avg_size.max(64).next_power_of_two();
+        Chunker {
+            min_size: avg_size / 4,
+            max_size: avg_size * 4,
+            mask: avg_size as u64 - 1,
+        }
+    }
+
+    /// Chunks of the bytes, concatenated they are the bytes
+    pub fn split<'a>(&self, bytes: &'a [u8]) -> Vec<&'a [u8]> {
+        let mut chunks = vec![];
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            let (chunk, tail) = rest.split_at(self.boundary(rest));
+            chunks.push(chunk);
+            rest = tail;
+        }
+
+        chunks
+    }
+
+    // end of the first chunk of the bytes
+    fn boundary(&self, bytes: &[u8]) -> usize {
+        if bytes.len() <= self.min_size {
+            return bytes.len();
+        }
+
+        let end = bytes.len().min(self.max_size);
+        let mut hash = 0u64;
+        for (i, byte) in bytes.iter().enumerate().take(end).skip(self.min_size) {
+            hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+            if hash & self.mask == 0 {
+                return i + 1;
+            }
+        }
+
+        end
+    }
+}
+
+// random values for the gear hash, fixed so that boundaries are stable across builds
+const GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state = 0x9e3779b97f4a7c15u64;
+    let mut i = 0;
+    while i < 256 {
+        // splitmix64
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+
+    table
+}
+
+/// ChunkedSnapshot is the manifest of a snapshot kept in a chunk store
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChunkedSnapshot {
+    pub doc_id: String,
+    /// hashes of the chunks in order
+    pub chunks: Vec<String>,
+    /// size of the encoded snapshot in bytes
+    pub size: usize,
+    /// chunks that were not in the store when the snapshot was saved
+    pub written: usize,
+}
+
+impl Doc {
+    /// Save the encoded document into the chunk store, only the chunks missing
+    /// in the store are written
+    pub fn save_chunked(
+        &self,
+        store: &mut impl ChunkStore,
+        chunker: &Chunker,
+    ) -> Result<ChunkedSnapshot, String> {
+        let mut e = EncoderV1::new();
+        self.diff(ClientState::default())
+            .encode(&mut e, &mut EncodeContext::default());
+        let bytes = e.buffer();
+
+        let mut chunks = vec![];
+        let mut written = 0;
+        for chunk in chunker.split(&bytes) {
+            let hash = chunk_hash(chunk);
+            if !store.contains(&hash)? {
+                store.put(&hash, chunk)?;
+                written += 1;
+            }
+            chunks.push(hash);
+        }
+
+        Ok(ChunkedSnapshot {
+            doc_id: self.id().to_string(),
+            chunks,
+            size: bytes.len(),
+            written,
+        })
+    }
+
+    /// Load the document of a chunked snapshot, every chunk is checked against its hash
+    pub fn load_chunked(
+        snapshot: &ChunkedSnapshot,
+        store: &impl ChunkStore,
+    ) -> Result<Doc, String> {
+        let mut bytes = Vec::with_capacity(snapshot.size);
+        for hash in &snapshot.chunks {
+            let chunk = store
+                .get(hash)?
+                .ok_or_else(|| format!("missing snapshot chunk {}", hash))?;
+            if chunk_hash(&chunk) != *hash {
+                return Err(format!("corrupted snapshot chunk {}", hash));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        let mut d = DecoderV1::try_new(bytes)?;
+        let diff = Diff::decode(&mut d, &DecodeContext::default())?;
+        let doc = Doc::from(&diff).ok_or("snapshot has no document root")?;
+        if DocId::from_str(&snapshot.doc_id)? != doc.id() {
+            return Err(format!(
+                "snapshot of {} has a different doc id",
+                snapshot.doc_id
+            ));
+        }
+
+        Ok(doc)
+    }
+}
+
+pub(crate) fn chunk_hash(chunk: &[u8]) -> String {
+    Sha1::digest(chunk)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sync::equal_docs;
+
+    use super::*;
+
+    #[test]
+    fn test_snapshots_share_chunks() {
+        let doc = Doc::default();
+        let list = doc.list();
+        doc.set("list", list.clone());
+        for i in 0..400 {
+            list.append(doc.atom(format!("item number {}", i)));
+        }
+        doc.commit();
+
+        let chunker = Chunker::new(256);
+        let mut store = MemoryChunkStore::default();
+        let first = doc.save_chunked(&mut store, &chunker).unwrap();
+        assert_eq!(first.written, store.len());
+        assert!(first.chunks.len() > 4);
+
+        list.append(doc.atom("one more"));
+        doc.commit();
+
+        let second = doc.save_chunked(&mut store, &chunker).unwrap();
+        assert!(second.written < second.chunks.len() / 2);
+
+        let loaded = Doc::load_chunked(&second, &store).unwrap();
+        assert!(equal_docs(&doc, &loaded));
+        assert_eq!(
+            Doc::load_chunked(&first, &store)
+                .unwrap()
+                .get("list")
+                .unwrap()
+                .size(),
+            400
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use std::time::{Duration, Instant};
+
+use crate::apply_stats::ApplyStats;
+use crate::diff::Diff;
+use crate::doc::Doc;
+use crate::state::ClientState;
+
+/// ChangeCoalescer buffers locally committed changes and broadcasts them as a single diff.
+///
+/// A batch is sent when it is older than the window, and always before a remote diff is applied
+/// so that a batch never spans a remote change the local changes may depend on.
+/// Remote diffs must be applied with `apply_remote` for the check to work,
+/// a batch that notices a remote change applied elsewhere is sent right away.
+#[derive(Debug, Clone)]
+pub struct ChangeCoalescer {
+    window: Duration,
+    // document version at the last broadcast
+    base: ClientState,
+    // sum of the remote clocks at the last broadcast
+    remote_ticks: u64,
+    // commit time of the first buffered change
+    since: Option<Instant>,
+}
+
+impl ChangeCoalescer {
+    /// Start coalescing the changes committed after the current document version
+    pub fn new(doc: &Doc, window: Duration) -> Self {
+        Self {
+            window,
+            base: doc.version(),
+            remote_ticks: remote_ticks(doc),
+            since: None,
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.since.is_none()
+    }
+
+    /// Call after a local commit, returns a diff when the batch is due
+    pub fn on_commit(&mut self, doc: &Doc, now: Instant) -> Option<Diff> {
+        self.since.get_or_insert(now);
+
+        // a remote change was applied behind our back, the batch is not causally safe
+        if remote_ticks(doc) != self.remote_ticks {
+            return self.flush(doc);
+        }
+
+        self.poll(doc, now)
+    }
+
+    /// Send the batch when the window has passed
+    pub fn poll(&mut self, doc: &Doc, now: Instant) -> Option<Diff> {
+        match self.since {
+            Some(since) if now.duration_since(since) >= self.window => self.flush(doc),
+            _ => None,
+        }
+    }
+
+    /// Send the buffered changes now
+    pub fn flush(&mut self, doc: &Doc) -> Option<Diff> {
+        self.since.take()?;
+
+        let diff = doc.diff(self.base.clone());
+        self.base = doc.version();
+        self.remote_ticks = remote_ticks(doc);
+
+        Some(diff)
+    }
+
+    /// Flush the batch and apply the remote diff, the flushed batch must be sent before
+    /// any change committed after this call
+    pub fn apply_remote(&mut self, doc: &Doc, diff: &Diff) -> (Option<Diff>, ApplyStats) {
+        let batch = self.flush(doc);
+        let stats = doc.apply(diff);
+
+        // remote changes are not ours to broadcast
+        self.base = doc.version();
+        self.remote_ticks = remote_ticks(doc);
+
+        (batch, stats)
+    }
+}
+
+impl Doc {
+    /// Keep a single value per change for the map key, for values updated many times per
+    /// second like sliders or cursors. Setting a fresh atom on the key replaces the atom set
+    /// earlier in the pending change, the commit carries only the latest value.
+    /// The policy is local and applies to the key in every map.
+    pub fn coalesce_values(&self, key: impl Into<String>, enabled: bool) {
+        let mut store = self.store_mut("coalesce_values");
+        let key = key.into();
+        if enabled {
+            store.coalesced_keys.insert(key);
+        } else {
+            store.coalesced_keys.remove(&key);
+        }
+    }
+}
+
+// sum of the clocks of all clients except the local one, grows with every remote change
+fn remote_ticks(doc: &Doc) -> u64 {
+    let local = doc.store.borrow().client;
+    doc.version()
+        .state()
+        .into_iter()
+        .filter(|(client, _)| *client != local)
+        .map(|(_, clock)| clock as u64)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doc::CloneDeep;
+
+    use super::*;
+
+    #[test]
+    fn test_coalesce_local_changes() {
+        let d1 = Doc::default();
+        d1.commit();
+        let d2 = d1.clone_deep();
+        d2.update_client();
+
+        let now = Instant::now();
+        let window = Duration::from_millis(50);
+        let mut coalescer = ChangeCoalescer::new(&d1, window);
+
+        d1.set("a", d1.atom("a"));
+        d1.commit();
+        assert!(coalescer.on_commit(&d1, now).is_none());
+
+        d1.set("b", d1.atom("b"));
+        d1.commit();
+        assert!(coalescer.on_commit(&d1, now + window / 2).is_none());
+
+        let batch = coalescer.poll(&d1, now + window).unwrap();
+        assert!(coalescer.is_empty());
+
+        d2.apply(&batch);
+        assert_eq!(
+            d2.get("a").unwrap().to_json(),
+            d1.get("a").unwrap().to_json()
+        );
+        assert!(d2.get("b").is_some());
+
+        // a remote diff flushes the pending batch first
+        d1.set("c", d1.atom("c"));
+        d1.commit();
+        assert!(coalescer.on_commit(&d1, now).is_none());
+
+        d2.set("d", d2.atom("d"));
+        d2.commit();
+        let (batch, _) = coalescer.apply_remote(&d1, &d2.diff(ClientState::default()));
+        assert!(batch.is_some());
+        assert!(d1.get("d").is_some());
+        assert!(coalescer.is_empty());
+    }
+
+    #[test]
+    fn test_coalesce_values() {
+        let d1 = Doc::default();
+        d1.coalesce_values("volume", true);
+        let settings = d1.map();
+        d1.set("settings", settings.clone());
+        d1.commit();
+        let base = d1.version();
+
+        for i in 0..50 {
+            settings.set("volume", d1.atom(i.to_string()));
+            settings.set("balance", d1.atom(i.to_string()));
+        }
+        assert_eq!(settings.get("volume").unwrap().to_json(), "49");
+        d1.commit();
+
+        // one item for the coalesced key, every value for the other one
+        let size = d1.diff(base).items.size();
+        assert_eq!(size, 51);
+
+        let d2 = Doc::from(&d1.diff(ClientState::default())).unwrap();
+        let settings = d2.get("settings").unwrap();
+        assert_eq!(settings.get("volume").unwrap().to_json(), "49");
+
+        // the next change starts a new value
+        d1.get("settings").unwrap().set("volume", d1.atom("50"));
+        d1.commit();
+        d2.apply(&d1.diff(d2.version()));
+        assert_eq!(settings.get("volume").unwrap().to_json(), "50");
+    }
+}
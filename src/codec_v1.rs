@@ -5,7 +5,7 @@ use crate::encoder::{Encode, EncodeContext, Encoder};
 use crate::id::Id;
 use crate::item::{Content, ItemData, ItemKind, ItemKindFlags, ItemSide, ItemSideFlags};
 
-const VERSION: u8 = 1;
+pub(crate) const VERSION: u8 = 1;
 const BUF_STEP: usize = 1024;
 const INIT_SIZE: usize = 1024;
 
@@ -34,6 +34,14 @@ impl EncoderV1 {
         .write_header()
     }
 
+    // an encoder without the version header, for the sections of other codecs
+    pub(crate) fn headless() -> Self {
+        Self {
+            buf: Vec::with_capacity(INIT_SIZE),
+            pos: 0,
+        }
+    }
+
     fn ensure_capacity(&mut self, size: usize) {
         // println!("size: {}, pos: {}, len: {}", size, self.pos, self.buf.len());
         if self.buf.len() + size > self.buf.capacity() {
@@ -138,42 +146,51 @@ pub struct DecoderV1 {
 
 impl DecoderV1 {
     pub fn new(buf: Vec<u8>) -> Self {
+        Self::try_new(buf).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// a decoder of received or stored bytes, fails on missing or unknown version
+    pub fn try_new(buf: Vec<u8>) -> Result<Self, String> {
         let mut d = Self { buf, pos: 0 };
 
-        if d.u8().unwrap() != VERSION {
-            panic!("decoder: invalid version");
+        match d.u8()? {
+            VERSION => Ok(d),
+            _ => Err("decoder: invalid version".to_string()),
         }
+    }
 
-        // println!("buffer: {:?}", d.buf);
-
-        d
+    // a decoder of bytes without the version header
+    pub(crate) fn headless(buf: Vec<u8>) -> Self {
+        Self { buf, pos: 0 }
     }
 
-    fn ensure_capacity(&mut self, size: usize) {
+    fn ensure_capacity(&mut self, size: usize) -> Result<(), String> {
         // println!("size: {}, pos: {}, len: {}", size, self.pos, self.buf.len());
         if self.pos + size > self.buf.len() {
-            panic!("decoder: out of bounds");
+            return Err("decoder: out of bounds".to_string());
         }
+
+        Ok(())
     }
 }
 
 impl Decoder for DecoderV1 {
     fn u8(&mut self) -> Result<u8, String> {
-        self.ensure_capacity(1);
+        self.ensure_capacity(1)?;
         let value = self.buf[self.pos];
         self.pos += 1;
         Ok(value)
     }
 
     fn u16(&mut self) -> Result<u16, String> {
-        self.ensure_capacity(2);
+        self.ensure_capacity(2)?;
         let value = u16::from_be_bytes([self.buf[self.pos], self.buf[self.pos + 1]]);
         self.pos += 2;
         Ok(value)
     }
 
     fn u32(&mut self) -> Result<u32, String> {
-        self.ensure_capacity(4);
+        self.ensure_capacity(4)?;
         let value = u32::from_be_bytes([
             self.buf[self.pos],
             self.buf[self.pos + 1],
@@ -185,7 +202,7 @@ impl Decoder for DecoderV1 {
     }
 
     fn u64(&mut self) -> Result<u64, String> {
-        self.ensure_capacity(8);
+        self.ensure_capacity(8)?;
         let value = u64::from_be_bytes([
             self.buf[self.pos],
             self.buf[self.pos + 1],
@@ -201,7 +218,7 @@ impl Decoder for DecoderV1 {
     }
 
     fn uuid(&mut self) -> Result<[u8; 16], String> {
-        self.ensure_capacity(16);
+        self.ensure_capacity(16)?;
         let mut value = [0; 16];
         value.copy_from_slice(&self.buf[self.pos..self.pos + 16]);
         self.pos += 16;
@@ -210,7 +227,7 @@ impl Decoder for DecoderV1 {
 
     fn string(&mut self) -> Result<String, String> {
         let len = self.u32()? as usize;
-        self.ensure_capacity(len);
+        self.ensure_capacity(len)?;
         let value = String::from_utf8(self.buf[self.pos..self.pos + len].to_vec())
             .map_err(|_| "decoder: invalid utf8 string".to_string())?;
         self.pos += len;
@@ -219,14 +236,14 @@ impl Decoder for DecoderV1 {
 
     fn bytes(&mut self) -> Result<Vec<u8>, String> {
         let len = self.u32()? as usize;
-        self.ensure_capacity(len);
+        self.ensure_capacity(len)?;
         let value = self.buf[self.pos..self.pos + len].to_vec();
         self.pos += len;
         Ok(value)
     }
 
     fn slice(&mut self, len: usize) -> Result<&[u8], String> {
-        self.ensure_capacity(len);
+        self.ensure_capacity(len)?;
         let value = &self.buf[self.pos..self.pos + len];
         self.pos += len;
         Ok(value)
@@ -235,35 +252,17 @@ impl Decoder for DecoderV1 {
     fn item(&mut self, ctx: &DecodeContext) -> Result<ItemData, String> {
         decode_item(self, ctx)
     }
+
+    fn remaining(&self) -> usize {
+        self.buf.len().saturating_sub(self.pos)
+    }
 }
 
 fn encode_item(e: &mut EncoderV1, cx: &mut EncodeContext, value: &ItemData) {
     // | kind, content, field, parent | left, right | ...
     // println!("encode_item: {}, {:?}", value.kind, value.id);
-    let kind_flags = ItemKindFlags::from(&value.kind).bits();
+    let (kind_flags, flags) = item_flags(value);
     e.u8(kind_flags);
-
-    let mut flags = ItemSideFlags::from(&value.side).bits() << 4;
-
-    // let is_root = matches!(value.content, Content::Doc(_));
-    if !matches!(value.content, Content::Null) {
-        flags |= 1 << 3; // has content
-    }
-
-    if value.field.is_some() {
-        flags |= 1 << 2; // has field
-    }
-
-    // if left_id is not None then we can get the parent_id from left item during integration,
-    // so we don't need to store parent_id in the item
-    if value.left_id.is_some() {
-        flags |= 1 << 1;
-    }
-
-    if value.right_id.is_some() {
-        flags |= 1;
-    }
-
     e.u8(flags);
 
     // println!("flags: {:b}", flags);
@@ -297,6 +296,34 @@ fn encode_item(e: &mut EncoderV1, cx: &mut EncodeContext, value: &ItemData) {
     // cx.table.add(value, kind_flags, flags);
 }
 
+// the kind flags and the side and presence flags of the item
+pub(crate) fn item_flags(value: &ItemData) -> (u8, u8) {
+    let kind_flags = ItemKindFlags::from(&value.kind).bits();
+
+    let mut flags = ItemSideFlags::from(&value.side).bits() << 4;
+
+    // let is_root = matches!(value.content, Content::Doc(_));
+    if !matches!(value.content, Content::Null) {
+        flags |= 1 << 3; // has content
+    }
+
+    if value.field.is_some() {
+        flags |= 1 << 2; // has field
+    }
+
+    // if left_id is not None then we can get the parent_id from left item during integration,
+    // so we don't need to store parent_id in the item
+    if value.left_id.is_some() {
+        flags |= 1 << 1;
+    }
+
+    if value.right_id.is_some() {
+        flags |= 1;
+    }
+
+    (kind_flags, flags)
+}
+
 fn decode_item(d: &mut DecoderV1, ctx: &DecodeContext) -> Result<ItemData, String> {
     let kind_flag = d.u8()?;
     // println!("flags: {:b}", flags);
@@ -325,8 +352,6 @@ fn decode_item(d: &mut DecoderV1, ctx: &DecodeContext) -> Result<ItemData, Strin
     let mut left_id = None;
     let mut right_id = None;
     let mut parent_id = None;
-    let mut target_id = None;
-    let mut mover_id = None;
 
     if !is_root && flags & 0b10 != 0 {
         left_id = Some(Id::decode(d, ctx)?)
@@ -340,14 +365,6 @@ fn decode_item(d: &mut DecoderV1, ctx: &DecodeContext) -> Result<ItemData, Strin
         parent_id = Some(Id::decode(d, ctx)?)
     }
 
-    if flags & 0b1 != 0 {
-        target_id = Some(Id::decode(d, ctx)?)
-    }
-
-    if flags & 0b1 != 0 {
-        mover_id = Some(Id::decode(d, ctx)?)
-    }
-
     // println!("id: {:?}, field: {:?}", id, field);
 
     Ok(ItemData {
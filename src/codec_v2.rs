@@ -0,0 +1,271 @@
+use std::collections::VecDeque;
+
+use crate::codec_v1::{self, item_flags, DecoderV1, EncoderV1};
+use crate::decoder::{Decode, DecodeContext, Decoder};
+use crate::encoder::{Encode, EncodeContext, Encoder};
+use crate::item::{Content, ItemData};
+use crate::table::Table;
+
+pub(crate) const VERSION: u8 = 2;
+
+/// EncoderV2 writes the items column by column with run-length and delta encoded ids,
+/// everything else is written like `EncoderV1` does.
+///
+/// | version | table size | table | contents size | contents | rest |
+#[derive(Debug, Clone)]
+pub struct EncoderV2 {
+    rest: EncoderV1,
+    table: Table,
+    contents: EncoderV1,
+}
+
+impl Default for EncoderV2 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EncoderV2 {
+    pub fn new() -> Self {
+        Self {
+            rest: EncoderV1::headless(),
+            table: Table::default(),
+            contents: EncoderV1::headless(),
+        }
+    }
+}
+
+impl Encoder for EncoderV2 {
+    fn u8(&mut self, value: u8) {
+        self.rest.u8(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.rest.u16(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.rest.u32(value);
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.rest.u64(value);
+    }
+
+    fn uuid(&mut self, value: &[u8]) {
+        self.rest.uuid(value);
+    }
+
+    fn string(&mut self, value: &str) {
+        self.rest.string(value);
+    }
+
+    fn bytes(&mut self, value: &[u8]) {
+        self.rest.bytes(value);
+    }
+
+    fn slice(&mut self, value: &[u8]) {
+        self.rest.slice(value);
+    }
+
+    fn item(&mut self, cx: &mut EncodeContext, value: &ItemData) {
+        let (kind_flags, flags) = item_flags(value);
+        self.table.add(value, kind_flags, flags);
+        if !matches!(value.content, Content::Null) {
+            value.content.encode(&mut self.contents, cx);
+        }
+    }
+
+    fn finish(&mut self) {
+        self.rest.finish();
+        self.contents.finish();
+    }
+
+    fn decoder(&mut self) -> Box<dyn Decoder> {
+        self.finish();
+        Box::new(DecoderV2::new(self.buffer()).unwrap())
+    }
+
+    fn buffer(&self) -> Vec<u8> {
+        let table = self.table.buffer();
+        let mut buf = Vec::with_capacity(table.len() + self.contents.size() + self.rest.size() + 9);
+        buf.push(VERSION);
+        buf.extend_from_slice(&(table.len() as u32).to_be_bytes());
+        buf.extend_from_slice(&table);
+        buf.extend_from_slice(&(self.contents.size() as u32).to_be_bytes());
+        buf.extend_from_slice(&self.contents.buffer());
+        buf.extend_from_slice(&self.rest.buffer());
+
+        buf
+    }
+
+    fn size(&self) -> usize {
+        self.buffer().len()
+    }
+}
+
+/// DecoderV2 reads the bytes written by `EncoderV2`, the items are decoded up front
+pub struct DecoderV2 {
+    rest: DecoderV1,
+    items: VecDeque<ItemData>,
+}
+
+impl DecoderV2 {
+    pub fn new(buf: Vec<u8>) -> Result<Self, String> {
+        if buf.first() != Some(&VERSION) {
+            return Err("decoder: invalid version".to_string());
+        }
+
+        let (table, rest) = section(&buf[1..])?;
+        let (contents, rest) = section(rest)?;
+
+        let table = Table::from_bytes(table)?;
+        let mut contents = DecoderV1::headless(contents.to_vec());
+        let cx = DecodeContext::default();
+        let mut items = VecDeque::with_capacity(table.len());
+        for row in 0..table.len() {
+            let content = match table.has_content(row) {
+                true => Content::decode(&mut contents, &cx)?,
+                false => Content::Null,
+            };
+            items.push_back(table.item(row, content)?);
+        }
+
+        Ok(Self {
+            rest: DecoderV1::headless(rest.to_vec()),
+            items,
+        })
+    }
+}
+
+// a size prefixed section and the bytes after it
+fn section(buf: &[u8]) -> Result<(&[u8], &[u8]), String> {
+    let size = buf
+        .get(..4)
+        .map(|size| u32::from_be_bytes(size.try_into().unwrap()) as usize)
+        .ok_or("decoder: out of bounds")?;
+    let rest = &buf[4..];
+    if rest.len() < size {
+        return Err("decoder: out of bounds".to_string());
+    }
+
+    Ok(rest.split_at(size))
+}
+
+impl Decoder for DecoderV2 {
+    fn u8(&mut self) -> Result<u8, String> {
+        self.rest.u8()
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        self.rest.u16()
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        self.rest.u32()
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        self.rest.u64()
+    }
+
+    fn uuid(&mut self) -> Result<[u8; 16], String> {
+        self.rest.uuid()
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.rest.string()
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, String> {
+        self.rest.bytes()
+    }
+
+    fn slice(&mut self, len: usize) -> Result<&[u8], String> {
+        self.rest.slice(len)
+    }
+
+    fn item(&mut self, _ctx: &DecodeContext) -> Result<ItemData, String> {
+        self.items
+            .pop_front()
+            .ok_or_else(|| "decoder: no more items".to_string())
+    }
+
+    fn remaining(&self) -> usize {
+        self.rest.remaining()
+    }
+}
+
+/// Encode the value with the codec of the version
+pub fn encode_with_version(value: &impl Encode, version: u8) -> Result<Vec<u8>, String> {
+    let mut cx = EncodeContext {
+        version,
+        ..EncodeContext::default()
+    };
+
+    match version {
+        codec_v1::VERSION => {
+            let mut e = EncoderV1::new();
+            value.encode(&mut e, &mut cx);
+            Ok(e.buffer())
+        }
+        VERSION => {
+            let mut e = EncoderV2::new();
+            value.encode(&mut e, &mut cx);
+            Ok(e.buffer())
+        }
+        _ => Err(format!("unsupported codec version {}", version)),
+    }
+}
+
+/// Decode bytes of any codec version, the version is the first byte
+pub fn decode_bytes<T: Decode>(bytes: Vec<u8>) -> Result<T, String> {
+    let version = *bytes.first().ok_or("decoder: empty bytes")?;
+    let cx = DecodeContext {
+        version,
+        ..DecodeContext::default()
+    };
+
+    match version {
+        codec_v1::VERSION => T::decode(&mut DecoderV1::try_new(bytes)?, &cx),
+        VERSION => T::decode(&mut DecoderV2::new(bytes)?, &cx),
+        _ => Err(format!("unsupported codec version {}", version)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diff::Diff;
+    use crate::doc::Doc;
+    use crate::state::ClientState;
+
+    use super::*;
+
+    #[test]
+    fn test_encode_decode_v2() {
+        let doc = Doc::default();
+        let text = doc.text();
+        doc.set("text", text.clone());
+        text.append(doc.string("hello"));
+        let list = doc.list();
+        doc.set("list", list.clone());
+        for i in 0..200 {
+            list.append(doc.atom(format!("item {}", i)));
+        }
+        list.prepend(doc.map());
+        doc.commit();
+
+        let diff = doc.diff(ClientState::default());
+        let v1 = encode_with_version(&diff, codec_v1::VERSION).unwrap();
+        let v2 = encode_with_version(&diff, VERSION).unwrap();
+        assert!(v2.len() < v1.len(), "v2 {} >= v1 {}", v2.len(), v1.len());
+
+        // old payloads still decode
+        assert_eq!(decode_bytes::<Diff>(v1).unwrap(), diff);
+        assert_eq!(decode_bytes::<Diff>(v2.clone()).unwrap(), diff);
+
+        let copy = Doc::from(&decode_bytes::<Diff>(v2).unwrap()).unwrap();
+        assert_eq!(copy.get("text").unwrap().text_content(), "hello");
+        assert_eq!(copy.get("list").unwrap().size(), 201);
+    }
+}
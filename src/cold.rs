@@ -0,0 +1,336 @@
+use std::collections::BTreeMap;
+
+use hashbrown::HashMap;
+use miniz_oxide::deflate::compress_to_vec;
+use miniz_oxide::inflate::decompress_to_vec;
+use serde::Serialize;
+
+use crate::codec_v1::{DecoderV1, EncoderV1};
+use crate::decoder::{DecodeContext, Decoder};
+use crate::diff::Diff;
+use crate::doc::Doc;
+use crate::encoder::{EncodeContext, Encoder};
+use crate::id::{Id, WithId, WithIdRange};
+use crate::id_set::IdSet;
+use crate::item::{ItemData, ItemRef};
+use crate::store::DocStore;
+use crate::types::Type;
+
+/// MemoryStats shows how much of the document is materialized
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize)]
+pub struct MemoryStats {
+    /// number of materialized items
+    pub hot_items: usize,
+    /// number of items kept in frozen buffers
+    pub cold_items: usize,
+    /// number of frozen texts
+    pub frozen_texts: usize,
+    /// compressed size of the frozen buffers
+    pub frozen_bytes: usize,
+}
+
+// string items of an idle text, encoded in list order and compressed
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+struct FrozenText {
+    ids: IdSet,
+    items: usize,
+    buf: Vec<u8>,
+}
+
+/// ColdStore keeps idle texts as frozen buffers, a frozen text is decoded on first access
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub(crate) struct ColdStore {
+    texts: BTreeMap<Id, FrozenText>,
+    // edit counter at the last change of every container
+    touched: HashMap<Id, u64>,
+    edits: u64,
+}
+
+impl ColdStore {
+    pub(crate) fn touch(&mut self, container: Id) {
+        self.edits += 1;
+        self.touched.insert(container, self.edits);
+    }
+
+    // number of edits since the last change of the container
+    pub(crate) fn idle(&self, container: &Id) -> u64 {
+        self.edits - self.touched.get(container).copied().unwrap_or_default()
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.texts.is_empty()
+    }
+
+    #[inline]
+    pub(crate) fn is_frozen(&self, text: &Id) -> bool {
+        self.texts.contains_key(text)
+    }
+
+    // frozen text that holds the item id
+    pub(crate) fn text_of(&self, id: &Id) -> Option<Id> {
+        self.texts
+            .iter()
+            .find(|(_, frozen)| frozen.ids.contains(id))
+            .map(|(text, _)| *text)
+    }
+
+    pub(crate) fn texts(&self) -> Vec<Id> {
+        self.texts.keys().copied().collect()
+    }
+
+    fn freeze(&mut self, text: Id, items: &[Type]) {
+        let mut e = EncoderV1::new();
+        let mut cx = EncodeContext::default();
+        let mut ids = IdSet::new();
+
+        e.u32(items.len() as u32);
+        for item in items {
+            ids.insert(item.range());
+
+            let item = item.item_ref();
+            let item = item.borrow();
+            e.u8(item.flags);
+            e.item(&mut cx, &item.data);
+        }
+
+        let frozen = FrozenText {
+            ids,
+            items: items.len(),
+            buf: compress_to_vec(&e.buffer(), 6),
+        };
+
+        self.texts.insert(text, frozen);
+    }
+
+    fn take(&mut self, text: &Id) -> Option<Vec<(u8, ItemData)>> {
+        let frozen = self.texts.remove(text)?;
+        Some(decode_items(*text, &frozen.buf))
+    }
+
+    // data of all frozen items, used to include the frozen items in diffs
+    pub(crate) fn items(&self) -> Vec<ItemData> {
+        self.flagged_items()
+            .into_iter()
+            .map(|(_, data)| data)
+            .collect()
+    }
+
+    // data of all frozen items with the item flags
+    pub(crate) fn flagged_items(&self) -> Vec<(u8, ItemData)> {
+        self.texts
+            .iter()
+            .flat_map(|(text, frozen)| decode_items(*text, &frozen.buf))
+            .collect()
+    }
+
+    fn stats(&self) -> (usize, usize) {
+        self.texts.values().fold((0, 0), |(items, bytes), frozen| {
+            (items + frozen.items, bytes + frozen.buf.len())
+        })
+    }
+}
+
+fn decode_items(text: Id, buf: &[u8]) -> Vec<(u8, ItemData)> {
+    // the buffer is written by freeze, a broken buffer is a bug
+    let buf = decompress_to_vec(buf).expect("frozen text buffer is corrupted");
+    let mut d = DecoderV1::new(buf);
+    let ctx = DecodeContext::default();
+
+    let len = d.u32().unwrap();
+    (0..len)
+        .map(|_| {
+            let flags = d.u8().unwrap();
+            let mut data = d.item(&ctx).unwrap();
+            // the codec drops the parent of items with a left origin
+            data.parent_id = Some(text);
+            (flags, data)
+        })
+        .collect()
+}
+
+impl DocStore {
+    // replace the string items of the text with a frozen buffer,
+    // texts with movers or non string children stay materialized
+    pub(crate) fn freeze_text(&mut self, text: &Type) -> bool {
+        let items = text.item_ref().borrow().all_items();
+        let freezable = !items.is_empty()
+            && items.iter().all(|item| {
+                item.kind().is_string() && !item.is_moved() && !self.moves.contains_key(&item.id())
+            });
+
+        if !freezable || self.cold.is_frozen(&text.id()) {
+            return false;
+        }
+
+        self.cold.freeze(text.id(), &items);
+
+        // drop the links so that the items can be released
+        text.set_start(None);
+        text.set_end(None);
+        for item in &items {
+            item.set_left(None);
+            item.set_right(None);
+            item.set_parent(None);
+            self.items.remove(&item.id());
+        }
+
+        true
+    }
+
+    // decode the frozen string items of the text and link them back
+    pub(crate) fn thaw_text(&mut self, text: &Type) {
+        let Some(items) = self.cold.take(&text.id()) else {
+            return;
+        };
+
+        let store = text.item_ref().store.clone();
+        let mut prev: Option<Type> = None;
+        for (flags, data) in items {
+            let item: Type = ItemRef::new(data.into(), store.clone()).into();
+            item.item_ref().borrow_mut().flags = flags;
+            item.set_parent(text.clone());
+
+            match &prev {
+                Some(prev) => {
+                    prev.set_right(item.clone());
+                    item.set_left(prev.clone());
+                }
+                None => text.set_start(item.clone()),
+            }
+
+            self.items.insert(item.clone());
+            prev = Some(item);
+        }
+
+        text.set_end(prev);
+        self.cold.touch(text.id());
+    }
+
+    // mark the containers changed by the diff as hot and thaw the frozen ones
+    pub(crate) fn warm_diff(&mut self, diff: &Diff) {
+        for (_, items) in diff.items.iter() {
+            for (_, data) in items.iter() {
+                if let Some(parent_id) = data.parent_id {
+                    self.cold.touch(parent_id);
+                }
+
+                if !self.cold.is_empty() {
+                    for id in [data.parent_id, data.left_id, data.right_id]
+                        .iter()
+                        .flatten()
+                    {
+                        self.thaw_id(id);
+                    }
+                }
+            }
+        }
+
+        for (_, items) in diff.deletes.iter() {
+            for (_, item) in items.iter() {
+                self.thaw_id(&item.target());
+                if let Some(parent_id) = self.find(&item.target()).and_then(|t| t.parent_id()) {
+                    self.cold.touch(parent_id);
+                }
+            }
+        }
+    }
+
+    // thaw the frozen text that holds the item id
+    pub(crate) fn thaw_id(&mut self, id: &Id) {
+        if self.cold.is_empty() {
+            return;
+        }
+
+        let text = if self.cold.is_frozen(id) {
+            Some(*id)
+        } else {
+            self.cold.text_of(id)
+        };
+
+        if let Some(text) = text.and_then(|text| self.find(&text)) {
+            self.thaw_text(&text);
+        }
+    }
+}
+
+impl Doc {
+    /// Freeze the texts that were not edited during the last `idle` edits of the document.
+    /// The string items of a frozen text are kept in a compressed buffer and decoded
+    /// when the text is accessed again, string handles taken before the freeze are detached.
+    /// Returns the number of frozen texts.
+    pub fn freeze_idle(&self, idle: u64) -> usize {
+        let mut store = self.store_mut("freeze_idle");
+        let texts: Vec<Type> = store
+            .items
+            .iter()
+            .flat_map(|(_, items)| items.iter().map(|(_, item)| item.clone()))
+            .filter(|item| item.kind().is_text() && store.cold.idle(&item.id()) >= idle)
+            .collect();
+
+        texts.iter().filter(|text| store.freeze_text(text)).count()
+    }
+
+    /// Decode all frozen texts
+    pub fn thaw_all(&self) {
+        let mut store = self.store_mut("thaw_all");
+        for text in store.cold.texts() {
+            store.thaw_id(&text);
+        }
+    }
+
+    /// Hot and cold set sizes of the document
+    pub fn memory_stats(&self) -> MemoryStats {
+        let store = self.store.borrow();
+        let (cold_items, frozen_bytes) = store.cold.stats();
+
+        MemoryStats {
+            hot_items: store.items.iter().map(|(_, items)| items.size()).sum(),
+            cold_items,
+            frozen_texts: store.cold.texts.len(),
+            frozen_bytes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doc::CloneDeep;
+
+    use super::*;
+
+    #[test]
+    fn test_freeze_idle_text() {
+        let doc = Doc::default();
+        let text = doc.text();
+        doc.set("text", text.clone());
+        text.append(doc.string("hello"));
+        text.append(doc.string(" world"));
+        doc.commit();
+
+        let list = doc.list();
+        doc.set("list", list.clone());
+        for i in 0..4 {
+            list.append(doc.atom(i.to_string()));
+        }
+        doc.commit();
+
+        let before = doc.memory_stats();
+        assert_eq!(doc.freeze_idle(4), 1);
+
+        let stats = doc.memory_stats();
+        assert_eq!(stats.frozen_texts, 1);
+        assert_eq!(stats.cold_items, 2);
+        assert_eq!(stats.hot_items, before.hot_items - 2);
+
+        // frozen items are still sent to other sites
+        let copy = doc.clone_deep();
+        let copy_text = copy.get("text").unwrap().as_text().unwrap();
+        assert_eq!(copy_text.text_content(), "hello world");
+
+        // accessing the text decodes it
+        text.append(doc.string("!"));
+        assert_eq!(text.text_content(), "hello world!");
+        assert_eq!(doc.memory_stats().frozen_texts, 0);
+    }
+}
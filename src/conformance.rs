@@ -0,0 +1,360 @@
+use serde_json::{json, Value};
+
+use crate::doc::Doc;
+use crate::mark::Mark;
+use crate::nlist::NList;
+use crate::ntext::NText;
+use crate::state::ClientState;
+use crate::sync::equal_docs;
+use crate::text_mark::Expand;
+use crate::types::Type;
+
+/// ConflictCase is the documented outcome of two operations made concurrently on two
+/// replicas of the same document. The outcomes are part of the public semantics, a case
+/// that starts failing is a breaking change.
+#[derive(Debug, Clone, Copy)]
+pub struct ConflictCase {
+    /// the two operations, e.g. "map set/remove"
+    pub name: &'static str,
+    /// what both replicas show after the merge
+    pub outcome: &'static str,
+    check: fn() -> Result<(), String>,
+}
+
+impl ConflictCase {
+    /// Run the operations on two fresh replicas, merge them and compare with the outcome
+    pub fn check(&self) -> Result<(), String> {
+        (self.check)().map_err(|e| format!("{}: {}", self.name, e))
+    }
+}
+
+/// Every documented pairwise conflict
+pub fn conflict_cases() -> Vec<ConflictCase> {
+    vec![
+        ConflictCase {
+            name: "map set/set",
+            outcome: "the replicas agree on one of the two values",
+            check: map_set_set,
+        },
+        ConflictCase {
+            name: "map set/remove",
+            outcome: "the new value stays, a remove only removes the value it saw",
+            check: map_set_remove,
+        },
+        ConflictCase {
+            name: "map remove/remove",
+            outcome: "the key is removed",
+            check: map_remove_remove,
+        },
+        ConflictCase {
+            name: "list insert/insert",
+            outcome: "both items are kept in the same order on both replicas",
+            check: list_insert_insert,
+        },
+        ConflictCase {
+            name: "list insert/delete",
+            outcome: "the inserted item stays when its left sibling is deleted",
+            check: list_insert_delete,
+        },
+        ConflictCase {
+            name: "list delete/delete",
+            outcome: "the item is deleted once",
+            check: list_delete_delete,
+        },
+        ConflictCase {
+            name: "text insert/insert",
+            outcome: "both strings are kept in the same order on both replicas",
+            check: text_insert_insert,
+        },
+        ConflictCase {
+            name: "text insert/delete",
+            outcome: "a string inserted inside a deleted string stays",
+            check: text_insert_delete,
+        },
+        ConflictCase {
+            name: "counter increment/increment",
+            outcome: "the increments add up",
+            check: counter_increment_increment,
+        },
+        ConflictCase {
+            name: "format/format",
+            outcome: "the replicas agree on one of the two marks with the same name",
+            check: format_format,
+        },
+        ConflictCase {
+            name: "format/delete",
+            outcome: "the deleted text stays deleted",
+            check: format_delete,
+        },
+        ConflictCase {
+            name: "move/move",
+            outcome: "the value ends up under exactly one of the two keys",
+            check: move_move,
+        },
+        ConflictCase {
+            name: "move/delete",
+            outcome: "the deleted value is gone from both keys",
+            check: move_delete,
+        },
+    ]
+}
+
+/// Check every conflict case, returns the number of checked cases or the failed ones
+pub fn check_conflict_semantics() -> Result<usize, String> {
+    let cases = conflict_cases();
+    let failed: Vec<String> = cases.iter().filter_map(|c| c.check().err()).collect();
+    if !failed.is_empty() {
+        return Err(failed.join("\n"));
+    }
+
+    Ok(cases.len())
+}
+
+// two replicas with the same committed content
+fn replicas(setup: impl Fn(&Doc)) -> (Doc, Doc) {
+    let d1 = Doc::default();
+    setup(&d1);
+    d1.commit();
+
+    let d2 = Doc::new(d1.meta.clone());
+    d2.apply(&d1.diff(ClientState::default()));
+    d2.update_client();
+
+    (d1, d2)
+}
+
+// exchange the concurrent changes, the replicas must converge
+fn merge(d1: &Doc, d2: &Doc) -> Result<(), String> {
+    d1.commit();
+    d2.commit();
+    d1.apply(&d2.diff(d1.state()));
+    d2.apply(&d1.diff(d2.state()));
+
+    ensure(equal_docs(d1, d2), "the replicas diverged")
+}
+
+fn ensure(ok: bool, reason: &str) -> Result<(), String> {
+    if ok {
+        Ok(())
+    } else {
+        Err(reason.to_string())
+    }
+}
+
+fn json_of(doc: &Doc, key: &str) -> Value {
+    doc.get(key).map(|v| v.to_json()).unwrap_or(Value::Null)
+}
+
+fn list_of(doc: &Doc) -> Result<NList, String> {
+    match doc.get("list") {
+        Some(Type::List(list)) => Ok(list),
+        _ => Err("list not synced".to_string()),
+    }
+}
+
+fn text_of(doc: &Doc) -> Result<NText, String> {
+    match doc.get("text") {
+        Some(Type::Text(text)) => Ok(text),
+        _ => Err("text not synced".to_string()),
+    }
+}
+
+// remove all the visible strings of the text
+fn clear_text(text: &NText) {
+    for item in text.borrow().as_list() {
+        item.item_ref().delete(item.size());
+    }
+}
+
+fn with_text(content: &'static str) -> impl Fn(&Doc) {
+    move |d: &Doc| {
+        let text = d.text();
+        d.set("text", text.clone());
+        text.append(d.string(content));
+    }
+}
+
+fn map_set_set() -> Result<(), String> {
+    let (d1, d2) = replicas(|d| d.set("title", d.atom("draft")));
+    d1.set("title", d1.atom("a"));
+    d2.set("title", d2.atom("b"));
+    merge(&d1, &d2)?;
+
+    let title = json_of(&d1, "title");
+    ensure(title == "a" || title == "b", "a value of the merge is lost")
+}
+
+fn map_set_remove() -> Result<(), String> {
+    let (d1, d2) = replicas(|d| d.set("title", d.atom("draft")));
+    d1.set("title", d1.atom("a"));
+    Type::Map(d2.root.clone()).remove("title".into());
+    merge(&d1, &d2)?;
+
+    ensure(json_of(&d1, "title") == "a", "the new value is removed")
+}
+
+fn map_remove_remove() -> Result<(), String> {
+    let (d1, d2) = replicas(|d| d.set("title", d.atom("draft")));
+    Type::Map(d1.root.clone()).remove("title".into());
+    Type::Map(d2.root.clone()).remove("title".into());
+    merge(&d1, &d2)?;
+
+    ensure(d1.get("title").is_none(), "the key is back")
+}
+
+fn list_insert_insert() -> Result<(), String> {
+    let (d1, d2) = replicas(|d| {
+        let list = d.list();
+        d.set("list", list.clone());
+        list.append(d.atom("x"));
+    });
+    list_of(&d1)?.append(d1.atom("a"));
+    list_of(&d2)?.append(d2.atom("b"));
+    merge(&d1, &d2)?;
+
+    let list = json_of(&d1, "list");
+    ensure(
+        list == json!(["x", "a", "b"]) || list == json!(["x", "b", "a"]),
+        "an insert is lost",
+    )
+}
+
+fn list_insert_delete() -> Result<(), String> {
+    let (d1, d2) = replicas(|d| {
+        let list = d.list();
+        d.set("list", list.clone());
+        list.append(d.atom("x"));
+    });
+    list_of(&d1)?.append(d1.atom("a"));
+    list_of(&d2)?.borrow().as_list()[0].delete();
+    merge(&d1, &d2)?;
+
+    ensure(json_of(&d1, "list") == json!(["a"]), "the insert is lost")
+}
+
+fn list_delete_delete() -> Result<(), String> {
+    let (d1, d2) = replicas(|d| {
+        let list = d.list();
+        d.set("list", list.clone());
+        list.append(d.atom("x"));
+        list.append(d.atom("y"));
+    });
+    list_of(&d1)?.borrow().as_list()[0].delete();
+    list_of(&d2)?.borrow().as_list()[0].delete();
+    merge(&d1, &d2)?;
+
+    ensure(json_of(&d1, "list") == json!(["y"]), "the delete spread")
+}
+
+fn text_insert_insert() -> Result<(), String> {
+    let (d1, d2) = replicas(with_text("ab"));
+    text_of(&d1)?.insert(1, d1.string("x"));
+    text_of(&d2)?.insert(1, d2.string("y"));
+    merge(&d1, &d2)?;
+
+    let content = text_of(&d1)?.text_content();
+    ensure(
+        content == "axyb" || content == "ayxb",
+        "an insert is lost or misplaced",
+    )
+}
+
+fn text_insert_delete() -> Result<(), String> {
+    let (d1, d2) = replicas(with_text("hello"));
+    text_of(&d1)?.insert(2, d1.string("!"));
+    clear_text(&text_of(&d2)?);
+    merge(&d1, &d2)?;
+
+    ensure(text_of(&d1)?.text_content() == "!", "the insert is lost")
+}
+
+fn counter_increment_increment() -> Result<(), String> {
+    let (d1, d2) = replicas(|d| d.set("likes", d.counter()));
+    let counter = |doc: &Doc| match doc.get("likes") {
+        Some(Type::Counter(counter)) => Ok(counter),
+        _ => Err("counter not synced".to_string()),
+    };
+    counter(&d1)?.increment(2);
+    counter(&d2)?.increment(3);
+    merge(&d1, &d2)?;
+
+    ensure(counter(&d1)?.value() == 5, "an increment is lost")
+}
+
+fn format_format() -> Result<(), String> {
+    let (d1, d2) = replicas(with_text("hello"));
+    let red = Mark::Color("red".into());
+    let blue = Mark::Color("blue".into());
+    text_of(&d1)?.mark(0, 5, red.clone(), Expand::None)?;
+    text_of(&d2)?.mark(0, 5, blue.clone(), Expand::None)?;
+    merge(&d1, &d2)?;
+
+    let marks = text_of(&d1)?.marks_at(0..5);
+    ensure(
+        marks == text_of(&d2)?.marks_at(0..5),
+        "the replicas show different marks",
+    )?;
+    ensure(marks == vec![red] || marks == vec![blue], "the marks stack")
+}
+
+fn format_delete() -> Result<(), String> {
+    let (d1, d2) = replicas(with_text("hello"));
+    text_of(&d1)?.mark(0, 5, Mark::Bold, Expand::After)?;
+    clear_text(&text_of(&d2)?);
+    merge(&d1, &d2)?;
+
+    ensure(text_of(&d1)?.text_content().is_empty(), "the text is back")
+}
+
+fn move_move() -> Result<(), String> {
+    let (d1, d2) = replicas(|d| {
+        let map = d.map();
+        d.set("map", map.clone());
+        map.set("draft", d.atom("milk"));
+    });
+    for (doc, key) in [(&d1, "todo"), (&d2, "done")] {
+        let map = doc.get("map").ok_or("map not synced")?;
+        let value = map.get("draft").ok_or("value not synced")?;
+        value.move_to_key(map, key);
+    }
+    merge(&d1, &d2)?;
+
+    let map = json_of(&d1, "map");
+    ensure(
+        map == json!({"todo": "milk"}) || map == json!({"done": "milk"}),
+        "the value is not under exactly one key",
+    )
+}
+
+fn move_delete() -> Result<(), String> {
+    let (d1, d2) = replicas(|d| {
+        let map = d.map();
+        d.set("map", map.clone());
+        map.set("draft", d.atom("milk"));
+    });
+    let map = d1.get("map").ok_or("map not synced")?;
+    map.get("draft")
+        .ok_or("value not synced")?
+        .move_to_key(map, "done");
+    let map = d2.get("map").ok_or("map not synced")?;
+    map.get("draft").ok_or("value not synced")?.delete();
+    merge(&d1, &d2)?;
+
+    ensure(
+        json_of(&d1, "map") == json!({}),
+        "the deleted value is back",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conflict_semantics() {
+        for case in conflict_cases() {
+            assert_eq!(case.check(), Ok(()), "{}", case.outcome);
+        }
+        assert_eq!(check_conflict_semantics(), Ok(conflict_cases().len()));
+    }
+}
@@ -0,0 +1,116 @@
+use serde_json::Value;
+
+use crate::doc::Doc;
+use crate::ntext::NText;
+
+/// ContentEq compares the visible content of texts and documents, e.g. to skip a pipeline
+/// step when nothing actually changed. Marks, tombstones, item ids and the edit history are
+/// ignored, two documents built by different edits are equal when they read the same.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct ContentEq {
+    ignore_whitespace: bool,
+}
+
+impl ContentEq {
+    /// Treat every run of whitespace as a single space and ignore the whitespace at the
+    /// ends of texts and strings
+    pub fn ignore_whitespace(mut self) -> Self {
+        self.ignore_whitespace = true;
+        self
+    }
+
+    pub fn texts(&self, a: &NText, b: &NText) -> bool {
+        self.normalize(&a.text_content()) == self.normalize(&b.text_content())
+    }
+
+    pub fn docs(&self, a: &Doc, b: &Doc) -> bool {
+        let a = self.normalize_value(a.snapshot().to_json());
+        let b = self.normalize_value(b.snapshot().to_json());
+        a == b
+    }
+
+    fn normalize(&self, text: &str) -> String {
+        match self.ignore_whitespace {
+            true => text.split_whitespace().collect::<Vec<_>>().join(" "),
+            false => text.to_string(),
+        }
+    }
+
+    fn normalize_value(&self, value: Value) -> Value {
+        if !self.ignore_whitespace {
+            return value;
+        }
+
+        match value {
+            Value::String(text) => Value::String(self.normalize(&text)),
+            Value::Array(items) => items
+                .into_iter()
+                .map(|item| self.normalize_value(item))
+                .collect(),
+            Value::Object(entries) => entries
+                .into_iter()
+                .map(|(key, value)| (key, self.normalize_value(value)))
+                .collect(),
+            value => value,
+        }
+    }
+}
+
+impl NText {
+    /// The texts read the same, see `ContentEq` to ignore whitespace differences
+    pub fn content_eq(&self, other: &NText) -> bool {
+        ContentEq::default().texts(self, other)
+    }
+}
+
+impl Doc {
+    /// The documents hold the same visible values, see `ContentEq`
+    pub fn content_eq(&self, other: &Doc) -> bool {
+        ContentEq::default().docs(self, other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mark::Mark;
+    use crate::text_mark::Expand;
+
+    use super::*;
+
+    #[test]
+    fn test_content_eq() {
+        let d1 = Doc::default();
+        let t1 = d1.text();
+        d1.set("title", d1.atom("notes"));
+        d1.set("body", t1.clone());
+        t1.append(d1.string("hello world"));
+        t1.mark(0, 5, Mark::Bold, Expand::After).unwrap();
+        d1.commit();
+
+        // the same content reached through other edits
+        let d2 = Doc::default();
+        let t2 = d2.text();
+        d2.set("body", t2.clone());
+        d2.set("title", d2.atom("draft"));
+        d2.set("title", d2.atom("notes"));
+        t2.append(d2.string("hello"));
+        let typo = d2.string(" wrold");
+        t2.append(typo.clone());
+        typo.item_ref().delete(typo.size());
+        t2.append(d2.string(" world"));
+        d2.commit();
+
+        assert!(t1.content_eq(&t2));
+        assert!(d1.content_eq(&d2));
+
+        t2.append(d2.string("  \n"));
+        t2.insert(5, d2.string("  "));
+        assert!(!d1.content_eq(&d2));
+        let loose = ContentEq::default().ignore_whitespace();
+        assert!(loose.texts(&t1, &t2));
+        assert!(loose.docs(&d1, &d2));
+
+        t2.append(d2.string("!"));
+        assert!(!loose.docs(&d1, &d2));
+    }
+}
@@ -1,9 +1,8 @@
 use std::cmp::Ordering;
 
 use crate::bimapid::ClientMap;
-use crate::id::{Id, WithId};
+use crate::id::{Id, IdRange, WithId, WithIdRange};
 use crate::item::Linked;
-use crate::store::ClientStore;
 use crate::types::Type;
 
 // integrate an item into the list of items, resolving conflicts
@@ -53,8 +52,10 @@ where
 
     let mut counter = 0;
     {
-        let mut conflict_items = ClientStore::default();
-        let mut items_before_origin = ClientStore::default();
+        // ranges of the scanned items, an origin can point inside a string run
+        let mut conflict_items: Vec<IdRange> = vec![];
+        let mut items_before_origin: Vec<IdRange> = vec![];
+        let contains = |ranges: &[IdRange], id: &Id| ranges.iter().any(|r| r.contains(id));
 
         let item_id = item.id();
 
@@ -86,8 +87,8 @@ where
                 return Err("infinite loop".to_string());
             }
 
-            items_before_origin.insert(conflict_id);
-            conflict_items.insert(conflict_id);
+            items_before_origin.push(curr_conflict.range());
+            conflict_items.push(curr_conflict.range());
 
             let conflict_left_id = conflict.as_ref().and_then(|c| c.left_id());
             let item_left_id = item.left_id();
@@ -107,9 +108,9 @@ where
                 }
                 // item right id is not matched with conflict right id
             } else if conflict_left_id.is_some()
-                && items_before_origin.contains(&conflict_left_id.unwrap())
+                && contains(&items_before_origin, &conflict_left_id.unwrap())
             {
-                if !conflict_items.contains(&conflict_left_id.unwrap()) {
+                if !contains(&conflict_items, &conflict_left_id.unwrap()) {
                     left.clone_from(&conflict);
                     conflict_items.clear();
                 }
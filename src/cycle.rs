@@ -3,17 +3,23 @@ use crate::Type;
 
 /// check if the parenting relationship between `parent` and `child` creates a cycle
 pub(crate) fn creates_cycle(parent: &Type, child: &Type) -> bool {
+    let child_id = child.id();
+    if parent.id().eq(&child_id) {
+        return true;
+    }
+
     // moving child to higher level in the tree does not create a cycle
     if child.depth() >= parent.depth() {
         return false;
     }
 
-    let child_id = child.id();
     // if the child is already a parent of the parent, it will create a cycle
-    while let Some(parent) = parent.parent() {
+    let mut ancestor = parent.parent();
+    while let Some(parent) = ancestor {
         if parent.id().eq(&child_id) {
             return true;
         }
+        ancestor = parent.parent();
     }
 
     false
@@ -293,6 +293,75 @@ impl ChangeDag {
         });
     }
 
+    // report changes of a client that are out of clock order and dependency cycles
+    pub(crate) fn check(&self) -> Vec<String> {
+        let mut errors = vec![];
+        let mut parents: HashMap<Id, Vec<Id>> = HashMap::new();
+
+        for (client, _) in self.store.iter() {
+            let mut prev: Option<&ChangeId> = None;
+            for node in self.store.changes(*client).unwrap_or_default() {
+                if let Some(prev) = prev {
+                    if node.change.start <= prev.end {
+                        errors.push(format!(
+                            "change {:?} does not follow {:?}",
+                            node.change, prev
+                        ));
+                    }
+                }
+                prev = Some(&node.change);
+
+                let ids = node.parents.iter().map(|p| p.id()).collect();
+                parents.insert(node.change.id(), ids);
+            }
+        }
+
+        // depth first search, a parent on the current path closes a cycle
+        let mut done: HashSet<Id> = HashSet::new();
+        let mut path: HashSet<Id> = HashSet::new();
+        for start in parents.keys() {
+            if done.contains(start) {
+                continue;
+            }
+
+            let mut stack = vec![(*start, 0)];
+            path.insert(*start);
+            while let Some((id, index)) = stack.pop() {
+                let next = parents.get(&id).and_then(|p| p.get(index)).copied();
+                match next {
+                    Some(parent) => {
+                        stack.push((id, index + 1));
+                        if path.contains(&parent) {
+                            errors.push(format!(
+                                "change {} depends on itself through {}",
+                                parent, id
+                            ));
+                        } else if !done.contains(&parent) {
+                            path.insert(parent);
+                            stack.push((parent, 0));
+                        }
+                    }
+                    None => {
+                        path.remove(&id);
+                        done.insert(id);
+                    }
+                }
+            }
+        }
+
+        errors
+    }
+
+    // changes with their parents, the changes of a client in clock order
+    pub(crate) fn nodes(&self) -> impl Iterator<Item = (&ChangeId, &[ChangeId])> {
+        self.store.iter().flat_map(|(_, store)| {
+            store
+                .changes()
+                .iter()
+                .map(|node| (&node.change, node.parents.as_slice()))
+        })
+    }
+
     // this is for testing purposes, to sort the changes in the order they were undone
     fn sort_changes<T: ClientMapper>(&mut self, client_map: &T) -> Vec<ChangeId> {
         let mut sorted_changes = Vec::new();
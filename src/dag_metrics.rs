@@ -0,0 +1,225 @@
+use std::collections::BTreeMap;
+
+use hashbrown::{HashMap, HashSet};
+use serde::Serialize;
+
+use crate::bimapid::ClientMapper;
+use crate::change::ChangeId;
+use crate::dag::ChangeDag;
+use crate::doc::Doc;
+use crate::id::{Client, Id, WithId};
+
+/// DagMetrics describes the shape of the change graph, e.g. for sync health dashboards.
+///
+/// The changes are replayed in causal order, a change of a client depends on the previous
+/// change of the client besides its recorded parents.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize)]
+pub struct DagMetrics {
+    pub changes: u32,
+    /// changes on the longest dependency chain
+    pub depth: u32,
+    /// most changes at the same depth, that is the widest burst of concurrent changes
+    pub width: u32,
+    /// changes of every client that forked from a change another change already built on
+    pub branches: BTreeMap<Client, u32>,
+    /// number of concurrent heads after every change in causal order
+    pub frontiers: Vec<u32>,
+}
+
+impl DagMetrics {
+    /// Most heads the graph had at once, more than a few hint at replicas drifting apart
+    pub fn max_frontier(&self) -> u32 {
+        self.frontiers.iter().copied().max().unwrap_or_default()
+    }
+
+    /// Compact form for dashboards, the heads over time are run length encoded,
+    /// e.g. `c=5;d=4;w=2;b=<client>:1;f=1x2,2x2,1x1`
+    pub fn to_compact(&self) -> String {
+        let branches: Vec<String> = self
+            .branches
+            .iter()
+            .map(|(client, count)| format!("{}:{}", client, count))
+            .collect();
+
+        let mut runs: Vec<(u32, u32)> = vec![];
+        for heads in &self.frontiers {
+            match runs.last_mut() {
+                Some((last, count)) if last == heads => *count += 1,
+                _ => runs.push((*heads, 1)),
+            }
+        }
+        let frontiers: Vec<String> = runs
+            .iter()
+            .map(|(heads, count)| format!("{}x{}", heads, count))
+            .collect();
+
+        format!(
+            "c={};d={};w={};b={};f={}",
+            self.changes,
+            self.depth,
+            self.width,
+            branches.join(","),
+            frontiers.join(",")
+        )
+    }
+}
+
+impl ChangeDag {
+    pub(crate) fn metrics<T: ClientMapper>(&self, client_map: &T) -> DagMetrics {
+        // parents of every change, the previous change of the client included
+        let mut parents: HashMap<Id, (ChangeId, Vec<Id>)> = HashMap::new();
+        let mut last: HashMap<Id, Id> = HashMap::new();
+        for (change, deps) in self.nodes() {
+            let mut ids: Vec<Id> = deps.iter().map(|dep| dep.id()).collect();
+            if change.start > 1 {
+                last.insert(change.id(), Id::new(change.client, change.start - 1));
+            }
+            ids.sort_by_key(|id| (id.client, id.clock));
+            ids.dedup();
+            parents.insert(change.id(), (*change, ids));
+        }
+        // the previous change is found by its last clock
+        let ends: HashMap<Id, Id> = parents
+            .values()
+            .map(|(change, _)| (Id::new(change.client, change.end), change.id()))
+            .collect();
+        for (id, prev) in last {
+            if let (Some(prev), Some((_, ids))) = (ends.get(&prev), parents.get_mut(&id)) {
+                if !ids.contains(prev) {
+                    ids.push(*prev);
+                }
+            }
+        }
+
+        let depths = depths(&parents);
+        let mut order: Vec<&(ChangeId, Vec<Id>)> = parents.values().collect();
+        order.sort_by_key(|(change, _)| (depths[&change.id()], change.client, change.start));
+
+        let mut metrics = DagMetrics {
+            changes: order.len() as u32,
+            ..DagMetrics::default()
+        };
+        let mut levels: HashMap<u32, u32> = HashMap::new();
+        let mut heads: HashSet<Id> = HashSet::new();
+        let mut children: HashMap<Id, u32> = HashMap::new();
+        for (change, ids) in order {
+            let depth = depths[&change.id()];
+            metrics.depth = metrics.depth.max(depth);
+            *levels.entry(depth).or_default() += 1;
+
+            let mut forked = false;
+            for parent in ids.iter().filter(|id| parents.contains_key(*id)) {
+                heads.remove(parent);
+                let count = children.entry(*parent).or_default();
+                forked |= *count > 0;
+                *count += 1;
+            }
+            if forked {
+                if let Some(client) = client_map.get_client(&change.client) {
+                    *metrics.branches.entry(client.clone()).or_default() += 1;
+                }
+            }
+
+            heads.insert(change.id());
+            metrics.frontiers.push(heads.len() as u32);
+        }
+        metrics.width = levels.values().copied().max().unwrap_or_default();
+
+        metrics
+    }
+}
+
+// length of the longest dependency chain ending at every change, the chains are followed
+// without recursion and a cycle is cut where it closes
+fn depths(parents: &HashMap<Id, (ChangeId, Vec<Id>)>) -> HashMap<Id, u32> {
+    let mut depths: HashMap<Id, u32> = HashMap::new();
+    let mut path: HashSet<Id> = HashSet::new();
+    for start in parents.keys() {
+        let mut stack = vec![*start];
+        while let Some(id) = stack.last().copied() {
+            if depths.contains_key(&id) {
+                stack.pop();
+                continue;
+            }
+            path.insert(id);
+
+            let ids = parents
+                .get(&id)
+                .map(|(_, ids)| ids.as_slice())
+                .unwrap_or_default();
+            let pending: Vec<Id> = ids
+                .iter()
+                .filter(|p| {
+                    parents.contains_key(*p) && !depths.contains_key(*p) && !path.contains(*p)
+                })
+                .copied()
+                .collect();
+            if !pending.is_empty() {
+                stack.extend(pending);
+                continue;
+            }
+
+            let depth = ids
+                .iter()
+                .filter_map(|p| depths.get(p))
+                .max()
+                .copied()
+                .unwrap_or_default();
+            depths.insert(id, depth + 1);
+            path.remove(&id);
+            stack.pop();
+        }
+    }
+
+    depths
+}
+
+impl Doc {
+    /// Shape of the change graph of the document, see `DagMetrics`
+    pub fn change_metrics(&self) -> DagMetrics {
+        let store = self.store.borrow();
+        store.dag.metrics(&store.state.clients)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::Type;
+
+    use super::*;
+
+    #[test]
+    fn test_change_metrics() {
+        let d1 = Doc::default();
+        let list = d1.list();
+        d1.set("list", list.clone());
+        list.append(d1.atom("a"));
+        d1.commit();
+
+        let d2 = Doc::new(d1.meta.clone());
+        d2.apply(&d1.diff(d2.version()));
+        d2.update_client();
+        let before = d1.change_metrics();
+        assert_eq!(before.max_frontier(), 1);
+        assert!(before.branches.is_empty());
+
+        // concurrent appends fork the graph
+        list.append(d1.atom("b"));
+        d1.commit();
+        let Some(Type::List(remote)) = d2.get("list") else {
+            panic!("list not synced");
+        };
+        remote.append(d2.atom("c"));
+        d2.commit();
+        d1.apply(&d2.diff(d1.version()));
+
+        let metrics = d1.change_metrics();
+        assert_eq!(metrics.changes, before.changes + 2);
+        assert_eq!(metrics.depth, before.depth + 1);
+        assert_eq!(metrics.width, 2);
+        assert_eq!(metrics.max_frontier(), 2);
+        assert_eq!(metrics.branches.values().sum::<u32>(), 1);
+        assert_eq!(metrics.frontiers.last(), Some(&2));
+        assert!(metrics.to_compact().ends_with(",2x1"));
+    }
+}
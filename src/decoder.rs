@@ -1,4 +1,5 @@
 use crate::item::ItemData;
+use crate::limits::DiffLimits;
 
 pub trait Decoder {
     fn u8(&mut self) -> Result<u8, String>;
@@ -10,6 +11,8 @@ pub trait Decoder {
     fn bytes(&mut self) -> Result<Vec<u8>, String>;
     fn slice(&mut self, len: usize) -> Result<&[u8], String>;
     fn item(&mut self, ctx: &DecodeContext) -> Result<ItemData, String>;
+    /// number of bytes left to decode
+    fn remaining(&self) -> usize;
 }
 
 impl Decoder for Box<dyn Decoder> {
@@ -48,11 +51,17 @@ impl Decoder for Box<dyn Decoder> {
     fn item(&mut self, ctx: &DecodeContext) -> Result<ItemData, String> {
         self.as_mut().item(ctx)
     }
+
+    fn remaining(&self) -> usize {
+        self.as_ref().remaining()
+    }
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct DecodeContext {
     pub(crate) version: u8,
+    // declared sizes over the limits fail the decode
+    pub(crate) limits: DiffLimits,
 }
 
 pub trait Decode {
@@ -30,6 +30,20 @@ impl DeleteItem {
         self.range.id()
     }
 
+    // grow the range with a range touching it, false when the ranges are apart or overlap
+    pub(crate) fn extend(&mut self, range: &IdRange) -> bool {
+        if !self.range.is_adjacent(range) || self.range.intersect(range).is_some() {
+            return false;
+        }
+
+        self.range = IdRange::new(
+            range.client,
+            self.range.start.min(range.start),
+            self.range.end.max(range.end),
+        );
+        true
+    }
+
     pub(crate) fn adjust(&self, before: &ClientMap, after: &ClientMap) -> DeleteItem {
         let mut adjust = self.clone();
 
@@ -78,6 +92,8 @@ impl WithId for DeleteItem {
 #[cfg(test)]
 mod tests {
     use crate::codec_v1::EncoderV1;
+    use crate::doc::Doc;
+    use crate::types::Type;
 
     use super::*;
 
@@ -102,4 +118,40 @@ mod tests {
         assert_eq!(d2, dd2);
         assert_eq!(d3, dd3);
     }
+
+    #[test]
+    fn test_coalesce_backspace_run() {
+        let doc = Doc::default();
+        let text = doc.text();
+        doc.set("text", text.clone());
+        let chars: Vec<Type> = ["a", "b", "c", "d"]
+            .map(|c| Type::from(doc.string(c)))
+            .into();
+        for c in &chars {
+            text.append(c.clone());
+        }
+        doc.commit();
+        let before = doc.store.borrow().deletes.size();
+
+        // backspace from the end, then a delete in a change of its own
+        chars[3].delete();
+        chars[2].delete();
+        chars[1].delete();
+        doc.commit();
+        chars[0].delete();
+        doc.commit();
+
+        let deletes: Vec<DeleteItem> = doc
+            .store
+            .borrow()
+            .deletes
+            .iter()
+            .flat_map(|(_, store)| store.iter().map(|(_, item)| item.clone()))
+            .collect();
+        assert_eq!(deletes.len() as u32, before + 2);
+        assert!(deletes
+            .iter()
+            .any(|d| d.target() == chars[1].id() && d.range().size() == 3));
+        assert_eq!(text.text_content(), "");
+    }
 }
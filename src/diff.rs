@@ -3,6 +3,7 @@ use crate::change::{ChangeData, ChangeId, ChangeStore};
 use crate::decoder::{Decode, DecodeContext, Decoder};
 use crate::doc::DocId;
 use crate::encoder::{Encode, EncodeContext, Encoder};
+use crate::features::FeatureSet;
 use crate::id::{Id, IdRange, WithId, WithIdRange};
 use crate::item::{ItemData, Optimize};
 use crate::state::ClientState;
@@ -16,6 +17,11 @@ use std::cell::RefMut;
 use std::cmp::max;
 use std::ops::Add;
 
+/// Diff holds the items, deletes and changes missing from a document state.
+///
+/// The features of the diff are encoded only when there are some, so that the diffs of
+/// documents without features keep the older layout. The decoder reads them from the bytes
+/// left after the changes, an encoded diff must be the last value of its buffer.
 #[derive(Debug, Clone, Default, Eq, PartialEq)]
 pub struct Diff {
     pub created_by: Client,
@@ -25,6 +31,7 @@ pub struct Diff {
     pub changes: ChangeStore,
     pub items: ItemDataStore,
     pub deletes: DeleteItemStore,
+    pub features: FeatureSet,
 }
 
 impl Diff {
@@ -66,6 +73,7 @@ impl Diff {
             changes,
             items,
             deletes,
+            features: FeatureSet::default(),
         }
     }
 
@@ -167,6 +175,7 @@ impl Diff {
             changes: self.changes.clone(),
             items: self.items.diff(state),
             deletes: self.deletes.diff(state),
+            features: self.features.clone(),
         }
     }
 
@@ -214,25 +223,21 @@ impl Diff {
             }
         }
 
-        // drop the changes which are already integrated to the document
-        let mut changes = self.changes.clone();
-        self.changes.iter().for_each(|(id, change_store)| {
-            change_store.iter().for_each(|change| {
-                if store.changes.contains(&change.id()) {
-                    changes.remove(&change.id());
-                }
-            })
-        });
+        // the changes are kept in the client ids of the document like the items
+        let changes = self.changes.adjust(&self.state.clients, &state.clients);
 
-        Diff::from(
+        let mut diff = Diff::from(
             self.doc_id.clone(),
             self.created_by.clone(),
             fields.clone(),
-            self.changes.clone(),
+            changes,
             state.clone(),
             items,
             deletes,
-        )
+        );
+        diff.features = self.features.clone();
+
+        diff
     }
 
     // adjust the diff to the current state of the store
@@ -269,19 +274,26 @@ impl Diff {
             }
         }
 
-        Diff::from(
+        let changes = self.changes.adjust(&self.state.clients, &state.clients);
+
+        let mut diff = Diff::from(
             self.doc_id.clone(),
             self.created_by.clone(),
             fields,
-            self.changes.clone(),
+            changes,
             state,
             items,
             deletes,
-        )
+        );
+        diff.features = self.features.clone();
+
+        diff
     }
 
-    // merge two diffs together into self
-    pub fn merge(&mut self, other: &Diff) {
+    /// Merge two diffs of the same document without a document, e.g. to coalesce the diffs
+    /// a server received. The other diff is mapped onto the client and field ids of this
+    /// one, ids present in both diffs are kept once.
+    pub fn merge(&self, other: &Diff) -> Diff {
         if self.doc_id != other.doc_id {
             panic!("cannot merge diffs with different doc ids");
         }
@@ -289,10 +301,36 @@ impl Diff {
             panic!("cannot merge diffs with different created_by");
         }
 
-        self.fields = self.fields.merge(&other.fields);
-        self.state = self.state.merge(&other.state);
-        self.items = self.items.merge(&other.items);
-        self.deletes = self.deletes.merge(&other.deletes);
+        let adjusted = other.adjust_diff(self);
+        let fields = self.fields.merge(&adjusted.fields);
+        let state = self.state.merge(&adjusted.state);
+
+        let mut items = self.items.clone();
+        for (_, store) in adjusted.items.iter() {
+            for (_, item) in store.iter() {
+                items.insert_missing(item.clone());
+            }
+        }
+        let deletes = self.deletes.merge(&adjusted.deletes);
+
+        let mut changes = self.changes.clone();
+        for (_, store) in adjusted.changes.iter() {
+            store.iter().for_each(|change| changes.insert(*change));
+        }
+
+        let mut diff = Diff::from(
+            self.doc_id.clone(),
+            self.created_by.clone(),
+            fields,
+            changes,
+            state,
+            items,
+            deletes,
+        );
+        diff.features = self.features.clone();
+        diff.features.extend(&other.features);
+
+        diff
     }
 
     /// optimize the diff for storage
@@ -303,6 +341,27 @@ impl Diff {
             }
         }
     }
+
+    // the optimized diff drops the parent of items with an origin, follow the origins
+    // through the diff until an item with a parent or a stored item is found
+    pub(crate) fn parent_id(&self, store: &DocStore, data: &ItemData) -> Option<Id> {
+        let mut data = data.clone();
+        loop {
+            if data.parent_id.is_some() {
+                return data.parent_id;
+            }
+
+            let origin = data.left_id.or(data.right_id)?;
+            if let Some(item) = store.find(&origin) {
+                return item.parent_id();
+            }
+
+            // the origin may point inside a string run of the diff
+            let items = self.items.id_store(&origin.client)?.iter();
+            let item = items.take_while(|(id, _)| id.clock <= origin.clock).last();
+            data = item?.1.clone();
+        }
+    }
 }
 
 impl Serialize for Diff {
@@ -310,7 +369,7 @@ impl Serialize for Diff {
     where
         S: Serializer,
     {
-        let mut s = serializer.serialize_struct("Diff", 7)?;
+        let mut s = serializer.serialize_struct("Diff", 8)?;
         s.serialize_field("doc_id", &self.doc_id)?;
         s.serialize_field("created_by", &self.created_by)?;
         s.serialize_field("fields", &self.fields)?;
@@ -318,6 +377,7 @@ impl Serialize for Diff {
         s.serialize_field("changes", &self.changes)?;
         s.serialize_field("deletes", &self.deletes)?;
         s.serialize_field("items", &self.items)?;
+        s.serialize_field("features", &self.features)?;
         s.end()
     }
 }
@@ -331,6 +391,11 @@ impl Encode for Diff {
         self.deletes.encode(e, cx);
         self.items.encode(e, cx);
         self.changes.encode(e, cx);
+        // diffs without features encode as before the feature flags, a diff is the last
+        // value of its buffer
+        if !self.features.is_empty() {
+            self.features.encode(e, cx);
+        }
     }
 }
 
@@ -343,6 +408,10 @@ impl Decode for Diff {
         let deletes = DeleteItemStore::decode(d, ctx)?;
         let items = ItemDataStore::decode(d, ctx)?;
         let changes = ChangeStore::decode(d, ctx)?;
+        let features = match d.remaining() {
+            0 => FeatureSet::default(),
+            _ => FeatureSet::decode(d, ctx)?,
+        };
 
         Ok(Diff {
             doc_id,
@@ -352,6 +421,7 @@ impl Decode for Diff {
             state,
             deletes,
             items,
+            features,
         })
     }
 }
@@ -388,4 +458,66 @@ mod test {
 
         assert_eq!(diff, decoded);
     }
+
+    #[test]
+    fn test_encode_decode_diff_features() {
+        let doc = Doc::default();
+        doc.set("k1", doc.atom("fe"));
+        let mut diff = doc.diff(ClientState::default());
+
+        let encode = |diff: &Diff| {
+            let mut encoder = EncoderV1::default();
+            diff.encode(&mut encoder, &mut Default::default());
+            encoder
+        };
+
+        // a diff without features keeps the layout of the older diffs
+        let plain = encode(&diff);
+        diff.features.insert("moves");
+        let mut flagged = encode(&diff);
+        assert!(plain.size() < flagged.size());
+        assert!(flagged.buffer().starts_with(&plain.buffer()));
+
+        let decoded = Diff::decode(&mut flagged.decoder(), &Default::default()).unwrap();
+        assert_eq!(decoded, diff);
+        assert!(decoded.features.contains("moves"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doc::{CloneDeep, Doc};
+
+    use super::*;
+
+    #[test]
+    fn test_merge_diffs() {
+        let doc = Doc::default();
+        let text = doc.text();
+        doc.set("text", text.clone());
+        text.append(doc.string("hello"));
+        doc.commit();
+
+        // the second client splits the string of the first one
+        let doc2 = doc.clone_deep();
+        doc2.update_client();
+        doc2.get("text").unwrap().insert(2, doc2.string("y"));
+        doc2.commit();
+
+        let left = doc.diff(ClientState::default());
+        let right = doc2.diff(ClientState::default());
+        for merged in [left.merge(&right), right.merge(&left)] {
+            assert_eq!(Doc::from(&merged).unwrap(), doc2);
+        }
+
+        // an incremental diff of a third client
+        let doc3 = doc2.clone_deep();
+        doc3.update_client();
+        doc3.set("key", doc3.atom("value"));
+        doc3.commit();
+
+        let merged = left.merge(&right).merge(&doc3.diff(doc2.state()));
+        assert_eq!(Doc::from(&merged).unwrap(), doc3);
+        assert_eq!(merged.changes.iter().count(), 3);
+    }
 }
@@ -44,8 +44,7 @@ impl DiffStore for InMemoryDiffStore {
         let old_diff = self.diffs.get_mut(&doc_id);
         match old_diff {
             Some(old_diff) => {
-                let adjusted = diff.adjust_diff(&old_diff);
-                old_diff.merge(&adjusted);
+                *old_diff = old_diff.merge(&diff);
             }
             None => {
                 self.diffs.insert(doc_id, diff);
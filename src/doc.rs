@@ -5,24 +5,33 @@ use serde_json::Value;
 use std::cell::RefCell;
 use std::collections::VecDeque;
 use std::rc::Rc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use uuid::{Timestamp, Uuid};
 
+use crate::activity::ClientActivity;
+use crate::apply_stats::ApplyStats;
+use crate::bimapid::ClientId;
+use crate::cbor::{build_doc, CborDecoder, CborEncoder};
 use crate::change::{sort_changes, ChangeData, ChangeId, ChangeStore};
 use crate::cycle::creates_cycle;
 use crate::dag::{ChangeNode, ChangeNodeFlags};
 use crate::decoder::{Decode, DecodeContext, Decoder};
 use crate::diff::Diff;
 use crate::encoder::{Encode, EncodeContext, Encoder};
-use crate::id::{Id, WithId, WithTarget};
+use crate::features::{is_supported, FeatureSet, MergeStrategy, COUNTERS, PROXIES};
+use crate::id::{Id, IdRange, WithId, WithTarget};
 use crate::item::{Content, DocProps, ItemKey};
 use crate::json::JsonDoc;
 use crate::mark::Mark;
 use crate::natom::NAtom;
+use crate::ncounter::NCounter;
 use crate::nlist::NList;
 use crate::nmap::NMap;
+use crate::nproxy::NProxy;
 use crate::nstring::NString;
 use crate::ntext::NText;
+use crate::schema::QuarantinedDiff;
+use crate::snapshot::DocSnapshot;
 use crate::state::ClientState;
 use crate::store::{DocStore, StoreRef};
 use crate::tx::Tx;
@@ -49,6 +58,7 @@ impl Doc {
 
         store.doc_id = opts.id.clone();
         store.created_by = opts.crated_by.clone();
+        store.features = opts.features.clone();
 
         // doc is always created by the client with clock 0,
         // so we need to increment the clock for next client items
@@ -61,7 +71,9 @@ impl Doc {
         let weak = Rc::downgrade(&store_ref);
         let root = NMap::new(root_id, weak);
 
-        root.set_content(DocProps::new(opts.id.clone(), opts.crated_by.clone()));
+        let mut props = DocProps::new(opts.id.clone(), opts.crated_by.clone());
+        props.features = opts.features.clone();
+        root.set_content(props);
 
         store_ref.borrow_mut().insert(root.clone());
 
@@ -85,11 +97,20 @@ impl Doc {
     pub fn from(diff: &Diff) -> Option<Doc> {
         if let Some(root) = &diff.get_root() {
             if let Content::Doc(content) = &root.content {
+                let mut features = content.features.clone();
+                features.extend(&diff.features);
+                if let Err(err) = features.check() {
+                    log::warn!("cannot create document {:?}: {}", content.id, err);
+                    return None;
+                }
+
                 let doc = Doc::new(DocMeta {
                     id: content.id.clone(),
                     created_at: content.created_at,
                     crated_by: content.created_by.clone().into(),
                     props: content.props.clone().into_kv_map(),
+                    strategy: MergeStrategy::default(),
+                    features,
                 });
 
                 doc.apply(&diff);
@@ -108,6 +129,7 @@ impl Doc {
     }
 
     pub(crate) fn state(&self) -> ClientState {
+        self.hydrate(None);
         let store = self.store.borrow();
 
         let state = &store.state;
@@ -117,6 +139,7 @@ impl Doc {
     /// Create a new document diff from the current document and the given ClientState
     #[inline]
     pub fn diff(&self, state: impl Into<ClientState>) -> Diff {
+        self.hydrate(None);
         let mut diff = self.store.borrow().diff(
             self.meta.id.clone(),
             self.meta.crated_by.clone(),
@@ -127,20 +150,118 @@ impl Doc {
         diff
     }
 
-    /// Apply a diff to the document from remote client
-    pub fn apply(&self, diff: &Diff) {
+    /// Features used by the document
+    pub fn features(&self) -> FeatureSet {
+        self.store.borrow().features.clone()
+    }
+
+    /// Algorithm the document integrates concurrent inserts with
+    pub fn strategy(&self) -> MergeStrategy {
+        self.meta.strategy
+    }
+
+    // a diff made under another strategy would order the items differently on this replica
+    fn check_strategy(&self, diff: &Diff) -> Result<(), String> {
+        let (strategy, local) = (MergeStrategy::of(&diff.features)?, self.strategy());
+        if strategy != local {
+            return Err(format!(
+                "diff merges with {:?}, the document merges with {:?}",
+                strategy, local
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Mark the document as using a feature, the feature must be supported by the local build.
+    /// The feature is sent along with the next diffs so that remote sites can refuse them
+    /// instead of corrupting their state.
+    pub fn enable_feature(&self, feature: impl Into<String>) -> Result<(), String> {
+        let feature = feature.into();
+        if !is_supported(&feature) {
+            return Err(format!("unsupported document feature: {}", feature));
+        }
+
+        self.store_mut("enable_feature").features.insert(feature);
+
+        Ok(())
+    }
+
+    // mark the document as holding content of a supported feature
+    pub(crate) fn use_feature(&self, feature: &str) {
+        self.store_mut("use_feature").features.insert(feature);
+    }
+
+    /// Apply a diff to the document, fails if the diff uses features the local build does not support
+    pub fn try_apply(&self, diff: &Diff) -> Result<(), String> {
+        diff.features.check()?;
+        self.check_strategy(diff)?;
+        self.apply(diff);
+
+        Ok(())
+    }
+
+    /// Apply a diff to the document from remote client, returns what the apply did
+    pub fn apply(&self, diff: &Diff) -> ApplyStats {
+        let now = Instant::now();
+        // remote changes may refer to any entry of a lazily loaded document
+        self.hydrate(None);
+
+        // applying unknown features could corrupt the document, drop the diff instead
+        if let Err(err) = diff
+            .features
+            .check()
+            .and_then(|_| self.check_strategy(diff))
+        {
+            log::warn!("ignoring diff for document {:?}: {}", diff.doc_id, err);
+            return ApplyStats::rejected(err);
+        }
+
+        let limits = self.store.borrow().diff_limits;
+        if let Err(err) = limits.check_diff(diff) {
+            log::warn!("ignoring diff for document {:?}: {}", diff.doc_id, err);
+            return ApplyStats::over_limit(err);
+        }
+
+        self.store_mut("apply").features.extend(&diff.features);
+
         // adjust the diff to the current state of the document
-        let mut diff = {
-            let store_ref = self.store.borrow_mut();
+        let adjusted = {
+            let store_ref = self.store_mut("apply");
             diff.adjust(&store_ref)
         };
 
+        // keep the document structurally sound, violating diffs are left to the host
+        if let Err(errors) = self.validate_diff(&adjusted) {
+            log::warn!(
+                "quarantined diff for document {:?}: {:?}",
+                diff.doc_id,
+                errors
+            );
+            self.store_mut("apply").quarantine.push(QuarantinedDiff {
+                diff: diff.clone(),
+                errors,
+            });
+            return ApplyStats::rejected("schema violation");
+        }
+
+        let mut diff = adjusted;
+        let mut undo_steps = 0;
+        let mut redo_steps = 0;
+        let mut redo = Vec::new();
+
         {
-            let mut store = self.store.borrow_mut();
+            let mut store = self.store_mut("apply");
+            let local = store.state.clone();
+            store.activity.record(&diff, &local);
+            store.warm_diff(&diff);
+
             store.fields.extend(&diff.fields);
             store.state.clients.extend(&diff.state.clients);
 
             let (mut changes, mut movers) = diff.changes();
+            // the known changes are already connected to the change dag
+            changes.retain(|id, _| !store.changes.contains(&id.id()));
             // println!("changes: {:?}", changes);
             // println!("movers: {:?}", movers);
 
@@ -153,14 +274,18 @@ impl Doc {
             let clients = &store.state.clients.clone();
             let mut parents = HashMap::new();
 
-            // find parents for each change
-            for (_, change) in &changes {
+            // find parents for each change, the changes of a client are kept in clock order
+            let mut ordered = changes.iter().collect::<Vec<_>>();
+            ordered.sort_by_key(|(id, _)| **id);
+            for (_, change) in ordered {
                 // println!("change_id: {:?}, deps: {:?}", change.id, change.deps);
                 let parent_change_ids: Vec<ChangeId> = change
                     .deps
                     .iter()
                     .filter(|id| !change.id.contains(id)) // filter out the self dependency
-                    .map(|id| store.changes.get(id).cloned().unwrap()) // find the parent change IDs
+                    // find the parent change IDs, items copied before their change was
+                    // committed have no change and do not order the change
+                    .filter_map(|id| store.changes.get(id).cloned())
                     .collect::<HashSet<_>>()
                     .into_iter()
                     .collect::<Vec<_>>();
@@ -184,7 +309,6 @@ impl Doc {
                 store.changes.remove(&change_id.id());
             });
 
-            let mut redo = Vec::new();
             let mut undo_movers = Vec::new();
 
             if !movers.is_empty() {
@@ -192,6 +316,7 @@ impl Doc {
                 // undo the changes until we undo all diff movers
                 while !movers.is_empty() {
                     if let Some((undo_change_id, flag)) = store.dag.undo(clients) {
+                        undo_steps += 1;
                         movers.remove(&undo_change_id);
 
                         if change_ids.remove(&undo_change_id) {
@@ -234,57 +359,98 @@ impl Doc {
             }
 
             // NOTE: this section is active only when there was mover item in undo-changes and in current change
-            // do - redo the changes
-            // redo the changes that were undone
+            // redo the changes that were undone, the movers are re-applied once the items
+            // of the diff are integrated
             for change_id in &redo {
-                // if this change was undone, and it includes a mover, we need to re-apply the mover
-                // if applying the mover creates cycle, we need to skip it
-                if undo_movers.contains(change_id) {
-                    for mover in store.movers.get_by_range(*change_id) {
-                        match (mover.item_ref().get_target(), mover.parent()) {
-                            (Some(target), Some(parent)) => {
-                                if !creates_cycle(&parent, &target) {
-                                    store.add_mover(target.id(), mover);
-                                }
-                            }
-                            _ => todo!(),
-                        }
-                    }
-                } else {
-
-                    // do integrate the items from new change
-                    // println!("redo: {:?}", change_id);
-                }
-
                 store.changes.insert(change_id.clone());
             }
 
             change_ids.iter().for_each(|change_id| {
                 store.changes.insert(*change_id.clone());
-            })
+            });
+
+            redo_steps = redo.len();
         }
 
-        {
-            // TODO: for now we just apply the changes using a transaction, the changes are not used yet
-            let mut tx = Tx::new(Rc::downgrade(&self.store.clone()), diff);
-            tx.commit();
+        let changed: Vec<(Id, ClientId)> = if !self.store.borrow().tracks_changes() {
+            vec![]
+        } else {
+            // changed ids with the client that made the change
+            let items = diff
+                .items
+                .iter()
+                .flat_map(|(_, s)| s.iter().map(|(id, _)| (*id, id.client)));
+            let deletes = diff
+                .deletes
+                .iter()
+                .flat_map(|(_, s)| s.iter().map(|(id, d)| (d.target(), id.client)));
+            items.chain(deletes).collect()
+        };
+
+        // TODO: for now we just apply the changes using a transaction, the changes are not used yet
+        let mut tx = Tx::new(Rc::downgrade(&self.store.clone()), diff);
+        tx.commit();
+        self.redo_movers(&redo, tx.integrated());
+
+        let mut stats = tx.stats();
+        stats.undo_steps = undo_steps;
+        stats.redo_steps = redo_steps;
+        stats.finish(now.elapsed());
+        self.bound_pending(&mut stats);
+
+        let ids: Vec<Id> = changed.iter().map(|(id, _)| *id).collect();
+        self.store_mut("apply").invalidate_checksums(&ids);
+        self.store_mut("apply").invalidate_line_indexes();
+        self.store_mut("apply").invalidate_offset_indexes();
+        self.record_history();
+        self.notify_paths(changed, false);
+        self.assert_invariants("apply");
+
+        stats
+    }
+
+    // re-apply the movers of the redone changes in change order and the other integrated
+    // movers after them, the stack of each target keeps its movers in the same order on every
+    // replica. A mover that would create a cycle stays inactive.
+    fn redo_movers(&self, redo: &[ChangeId], integrated: &[Type]) {
+        let mut store = self.store_mut("redo_movers");
+        let mut movers = vec![];
+        for change_id in redo.iter().rev() {
+            movers.extend(store.movers.get_by_range(*change_id));
+        }
+        let redone: HashSet<Id> = movers.iter().map(|mover| mover.id()).collect();
+        movers.extend(
+            integrated
+                .iter()
+                .filter(|item| item.kind().is_move() && !redone.contains(&item.id()))
+                .cloned(),
+        );
+
+        for mover in movers {
+            match (mover.item_ref().get_target(), mover.parent()) {
+                (Some(target), Some(parent)) if !creates_cycle(&parent, &target) => {
+                    target.item_ref().mark_moved();
+                    store.add_mover(target.id(), mover);
+                }
+                _ => mover.item_ref().mark_inactive(),
+            }
         }
     }
 
     /// Create a new list type in the document
     pub fn list(&self) -> NList {
-        let id = self.store.borrow_mut().next_id();
+        let id = self.store_mut("list").next_id();
         let list = NList::new(id, Rc::downgrade(&self.store));
-        self.store.borrow_mut().insert(list.clone());
+        self.store_mut("list").insert(list.clone());
 
         list
     }
 
     /// Create a new map type in the document
     pub fn map(&self) -> NMap {
-        let id = self.store.borrow_mut().next_id();
+        let id = self.store_mut("map").next_id();
         let map = NMap::new(id, Rc::downgrade(&self.store));
-        self.store.borrow_mut().insert(map.clone());
+        self.store_mut("map").insert(map.clone());
 
         map
     }
@@ -292,15 +458,41 @@ impl Doc {
     /// Create a new atom type in the document
     pub fn atom(&self, content: impl Into<Content>) -> NAtom {
         let atom = NAtom::new(self.next_id(), content.into(), Rc::downgrade(&self.store));
-        self.store.borrow_mut().insert(atom.clone());
+        self.store_mut("atom").insert(atom.clone());
 
         atom
     }
 
+    /// Create a new counter in the document, see `NCounter`
+    pub fn counter(&self) -> NCounter {
+        let counter = NCounter::new(self.next_id(), Rc::downgrade(&self.store));
+        self.store_mut("counter").insert(counter.clone());
+        self.use_feature(COUNTERS);
+
+        counter
+    }
+
+    /// Create a new proxy referencing the target, see `NProxy`
+    pub fn proxy(&self, target: &Type) -> NProxy {
+        let proxy = NProxy::new(self.next_id(), target, Rc::downgrade(&self.store));
+        self.store_mut("proxy").insert(proxy.clone());
+        self.use_feature(PROXIES);
+
+        proxy
+    }
+
     /// Create a new text type in the document
     pub fn text(&self) -> NText {
         let text = NText::new(self.next_id(), Rc::downgrade(&self.store));
-        self.store.borrow_mut().insert(text.clone());
+        self.store_mut("text").insert(text.clone());
+
+        text
+    }
+
+    /// Create a new plain text type in the document, see `NText::upgrade_from_plaintext`
+    pub fn plain_text(&self) -> NText {
+        let text = NText::new_plain(self.next_id(), Rc::downgrade(&self.store));
+        self.store_mut("plain_text").insert(text.clone());
 
         text
     }
@@ -309,41 +501,149 @@ impl Doc {
     pub fn string(&self, value: impl Into<String>) -> NString {
         let content = value.into();
         let id = self
-            .store
-            .borrow_mut()
+            .store_mut("string")
             .next_id_range(content.len() as ClockTick)
             .start_id();
         let string = NString::new(id, content, Rc::downgrade(&self.store));
-        self.store.borrow_mut().insert(string.clone());
+        self.store_mut("string").insert(string.clone());
 
         string
     }
 
     /// Create a new change in the document
     pub fn commit(&self) {
-        self.store.borrow_mut().commit();
+        // a commit from a listener or visitor waits for the running callbacks
+        if self.in_callback() {
+            self.defer(|doc| doc.commit());
+            return;
+        }
+        if let Err(errors) = self.try_commit() {
+            log::warn!("rolled back a change violating the schema: {:?}", errors);
+        }
+    }
+
+    /// Commit the pending change if it holds to the document schema, a violating change is
+    /// rolled back and the violations are returned
+    pub fn try_commit(&self) -> Result<(), Vec<String>> {
+        if let Err(errors) = self.validate_pending() {
+            self.rollback();
+            return Err(errors);
+        }
+
+        let (changed, before) = {
+            let mut store = self.store_mut("commit");
+            let range = IdRange::new(store.client, store.commited_clock, store.clock);
+            // version without the change, the update listeners get the change only
+            let before = (!store.update_listeners.is_empty() && store.commited_clock < store.clock)
+                .then(|| {
+                    let mut state = store.state.clone();
+                    let clock = store.commited_clock.saturating_sub(1);
+                    state.state.update(store.client, clock);
+                    state
+                });
+            store.commit();
+
+            let changed = store.changed_ids(range);
+            store.invalidate_checksums(&changed);
+            let client = store.client;
+            let changed = changed
+                .into_iter()
+                .map(|id| (id, client))
+                .collect::<Vec<_>>();
+            (changed, before)
+        };
+
+        self.record_history();
+        self.notify_paths(changed, true);
+        if let Some(before) = before {
+            self.emit_update(before);
+        }
+        self.assert_invariants("commit");
+
+        Ok(())
     }
 
     /// Remove the uncommited change from the document
     pub fn rollback(&self) {
-        self.store.borrow_mut().rollback()
+        let mut store = self.store_mut("rollback");
+        store.rollback();
+        store.invalidate_line_indexes();
+        store.invalidate_offset_indexes();
+    }
+
+    /// Limit the size of string items inserted into texts, larger strings are split into chunks.
+    /// `None` disables chunking.
+    pub fn set_max_string_size(&self, size: Option<u32>) {
+        self.store_mut("set_max_string_size").max_string_size = size.filter(|size| *size > 0);
+    }
+
+    #[inline]
+    pub fn max_string_size(&self) -> Option<u32> {
+        self.store.borrow().max_string_size
+    }
+
+    /// Split the existing text strings larger than the max string size,
+    /// returns the number of splits. Splitting does not create new changes.
+    pub fn normalize_strings(&self) -> usize {
+        let max = match self.max_string_size() {
+            Some(max) => max,
+            None => return 0,
+        };
+
+        let strings = {
+            let store = self.store.borrow();
+            store
+                .items
+                .iter()
+                .flat_map(|(_, items)| items.iter())
+                .filter_map(|(_, item)| match item {
+                    Type::String(s) if s.size() > max && item.is_visible() => Some(s.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        strings.iter().map(|s| s.chunk(max)).sum()
+    }
+
+    /// Activity counters of the remote clients collected while applying diffs
+    pub fn client_activity(&self) -> HashMap<Client, ClientActivity> {
+        self.store.borrow().activity.all()
+    }
+
+    /// Activity counters of a single remote client
+    pub fn client_activity_of(&self, client: &Client) -> Option<ClientActivity> {
+        self.store.borrow().activity.get(client).cloned()
+    }
+
+    /// Reset the client activity counters, e.g. at the start of a new rate limit window
+    pub fn reset_client_activity(&self) {
+        self.store_mut("reset_client_activity").activity.clear();
     }
 
     /// Find an item by its ID
     pub fn find_by_id(&self, id: &Id) -> Option<Type> {
+        self.hydrate(None);
+        self.store_mut("find_by_id").thaw_id(id);
         self.store.borrow().find(id)
     }
 
     /// Update the current client ID with a new one
     pub fn update_client(&self) -> Client {
         let client_id = Uuid::new_v4().into();
-        self.store.borrow_mut().update_client(&client_id, 1);
+        self.store_mut("update_client").update_client(&client_id, 1);
 
         client_id
     }
 
+    /// Number of clock ticks the current client can allocate before the document
+    /// switches to a new client to avoid clock overflow
+    pub fn clock_headroom(&self) -> ClockTick {
+        self.store.borrow().clock_headroom()
+    }
+
     fn next_id(&self) -> Id {
-        self.store.borrow_mut().next_id()
+        self.store_mut("next_id").next_id()
     }
 
     pub fn changes(&self) -> ChangeStore {
@@ -388,6 +688,7 @@ impl Doc {
     }
 
     pub fn version(&self) -> ClientState {
+        self.hydrate(None);
         self.store.borrow().state.clone()
     }
 
@@ -419,6 +720,24 @@ impl Doc {
 
         serde_json::Value::Object(map)
     }
+
+    /// Take an immutable snapshot of the visible document tree that can be sent to other threads
+    pub fn snapshot(&self) -> DocSnapshot {
+        DocSnapshot::new(self)
+    }
+
+    /// Export the visible document tree as CBOR, binary content is kept as byte strings
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let mut encoder = CborEncoder::new();
+        encoder.doc(self);
+        encoder.finish()
+    }
+
+    /// Create a new document from CBOR bytes, the top level value must be a map
+    pub fn from_cbor(bytes: &[u8]) -> Result<Doc, String> {
+        let value = CborDecoder::new(bytes).value()?;
+        build_doc(value)
+    }
 }
 
 impl Default for Doc {
@@ -427,6 +746,17 @@ impl Default for Doc {
     }
 }
 
+impl Drop for Doc {
+    fn drop(&mut self) {
+        // the last handle releases the items, user held types are detached
+        if Rc::strong_count(&self.store) == 1 {
+            if let Ok(mut store) = self.store.try_borrow_mut() {
+                store.unlink_items();
+            }
+        }
+    }
+}
+
 impl From<Doc> for ClientState {
     fn from(value: Doc) -> Self {
         value.state()
@@ -496,6 +826,9 @@ pub struct DocMeta {
     pub created_at: u64,
     pub crated_by: Client,
     pub props: HashMap<String, String>,
+    pub features: FeatureSet,
+    /// algorithm ordering concurrent inserts, the same on every replica
+    pub strategy: MergeStrategy,
 }
 
 impl DocMeta {
@@ -505,6 +838,8 @@ impl DocMeta {
             created_at: Self::now(),
             crated_by: created_by,
             props: HashMap::new(),
+            features: FeatureSet::default(),
+            strategy: MergeStrategy::default(),
         }
     }
 
@@ -515,6 +850,8 @@ impl DocMeta {
             created_at: Self::now(),
             crated_by: created_by,
             props: HashMap::new(),
+            features: FeatureSet::default(),
+            strategy: MergeStrategy::default(),
         }
     }
 
@@ -524,6 +861,11 @@ impl DocMeta {
             .unwrap()
             .as_secs()
     }
+
+    pub fn with_strategy(mut self, strategy: MergeStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
 }
 
 impl Default for DocMeta {
@@ -534,6 +876,8 @@ impl Default for DocMeta {
             created_at: Self::now(),
             crated_by: client_id,
             props: HashMap::new(),
+            features: FeatureSet::default(),
+            strategy: MergeStrategy::default(),
         }
     }
 }
@@ -615,8 +959,9 @@ mod test {
     use rand::random;
 
     use crate::codec_v1::EncoderV1;
-    use crate::doc::{CloneDeep, Doc};
+    use crate::doc::{CloneDeep, Doc, DocMeta};
     use crate::encoder::{Encode, Encoder};
+    use crate::features::MergeStrategy;
     use crate::state::ClientState;
 
     #[test]
@@ -806,4 +1151,69 @@ mod test {
         assert_eq!(a1.depth(), 2);
         assert_eq!(a3.depth(), 3);
     }
+
+    #[test]
+    fn test_reject_unsupported_features() {
+        let d1 = Doc::default();
+        d1.set("a", d1.atom("a"));
+        d1.commit();
+
+        assert!(d1.enable_feature("marks").is_ok());
+        assert!(d1.enable_feature("moves-v2").is_err());
+
+        let mut diff = d1.diff(ClientState::default());
+        assert!(diff.features.contains("marks"));
+
+        let d2 = Doc::from(&diff).unwrap();
+        assert!(d2.features().contains("marks"));
+
+        diff.features.insert("moves-v2");
+        let d3 = Doc::new(d1.meta.clone());
+        assert!(d3.try_apply(&diff).is_err());
+        assert!(d3.get("a").is_none());
+    }
+
+    #[test]
+    fn test_content_enables_features() {
+        let d1 = Doc::default();
+        d1.set("a", d1.atom("a"));
+        d1.commit();
+        assert!(d1.features().is_empty());
+
+        let counter = d1.counter();
+        d1.set("likes", counter.clone());
+        d1.set("link", d1.proxy(&counter.into()));
+        d1.xml_fragment("body").unwrap();
+        d1.commit();
+
+        // the diffs carry the features, a site without them refuses the content
+        let diff = d1.diff(ClientState::default());
+        for feature in ["counters", "proxies", "xml-types"] {
+            assert!(diff.features.contains(feature));
+        }
+        let d2 = Doc::new(d1.meta.clone());
+        assert!(d2.try_apply(&diff).is_ok());
+        assert_eq!(d2.features(), d1.features());
+    }
+
+    #[test]
+    fn test_merge_strategy_is_agreed() {
+        let d1 = Doc::new(DocMeta::default().with_strategy(MergeStrategy::Yata));
+        d1.set("a", d1.atom("a"));
+        d1.commit();
+        assert_eq!(d1.strategy(), MergeStrategy::Yata);
+
+        let d2 = Doc::new(d1.meta.clone());
+        assert!(d2.try_apply(&d1.diff(ClientState::default())).is_ok());
+
+        // a diff made under a strategy this build can not integrate is refused
+        let mut diff = d1.diff(ClientState::default());
+        diff.features.insert("fugue");
+        assert!(MergeStrategy::of(&diff.features).is_err());
+        let d3 = Doc::new(d1.meta.clone());
+        assert!(d3.try_apply(&diff).is_err());
+        assert!(!d3.apply(&diff).warnings.is_empty());
+        assert!(d3.get("a").is_none());
+        assert!(d1.enable_feature("fugue").is_err());
+    }
 }
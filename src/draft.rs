@@ -0,0 +1,190 @@
+use crate::decoder::{Decode, DecodeContext, Decoder};
+use crate::diff::Diff;
+use crate::doc::{Doc, DocId};
+use crate::encoder::{Encode, EncodeContext, Encoder};
+use crate::id::{ClockTick, Id};
+use crate::Client;
+
+/// Draft is the uncommitted change of a document packaged for another device of the same user.
+///
+/// A draft is not final, the receiving device takes over the client of the draft,
+/// continues editing and commits the draft together with its own edits as a single change.
+/// See `Doc::is_finalized` to check if the draft was committed.
+#[derive(Debug, Clone, Default)]
+pub struct Draft {
+    pub(crate) doc_id: DocId,
+    // client that created the uncommitted items
+    pub(crate) client: Client,
+    // first uncommitted clock tick
+    pub(crate) start: ClockTick,
+    // next clock tick of the client
+    pub(crate) end: ClockTick,
+    pub(crate) diff: Diff,
+}
+
+impl Draft {
+    #[inline]
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Number of clock ticks in the draft
+    #[inline]
+    pub fn size(&self) -> ClockTick {
+        self.end - self.start
+    }
+}
+
+impl Doc {
+    /// Package the uncommitted change as a draft, returns None when there is nothing uncommitted
+    pub fn draft(&self) -> Option<Draft> {
+        let (client, start, end, mut state) = {
+            let store = self.store.borrow();
+            if store.commited_clock == store.clock {
+                return None;
+            }
+
+            let client = store.state.get_client(&store.client)?.clone();
+            (
+                client,
+                store.commited_clock,
+                store.clock,
+                store.state.clone(),
+            )
+        };
+
+        // the diff should only carry the uncommitted items of the local client
+        let client_id = self.store.borrow().client;
+        state.state.update(client_id, start - 1);
+
+        Some(Draft {
+            doc_id: self.id(),
+            client,
+            start,
+            end,
+            diff: self.diff(state),
+        })
+    }
+
+    /// Package the uncommitted change as a draft and hand the client over to the other device.
+    /// The drafted items are rolled back and the local document continues with a new client,
+    /// the items come back with the change of the other device.
+    pub fn hand_off(&self) -> Option<Draft> {
+        let draft = self.draft()?;
+
+        let mut store = self.store_mut("hand_off");
+        store.rollback();
+        store.update_client(&Client::default(), 1);
+
+        Some(draft)
+    }
+
+    /// Check if the draft was committed in the document
+    pub fn is_finalized(&self, draft: &Draft) -> bool {
+        let store = self.store.borrow();
+        store
+            .state
+            .get_client_id(&draft.client)
+            .is_some_and(|client| store.changes.contains(&Id::new(*client, draft.start)))
+    }
+
+    /// Apply a draft from another device and continue the uncommitted change,
+    /// the next commit finalizes the draft and the local edits as a single change.
+    pub fn apply_draft(&self, draft: &Draft) -> Result<(), String> {
+        if draft.doc_id != self.id() {
+            return Err(format!(
+                "draft belongs to document {:?}, expected {:?}",
+                draft.doc_id,
+                self.id()
+            ));
+        }
+
+        {
+            let store = self.store.borrow();
+            if store.commited_clock != store.clock {
+                return Err("can not apply a draft over uncommitted local changes".to_string());
+            }
+        }
+
+        self.try_apply(&draft.diff)?;
+
+        // take over the draft client so that the draft stays open
+        let mut store = self.store_mut("apply_draft");
+        store.update_client(&draft.client, draft.end);
+        store.commited_clock = draft.start;
+
+        Ok(())
+    }
+}
+
+impl Encode for Draft {
+    fn encode<E: Encoder>(&self, e: &mut E, cx: &mut EncodeContext) {
+        self.doc_id.encode(e, cx);
+        self.client.encode(e, cx);
+        e.u32(self.start);
+        e.u32(self.end);
+        self.diff.encode(e, cx);
+    }
+}
+
+impl Decode for Draft {
+    fn decode<D: Decoder>(d: &mut D, ctx: &DecodeContext) -> Result<Draft, String> {
+        let doc_id = DocId::decode(d, ctx)?;
+        let client = Client::decode(d, ctx)?;
+        let start = d.u32()?;
+        let end = d.u32()?;
+        let diff = Diff::decode(d, ctx)?;
+
+        Ok(Draft {
+            doc_id,
+            client,
+            start,
+            end,
+            diff,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doc::CloneDeep;
+
+    use super::*;
+
+    #[test]
+    fn test_draft_hand_off() {
+        let d1 = Doc::default();
+        d1.set("a", d1.atom("a"));
+        d1.commit();
+
+        let d2 = d1.clone_deep();
+        d2.update_client();
+
+        // uncommitted edit on the first device
+        d1.set("b", d1.atom("b"));
+        let draft = d1.hand_off().unwrap();
+        assert!(d1.get("b").is_none());
+
+        d2.apply_draft(&draft).unwrap();
+        assert!(d2.get("b").is_some());
+        assert!(!d2.is_finalized(&draft));
+
+        // continue editing and finalize on the second device
+        d2.set("c", d2.atom("c"));
+        d2.commit();
+        assert!(d2.is_finalized(&draft));
+
+        // the first device gets the drafted items back with the change
+        d1.apply(&d2.diff(d1.version()));
+        assert!(d1.get("b").is_some());
+        assert!(d1.is_finalized(&draft));
+
+        let store = d2.store.borrow();
+        let client_id = store.state.get_client_id(&draft.client).unwrap();
+        let change = *store.changes.id_store(client_id).unwrap().last().unwrap();
+
+        // the draft and the following edit are committed as one change
+        assert_eq!(change.start, draft.start);
+        assert!(change.end >= draft.end);
+    }
+}
@@ -79,9 +79,13 @@ impl Encode for ItemData {
 
 impl<T: Encode> Encode for Option<T> {
     fn encode<E: Encoder>(&self, e: &mut E, ctx: &mut EncodeContext) {
+        // the decoder reads the presence flag first
         match self {
-            Some(value) => value.encode(e, ctx),
-            None => {}
+            Some(value) => {
+                e.u8(1);
+                value.encode(e, ctx);
+            }
+            None => e.u8(0),
         }
     }
 }
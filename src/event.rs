@@ -0,0 +1,188 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::id::{Client, Id, WithId, WithTarget};
+use crate::item::{Content, ItemKind, Linked};
+use crate::mark::Mark;
+use crate::observe::list_index;
+use crate::types::Type;
+
+/// Event describes a change of the value at an observed path.
+///
+/// Indexes and offsets are positions in the document after the change,
+/// text offsets are in bytes like the text api.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum Event {
+    /// a map key was set or removed, `new` is None for a removed key
+    MapSet {
+        key: String,
+        old: Option<Value>,
+        new: Option<Value>,
+    },
+    ListInsert {
+        index: usize,
+        values: Vec<Value>,
+    },
+    ListDelete {
+        index: usize,
+        len: usize,
+    },
+    /// a list item was moved from the `from` position of its source list
+    Move {
+        from: usize,
+        to: usize,
+    },
+    TextInsert {
+        offset: usize,
+        text: String,
+        /// names of the marks of the inserted text
+        marks: Vec<String>,
+    },
+    TextDelete {
+        offset: usize,
+        len: usize,
+    },
+    /// a mark was added to the item at the path, or to a string of the text at the path
+    MarkInsert {
+        name: String,
+        value: Value,
+    },
+    MarkDelete {
+        name: String,
+    },
+}
+
+/// DocEvent is a change delivered to the `Type::observe` and `Doc::observe_deep` listeners
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocEvent {
+    /// slash separated path of the changed container, empty for the root
+    pub path: String,
+    /// id of the changed container
+    pub target: Id,
+    /// client that made the change, None when the client is unknown
+    pub client: Option<Client>,
+    /// true for changes committed by the local client
+    pub local: bool,
+    pub event: Event,
+}
+
+// events of an inserted or deleted item, reported at the path of the item
+// and at the path of the text for strings
+pub(crate) fn item_events(item: &Type) -> Vec<Event> {
+    let Some(parent) = item.parent() else {
+        return vec![];
+    };
+
+    let deleted = item.is_deleted();
+    if let Content::Mark(mark) = item.content() {
+        let (name, value) = mark.key_value_without_range();
+        return match deleted {
+            true => vec![Event::MarkDelete { name }],
+            false => vec![Event::MarkInsert { name, value }],
+        };
+    }
+
+    let event = match parent.kind() {
+        ItemKind::Map => map_event(&parent, item, deleted),
+        ItemKind::List => Some(list_event(&parent, item, deleted)),
+        ItemKind::Text | ItemKind::PlaintText if item.kind() == ItemKind::String => {
+            Some(text_event(&parent, item, deleted))
+        }
+        _ => None,
+    };
+
+    event.into_iter().collect()
+}
+
+fn map_event(map: &Type, item: &Type, deleted: bool) -> Option<Event> {
+    let key = item.field()?;
+    let Type::Map(map) = map else {
+        return None;
+    };
+    let current = map.get(key.as_str());
+
+    let (old, new) = match deleted {
+        true => (Some(item.to_json()), current.map(|v| v.to_json())),
+        // a concurrent insert that lost against the current value changes nothing
+        false if current.as_ref().is_some_and(|v| v.id() != item.id()) => return None,
+        false => (shadowed(item, &key), Some(item.to_json())),
+    };
+
+    (old != new).then_some(Event::MapSet { key, old, new })
+}
+
+// the value of the key before the item was inserted
+fn shadowed(item: &Type, key: &str) -> Option<Value> {
+    let mut current = item.left();
+    while let Some(left) = current {
+        if left.is_visible() && left.field().as_deref() == Some(key) {
+            return Some(left.to_json());
+        }
+        current = left.left();
+    }
+
+    None
+}
+
+fn list_event(list: &Type, item: &Type, deleted: bool) -> Event {
+    let index = list_index(list, item);
+    if deleted {
+        return Event::ListDelete { index, len: 1 };
+    }
+
+    let target = match item.kind() {
+        ItemKind::Move => item.item_ref().get_target(),
+        _ => None,
+    };
+
+    match target.and_then(|target| target.parent().map(|source| (source, target))) {
+        Some((source, target)) => Event::Move {
+            from: list_index(&source, &target),
+            to: index,
+        },
+        None => Event::ListInsert {
+            index,
+            values: vec![item.to_json()],
+        },
+    }
+}
+
+fn text_event(text: &Type, item: &Type, deleted: bool) -> Event {
+    let id = item.id();
+    let offset = text
+        .item_ref()
+        .borrow()
+        .all_items()
+        .iter()
+        .take_while(|i| i.id() != id)
+        .filter(|i| i.is_visible())
+        .map(|i| i.size() as usize)
+        .sum();
+
+    match (deleted, item.content()) {
+        (false, Content::String(content)) => Event::TextInsert {
+            offset,
+            text: content,
+            marks: run_marks(text, item, offset),
+        },
+        _ => Event::TextDelete {
+            offset,
+            len: item.size() as usize,
+        },
+    }
+}
+
+// names of the marks of the string and of the text marks covering it, in name order
+fn run_marks(text: &Type, item: &Type, offset: usize) -> Vec<String> {
+    let mut marks: Vec<String> = item.marks().iter().map(|(_, mark)| mark.key()).collect();
+    if let Type::Text(text) = text {
+        let start = offset as u32;
+        let marked = text.marks_at(start..start + item.size());
+        marks.extend(marked.iter().map(Mark::key));
+    }
+    marks.sort();
+    marks.dedup();
+
+    marks
+}
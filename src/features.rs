@@ -0,0 +1,150 @@
+use std::collections::BTreeSet;
+
+use serde::{Serialize, Serializer};
+
+use crate::decoder::{Decode, DecodeContext, Decoder};
+use crate::encoder::{Encode, EncodeContext, Encoder};
+
+/// Features understood by this build of the library
+pub const SUPPORTED_FEATURES: &[&str] =
+    &["moves", "marks", "richtext", COUNTERS, XML_TYPES, PROXIES];
+
+// features enabled by the documents holding the content, sites that do not know the
+// content refuse the diffs instead of reading it as plain atoms, maps and lists
+pub(crate) const COUNTERS: &str = "counters";
+pub(crate) const XML_TYPES: &str = "xml-types";
+pub(crate) const PROXIES: &str = "proxies";
+
+/// FeatureSet is the set of capabilities a document uses.
+///
+/// The set is stored in the document props and sent along with every diff,
+/// a site refuses to apply a diff that needs a feature it does not support.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct FeatureSet {
+    features: BTreeSet<String>,
+}
+
+impl FeatureSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, feature: impl Into<String>) {
+        self.features.insert(feature.into());
+    }
+
+    #[inline]
+    pub fn contains(&self, feature: &str) -> bool {
+        self.features.contains(feature)
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.features.is_empty()
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.features.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &String> {
+        self.features.iter()
+    }
+
+    pub(crate) fn extend(&mut self, other: &FeatureSet) {
+        self.features.extend(other.features.iter().cloned());
+    }
+
+    /// Features in the set that the local build does not support
+    pub fn unsupported(&self) -> Vec<String> {
+        self.features
+            .iter()
+            .filter(|f| !is_supported(f))
+            .cloned()
+            .collect()
+    }
+
+    /// Check that every feature in the set is supported locally
+    pub(crate) fn check(&self) -> Result<(), String> {
+        let unsupported = self.unsupported();
+        if unsupported.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "unsupported document features: {}",
+                unsupported.join(", ")
+            ))
+        }
+    }
+}
+
+/// MergeStrategy is the algorithm ordering concurrent inserts, all replicas of a document
+/// must integrate with the same one.
+///
+/// A strategy other than the default is kept in the document features, so it is stored in
+/// the document props and sent along with every diff. Only YATA is integrated by this build,
+/// documents and diffs marked with another strategy are refused.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub enum MergeStrategy {
+    #[default]
+    Yata,
+}
+
+// features marking the strategies other than the default one
+const STRATEGY_FEATURES: &[&str] = &["fugue"];
+
+impl MergeStrategy {
+    /// Strategy of the documents using the features, fails for a strategy this build can
+    /// not integrate
+    pub fn of(features: &FeatureSet) -> Result<Self, String> {
+        match STRATEGY_FEATURES.iter().find(|f| features.contains(f)) {
+            Some(feature) => Err(format!("unsupported merge strategy: {}", feature)),
+            None => Ok(MergeStrategy::Yata),
+        }
+    }
+}
+
+#[inline]
+pub fn is_supported(feature: &str) -> bool {
+    SUPPORTED_FEATURES.contains(&feature)
+}
+
+impl<S: Into<String>> FromIterator<S> for FeatureSet {
+    fn from_iter<T: IntoIterator<Item = S>>(iter: T) -> Self {
+        Self {
+            features: iter.into_iter().map(|f| f.into()).collect(),
+        }
+    }
+}
+
+impl Serialize for FeatureSet {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.features.serialize(serializer)
+    }
+}
+
+impl Encode for FeatureSet {
+    fn encode<E: Encoder>(&self, e: &mut E, _cx: &mut EncodeContext) {
+        e.u32(self.features.len() as u32);
+        for feature in self.features.iter() {
+            e.string(feature);
+        }
+    }
+}
+
+impl Decode for FeatureSet {
+    fn decode<D: Decoder>(d: &mut D, _ctx: &DecodeContext) -> Result<FeatureSet, String> {
+        let len = d.u32()?;
+        let mut features = BTreeSet::new();
+        for _ in 0..len {
+            features.insert(d.string()?);
+        }
+
+        Ok(FeatureSet { features })
+    }
+}
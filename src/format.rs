@@ -0,0 +1,139 @@
+use crate::doc::Doc;
+use crate::id::{Id, WithId};
+use crate::item::ItemIterator;
+use crate::mark::Mark;
+use crate::ntext::NText;
+use crate::text_mark::Expand;
+use crate::types::Type;
+
+impl Doc {
+    /// Format the visible characters from the start to the end character inclusive, across
+    /// every text in between in document order. Each text gets a mark over its own
+    /// characters and all marks are committed as a single change, so undo and remote
+    /// replicas see the formatting as one edit.
+    pub fn format_range(&self, start: &Id, end: &Id, mark: Mark) -> Result<(), String> {
+        let mut texts = vec![];
+        collect_texts(&Type::Map(self.root.clone()), &mut texts);
+
+        let position = |id: &Id| {
+            texts
+                .iter()
+                .enumerate()
+                .find_map(|(index, text)| Some((index, char_offset(text, id)?)))
+                .ok_or_else(|| format!("anchor {} is not a visible character", id))
+        };
+        let (first, from) = position(start)?;
+        let (last, to) = position(end)?;
+        if (last, to) < (first, from) {
+            return Err("the end anchor precedes the start anchor".to_string());
+        }
+
+        let mut error = None;
+        self.transact(|tx| {
+            for (index, text) in texts[first..=last].iter().enumerate() {
+                let start = if index == 0 { from } else { 0 };
+                let end = if first + index == last {
+                    to + 1
+                } else {
+                    text.size()
+                };
+                if start >= end {
+                    continue;
+                }
+                if let Err(err) = text.mark(start, end, mark.clone(), Expand::default()) {
+                    error = Some(err);
+                    tx.abort();
+                    return;
+                }
+            }
+        })
+        .map_err(|err| error.take().unwrap_or(err))
+    }
+}
+
+// offset of the visible character with the id in the text
+fn char_offset(text: &NText, id: &Id) -> Option<u32> {
+    let mut offset = 0;
+    for item in text.visible_item_iter() {
+        let start = item.id();
+        let size = item.size();
+        if start.client == id.client && start.clock <= id.clock && id.clock < start.clock + size {
+            return Some(offset + id.clock - start.clock);
+        }
+        offset += size;
+    }
+
+    None
+}
+
+// formattable texts of the container in document order, map children in key order
+fn collect_texts(container: &Type, texts: &mut Vec<NText>) {
+    match container {
+        Type::Map(map) => {
+            let mut keys = map.keys();
+            keys.sort();
+            for key in keys {
+                if let Some(child) = map.get(key) {
+                    collect_texts(&child, texts);
+                }
+            }
+        }
+        Type::List(_) => {
+            for child in container.item_ref().borrow().as_list() {
+                collect_texts(&child, texts);
+            }
+        }
+        Type::Text(text) if !text.is_plain() => {
+            text.thaw();
+            texts.push(text.clone());
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::annotation::char_id;
+
+    use super::*;
+
+    #[test]
+    fn test_format_across_texts() {
+        let doc = Doc::default();
+        let blocks = doc.list();
+        doc.set("blocks", blocks.clone());
+        let mut texts = vec![];
+        for content in ["hello", "big", "world"] {
+            let text = doc.text();
+            blocks.append(text.clone());
+            text.append(doc.string(content));
+            texts.push(text);
+        }
+        doc.commit();
+
+        let start = char_id(&texts[0], 2).unwrap();
+        let end = char_id(&texts[2], 1).unwrap();
+        doc.format_range(&start, &end, Mark::Bold).unwrap();
+
+        assert!(texts[0].marks_at(0..2).is_empty());
+        assert_eq!(texts[0].marks_at(2..5), vec![Mark::Bold]);
+        assert_eq!(texts[1].marks_at(0..3), vec![Mark::Bold]);
+        assert_eq!(texts[2].marks_at(0..2), vec![Mark::Bold]);
+        assert!(texts[2].marks_at(2..5).is_empty());
+
+        // the marks of all texts are a single change
+        let marks: Vec<Id> = texts
+            .iter()
+            .flat_map(|text| Type::from(text.clone()).mark_items())
+            .map(|mark| mark.id())
+            .collect();
+        assert_eq!(marks.len(), 3);
+        let store = doc.store.borrow();
+        assert!(marks
+            .iter()
+            .all(|id| store.changes.get(id) == store.changes.get(&marks[0])));
+        drop(store);
+
+        assert!(doc.format_range(&end, &start, Mark::Bold).is_err());
+    }
+}
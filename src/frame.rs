@@ -0,0 +1,368 @@
+use crate::awareness::AwarenessUpdate;
+use crate::codec_v1::{DecoderV1, EncoderV1};
+use crate::decoder::{Decode, DecodeContext};
+use crate::diff::Diff;
+use crate::encoder::{Encode, EncodeContext, Encoder};
+use crate::transfer::{SnapshotChunk, SnapshotRequest};
+
+// kind byte and payload length
+const HEADER_SIZE: usize = 5;
+// frames larger than this are treated as a corrupted stream
+const DEFAULT_MAX_FRAME_SIZE: u32 = 64 * 1024 * 1024;
+
+/// FrameKind tells the receiver how to read the frame payload
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum FrameKind {
+    /// encoded document diff
+    Update,
+    /// encoded awareness update
+    Awareness,
+    /// application data that is not persisted
+    Ephemeral,
+    /// transport level messages, e.g. sync requests or acks
+    Control,
+    /// empty frame keeping an idle connection alive
+    Keepalive,
+    /// request for the snapshot of a document, possibly resuming a download
+    SnapshotRequest,
+    /// part of a snapshot download
+    SnapshotChunk,
+}
+
+impl FrameKind {
+    fn to_u8(self) -> u8 {
+        match self {
+            FrameKind::Update => 1,
+            FrameKind::Awareness => 2,
+            FrameKind::Ephemeral => 3,
+            FrameKind::Control => 4,
+            FrameKind::Keepalive => 5,
+            FrameKind::SnapshotRequest => 6,
+            FrameKind::SnapshotChunk => 7,
+        }
+    }
+
+    fn from_u8(value: u8) -> Result<FrameKind, String> {
+        match value {
+            1 => Ok(FrameKind::Update),
+            2 => Ok(FrameKind::Awareness),
+            3 => Ok(FrameKind::Ephemeral),
+            4 => Ok(FrameKind::Control),
+            5 => Ok(FrameKind::Keepalive),
+            6 => Ok(FrameKind::SnapshotRequest),
+            7 => Ok(FrameKind::SnapshotChunk),
+            _ => Err(format!("unknown frame kind: {}", value)),
+        }
+    }
+}
+
+/// Frame is a single message of the multiplexed collaboration stream.
+///
+/// On the wire a frame is `| kind: u8 | len: u32 | payload |`, frames are simply concatenated
+/// so that updates, awareness and application messages can share one socket.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(kind: FrameKind, payload: impl Into<Vec<u8>>) -> Self {
+        Self {
+            kind,
+            payload: payload.into(),
+        }
+    }
+
+    pub fn update(diff: &Diff) -> Self {
+        Self::new(FrameKind::Update, encode(diff))
+    }
+
+    pub fn awareness(update: &AwarenessUpdate) -> Self {
+        Self::new(FrameKind::Awareness, encode(update))
+    }
+
+    pub fn ephemeral(payload: impl Into<Vec<u8>>) -> Self {
+        Self::new(FrameKind::Ephemeral, payload)
+    }
+
+    pub fn control(payload: impl Into<Vec<u8>>) -> Self {
+        Self::new(FrameKind::Control, payload)
+    }
+
+    pub fn keepalive() -> Self {
+        Self::new(FrameKind::Keepalive, vec![])
+    }
+
+    pub fn snapshot_request(request: &SnapshotRequest) -> Self {
+        Self::new(FrameKind::SnapshotRequest, encode(request))
+    }
+
+    pub fn snapshot_chunk(chunk: &SnapshotChunk) -> Self {
+        Self::new(FrameKind::SnapshotChunk, encode(chunk))
+    }
+
+    /// Decode the diff of an update frame
+    pub fn to_diff(&self) -> Result<Diff, String> {
+        self.expect(FrameKind::Update)?;
+        decode(&self.payload)
+    }
+
+    /// Decode the awareness update of an awareness frame
+    pub fn to_awareness(&self) -> Result<AwarenessUpdate, String> {
+        self.expect(FrameKind::Awareness)?;
+        decode(&self.payload)
+    }
+
+    /// Decode the request of a snapshot request frame
+    pub fn to_snapshot_request(&self) -> Result<SnapshotRequest, String> {
+        self.expect(FrameKind::SnapshotRequest)?;
+        decode(&self.payload)
+    }
+
+    /// Decode the chunk of a snapshot chunk frame
+    pub fn to_snapshot_chunk(&self) -> Result<SnapshotChunk, String> {
+        self.expect(FrameKind::SnapshotChunk)?;
+        decode(&self.payload)
+    }
+
+    /// Size of the frame on the wire
+    #[inline]
+    pub fn size(&self) -> usize {
+        HEADER_SIZE + self.payload.len()
+    }
+
+    pub fn encode_to(&self, buf: &mut Vec<u8>) {
+        buf.reserve(self.size());
+        buf.push(self.kind.to_u8());
+        buf.extend_from_slice(&(self.payload.len() as u32).to_be_bytes());
+        buf.extend_from_slice(&self.payload);
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.size());
+        self.encode_to(&mut buf);
+        buf
+    }
+
+    fn expect(&self, kind: FrameKind) -> Result<(), String> {
+        if self.kind == kind {
+            Ok(())
+        } else {
+            Err(format!("expected {:?} frame, found {:?}", kind, self.kind))
+        }
+    }
+}
+
+/// Encode a batch of frames into a single message
+pub fn encode_frames(frames: &[Frame]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(frames.iter().map(|f| f.size()).sum());
+    frames.iter().for_each(|frame| frame.encode_to(&mut buf));
+    buf
+}
+
+/// FrameParser reads frames from a byte stream that may arrive in arbitrary chunks.
+///
+/// Bytes are buffered until a frame is complete, an error means the stream is corrupted
+/// and the connection should be closed.
+#[derive(Debug, Clone)]
+pub struct FrameParser {
+    buf: Vec<u8>,
+    max_frame_size: u32,
+}
+
+impl Default for FrameParser {
+    fn default() -> Self {
+        Self {
+            buf: Vec::new(),
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+}
+
+impl FrameParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_frame_size(mut self, size: u32) -> Self {
+        self.max_frame_size = size;
+        self
+    }
+
+    /// Add bytes read from the transport
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Number of buffered bytes that are not yet part of a complete frame
+    #[inline]
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Take the next complete frame, None when more bytes are needed
+    pub fn next_frame(&mut self) -> Result<Option<Frame>, String> {
+        if self.buf.len() < HEADER_SIZE {
+            return Ok(None);
+        }
+
+        let kind = FrameKind::from_u8(self.buf[0])?;
+        let len = u32::from_be_bytes([self.buf[1], self.buf[2], self.buf[3], self.buf[4]]);
+        if len > self.max_frame_size {
+            return Err(format!(
+                "frame size {} exceeds the limit {}",
+                len, self.max_frame_size
+            ));
+        }
+
+        let end = HEADER_SIZE + len as usize;
+        if self.buf.len() < end {
+            return Ok(None);
+        }
+
+        let payload = self.buf[HEADER_SIZE..end].to_vec();
+        self.buf.drain(..end);
+
+        Ok(Some(Frame::new(kind, payload)))
+    }
+
+    /// Take all complete frames
+    pub fn frames(&mut self) -> Result<Vec<Frame>, String> {
+        let mut frames = vec![];
+        while let Some(frame) = self.next_frame()? {
+            frames.push(frame);
+        }
+
+        Ok(frames)
+    }
+}
+
+/// Keepalive decides when to send keepalive frames and when the peer is gone.
+///
+/// Times are milliseconds of any monotonic clock, the caller polls on its own timer.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Keepalive {
+    interval: u64,
+    timeout: u64,
+    last_sent: u64,
+    last_seen: u64,
+}
+
+impl Keepalive {
+    pub fn new(interval: u64, timeout: u64, now: u64) -> Self {
+        Self {
+            interval,
+            timeout,
+            last_sent: now,
+            last_seen: now,
+        }
+    }
+
+    /// Any frame sent counts as a keepalive
+    pub fn on_send(&mut self, now: u64) {
+        self.last_sent = self.last_sent.max(now);
+    }
+
+    /// Any frame received proves the peer is alive
+    pub fn on_receive(&mut self, now: u64) {
+        self.last_seen = self.last_seen.max(now);
+    }
+
+    /// Keepalive frame to send when the connection was idle for the interval
+    pub fn poll(&mut self, now: u64) -> Option<Frame> {
+        if now.saturating_sub(self.last_sent) < self.interval {
+            return None;
+        }
+
+        self.on_send(now);
+        Some(Frame::keepalive())
+    }
+
+    /// Nothing was received for the timeout, the connection should be closed
+    #[inline]
+    pub fn is_timed_out(&self, now: u64) -> bool {
+        now.saturating_sub(self.last_seen) >= self.timeout
+    }
+}
+
+fn encode(value: &impl Encode) -> Vec<u8> {
+    let mut e = EncoderV1::new();
+    value.encode(&mut e, &mut EncodeContext::default());
+    e.buffer()
+}
+
+fn decode<T: Decode>(payload: &[u8]) -> Result<T, String> {
+    if payload.is_empty() {
+        return Err("empty frame payload".to_string());
+    }
+
+    let mut d = DecoderV1::try_new(payload.to_vec())?;
+    T::decode(&mut d, &DecodeContext::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::awareness::Awareness;
+    use crate::doc::Doc;
+    use crate::state::ClientState;
+    use crate::Client;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_frames_from_partial_reads() {
+        let doc = Doc::default();
+        doc.set("a", doc.atom("a"));
+        doc.commit();
+
+        let mut awareness = Awareness::new(Client::default());
+        awareness.set_local("cursor", json!({"anchor": 1}));
+
+        let frames = vec![
+            Frame::update(&doc.diff(ClientState::default())),
+            Frame::awareness(&awareness.full_update()),
+            Frame::ephemeral(b"typing".to_vec()),
+            Frame::control(vec![]),
+            Frame::keepalive(),
+        ];
+        let bytes = encode_frames(&frames);
+
+        // feed the stream in small chunks
+        let mut parser = FrameParser::new();
+        let mut parsed = vec![];
+        for chunk in bytes.chunks(7) {
+            parser.push(chunk);
+            parsed.extend(parser.frames().unwrap());
+        }
+
+        assert_eq!(parsed, frames);
+        assert_eq!(parser.buffered(), 0);
+        assert_eq!(parsed[1].to_awareness().unwrap(), awareness.full_update());
+        assert!(parsed[0].to_diff().is_ok());
+        assert!(parsed[2].to_diff().is_err());
+    }
+
+    #[test]
+    fn test_keepalive() {
+        let mut keepalive = Keepalive::new(1_000, 3_000, 0);
+        assert_eq!(keepalive.poll(500), None);
+        assert_eq!(keepalive.poll(1_000), Some(Frame::keepalive()));
+        keepalive.on_send(1_800);
+        assert_eq!(keepalive.poll(2_500), None);
+
+        assert!(!keepalive.is_timed_out(2_999));
+        assert!(keepalive.is_timed_out(3_000));
+        keepalive.on_receive(3_000);
+        assert!(!keepalive.is_timed_out(4_000));
+    }
+
+    #[test]
+    fn test_reject_oversized_frame() {
+        let mut parser = FrameParser::new().with_max_frame_size(4);
+        parser.push(&Frame::ephemeral(vec![0; 8]).to_bytes());
+        assert!(parser.next_frame().is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use hashbrown::{HashMap, HashSet};
+
+use crate::bimapid::ClientId;
+use crate::doc::Doc;
+use crate::id::{Id, IdRange, WithId, WithIdRange};
+use crate::item::{Content, ItemKind, Linked};
+use crate::state::ClientState;
+use crate::store::DocStore;
+use crate::types::Type;
+
+/// GcStats counts the work of a garbage collection pass
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct GcStats {
+    /// deleted string runs turned into tombstones
+    pub collected: usize,
+    /// tombstones merged into the tombstone on their left
+    pub merged: usize,
+    /// content bytes dropped from the collected runs
+    pub freed: usize,
+}
+
+impl DocStore {
+    /// Compact the deleted strings every replica at `keep_versions` has seen inserted and
+    /// deleted. The content of a collected run is dropped and runs with contiguous ids are
+    /// merged into a single tombstone. The ids stay in the id map, so remote items with an
+    /// origin inside a tombstone still integrate.
+    ///
+    /// Replicas behind `keep_versions` can not be served the collected runs anymore.
+    /// Runs visible at a version under a legal hold are kept.
+    pub(crate) fn gc(&mut self, keep_versions: &ClientState) -> GcStats {
+        let mut stats = GcStats::default();
+        let runs = self.collectable(keep_versions);
+        if runs.is_empty() {
+            return stats;
+        }
+
+        for run in &runs {
+            let size = run.size();
+            let item = run.item_ref();
+            let mut item = item.borrow_mut();
+            // tombstones of earlier passes are only merged with new ones
+            if let Content::String(content) = &item.content {
+                stats.freed += content.len();
+                item.content = Content::Tombstone(size);
+                stats.collected += 1;
+            }
+        }
+
+        // runs are in id order, a run absorbs the runs following it in the list
+        let collected: HashSet<Id> = runs.iter().map(|run| run.id()).collect();
+        let mut merged: HashSet<Id> = HashSet::new();
+        for run in &runs {
+            if merged.contains(&run.id()) {
+                continue;
+            }
+
+            while let Some(right) = run.right() {
+                let next_id = run.end_id().next();
+                if right.id() != next_id || !collected.contains(&next_id) {
+                    break;
+                }
+
+                self.merge_tombstones(run, &right);
+                merged.insert(right.id());
+                stats.merged += 1;
+            }
+        }
+
+        let changed: Vec<Id> = runs.iter().map(|run| run.id()).collect();
+        self.invalidate_checksums(&changed);
+
+        stats
+    }
+
+    // deleted strings inserted and deleted at or before the kept versions
+    fn collectable(&self, keep_versions: &ClientState) -> Vec<Type> {
+        let keep = keep_versions.as_per(&self.state);
+        let stable = |id: &Id| {
+            keep.get(&id.client).is_some_and(|clock| id.clock <= *clock) && !self.is_pending(id)
+        };
+
+        // deleted ranges with the id of the delete
+        let mut deleted: HashMap<ClientId, Vec<(Id, IdRange)>> = HashMap::new();
+        for (_, deletes) in self.deletes.iter() {
+            for (id, delete) in deletes.iter() {
+                if stable(id) {
+                    let range = *delete.range();
+                    deleted.entry(range.client).or_default().push((*id, range));
+                }
+            }
+        }
+
+        let mut runs = vec![];
+        for (client, items) in self.items.iter() {
+            let Some(deleted) = deleted.get(client) else {
+                continue;
+            };
+
+            for (_, item) in items.iter() {
+                if item.kind() != ItemKind::String || !item.is_deleted() {
+                    continue;
+                }
+
+                let range = item.range();
+                let covered = deleted.iter().find(|(_, delete)| {
+                    delete.contains(&range.start_id()) && delete.contains(&range.end_id())
+                });
+                let Some((delete_id, _)) = covered else {
+                    continue;
+                };
+                if stable(&range.end_id())
+                    && !self.anchors.is_referenced(&range)
+                    && !self.holds.protects(&range, delete_id)
+                {
+                    runs.push(item.clone());
+                }
+            }
+        }
+
+        runs
+    }
+
+    // merge the tombstone on the right into the tombstone on the left
+    fn merge_tombstones(&mut self, left: &Type, right: &Type) {
+        let size = left.size() + right.size();
+        let next = right.right();
+        left.set_right(next.clone());
+        match &next {
+            Some(next) => next.set_left(left.clone()),
+            None => {
+                if let Some(parent) = left.parent() {
+                    parent.set_end(left.clone());
+                }
+            }
+        }
+
+        // the right origin of a split run points into the merged run
+        if left
+            .right_id()
+            .is_some_and(|id| right.range().contains(&id))
+        {
+            left.set_right_id(right.right_id());
+        }
+        left.item_ref().borrow_mut().content = Content::Tombstone(size);
+
+        self.id_map.remove(&left.id());
+        self.id_map.remove(&right.id());
+        self.id_map.insert(left.id().range(size));
+        self.items.remove(&right.id());
+    }
+}
+
+impl Doc {
+    /// Collect the deleted strings, `keep_versions` is the version every replica has
+    /// reached, e.g. the lowest version acknowledged by the peers. See `DocStore::gc`.
+    pub fn gc(&self, keep_versions: &ClientState) -> GcStats {
+        let stats = self.store_mut("gc").gc(keep_versions);
+        self.assert_invariants("gc");
+
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doc::CloneDeep;
+
+    use super::*;
+
+    #[test]
+    fn test_gc_deleted_strings() {
+        let doc = Doc::default();
+        let text = doc.text();
+        doc.set("text", text.clone());
+        let strings: Vec<_> = ["ab", "cd", "ef", "gh"]
+            .iter()
+            .map(|content| doc.string(*content))
+            .collect();
+        strings
+            .iter()
+            .for_each(|string| text.append(string.clone()));
+        doc.commit();
+
+        // a replica inserts after a string deleted and collected concurrently
+        let remote = doc.clone_deep();
+        remote.update_client();
+        remote.get("text").unwrap().insert(4, remote.string("X"));
+        remote.commit();
+
+        strings[..3].iter().for_each(|string| string.delete());
+        doc.commit();
+
+        let stats = doc.gc(&doc.state());
+        assert_eq!(stats.collected, 3);
+        assert_eq!(stats.merged, 2);
+        assert_eq!(stats.freed, 6);
+        assert_eq!(text.text_content(), "gh");
+        assert!(doc.check_invariants().is_ok());
+
+        // collected runs are not collected again
+        assert_eq!(doc.gc(&doc.state()), GcStats::default());
+
+        doc.apply(&remote.diff(doc.state()));
+        assert_eq!(text.text_content(), "Xgh");
+    }
+}
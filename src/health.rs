@@ -0,0 +1,188 @@
+use serde::Serialize;
+
+use crate::doc::Doc;
+use crate::id::{Client, ClockTick};
+use crate::state::ClientState;
+
+// clock distance between the replicas over which a full resync beats incremental diffs
+const MAX_CLOCK_GAP: ClockTick = 10_000;
+
+/// HealthAction is the recommended reaction to a health report, ordered by severity
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Serialize)]
+pub enum HealthAction {
+    /// nothing unusual, the next sync settles the differences
+    Ignore,
+    /// exchange full snapshots instead of diffs
+    FullResync,
+    /// stop syncing with the remote until it is inspected
+    Quarantine,
+}
+
+/// HealthIssue is an anomaly found comparing the local and a remote state
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum HealthIssue {
+    /// the remote claims changes of the local client that were never made here
+    ImpossibleClock {
+        client: Client,
+        local: ClockTick,
+        remote: ClockTick,
+    },
+    /// the replicas are far apart for the client, a missing client counts as clock 0
+    ClockGap {
+        client: Client,
+        local: ClockTick,
+        remote: ClockTick,
+    },
+    /// the remote knows a client the local replica never heard of
+    UnknownClient { client: Client, remote: ClockTick },
+}
+
+impl HealthIssue {
+    pub fn action(&self) -> HealthAction {
+        match self {
+            HealthIssue::ImpossibleClock { .. } => HealthAction::Quarantine,
+            HealthIssue::ClockGap { .. } => HealthAction::FullResync,
+            HealthIssue::UnknownClient { .. } => HealthAction::Ignore,
+        }
+    }
+}
+
+/// HealthReport lists the anomalies between two replicas with the recommended action
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct HealthReport {
+    pub issues: Vec<HealthIssue>,
+    pub action: HealthAction,
+}
+
+impl HealthReport {
+    #[inline]
+    pub fn is_healthy(&self) -> bool {
+        self.action == HealthAction::Ignore
+    }
+}
+
+impl Doc {
+    /// Compare the committed local state with the state announced by a remote replica
+    pub fn health_check(&self, remote: &ClientState) -> HealthReport {
+        self.health_check_with(remote, MAX_CLOCK_GAP)
+    }
+
+    /// Same as `health_check` with the clock distance that counts as a gap
+    pub fn health_check_with(&self, remote: &ClientState, max_gap: ClockTick) -> HealthReport {
+        let store = self.store.borrow();
+        let local = &store.state;
+        let me = local.get_client(&store.client);
+        let clock_of = |state: &ClientState, client: &Client| {
+            state
+                .get_client_id(client)
+                .and_then(|id| state.get(id))
+                .copied()
+        };
+
+        let mut issues = vec![];
+        for (client, _) in remote.clients() {
+            let Some(remote_clock) = clock_of(remote, client) else {
+                continue;
+            };
+            let local_clock = clock_of(local, client);
+
+            // only this replica makes changes of the local client
+            if me == Some(client) {
+                let local_clock = local_clock.unwrap_or_default();
+                if remote_clock > local_clock {
+                    issues.push(HealthIssue::ImpossibleClock {
+                        client: client.clone(),
+                        local: local_clock,
+                        remote: remote_clock,
+                    });
+                }
+                continue;
+            }
+
+            if local_clock.is_none() {
+                issues.push(HealthIssue::UnknownClient {
+                    client: client.clone(),
+                    remote: remote_clock,
+                });
+            }
+
+            let local_clock = local_clock.unwrap_or_default();
+            if local_clock.abs_diff(remote_clock) > max_gap {
+                issues.push(HealthIssue::ClockGap {
+                    client: client.clone(),
+                    local: local_clock,
+                    remote: remote_clock,
+                });
+            }
+        }
+
+        // clients the remote never heard of, a gap when the remote misses a lot of them
+        for (client, _) in local.clients() {
+            if remote.get_client_id(client).is_some() {
+                continue;
+            }
+            let local_clock = clock_of(local, client).unwrap_or_default();
+            if local_clock > max_gap {
+                issues.push(HealthIssue::ClockGap {
+                    client: client.clone(),
+                    local: local_clock,
+                    remote: 0,
+                });
+            }
+        }
+
+        let action = issues
+            .iter()
+            .map(|issue| issue.action())
+            .max()
+            .unwrap_or(HealthAction::Ignore);
+
+        HealthReport { issues, action }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doc::CloneDeep;
+
+    use super::*;
+
+    #[test]
+    fn test_health_check() {
+        let d1 = Doc::default();
+        d1.set("title", d1.atom("draft"));
+        d1.commit();
+        let d2 = d1.clone_deep();
+        d2.update_client();
+        assert!(d1.health_check(&d2.version()).is_healthy());
+
+        // new edits of the remote are only unknown to the local replica
+        let list = d2.list();
+        d2.set("list", list.clone());
+        for i in 0..5 {
+            list.append(d2.atom(i.to_string()));
+        }
+        d2.commit();
+        let report = d1.health_check(&d2.version());
+        assert!(report.is_healthy());
+        assert!(matches!(
+            report.issues.as_slice(),
+            [HealthIssue::UnknownClient { .. }]
+        ));
+
+        let report = d1.health_check_with(&d2.version(), 2);
+        assert_eq!(report.action, HealthAction::FullResync);
+
+        // a remote claiming changes of the local client that were never made
+        let mut forged = d1.version();
+        let client = {
+            let store = d1.store.borrow();
+            store.state.get_client(&store.client).unwrap().clone()
+        };
+        let (id, clock) = forged.get_or_insert(&client);
+        forged.update(id, clock + 100);
+        let report = d1.health_check(&forged);
+        assert_eq!(report.action, HealthAction::Quarantine);
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+
+use hashbrown::HashSet;
+
+use crate::bimapid::ClientMapper;
+use crate::change::ChangeId;
+use crate::checkpoint::Checkpoint;
+use crate::doc::Doc;
+use crate::frontier::Frontier;
+use crate::id::{Client, Id, IdRange};
+use crate::snapshot::DocSnapshot;
+use crate::state::ClientState;
+use crate::store::DocStore;
+
+/// HistoryEntry is a committed change of the document
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct HistoryEntry {
+    /// clock ticks of the change
+    pub id: IdRange,
+    pub client: Client,
+    /// changes the change was made on top of, the previous change of the client included
+    pub parents: Vec<IdRange>,
+    /// unix time in seconds the replica first reached a version with the change, the
+    /// changes older than the kept history share the time of the oldest kept version
+    pub timestamp: Option<u64>,
+    /// items inserted by the change
+    pub items: usize,
+    /// items deleted by the change
+    pub deletes: usize,
+}
+
+impl HistoryEntry {
+    /// Version right after the change, to checkout the document at
+    #[inline]
+    pub fn frontier(&self) -> Frontier {
+        Frontier::new(self.id.end_id())
+    }
+}
+
+// the recorded parents of every change with the previous change of the client
+fn change_parents(store: &DocStore) -> BTreeMap<ChangeId, Vec<ChangeId>> {
+    let mut parents = BTreeMap::new();
+    for (change, deps) in store.dag.nodes() {
+        let mut deps = deps.to_vec();
+        let prev = (change.start > 1)
+            .then(|| store.changes.get(&Id::new(change.client, change.start - 1)))
+            .flatten();
+        if let Some(prev) = prev.filter(|prev| !deps.contains(*prev)) {
+            deps.push(*prev);
+        }
+        deps.sort();
+        parents.insert(*change, deps);
+    }
+
+    parents
+}
+
+impl Doc {
+    /// Committed changes of the document in causal order, a change comes after its parents
+    pub fn history(&self) -> impl Iterator<Item = HistoryEntry> {
+        let store = self.store.borrow();
+        let parents = change_parents(&store);
+
+        // depth first over the parents, a change is listed once all its parents are
+        let mut order = vec![];
+        let mut done: HashSet<ChangeId> = HashSet::new();
+        for start in parents.keys() {
+            let mut stack = vec![(*start, 0)];
+            while let Some((change, index)) = stack.pop() {
+                if done.contains(&change) {
+                    continue;
+                }
+                let deps = parents.get(&change).map(Vec::as_slice).unwrap_or_default();
+                match deps.get(index) {
+                    Some(dep) => {
+                        stack.push((change, index + 1));
+                        // a cycle is cut where it closes
+                        if !done.contains(dep) && !stack.iter().any(|(c, _)| c == dep) {
+                            stack.push((*dep, 0));
+                        }
+                    }
+                    None => {
+                        done.insert(change);
+                        order.push(change);
+                    }
+                }
+            }
+        }
+
+        let entries: Vec<HistoryEntry> = order
+            .into_iter()
+            .filter_map(|change| {
+                let client = store.state.clients.get_client(&change.client)?.clone();
+                let deps = parents.get(&change).cloned().unwrap_or_default();
+                Some(HistoryEntry {
+                    id: change.into(),
+                    client,
+                    parents: deps.into_iter().map(IdRange::from).collect(),
+                    timestamp: store.history.seen_at(&Id::new(change.client, change.end)),
+                    items: store.items.get_by_range(change).len(),
+                    deletes: store.deletes.get_by_range(change).len(),
+                })
+            })
+            .collect();
+
+        entries.into_iter()
+    }
+
+    /// Read only view of the document at the version, the version holds the change at the
+    /// frontier and every change it was made on top of
+    pub fn checkout(&self, version: &Frontier) -> Result<DocSnapshot, String> {
+        let state = {
+            let store = self.store.borrow();
+            let head = store
+                .changes
+                .get(&version.id())
+                .copied()
+                .ok_or_else(|| format!("no committed change at {}", version.id()))?;
+            let parents = change_parents(&store);
+
+            let mut state = ClientState {
+                clients: store.state.clients.clone(),
+                ..ClientState::default()
+            };
+            let mut seen: HashSet<ChangeId> = HashSet::new();
+            let mut stack = vec![head];
+            while let Some(change) = stack.pop() {
+                if !seen.insert(change) {
+                    continue;
+                }
+                state.update(change.client, change.end);
+                stack.extend(parents.get(&change).into_iter().flatten().copied());
+            }
+
+            state
+        };
+
+        self.snapshot_at(&Checkpoint::new(self.id(), state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::types::Type;
+
+    use super::*;
+
+    #[test]
+    fn test_history_checkout() {
+        let d1 = Doc::default();
+        let list = d1.list();
+        d1.set("list", list.clone());
+        list.append(d1.atom("a"));
+        d1.commit();
+
+        let d2 = Doc::new(d1.meta.clone());
+        d2.apply(&d1.diff(d2.version()));
+        d2.update_client();
+        let Some(Type::List(remote)) = d2.get("list") else {
+            panic!("list not synced");
+        };
+        remote.append(d2.atom("b"));
+        d2.commit();
+
+        // the concurrent change is not part of the local change made before the merge
+        list.append(d1.atom("c"));
+        d1.commit();
+        let local = d1.history().last().unwrap();
+        d1.apply(&d2.diff(d1.version()));
+        Type::Map(d1.root.clone()).remove("list".into());
+        d1.commit();
+
+        let history: Vec<HistoryEntry> = d1.history().collect();
+        assert_eq!(history.len(), 5);
+        for (i, entry) in history.iter().enumerate() {
+            let listed = |p: &IdRange| history[..i].iter().any(|e| e.id == *p);
+            assert!(entry.parents.iter().all(listed));
+            assert!(entry.timestamp.is_some());
+        }
+
+        let at = |entry: &HistoryEntry| d1.checkout(&entry.frontier()).unwrap().to_json();
+        assert_eq!(at(&history[0]), json!({}));
+        assert_eq!(history[1].items, 2);
+        assert_eq!(at(&history[1]), json!({"list": ["a"]}));
+        assert_eq!(at(&local), json!({"list": ["a", "c"]}));
+        let remote = history.iter().find(|e| e.client != local.client).unwrap();
+        assert_eq!(at(remote), json!({"list": ["a", "b"]}));
+        let delete = history.iter().find(|e| e.deletes == 1).unwrap();
+        assert_eq!(delete.items, 0);
+        assert_eq!(at(delete), json!({}));
+
+        assert!(d1.checkout(&Frontier::new(Id::new(9, 9))).is_err());
+    }
+}
@@ -15,6 +15,9 @@ use crate::hash::calculate_hash;
 use crate::Type;
 
 /// 32 bits Lamport Clock tick
+///
+/// When a client exhausts its clock space the document store commits the pending change
+/// and continues with a freshly generated client (see `DocStore::reserve_ticks`).
 pub type ClockTick = u32;
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -221,6 +224,16 @@ impl Id {
         Id { client, clock }
     }
 
+    #[inline]
+    pub fn client(&self) -> ClientId {
+        self.client
+    }
+
+    #[inline]
+    pub fn clock(&self) -> ClockTick {
+        self.clock
+    }
+
     #[inline]
     pub(crate) fn eq_opt(a: &Option<Id>, b: &Option<Id>) -> bool {
         match (a, b) {
@@ -272,7 +285,7 @@ impl Id {
     }
 
     #[inline]
-    pub(crate) fn range(&self, size: u32) -> IdRange {
+    pub fn range(&self, size: u32) -> IdRange {
         IdRange::new(self.client, self.clock, self.clock + size - 1)
     }
 }
@@ -371,23 +384,64 @@ impl Decode for Id {
     }
 }
 
+/// IdRange is an inclusive range of clock ticks of a single client
 #[derive(Clone, Copy, Default)]
-pub(crate) struct IdRange {
+pub struct IdRange {
     pub(crate) client: ClientId,
     pub(crate) start: ClockTick,
     pub(crate) end: ClockTick,
 }
 
 impl IdRange {
-    pub(crate) fn new(client: ClientId, start: ClockTick, end: ClockTick) -> IdRange {
+    pub fn new(client: ClientId, start: ClockTick, end: ClockTick) -> IdRange {
         IdRange { client, start, end }
     }
 
     #[inline]
-    pub(crate) fn size(&self) -> ClockTick {
+    pub fn client(&self) -> ClientId {
+        self.client
+    }
+
+    #[inline]
+    pub fn start(&self) -> ClockTick {
+        self.start
+    }
+
+    #[inline]
+    pub fn end(&self) -> ClockTick {
+        self.end
+    }
+
+    #[inline]
+    pub fn size(&self) -> ClockTick {
         self.end - self.start + 1
     }
 
+    #[inline]
+    pub fn contains(&self, id: &Id) -> bool {
+        self.client == id.client && self.start <= id.clock && id.clock <= self.end
+    }
+
+    /// Common part of two ranges of the same client
+    pub fn intersect(&self, other: &IdRange) -> Option<IdRange> {
+        if self.client != other.client {
+            return None;
+        }
+
+        let start = self.start.max(other.start);
+        let end = self.end.min(other.end);
+
+        (start <= end).then(|| IdRange::new(self.client, start, end))
+    }
+
+    /// Check if the ranges overlap or touch each other
+    #[inline]
+    pub fn is_adjacent(&self, other: &IdRange) -> bool {
+        self.client == other.client
+            && self.start <= other.end.saturating_add(1)
+            && other.start <= self.end.saturating_add(1)
+    }
+
     #[inline]
     pub(crate) fn eq_opt(a: Option<&IdRange>, b: Option<&IdRange>) -> bool {
         match (a, b) {
@@ -405,12 +459,12 @@ impl IdRange {
     }
 
     #[inline]
-    pub(crate) fn start_id(&self) -> Id {
+    pub fn start_id(&self) -> Id {
         Id::new(self.client, self.start)
     }
 
     #[inline]
-    pub(crate) fn end_id(&self) -> Id {
+    pub fn end_id(&self) -> Id {
         Id::new(self.client, self.end)
     }
 
@@ -443,7 +497,7 @@ impl IdRange {
     }
 
     // split the IdRange at the given offset, left side will have the offset size
-    pub(crate) fn split(&self, offset: u32) -> Result<(IdRange, IdRange), String> {
+    pub fn split(&self, offset: u32) -> Result<(IdRange, IdRange), String> {
         if offset == 0 || offset >= self.size() {
             return Err("Cannot split IdRange at invalid position".to_string());
         }
@@ -0,0 +1,230 @@
+use std::cell::RefCell;
+use std::fmt::{Debug, Formatter};
+use std::rc::Rc;
+
+use sha1::{Digest, Sha1};
+
+use crate::doc::Doc;
+use crate::id::{Client, ClockTick};
+use crate::store::DocStore;
+use crate::transaction::Transaction;
+
+/// IdAllocator picks the client of every new local change. The items of the change get
+/// the clocks following the last clock of the client, so a fresh client yields the same
+/// ids for the same edits on every server.
+pub trait IdAllocator {
+    fn next_client(&mut self) -> Client;
+}
+
+impl<F: FnMut() -> Client> IdAllocator for F {
+    fn next_client(&mut self) -> Client {
+        self()
+    }
+}
+
+/// IdSequence derives the clients of the changes from a seed, e.g. the key of an import
+/// job. The same seed always yields the same clients in the same order.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct IdSequence {
+    seed: String,
+    next: u64,
+}
+
+impl IdSequence {
+    pub fn new(seed: impl Into<String>) -> Self {
+        Self {
+            seed: seed.into(),
+            next: 0,
+        }
+    }
+
+    /// Client of the change at the index of the sequence
+    pub fn client_at(&self, index: u64) -> Client {
+        let hash = Sha1::new()
+            .chain_update(self.seed.as_bytes())
+            .chain_update(index.to_be_bytes())
+            .finalize();
+
+        Client::from_bytes(&hash[..16])
+    }
+}
+
+impl IdAllocator for IdSequence {
+    fn next_client(&mut self) -> Client {
+        let client = self.client_at(self.next);
+        self.next += 1;
+        client
+    }
+}
+
+// allocator installed on the store with the local client it replaced
+#[derive(Clone, Default)]
+pub(crate) struct IdAllocation {
+    allocator: Option<Rc<RefCell<dyn IdAllocator>>>,
+    local: Option<(Client, ClockTick)>,
+}
+
+impl Debug for IdAllocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IdAllocation")
+            .field("allocator", &self.allocator.is_some())
+            .field("local", &self.local)
+            .finish()
+    }
+}
+
+impl PartialEq for IdAllocation {
+    fn eq(&self, other: &Self) -> bool {
+        self.allocator.is_some() == other.allocator.is_some() && self.local == other.local
+    }
+}
+
+impl Eq for IdAllocation {}
+
+impl DocStore {
+    // let the allocator pick the client before the first id of a new local change
+    pub(crate) fn begin_change(&mut self) {
+        if self.commited_clock != self.clock {
+            return;
+        }
+        let Some(allocator) = self.id_allocation.allocator.clone() else {
+            return;
+        };
+
+        let client = allocator.borrow_mut().next_client();
+        let clock = self
+            .state
+            .get_client_id(&client)
+            .and_then(|id| self.state.get(id))
+            .map_or(1, |clock| clock + 1);
+        self.update_client(&client, clock);
+        self.commited_clock = self.clock;
+    }
+
+    fn local_client(&self) -> (Client, ClockTick) {
+        let client = self
+            .state
+            .get_client(&self.client)
+            .cloned()
+            .unwrap_or_default();
+
+        (client, self.clock)
+    }
+
+    fn install_allocator(&mut self, allocator: Option<Rc<RefCell<dyn IdAllocator>>>) {
+        self.commit();
+
+        match allocator {
+            Some(allocator) => {
+                let local = match self.id_allocation.local.take() {
+                    Some(local) => local,
+                    None => self.local_client(),
+                };
+                self.id_allocation = IdAllocation {
+                    allocator: Some(allocator),
+                    local: Some(local),
+                };
+            }
+            None => {
+                if let Some((client, clock)) = self.id_allocation.local.take() {
+                    self.update_client(&client, clock);
+                    self.commited_clock = self.clock;
+                }
+                self.id_allocation = IdAllocation::default();
+            }
+        }
+    }
+}
+
+impl Doc {
+    /// Create the items of every following local change with the ids of the allocator,
+    /// e.g. for bots and imports that must produce the same items on every server.
+    /// The pending local change is committed first.
+    pub fn set_id_allocator(&self, allocator: impl IdAllocator + 'static) {
+        let allocator: Rc<RefCell<dyn IdAllocator>> = Rc::new(RefCell::new(allocator));
+        self.store_mut("set_id_allocator").install_allocator(Some(allocator));
+    }
+
+    /// Remove the allocator, the following changes continue with the local client
+    pub fn reset_id_allocator(&self) {
+        self.store_mut("reset_id_allocator").install_allocator(None);
+    }
+
+    /// Run `f` as a transaction with the ids derived from the key, like an idempotent import.
+    /// When the document already holds the items of the key `f` is not run and None is
+    /// returned, a replica merging the same import from another server gets the same ids
+    /// and keeps a single copy.
+    pub fn transact_once<R>(
+        &self,
+        key: &str,
+        f: impl FnOnce(&mut Transaction) -> R,
+    ) -> Result<Option<R>, String> {
+        let mut sequence = IdSequence::new(key);
+        let client = sequence.client_at(0);
+        if self.store.borrow().state.get_client_id(&client).is_some() {
+            return Ok(None);
+        }
+
+        let previous = self.store.borrow().id_allocation.allocator.clone();
+        self.set_id_allocator(move || sequence.next_client());
+        let result = self.transact(f);
+        self.store_mut("transact_once").install_allocator(previous);
+
+        result.map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::id::WithId;
+    use crate::state::ClientState;
+
+    use super::*;
+
+    fn import(doc: &Doc) -> Option<()> {
+        doc.transact_once("import:contacts.csv", |tx| {
+            let contacts = tx.list();
+            tx.set("contacts", contacts.clone());
+            contacts.append(tx.atom("alice"));
+            contacts.append(tx.atom("bob"));
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_deterministic_ids() {
+        let mut sequence = IdSequence::new("bot");
+        assert_eq!(sequence.next_client(), IdSequence::new("bot").client_at(0));
+        assert_ne!(sequence.next_client(), IdSequence::new("bot").client_at(0));
+
+        let doc = Doc::default();
+        let server = Doc::new(doc.meta.clone());
+        server.apply(&doc.diff(ClientState::default()));
+        let local = doc.store.borrow().client;
+
+        // two servers run the same import, the second run on a server is skipped
+        assert!(import(&doc).is_some());
+        assert!(import(&doc).is_none());
+        assert!(import(&server).is_some());
+        assert_eq!(doc.version(), server.version());
+
+        doc.apply(&server.diff(doc.state()));
+        assert_eq!(
+            doc.get("contacts").unwrap().to_json(),
+            serde_json::json!(["alice", "bob"])
+        );
+
+        // local edits continue with the local client
+        let owner = doc.atom("carol");
+        assert_eq!(owner.id().client, local);
+
+        let bot = IdSequence::new("bot").client_at(0);
+        doc.set_id_allocator(IdSequence::new("bot"));
+        let status = doc.atom("synced");
+        doc.commit();
+        doc.reset_id_allocator();
+        let store = doc.store.borrow();
+        assert_eq!(store.state.get_client(&status.id().client), Some(&bot));
+        assert_eq!(store.client, local);
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Serializer};
+
+use crate::bimapid::ClientId;
+use crate::id::{ClockTick, Id, IdRange};
+use crate::state::ClientState;
+
+/// IdSet is a set of ids kept as sorted, disjoint and non adjacent ranges per client.
+///
+/// Sync servers can use it to reason about id coverage, e.g. which ranges a peer is missing.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct IdSet {
+    clients: BTreeMap<ClientId, Vec<(ClockTick, ClockTick)>>,
+}
+
+impl IdSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+
+    /// Number of ids in the set
+    pub fn size(&self) -> u64 {
+        self.iter().map(|range| range.size() as u64).sum()
+    }
+
+    pub fn insert(&mut self, range: IdRange) {
+        let ranges = self.clients.entry(range.client).or_default();
+        let (mut start, mut end) = (range.start, range.end);
+
+        // merge all overlapping or touching ranges into the new one
+        ranges.retain(|(s, e)| {
+            let touches = *s <= end.saturating_add(1) && start <= e.saturating_add(1);
+            if touches {
+                start = start.min(*s);
+                end = end.max(*e);
+            }
+            !touches
+        });
+
+        let index = ranges.partition_point(|(s, _)| *s < start);
+        ranges.insert(index, (start, end));
+    }
+
+    #[inline]
+    pub fn insert_id(&mut self, id: Id) {
+        self.insert(id.into());
+    }
+
+    pub fn contains(&self, id: &Id) -> bool {
+        self.clients.get(&id.client).is_some_and(|ranges| {
+            let index = ranges.partition_point(|(_, e)| *e < id.clock);
+            ranges.get(index).is_some_and(|(s, _)| *s <= id.clock)
+        })
+    }
+
+    /// Iterate over the ranges ordered by client and clock
+    pub fn iter(&self) -> impl Iterator<Item = IdRange> + '_ {
+        self.clients.iter().flat_map(|(client, ranges)| {
+            ranges
+                .iter()
+                .map(move |(start, end)| IdRange::new(*client, *start, *end))
+        })
+    }
+
+    /// Ids present in either set
+    pub fn union(&self, other: &IdSet) -> IdSet {
+        let mut set = self.clone();
+        other.iter().for_each(|range| set.insert(range));
+
+        set
+    }
+
+    /// Ids present in both sets
+    pub fn intersect(&self, other: &IdSet) -> IdSet {
+        let mut set = IdSet::new();
+        for range in self.iter() {
+            for other in other.client_ranges(range.client) {
+                if let Some(common) = range.intersect(&other) {
+                    set.insert(common);
+                }
+            }
+        }
+
+        set
+    }
+
+    /// Ids of self that are not in the other set
+    pub fn subtract(&self, other: &IdSet) -> IdSet {
+        let mut set = IdSet::new();
+        for range in self.iter() {
+            let mut start = Some(range.start);
+            for other in other.client_ranges(range.client) {
+                let Some(from) = start else {
+                    break;
+                };
+
+                if other.end < from || other.start > range.end {
+                    continue;
+                }
+
+                if other.start > from {
+                    set.insert(IdRange::new(range.client, from, other.start - 1));
+                }
+
+                // the rest of the range is covered by the other range
+                start = (other.end < range.end).then(|| other.end + 1);
+            }
+
+            if let Some(start) = start {
+                set.insert(IdRange::new(range.client, start, range.end));
+            }
+        }
+
+        set
+    }
+
+    /// Missing ranges between clock 1 and the highest id of every client
+    pub fn iter_gaps(&self) -> impl Iterator<Item = IdRange> + '_ {
+        self.clients.iter().flat_map(|(client, ranges)| {
+            let mut next = 1;
+            ranges.iter().filter_map(move |(start, end)| {
+                let gap = (*start > next).then(|| IdRange::new(*client, next, start - 1));
+                next = end.saturating_add(1);
+                gap
+            })
+        })
+    }
+
+    fn client_ranges(&self, client: ClientId) -> impl Iterator<Item = IdRange> + '_ {
+        self.clients
+            .get(&client)
+            .into_iter()
+            .flat_map(move |ranges| {
+                ranges
+                    .iter()
+                    .map(move |(start, end)| IdRange::new(client, *start, *end))
+            })
+    }
+
+    /// Convert the set into a state using the client map of the given state.
+    /// Only the contiguous ranges starting at clock 1 are covered by a state.
+    pub fn to_state(&self, base: &ClientState) -> ClientState {
+        let mut state = ClientState {
+            clients: base.clients.clone(),
+            ..ClientState::default()
+        };
+
+        for (client, ranges) in self.clients.iter() {
+            if let Some((1, end)) = ranges.first() {
+                state.update(*client, *end);
+            }
+        }
+
+        state
+    }
+}
+
+impl From<&ClientState> for IdSet {
+    fn from(state: &ClientState) -> Self {
+        let mut set = IdSet::new();
+        for (client, clock) in state.state.iter() {
+            if *clock > 0 {
+                set.insert(IdRange::new(*client, 1, *clock));
+            }
+        }
+
+        set
+    }
+}
+
+impl FromIterator<IdRange> for IdSet {
+    fn from_iter<T: IntoIterator<Item = IdRange>>(iter: T) -> Self {
+        let mut set = IdSet::new();
+        iter.into_iter().for_each(|range| set.insert(range));
+
+        set
+    }
+}
+
+impl Serialize for IdSet {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_id_set_operations() {
+        let a: IdSet = [IdRange::new(0, 1, 5), IdRange::new(0, 8, 10)]
+            .into_iter()
+            .collect();
+        let b: IdSet = [IdRange::new(0, 4, 9), IdRange::new(1, 1, 2)]
+            .into_iter()
+            .collect();
+
+        let union = a.union(&b);
+        assert_eq!(
+            union
+                .iter()
+                .map(|r| (r.client, r.start, r.end))
+                .collect::<Vec<_>>(),
+            vec![(0, 1, 10), (1, 1, 2)]
+        );
+
+        let common = a.intersect(&b);
+        assert_eq!(
+            common.iter().map(|r| (r.start, r.end)).collect::<Vec<_>>(),
+            vec![(4, 5), (8, 9)]
+        );
+
+        let rest = a.subtract(&b);
+        assert_eq!(
+            rest.iter().map(|r| (r.start, r.end)).collect::<Vec<_>>(),
+            vec![(1, 3), (10, 10)]
+        );
+
+        assert_eq!(
+            a.iter_gaps().map(|r| (r.start, r.end)).collect::<Vec<_>>(),
+            vec![(6, 7)]
+        );
+
+        assert!(a.contains(&Id::new(0, 9)));
+        assert!(!a.contains(&Id::new(0, 7)));
+        assert_eq!(a.size(), 8);
+    }
+}
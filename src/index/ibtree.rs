@@ -18,19 +18,31 @@ impl IBTree {
             btree: BTreeMap::new(),
         }
     }
+
+    pub(crate) fn extend(&mut self, items: impl IntoIterator<Item = Type>) {
+        self.btree
+            .extend(items.into_iter().map(|item| (item.index(), item)));
+    }
 }
 
+// the deleted items and the old places of moved items keep their index but are not counted
 impl ItemIndexMap<Type> for IBTree {
     fn size(&self) -> u32 {
-        self.btree.len() as u32
+        self.btree.values().filter(|v| v.is_visible()).count() as u32
     }
 
     fn at_index(&self, index: u32) -> Option<&Type> {
-        self.btree.iter().nth(index as usize).map(|(_, v)| v)
+        self.btree
+            .values()
+            .filter(|v| v.is_visible())
+            .nth(index as usize)
     }
 
     fn index_of(&self, typ: &Type) -> i32 {
-        self.btree.range(..typ.index()).count() as i32
+        self.btree
+            .range(..typ.index())
+            .filter(|(_, v)| v.is_visible())
+            .count() as i32
     }
 
     fn insert(&mut self, value: Type) {
@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::codec_v2::decode_bytes;
+use crate::diff::Diff;
+use crate::{codec_v1, codec_v2};
+
+/// KindStats counts the items of a kind
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize)]
+pub struct KindStats {
+    pub count: usize,
+    /// clock ticks of the items, about the content size for strings
+    pub ticks: usize,
+}
+
+/// DiffInspection describes an encoded diff without building a document from it
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize)]
+pub struct DiffInspection {
+    /// codec version of the encoding
+    pub version: u8,
+    pub doc_id: String,
+    /// clients known to the diff state
+    pub clients: usize,
+    /// items by kind name
+    pub items: BTreeMap<String, KindStats>,
+    pub deletes: usize,
+    pub features: Vec<String>,
+    /// size of the encoding in bytes
+    pub size: usize,
+}
+
+impl DiffInspection {
+    /// Items of all kinds
+    pub fn item_count(&self) -> usize {
+        self.items.values().map(|stats| stats.count).sum()
+    }
+}
+
+impl Diff {
+    /// Decode the bytes of a diff and report what is in it, for triaging unknown blobs
+    /// before a document is opened. Unsupported versions and truncated or corrupt bytes
+    /// are an error.
+    pub fn inspect_bytes(bytes: &[u8]) -> Result<DiffInspection, String> {
+        let version = *bytes.first().ok_or("empty diff bytes")?;
+        if version != codec_v1::VERSION && version != codec_v2::VERSION {
+            return Err(format!("unsupported codec version {}", version));
+        }
+
+        let diff = decode_bytes::<Diff>(bytes.to_vec())?;
+
+        let mut items: BTreeMap<String, KindStats> = BTreeMap::new();
+        for (_, store) in diff.items.iter() {
+            for (_, item) in store.iter() {
+                let stats = items.entry(item.kind.to_string()).or_default();
+                stats.count += 1;
+                stats.ticks += item.ticks() as usize;
+            }
+        }
+
+        Ok(DiffInspection {
+            version,
+            doc_id: diff.doc_id.to_string(),
+            clients: diff.state.clients.size() as usize,
+            items,
+            deletes: diff.deletes.size() as usize,
+            features: diff.features.iter().cloned().collect(),
+            size: bytes.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec_v1::EncoderV1;
+    use crate::doc::Doc;
+    use crate::encoder::{Encode, EncodeContext, Encoder};
+    use crate::state::ClientState;
+
+    use super::*;
+
+    #[test]
+    fn test_inspect_bytes() {
+        let doc = Doc::default();
+        let text = doc.text();
+        doc.set("text", text.clone());
+        text.append(doc.string("hello"));
+        let list = doc.list();
+        doc.set("list", list.clone());
+        list.append(doc.atom("a"));
+        list.append(doc.atom("b"));
+        list.get(0u32).unwrap().delete();
+        doc.commit();
+
+        let mut e = EncoderV1::new();
+        doc.diff(ClientState::default())
+            .encode(&mut e, &mut EncodeContext::default());
+        let bytes = e.buffer();
+
+        let inspection = Diff::inspect_bytes(&bytes).unwrap();
+        assert_eq!(inspection.version, codec_v1::VERSION);
+        assert_eq!(inspection.doc_id, doc.id().to_string());
+        assert_eq!(inspection.clients, 1);
+        assert_eq!(inspection.items["atom"].count, 2);
+        assert_eq!(inspection.items["string"].ticks, 5);
+        assert_eq!(inspection.deletes, 1);
+        assert_eq!(inspection.size, bytes.len());
+
+        assert!(Diff::inspect_bytes(&bytes[..bytes.len() / 2]).is_err());
+
+        let diff = doc.diff(ClientState::default());
+        let bytes = codec_v2::encode_with_version(&diff, codec_v2::VERSION).unwrap();
+        let v2 = Diff::inspect_bytes(&bytes).unwrap();
+        assert_eq!(v2.version, codec_v2::VERSION);
+        assert_eq!(v2.items, inspection.items);
+        assert!(Diff::inspect_bytes(&[9, 0, 0]).is_err());
+        assert!(Diff::inspect_bytes(&[]).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use crate::doc::Doc;
+use crate::id::{IdRange, WithId, WithIdRange};
+use crate::item::{Linked, StartEnd};
+use crate::store::DocStore;
+
+impl DocStore {
+    // check the crdt invariants, every broken invariant is reported as a line
+    pub(crate) fn check_invariants(&self) -> Vec<String> {
+        let mut errors = vec![];
+
+        if self.commited_clock > self.clock {
+            errors.push(format!(
+                "commited clock {} is ahead of the clock {}",
+                self.commited_clock, self.clock
+            ));
+        }
+
+        for (client, items) in self.items.iter() {
+            let mut prev: Option<IdRange> = None;
+            for (id, item) in items.iter() {
+                let range = item.range();
+                if range.id() != *id {
+                    errors.push(format!("item {} is stored as {}", range.id(), id));
+                }
+
+                // id ranges of a client never overlap
+                if let Some(prev) = prev {
+                    if range.start <= prev.end {
+                        errors.push(format!("item {} overlaps {}", range, prev));
+                    }
+                }
+                prev = Some(range);
+
+                let clock = self.state.get(client).copied().unwrap_or_default();
+                if range.end > clock.max(self.clock) {
+                    errors.push(format!(
+                        "item {} is ahead of the client clock {}",
+                        range, clock
+                    ));
+                }
+
+                if let Some(left) = item.left() {
+                    if left.right().map(|r| r.id()) != Some(item.id()) {
+                        errors.push(format!("left of {} does not link back", id));
+                    }
+                    if left.parent().map(|p| p.id()) != item.parent().map(|p| p.id()) {
+                        errors.push(format!("left of {} has another parent", id));
+                    }
+                }
+                if let Some(right) = item.right() {
+                    if right.left().map(|l| l.id()) != Some(item.id()) {
+                        errors.push(format!("right of {} does not link back", id));
+                    }
+                }
+
+                if item.start().is_some_and(|start| start.left().is_some()) {
+                    errors.push(format!("start of {} has a left item", id));
+                }
+                if item.end().is_some_and(|end| end.right().is_some()) {
+                    errors.push(format!("end of {} has a right item", id));
+                }
+            }
+        }
+
+        for (client, ranges) in self.id_map.map.iter() {
+            let mut prev: Option<&IdRange> = None;
+            for range in ranges.iter() {
+                if range.client != *client {
+                    errors.push(format!("range {} is kept for client {}", range, client));
+                }
+                if prev.is_some_and(|prev| range.start <= prev.end) {
+                    errors.push(format!("id ranges {} and {:?} overlap", range, prev));
+                }
+                prev = Some(range);
+            }
+        }
+
+        errors.extend(self.dag.check());
+
+        errors
+    }
+}
+
+impl Doc {
+    /// Check the crdt invariants: item links, id ranges, clocks and the change dag
+    pub fn check_invariants(&self) -> Result<(), Vec<String>> {
+        let errors = self.store.borrow().check_invariants();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Check the invariants after every commit and apply and panic on the first broken one.
+    /// The checks walk the whole document, use it while developing new features.
+    /// Always on with the `strict` feature.
+    pub fn set_strict(&self, strict: bool) {
+        self.store_mut("set_strict").strict = strict;
+    }
+
+    #[inline]
+    pub fn is_strict(&self) -> bool {
+        cfg!(feature = "strict") || self.store.borrow().strict
+    }
+
+    pub(crate) fn assert_invariants(&self, after: &str) {
+        if !self.is_strict() {
+            return;
+        }
+
+        if let Err(errors) = self.check_invariants() {
+            panic!(
+                "document invariants are broken after {}:\n{}",
+                after,
+                errors.join("\n")
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doc::CloneDeep;
+    use crate::state::ClientState;
+
+    use super::*;
+
+    #[test]
+    fn test_strict_mode() {
+        let d1 = Doc::default();
+        d1.set_strict(true);
+
+        let list = d1.list();
+        d1.set("list", list.clone());
+        list.append(d1.atom("a"));
+        list.append(d1.atom("b"));
+        d1.commit();
+
+        let d2 = d1.clone_deep();
+        d2.update_client();
+        d2.set_strict(true);
+        let text = d2.text();
+        d2.set("text", text.clone());
+        text.append(d2.string("hello"));
+        d2.commit();
+
+        d1.apply(&d2.diff(ClientState::default()));
+        assert!(d1.check_invariants().is_ok());
+    }
+}
@@ -1,12 +1,13 @@
 use crate::bimapid::{ClientMap, FieldId, FieldMap};
 use crate::decoder::{Decode, DecodeContext, Decoder};
-use crate::delete::DeleteItem;
 use crate::doc::DocId;
 use crate::encoder::{Encode, EncodeContext, Encoder};
+use crate::features::FeatureSet;
 use crate::id::{Id, IdRange, Split, WithId, WithIdRange, WithTarget};
 use crate::item::Any::U32;
 use crate::mark::MarkContent;
 use crate::nmark::NMark;
+use crate::packed::PackedArray;
 use crate::store::WeakStoreRef;
 use crate::types::Type;
 use crate::{print_yaml, Client, NString};
@@ -59,6 +60,7 @@ impl ItemRef {
     pub(crate) fn text_content(&self) -> String {
         match self.borrow().content {
             Content::String(ref s) => s.clone(),
+            Content::Tombstone(_) => String::new(),
             _ => {
                 panic!("NString has invalid content")
             }
@@ -177,9 +179,7 @@ impl ItemRef {
 
     pub(crate) fn delete(&self, size: u32) {
         let store = self.store.upgrade().unwrap();
-        let id = store.borrow_mut().next_id();
-        let item = DeleteItem::new(id, self.id().range(size));
-        store.borrow_mut().insert_delete(item);
+        store.borrow_mut().delete_range(self.id().range(size));
         self.borrow_mut().make_deleted();
     }
 }
@@ -487,6 +487,7 @@ impl Item {
     pub(crate) fn size(&self) -> u32 {
         match &self.data.content {
             Content::String(s) => s.len() as u32,
+            Content::Tombstone(size) => *size,
             Content::Mark(m) => m.size(),
             _ => 1,
         }
@@ -863,6 +864,20 @@ impl ItemData {
             .right_id
             .map(|id| id.adjust(before_clients, after_clients));
 
+        // text marks point at the characters of their edges
+        if let Content::Mark(mark) = &mut data.content {
+            if let Some((start, end)) = &mut mark.anchors {
+                for anchor in [start, end] {
+                    anchor.id = anchor.id.map(|id| id.adjust(before_clients, after_clients));
+                }
+            }
+        }
+
+        // movers and proxies point at their targets
+        if let Content::Id(target) = &mut data.content {
+            *target = target.adjust(before_clients, after_clients);
+        }
+
         let field = data.field.and_then(|field_id| {
             let field = before_fields.get_field(&field_id);
             field.and_then(|field| after_fields.get_field_id(field))
@@ -890,12 +905,20 @@ impl ItemData {
             deps.push(right_id.clone().into());
         }
 
+        // the target of a proxy or a mover is integrated before it
+        if let (ItemKind::Proxy | ItemKind::Move, Content::Id(target)) = (&self.kind, &self.content)
+        {
+            deps.push(*target);
+        }
+
         deps
     }
 
     pub(crate) fn ticks(&self) -> u32 {
         match &self.content {
-            Content::String(s) => s.len() as u32,
+            // an atom holding a string takes a single clock tick
+            Content::String(s) if self.kind == ItemKind::String => s.len() as u32,
+            Content::Tombstone(size) => *size,
             Content::Mark(m) => m.size(),
             _ => 1,
         }
@@ -983,6 +1006,7 @@ impl Split for ItemData {
 
         let size = match &self.content {
             Content::String(s) => s.len() as u32,
+            Content::Tombstone(size) => *size,
             Content::Mark(m) => m.size(),
             _ => return Err("Cannot split non-string item".to_string()),
         };
@@ -992,7 +1016,8 @@ impl Split for ItemData {
         left.id = left_range.start_id();
         right.id = right_range.start_id();
 
-        left.right_id = Some(right_range.start_id());
+        // the left half keeps the right origin, pointing it at the right half would make
+        // the halves depend on each other when integrated remotely
         right.left_id = Some(left_range.end_id());
 
         match &self.content {
@@ -1001,6 +1026,10 @@ impl Split for ItemData {
                 left.content = Content::String(l.to_string());
                 right.content = Content::String(r.to_string());
             }
+            Content::Tombstone(size) => {
+                left.content = Content::Tombstone(offset);
+                right.content = Content::Tombstone(size - offset);
+            }
             Content::Mark(m) => {
                 let (l, r) = m.split(offset);
                 left.content = Content::Mark(l);
@@ -1064,8 +1093,8 @@ pub(crate) enum ContainerKind {
     PlainText,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Copy)]
-pub(crate) enum ItemKind {
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Copy, Hash)]
+pub enum ItemKind {
     Map,
     List,
     Text,
@@ -1075,6 +1104,7 @@ pub(crate) enum ItemKind {
     Move,
     Mark,
     PlaintText,
+    Counter,
 }
 
 impl ItemKind {
@@ -1115,6 +1145,10 @@ impl ItemKind {
     pub(crate) fn is_plaintext(&self) -> bool {
         self == &Self::PlaintText
     }
+
+    pub(crate) fn is_counter(&self) -> bool {
+        self == &Self::Counter
+    }
 }
 
 bitflags! {
@@ -1128,6 +1162,7 @@ bitflags! {
         const MOVE = 0x6;
         const MARK = 0x7;
         const PLAINTEXT = 0x8;
+        const COUNTER = 0x9;
     }
 }
 
@@ -1143,6 +1178,7 @@ impl From<ItemKind> for ItemKindFlags {
             ItemKind::Move => Self::MOVE,
             ItemKind::Mark => Self::MARK,
             ItemKind::PlaintText => Self::PLAINTEXT,
+            ItemKind::Counter => Self::COUNTER,
         }
     }
 }
@@ -1159,6 +1195,7 @@ impl From<&ItemKind> for ItemKindFlags {
             ItemKind::Move => Self::MOVE,
             ItemKind::Mark => Self::MARK,
             ItemKind::PlaintText => Self::PLAINTEXT,
+            ItemKind::Counter => Self::COUNTER,
         }
     }
 }
@@ -1176,6 +1213,7 @@ impl From<ItemKindFlags> for ItemKind {
             0x06 => ItemKind::Move,
             0x07 => ItemKind::Mark,
             0x08 => ItemKind::PlaintText,
+            0x09 => ItemKind::Counter,
             _ => ItemKind::Atom,
         }
     }
@@ -1193,6 +1231,7 @@ impl Display for ItemKind {
             Self::Move => write!(f, "move"),
             Self::Mark => write!(f, "mark"),
             Self::PlaintText => write!(f, "plaintext"),
+            Self::Counter => write!(f, "counter"),
         }
     }
 }
@@ -1258,6 +1297,8 @@ pub enum Content {
     Binary(Vec<u8>),
     String(String),
     Embed(Any),
+    // byte length of a collected deleted string run, see DocStore::gc
+    Tombstone(u32),
     Null,
 }
 
@@ -1271,6 +1312,7 @@ bitflags! {
         const DOC = 0x11;
         const NULL = 0x12;
         const ID = 0x13;
+        const TOMBSTONE = 0x14;
     }
 }
 
@@ -1284,7 +1326,7 @@ impl Content {
             Self::Embed(a) => a.to_json(),
             Self::Doc(d) => Value::String(serde_json::to_string(&d.id).unwrap()),
             Self::Id(id) => Value::String(id.to_string()),
-            Self::Null => Value::Null,
+            Self::Tombstone(_) | Self::Null => Value::Null,
         }
     }
 }
@@ -1330,9 +1372,13 @@ impl Encode for Content {
             Self::Types(_) => {
                 // e.array(t)
             }
-            Self::Embed(_) => {
+            Self::Embed(Any::Array(_) | Any::Map(_) | Any::KV(_)) => {
                 // a.encode(e)
             }
+            Self::Embed(a) => {
+                e.u8(ContentFlags::EMBED.bits());
+                a.encode(e, ctx)
+            }
             Self::Doc(d) => {
                 e.u8(ContentFlags::DOC.bits());
                 d.encode(e, ctx)
@@ -1341,6 +1387,10 @@ impl Encode for Content {
                 e.u8(ContentFlags::ID.bits());
                 id.encode(e, ctx)
             }
+            Self::Tombstone(size) => {
+                e.u8(ContentFlags::TOMBSTONE.bits());
+                e.u32(*size)
+            }
             Self::Null => {}
         }
     }
@@ -1366,9 +1416,9 @@ impl Decode for Content {
                 Ok(Self::Types(types))
             }
             0x10 => {
-                // let any = Any::decode(d, ctx)?;
-                // Ok(Self::Embed(any))
-                Ok(Self::Null)
+                // only packed arrays and counter deltas are encoded for now
+                let any = Any::decode(d, ctx)?;
+                Ok(Self::Embed(any))
             }
             0x11 => {
                 let doc = DocProps::decode(d, ctx)?;
@@ -1376,6 +1426,7 @@ impl Decode for Content {
             }
             0x12 => Ok(Self::Null),
             0x13 => Ok(Self::Id(Id::decode(d, ctx)?)),
+            0x14 => Ok(Self::Tombstone(d.u32()?)),
             _ => Err(format!("Invalid content flags: {}", flags)),
         }
     }
@@ -1449,6 +1500,8 @@ pub(crate) struct DocProps {
     pub(crate) created_by: Client,
     // custom create time props fot the document
     pub(crate) props: Any,
+    // capabilities the document was created with
+    pub(crate) features: FeatureSet,
 }
 
 impl DocProps {
@@ -1461,6 +1514,7 @@ impl DocProps {
                 .unwrap()
                 .as_secs(),
             props: Any::Null,
+            features: FeatureSet::default(),
         }
     }
 }
@@ -1471,6 +1525,7 @@ impl Encode for DocProps {
         e.u64(self.created_at);
         self.created_by.encode(e, ctx);
         self.props.encode(e, ctx);
+        self.features.encode(e, ctx);
     }
 }
 
@@ -1483,12 +1538,14 @@ impl Decode for DocProps {
         let created_at = d.u64()?;
         let created_by = Client::decode(d, ctx)?;
         let props = Any::decode(d, ctx)?;
+        let features = FeatureSet::decode(d, ctx)?;
 
         Ok(Self {
             id: doc_id,
             created_at: created_at.into(),
             created_by,
             props,
+            features,
         })
     }
 }
@@ -1514,6 +1571,7 @@ pub(crate) enum Any {
     Array(Vec<Any>),
     Map(Vec<(String, Any)>),
     KV(Vec<(String, String)>),
+    Packed(PackedArray),
 }
 
 impl Any {
@@ -1549,6 +1607,7 @@ impl Any {
                 }
                 Value::Object(map)
             }
+            Self::Packed(p) => p.to_json(),
         }
     }
 
@@ -1584,6 +1643,7 @@ bitflags! {
         const BINARY = 0x0E;
         const ARRAY = 0x0F;
         const MAP = 0x10;
+        const PACKED = 0x11;
     }
 }
 
@@ -1599,33 +1659,67 @@ impl Encode for Any {
             Any::False => {
                 e.u8(AnyFlags::FALSE.bits());
             }
-            Any::F32(_) => {
+            Any::F32(d) => {
                 e.u8(AnyFlags::FLOAT32.bits());
-                // e.f32(*d);
+                e.u32(d.to_bits());
             }
-            Any::F64(d_) => {
+            Any::F64(d) => {
                 e.u8(AnyFlags::FLOAT64.bits());
-                // e.f64(*d);
+                e.u64(d.to_bits());
+            }
+            Any::I8(i) => {
+                e.u8(AnyFlags::INT8.bits());
+                e.u8(*i as u8);
+            }
+            Any::I16(i) => {
+                e.u8(AnyFlags::INT16.bits());
+                e.u16(*i as u16);
+            }
+            Any::I32(i) => {
+                e.u8(AnyFlags::INT32.bits());
+                e.u32(*i as u32);
+            }
+            Any::I64(i) => {
+                e.u8(AnyFlags::INT64.bits());
+                e.u64(*i as u64);
+            }
+            Any::U8(u) => {
+                e.u8(AnyFlags::UINT8.bits());
+                e.u8(*u);
+            }
+            Any::U16(u) => {
+                e.u8(AnyFlags::UINT16.bits());
+                e.u16(*u);
+            }
+            Any::U32(u) => {
+                e.u8(AnyFlags::UINT32.bits());
+                e.u32(*u);
+            }
+            Any::U64(u) => {
+                e.u8(AnyFlags::UINT64.bits());
+                e.u64(*u);
+            }
+            Any::String(s) => {
+                e.u8(AnyFlags::STRING.bits());
+                e.string(s);
+            }
+            Any::Binary(b) => {
+                e.u8(AnyFlags::BINARY.bits());
+                e.bytes(b);
             }
-            Any::I8(_) => {}
-            Any::I16(_) => {}
-            Any::I32(_) => {}
-            Any::I64(_) => {}
-            Any::U8(_) => {}
-            Any::U16(_) => {}
-            Any::U32(_) => {}
-            Any::U64(_) => {}
-            Any::String(_) => {}
-            Any::Binary(_) => {}
             Any::Array(_) => {}
             Any::Map(_) => {}
             Any::KV(_) => {}
+            Any::Packed(array) => {
+                e.u8(AnyFlags::PACKED.bits());
+                array.encode(e, ctx);
+            }
         }
     }
 }
 
 impl Decode for Any {
-    fn decode<T: Decoder>(d: &mut T, _ctx: &DecodeContext) -> Result<Self, String>
+    fn decode<T: Decoder>(d: &mut T, ctx: &DecodeContext) -> Result<Self, String>
     where
         Self: Sized,
     {
@@ -1634,69 +1728,22 @@ impl Decode for Any {
             0x00 => Ok(Self::Null),
             0x01 => Ok(Self::True),
             0x02 => Ok(Self::False),
-            0x03 => {
-                // let f = d.f32()?;
-                // Ok(Self::Float32(f))
-                Ok(Self::Null)
-            }
-            0x04 => {
-                // let f = d.f64()?;
-                // Ok(Self::Float64(f))
-                Ok(Self::Null)
-            }
-            0x05 => {
-                // let i = d.i8()?;
-                // Ok(Self::Int8(i))
-                Ok(Self::Null)
-            }
-            0x06 => {
-                // let i = d.i16()?;
-                // Ok(Self::Int16(i))
-                Ok(Self::Null)
-            }
-            0x07 => {
-                // let i = d.i32()?;
-                // Ok(Self::Int32(i))
-                Ok(Self::Null)
-            }
-            0x08 => {
-                // let i = d.i64()?;
-                // Ok(Self::Int64(i))
-                Ok(Self::Null)
-            }
-            0x09 => {
-                // let u = d.u8()?;
-                // Ok(Self::Uint8(u))
-                Ok(Self::Null)
-            }
-            0x0A => {
-                // let u = d.u16()?;
-                // Ok(Self::Uint16(u))
-                Ok(Self::Null)
-            }
-            0x0B => {
-                // let u = d.u32()?;
-                // Ok(Self::Uint32(u))
-                Ok(Self::Null)
-            }
-            0x0C => {
-                // let u = d.u64()?;
-                // Ok(Self::Uint64(u))
-                Ok(Self::Null)
-            }
-            0x0D => {
-                // let s = d.string()?;
-                // Ok(Self::String(s))
-                Ok(Self::Null)
-            }
-            0x0E => {
-                // let b = d.bytes()?;
-                // Ok(Self::Binary(b))
-                Ok(Self::Null)
-            }
+            0x03 => Ok(Self::F32(f32::from_bits(d.u32()?))),
+            0x04 => Ok(Self::F64(f64::from_bits(d.u64()?))),
+            0x05 => Ok(Self::I8(d.u8()? as i8)),
+            0x06 => Ok(Self::I16(d.u16()? as i16)),
+            0x07 => Ok(Self::I32(d.u32()? as i32)),
+            0x08 => Ok(Self::I64(d.u64()? as i64)),
+            0x09 => Ok(Self::U8(d.u8()?)),
+            0x0A => Ok(Self::U16(d.u16()?)),
+            0x0B => Ok(Self::U32(d.u32()?)),
+            0x0C => Ok(Self::U64(d.u64()?)),
+            0x0D => Ok(Self::String(d.string()?)),
+            0x0E => Ok(Self::Binary(d.bytes()?)),
             0x0F => {
                 panic!("Array not implemented");
             }
+            0x11 => Ok(Self::Packed(PackedArray::decode(d, ctx)?)),
             _ => {
                 panic!("Map not implemented");
             }
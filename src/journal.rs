@@ -0,0 +1,246 @@
+use crate::apply_stats::ApplyStats;
+use crate::codec_v1::{DecoderV1, EncoderV1};
+use crate::decoder::{Decode, DecodeContext, Decoder};
+use crate::diff::Diff;
+use crate::doc::Doc;
+use crate::encoder::{Encode, EncodeContext, Encoder};
+
+const RECORD_INTENT: u8 = 1;
+const RECORD_COMPLETE: u8 = 2;
+
+/// JournalRecord is an entry of the apply journal
+#[derive(Debug, Clone, PartialEq)]
+pub enum JournalRecord {
+    /// written before a diff is integrated
+    Intent { seq: u64, diff: Diff },
+    /// written after the diff was integrated
+    Complete { seq: u64 },
+}
+
+impl JournalRecord {
+    #[inline]
+    pub fn seq(&self) -> u64 {
+        match self {
+            JournalRecord::Intent { seq, .. } => *seq,
+            JournalRecord::Complete { seq } => *seq,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut e = EncoderV1::new();
+        self.encode(&mut e, &mut EncodeContext::default());
+        e.buffer()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<JournalRecord, String> {
+        let mut d = DecoderV1::try_new(bytes.to_vec())?;
+        JournalRecord::decode(&mut d, &DecodeContext::default())
+    }
+}
+
+impl Encode for JournalRecord {
+    fn encode<T: Encoder>(&self, e: &mut T, cx: &mut EncodeContext) {
+        match self {
+            JournalRecord::Intent { seq, diff } => {
+                e.u8(RECORD_INTENT);
+                e.u64(*seq);
+                diff.encode(e, cx);
+            }
+            JournalRecord::Complete { seq } => {
+                e.u8(RECORD_COMPLETE);
+                e.u64(*seq);
+            }
+        }
+    }
+}
+
+impl Decode for JournalRecord {
+    fn decode<T: Decoder>(d: &mut T, ctx: &DecodeContext) -> Result<Self, String> {
+        let kind = d.u8()?;
+        let seq = d.u64()?;
+        match kind {
+            RECORD_INTENT => Ok(JournalRecord::Intent {
+                seq,
+                diff: Diff::decode(d, ctx)?,
+            }),
+            RECORD_COMPLETE => Ok(JournalRecord::Complete { seq }),
+            _ => Err(format!("unknown journal record kind: {}", kind)),
+        }
+    }
+}
+
+/// Journal is the durable log used by `Doc::apply_journaled`.
+///
+/// `append` must not return before the record is durable, e.g. after an fsync,
+/// otherwise a crash can lose the intent of a partially persisted apply.
+pub trait Journal {
+    fn append(&mut self, record: JournalRecord) -> Result<(), String>;
+
+    /// All records in the order they were appended
+    fn records(&self) -> Result<Vec<JournalRecord>, String>;
+
+    /// Drop all records, called once every intent is resolved
+    fn clear(&mut self) -> Result<(), String>;
+
+    fn next_seq(&self) -> Result<u64, String> {
+        let last = self.records()?.iter().map(|r| r.seq()).max();
+        Ok(last.map_or(1, |seq| seq + 1))
+    }
+}
+
+/// MemoryJournal keeps the records in memory, for tests and as a reference implementation
+#[derive(Debug, Clone, Default)]
+pub struct MemoryJournal {
+    records: Vec<JournalRecord>,
+}
+
+impl Journal for MemoryJournal {
+    fn append(&mut self, record: JournalRecord) -> Result<(), String> {
+        self.records.push(record);
+        Ok(())
+    }
+
+    fn records(&self) -> Result<Vec<JournalRecord>, String> {
+        Ok(self.records.clone())
+    }
+
+    fn clear(&mut self) -> Result<(), String> {
+        self.records.clear();
+        Ok(())
+    }
+}
+
+/// RecoverAction decides what happens to the diffs whose apply did not complete
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum RecoverAction {
+    /// apply the diffs again, integrated items are skipped
+    #[default]
+    Reapply,
+    /// drop the diffs, the sender is expected to send them again
+    Discard,
+}
+
+/// RecoveryReport lists the outcome of `Doc::recover`
+#[derive(Debug, Clone, Default)]
+pub struct RecoveryReport {
+    /// sequence numbers of the applied intents
+    pub reapplied: Vec<u64>,
+    /// sequence numbers of the discarded or rejected intents
+    pub discarded: Vec<u64>,
+}
+
+impl RecoveryReport {
+    #[inline]
+    pub fn is_clean(&self) -> bool {
+        self.reapplied.is_empty() && self.discarded.is_empty()
+    }
+}
+
+impl Doc {
+    /// Apply the diff with an intent record written before and a completion record after
+    /// the integration, a crash in between is resolved by `recover` on reload.
+    pub fn apply_journaled(
+        &self,
+        diff: &Diff,
+        journal: &mut impl Journal,
+    ) -> Result<ApplyStats, String> {
+        let seq = journal.next_seq()?;
+        journal.append(JournalRecord::Intent {
+            seq,
+            diff: diff.clone(),
+        })?;
+
+        let stats = self.apply(diff);
+        journal.append(JournalRecord::Complete { seq })?;
+
+        Ok(stats)
+    }
+
+    /// Resolve the incomplete intents of the journal after the document was reloaded,
+    /// the journal is cleared afterwards.
+    pub fn recover(
+        &self,
+        journal: &mut impl Journal,
+        action: RecoverAction,
+    ) -> Result<RecoveryReport, String> {
+        let records = journal.records()?;
+        let completed: Vec<u64> = records
+            .iter()
+            .filter_map(|r| match r {
+                JournalRecord::Complete { seq } => Some(*seq),
+                _ => None,
+            })
+            .collect();
+
+        let mut report = RecoveryReport::default();
+        for record in records {
+            let JournalRecord::Intent { seq, diff } = record else {
+                continue;
+            };
+            if completed.contains(&seq) {
+                continue;
+            }
+
+            match action {
+                RecoverAction::Reapply if !self.apply(&diff).is_rejected() => {
+                    report.reapplied.push(seq)
+                }
+                _ => report.discarded.push(seq),
+            }
+        }
+
+        journal.clear()?;
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doc::CloneDeep;
+    use crate::state::ClientState;
+    use crate::sync::equal_docs;
+
+    use super::*;
+
+    #[test]
+    fn test_recover_incomplete_apply() {
+        let d1 = Doc::default();
+        d1.commit();
+        let d2 = d1.clone_deep();
+        d2.update_client();
+
+        d1.set("a", d1.atom("a"));
+        d1.commit();
+        let diff = d1.diff(ClientState::default());
+
+        let mut journal = MemoryJournal::default();
+        d2.apply_journaled(&diff, &mut journal).unwrap();
+        assert!(d2
+            .recover(&mut journal, RecoverAction::Reapply)
+            .unwrap()
+            .is_clean());
+
+        // the process died after the intent was written, the reloaded doc lacks the diff
+        let reloaded = d1.clone_deep();
+        reloaded.update_client();
+        d1.set("b", d1.atom("b"));
+        d1.commit();
+
+        let intent = JournalRecord::Intent {
+            seq: journal.next_seq().unwrap(),
+            diff: d1.diff(ClientState::default()),
+        };
+        let bytes = intent.to_bytes();
+        journal
+            .append(JournalRecord::from_bytes(&bytes).unwrap())
+            .unwrap();
+
+        let report = reloaded
+            .recover(&mut journal, RecoverAction::Reapply)
+            .unwrap();
+        assert_eq!(report.reapplied, vec![1]);
+        assert!(equal_docs(&d1, &reloaded));
+        assert!(journal.records().unwrap().is_empty());
+    }
+}
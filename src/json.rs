@@ -1,5 +1,11 @@
-use crate::{Doc, Type};
-use fake::Opt;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::checkpoint::delete_item;
+use crate::doc::Doc;
+use crate::id::{WithId, WithTarget};
+use crate::item::{Any, Content};
+use crate::types::Type;
 
 /// JsonDoc that can be converted to a Doc.
 /// It may not be optimum for many use cases as it might be
@@ -14,38 +20,316 @@ impl JsonDoc {
     }
 
     pub(crate) fn to_doc(mut self) -> Doc {
-        let mut doc = Doc::default();
+        let doc = Doc::default();
         // take the value out of the option
         let value = self.value.take().unwrap_or_default();
-        self.build(Type::from(doc.root.clone()), &value);
+        if let Value::Object(entries) = &value {
+            for (key, value) in entries {
+                doc.set(key.clone(), build(&doc, value));
+            }
+        }
 
         doc
     }
+}
 
-    fn build(&mut self, parent: Type, value: &serde_json::Value) {
-        match value {
-            serde_json::Value::Bool(b) => {
-                // parent.set(key, Type::from(b));
+/// JsonPatchOp is an operation of a json patch (RFC 6902), the paths are json pointers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum JsonPatchOp {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Move { from: String, path: String },
+}
+
+// the type holding the json value, containers are filled before they are attached
+fn build(doc: &Doc, value: &Value) -> Type {
+    match value {
+        Value::Object(entries) => {
+            let map = doc.map();
+            for (key, value) in entries {
+                map.set(key.clone(), build(doc, value));
             }
-            serde_json::Value::Null => {
-                // parent.set(key, Type::from(()));
+            map.into()
+        }
+        Value::Array(items) => {
+            let list = doc.list();
+            for value in items {
+                list.append(build(doc, value));
             }
-            serde_json::Value::Number(n) => {
-                // parent.set(key, Type::from(n));
+            list.into()
+        }
+        Value::String(s) => doc.atom(s.as_str()).into(),
+        Value::Bool(true) => doc.atom(Any::True).into(),
+        Value::Bool(false) => doc.atom(Any::False).into(),
+        Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(u), _) => doc.atom(Any::U64(u)).into(),
+            (_, Some(i)) => doc.atom(Any::I64(i)).into(),
+            _ => doc.atom(Any::F64(n.as_f64().unwrap_or_default())).into(),
+        },
+        Value::Null => doc.atom(Content::Null).into(),
+    }
+}
+
+// moved items are listed through their movers
+fn target_of(item: &Type) -> Type {
+    item.item_ref().get_target().unwrap_or(item.clone())
+}
+
+// json of the item with the map keys in order and the texts as plain strings
+fn to_value(item: &Type) -> Value {
+    match item {
+        Type::Map(map) => {
+            let mut entries: Vec<_> = map.visible_children().into_iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            let entries = entries.into_iter().map(|(key, v)| (key, to_value(&v)));
+            Value::Object(entries.collect::<Map<String, Value>>())
+        }
+        Type::List(list) => {
+            let items = list.unique_items();
+            Value::Array(
+                items
+                    .iter()
+                    .map(|item| to_value(&target_of(item)))
+                    .collect(),
+            )
+        }
+        Type::Text(text) => Value::String(text.text_content()),
+        _ => item.to_json(),
+    }
+}
+
+// reference tokens of a json pointer
+fn parse_pointer(path: &str) -> Result<Vec<String>, String> {
+    if path.is_empty() {
+        return Ok(vec![]);
+    }
+    let Some(rest) = path.strip_prefix('/') else {
+        return Err(format!("json pointer {} must start with /", path));
+    };
+
+    let tokens = rest
+        .split('/')
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .collect();
+
+    Ok(tokens)
+}
+
+// index of a list token, `-` is the end of the list
+fn parse_index(token: &str, size: u32, path: &str) -> Result<u32, String> {
+    if token == "-" {
+        return Ok(size);
+    }
+
+    token
+        .parse::<u32>()
+        .ok()
+        .filter(|index| *index <= size)
+        .ok_or_else(|| format!("index {} out of bounds at {}", token, path))
+}
+
+impl Doc {
+    /// Json of the document with the map keys in key order, the lists in list order
+    /// and the texts as plain strings. Replicas with the same content export the same
+    /// json, whatever the order the changes were applied in.
+    pub fn to_json_value(&self) -> Value {
+        to_value(&Type::Map(self.root.clone()))
+    }
+
+    /// Apply the operations of a json patch as one change. Added values become new
+    /// containers and atoms, a replaced text with a string value is rewritten in place.
+    /// When an operation fails the earlier operations are rolled back.
+    pub fn apply_json_patch(&self, patch: &[JsonPatchOp]) -> Result<(), String> {
+        let mut error = None;
+        let _ = self.transact(|tx| {
+            if let Err(e) = patch.iter().try_for_each(|op| self.apply_json_op(op)) {
+                error = Some(e);
+                tx.abort();
+            }
+        });
+
+        error.map_or(Ok(()), Err)
+    }
+
+    fn apply_json_op(&self, op: &JsonPatchOp) -> Result<(), String> {
+        match op {
+            JsonPatchOp::Add { path, value } => {
+                let (parent, key) = self.resolve_parent(path)?;
+                self.add_json(&parent, &key, build(self, value), path)
             }
-            serde_json::Value::String(s) => {
-                // parent.set(key, Type::from(s));
+            JsonPatchOp::Remove { path } => {
+                let item = self.resolve_pointer(path)?;
+                self.remove_json(&item, path)
             }
-            serde_json::Value::Array(arr) => {
-                for value in arr.iter() {
-                    // self.build(value);
+            JsonPatchOp::Replace { path, value } => {
+                let item = self.resolve_pointer(path)?;
+                match (&target_of(&item), value) {
+                    (Type::Text(text), Value::String(s)) => {
+                        text.clear();
+                        text.append(self.string(s.as_str()));
+                        Ok(())
+                    }
+                    _ => {
+                        let (parent, key) = self.resolve_parent(path)?;
+                        self.remove_json(&item, path)?;
+                        self.add_json(&parent, &key, build(self, value), path)
+                    }
                 }
             }
-            serde_json::Value::Object(obj) => {
-                for (key, value) in obj.iter() {
-                    // self.build(value);
+            JsonPatchOp::Move { from, path } => {
+                if from == path {
+                    return self.resolve_pointer(from).map(|_| ());
                 }
+                if path.starts_with(&format!("{}/", from)) {
+                    return Err(format!("can not move {} into itself", from));
+                }
+
+                let item = self.resolve_pointer(from)?;
+                let (source, index) = self.resolve_parent(from)?;
+                let (parent, key) = self.resolve_parent(path)?;
+                match (&source, &parent) {
+                    // lists move the item itself, concurrent edits of the item are kept
+                    (Type::List(_), Type::List(_)) => {
+                        // the path is counted without the item, the move offset with it
+                        let same = source.id() == parent.id();
+                        let mut offset = parse_index(&key, parent.size() - same as u32, path)?;
+                        let index = index.parse::<u32>().unwrap_or_default();
+                        if same && offset >= index {
+                            offset += 1;
+                        }
+                        target_of(&item).move_to(parent.clone(), offset);
+                        Ok(())
+                    }
+                    _ => {
+                        let target = target_of(&item);
+                        let copy = self
+                            .new_like(&target)
+                            .ok_or_else(|| format!("{} can not be moved", from))?;
+                        self.copy_children(&target, &copy);
+                        self.remove_json(&item, from)?;
+                        self.add_json(&parent, &key, copy, path)
+                    }
+                }
+            }
+        }
+    }
+
+    // the container of the last token of the path and the token
+    fn resolve_parent(&self, path: &str) -> Result<(Type, String), String> {
+        let mut tokens = parse_pointer(path)?;
+        let key = tokens
+            .pop()
+            .ok_or_else(|| "the document root can not be replaced".to_string())?;
+        let parent = target_of(&self.resolve_tokens(&tokens, path)?);
+
+        Ok((parent, key))
+    }
+
+    pub(crate) fn resolve_pointer(&self, path: &str) -> Result<Type, String> {
+        self.resolve_tokens(&parse_pointer(path)?, path)
+    }
+
+    fn resolve_tokens(&self, tokens: &[String], path: &str) -> Result<Type, String> {
+        let mut current = Type::Map(self.root.clone());
+        for token in tokens {
+            let child = match &target_of(&current) {
+                Type::Map(map) => map.get(token.as_str()),
+                Type::List(list) => token.parse::<u32>().ok().and_then(|i| list.get(i)),
+                _ => None,
+            };
+
+            current = child.ok_or_else(|| format!("path {} not found", path))?;
+        }
+
+        Ok(current)
+    }
+
+    fn add_json(&self, parent: &Type, key: &str, item: Type, path: &str) -> Result<(), String> {
+        match parent {
+            Type::Map(_) => {
+                parent.set(key, item);
+                Ok(())
+            }
+            Type::List(_) => {
+                let offset = parse_index(key, parent.size(), path)?;
+                parent.insert(offset, item);
+                Ok(())
             }
+            _ => Err(format!(
+                "{:?} at {} is not a map or list",
+                parent.kind(),
+                path
+            )),
+        }
+    }
+
+    fn remove_json(&self, item: &Type, path: &str) -> Result<(), String> {
+        if item.id() == self.root.id() {
+            return Err(format!("the document root at {} can not be removed", path));
         }
+
+        delete_item(item);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::state::ClientState;
+
+    use super::*;
+
+    #[test]
+    fn test_json_patch() {
+        let doc = Doc::default();
+        let title = doc.text();
+        doc.set("title", title.clone());
+        title.append(doc.string("draft"));
+        doc.commit();
+
+        let patch: Vec<JsonPatchOp> = serde_json::from_value(json!([
+            { "op": "add", "path": "/tags", "value": ["b", "c"] },
+            { "op": "add", "path": "/tags/0", "value": "a" },
+            { "op": "add", "path": "/tags/-", "value": { "x~y": 1, "a/b": true } },
+            { "op": "replace", "path": "/title", "value": "final" },
+            { "op": "move", "from": "/tags/0", "path": "/tags/2" },
+            { "op": "move", "from": "/tags/3/a~1b", "path": "/flag" },
+            { "op": "remove", "path": "/tags/3/x~0y" },
+        ]))
+        .unwrap();
+        doc.apply_json_patch(&patch).unwrap();
+
+        let expected = json!({
+            "flag": true,
+            "tags": ["b", "c", "a", {}],
+            "title": "final",
+        });
+        assert_eq!(doc.to_json_value(), expected);
+        assert_eq!(
+            serde_json::to_string(&doc.to_json_value()).unwrap(),
+            r#"{"flag":true,"tags":["b","c","a",{}],"title":"final"}"#
+        );
+
+        // a failing operation rolls the patch back
+        let patch = vec![
+            JsonPatchOp::Remove {
+                path: "/flag".to_string(),
+            },
+            JsonPatchOp::Remove {
+                path: "/missing".to_string(),
+            },
+        ];
+        assert!(doc.apply_json_patch(&patch).is_err());
+        assert_eq!(doc.to_json_value(), expected);
+
+        // the patch syncs like any other change
+        let reader = Doc::new(doc.meta.clone());
+        reader.apply(&doc.diff(ClientState::default()));
+        assert_eq!(reader.to_json_value(), expected);
+        assert!(doc.check_invariants().is_ok());
     }
 }
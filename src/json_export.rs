@@ -0,0 +1,187 @@
+use serde_json::{json, Map, Value};
+
+use crate::doc::Doc;
+use crate::types::Type;
+
+/// key of the truncation markers
+pub const TRUNCATED: &str = "$truncated";
+
+/// JsonExportOptions bound the json export of untrusted documents.
+///
+/// A container below the max depth is replaced by `{"$truncated": "depth", "kind": ..}`.
+/// The children over the max of a list or text are replaced by a trailing
+/// `{"$truncated": "children", "omitted": n}` entry, a map keeps the first keys in key
+/// order and gets the marker under the `$truncated` key.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct JsonExportOptions {
+    /// depth of the deepest exported container, the root is at depth 0
+    pub max_depth: Option<usize>,
+    /// children exported per container
+    pub max_children: Option<usize>,
+}
+
+impl JsonExportOptions {
+    pub fn with_max_depth(mut self, max: usize) -> Self {
+        self.max_depth = Some(max);
+        self
+    }
+
+    pub fn with_max_children(mut self, max: usize) -> Self {
+        self.max_children = Some(max);
+        self
+    }
+}
+
+// a container being exported, the frames are kept on a heap stack instead of the call stack
+struct Frame {
+    children: std::vec::IntoIter<(Option<String>, Type)>,
+    omitted: usize,
+    list: Vec<Value>,
+    map: Option<Map<String, Value>>,
+    // map key of the child frame on top of this one
+    key: Option<String>,
+}
+
+impl Frame {
+    fn new(container: &Type, options: &JsonExportOptions) -> Frame {
+        let mut children = exported_children(container);
+        let map = matches!(container, Type::Map(_)).then(Map::new);
+
+        let max = options.max_children.unwrap_or(usize::MAX);
+        let omitted = children.len().saturating_sub(max);
+        children.truncate(max);
+
+        Frame {
+            children: children.into_iter(),
+            omitted,
+            list: vec![],
+            map,
+            key: None,
+        }
+    }
+
+    fn push(&mut self, key: Option<String>, value: Value) {
+        match (&mut self.map, key) {
+            (Some(map), Some(key)) => {
+                map.insert(key, value);
+            }
+            _ => self.list.push(value),
+        }
+    }
+
+    fn finish(self) -> Value {
+        let marker =
+            (self.omitted > 0).then(|| json!({ TRUNCATED: "children", "omitted": self.omitted }));
+
+        match self.map {
+            Some(mut map) => {
+                if let Some(marker) = marker {
+                    map.insert(TRUNCATED.to_string(), marker);
+                }
+                Value::Object(map)
+            }
+            None => {
+                let mut list = self.list;
+                list.extend(marker);
+                Value::Array(list)
+            }
+        }
+    }
+}
+
+pub(crate) fn is_container(item: &Type) -> bool {
+    matches!(item, Type::Map(_) | Type::List(_) | Type::Text(_))
+}
+
+// children of the container in export order, the map entries are sorted by key
+pub(crate) fn exported_children(container: &Type) -> Vec<(Option<String>, Type)> {
+    match container {
+        Type::Map(map) => {
+            let mut entries: Vec<_> = map.visible_children().into_iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            entries.into_iter().map(|(k, v)| (Some(k), v)).collect()
+        }
+        Type::List(list) => list.unique_items().into_iter().map(|v| (None, v)).collect(),
+        Type::Text(text) => {
+            text.thaw();
+            let items = text.borrow().as_list().into_iter();
+            items.map(|v| (None, v)).collect()
+        }
+        _ => vec![],
+    }
+}
+
+impl Doc {
+    /// Json of the document content within the bounds of the options. The export does
+    /// not recurse, a deep document can not overflow the stack.
+    pub fn to_json_with(&self, options: &JsonExportOptions) -> Value {
+        let max_depth = options.max_depth.unwrap_or(usize::MAX);
+        let mut stack = vec![Frame::new(&Type::Map(self.root.clone()), options)];
+
+        loop {
+            let depth = stack.len();
+            let top = stack.last_mut().unwrap();
+            match top.children.next() {
+                Some((key, child)) if is_container(&child) && depth > max_depth => {
+                    let marker = json!({ TRUNCATED: "depth", "kind": child.kind().to_string() });
+                    top.push(key, marker);
+                }
+                Some((key, child)) if is_container(&child) => {
+                    top.key = key;
+                    let frame = Frame::new(&child, options);
+                    stack.push(frame);
+                }
+                Some((key, child)) => top.push(key, child.to_json()),
+                None => {
+                    let value = stack.pop().unwrap().finish();
+                    match stack.last_mut() {
+                        Some(parent) => {
+                            let key = parent.key.take();
+                            parent.push(key, value);
+                        }
+                        None => return value,
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounded_json_export() {
+        let doc = Doc::default();
+        let mut list = doc.list();
+        doc.set("deep", list.clone());
+        for _ in 0..1_000 {
+            let child = doc.list();
+            list.append(child.clone());
+            list = child;
+        }
+        let wide = doc.list();
+        doc.set("wide", wide.clone());
+        for i in 0..5 {
+            wide.append(doc.atom(i.to_string()));
+        }
+        doc.commit();
+
+        let json = doc.to_json_with(&JsonExportOptions::default());
+        assert_eq!(json["wide"].as_array().unwrap().len(), 5);
+
+        let options = JsonExportOptions::default()
+            .with_max_depth(2)
+            .with_max_children(3);
+        let json = doc.to_json_with(&options);
+        assert_eq!(
+            json["deep"],
+            json!([[{ TRUNCATED: "depth", "kind": "list" }]])
+        );
+        assert_eq!(
+            json["wide"],
+            json!(["0", "1", "2", { TRUNCATED: "children", "omitted": 2 }])
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use std::io::Write;
+
+use crate::doc::Doc;
+use crate::json_export::{exported_children, is_container};
+use crate::types::Type;
+
+// a container being written, only the child handles of the open containers are held
+struct StreamFrame {
+    children: std::vec::IntoIter<(Option<String>, Type)>,
+    map: bool,
+    first: bool,
+}
+
+impl StreamFrame {
+    fn new(container: &Type) -> Self {
+        Self {
+            children: exported_children(container).into_iter(),
+            map: matches!(container, Type::Map(_)),
+            first: true,
+        }
+    }
+
+    fn open(&self) -> &'static [u8] {
+        if self.map {
+            b"{"
+        } else {
+            b"["
+        }
+    }
+
+    fn close(&self) -> &'static [u8] {
+        if self.map {
+            b"}"
+        } else {
+            b"]"
+        }
+    }
+}
+
+// write the json of the item, the containers are written without recursion
+fn write_type<W: Write>(writer: &mut W, item: &Type) -> Result<(), String> {
+    if !is_container(item) {
+        return serde_json::to_writer(writer, &item.to_json()).map_err(|e| e.to_string());
+    }
+
+    let root = StreamFrame::new(item);
+    write(writer, root.open())?;
+    let mut stack = vec![root];
+    while let Some(top) = stack.last_mut() {
+        let Some((key, child)) = top.children.next() else {
+            let frame = stack.pop().unwrap();
+            write(writer, frame.close())?;
+            continue;
+        };
+
+        if !top.first {
+            write(writer, b",")?;
+        }
+        top.first = false;
+        if let Some(key) = key.filter(|_| top.map) {
+            serde_json::to_writer(&mut *writer, &key).map_err(|e| e.to_string())?;
+            write(writer, b":")?;
+        }
+
+        if is_container(&child) {
+            let frame = StreamFrame::new(&child);
+            write(writer, frame.open())?;
+            stack.push(frame);
+        } else {
+            serde_json::to_writer(&mut *writer, &child.to_json()).map_err(|e| e.to_string())?;
+        }
+    }
+
+    Ok(())
+}
+
+#[inline]
+fn write<W: Write>(writer: &mut W, bytes: &[u8]) -> Result<(), String> {
+    writer.write_all(bytes).map_err(|e| e.to_string())
+}
+
+impl Doc {
+    /// Write the json of the document content to the writer container by container, the
+    /// output matches `Doc::to_json_with` without bounds. Wrap unbuffered writers in a
+    /// `BufWriter`.
+    pub fn write_json<W: Write>(&self, mut writer: W) -> Result<(), String> {
+        write_type(&mut writer, &Type::Map(self.root.clone()))?;
+        writer.flush().map_err(|e| e.to_string())
+    }
+
+    /// Write every root entry as a `{"key": value}` line, returns the number of lines
+    pub fn write_ndjson<W: Write>(&self, mut writer: W) -> Result<usize, String> {
+        let entries = exported_children(&Type::Map(self.root.clone()));
+        for (key, value) in entries.iter() {
+            let key = key.as_deref().unwrap_or_default();
+            write(&mut writer, b"{")?;
+            serde_json::to_writer(&mut writer, key).map_err(|e| e.to_string())?;
+            write(&mut writer, b":")?;
+            write_type(&mut writer, value)?;
+            write(&mut writer, b"}\n")?;
+        }
+        writer.flush().map_err(|e| e.to_string())?;
+
+        Ok(entries.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use crate::json_export::JsonExportOptions;
+
+    use super::*;
+
+    #[test]
+    fn test_json_stream() {
+        let doc = Doc::default();
+        let todos = doc.list();
+        doc.set("todos", todos.clone());
+        for title in ["milk", "say \"hi\"\n"] {
+            let todo = doc.map();
+            todos.append(todo.clone());
+            todo.set("title", doc.atom(title));
+            todo.set("tags", doc.list());
+        }
+        let note = doc.text();
+        doc.set("note", note.clone());
+        note.append(doc.string("hello"));
+        doc.set("title", doc.atom("groceries"));
+        doc.commit();
+
+        let mut out = vec![];
+        doc.write_json(&mut out).unwrap();
+        let streamed: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(streamed, doc.to_json_with(&JsonExportOptions::default()));
+        assert_eq!(streamed["todos"][1]["title"], json!("say \"hi\"\n"));
+
+        let mut out = vec![];
+        assert_eq!(doc.write_ndjson(&mut out).unwrap(), 3);
+        let lines: Vec<Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines[0], json!({"note": streamed["note"]}));
+        assert_eq!(lines[1], json!({"title": "groceries"}));
+        assert_eq!(lines[2], json!({"todos": streamed["todos"]}));
+    }
+}
@@ -0,0 +1,234 @@
+use hashbrown::HashMap;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::doc::Doc;
+use crate::id::{ClockTick, Id, WithId};
+use crate::store::DocStore;
+use crate::types::Type;
+
+/// ViewOffset is where a json view page starts
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ViewOffset {
+    /// position of the first entry
+    Index(usize),
+    /// continuation token of the last entry of the previous page
+    After(String),
+}
+
+impl Default for ViewOffset {
+    fn default() -> Self {
+        ViewOffset::Index(0)
+    }
+}
+
+impl From<usize> for ViewOffset {
+    fn from(index: usize) -> Self {
+        ViewOffset::Index(index)
+    }
+}
+
+impl From<&str> for ViewOffset {
+    fn from(token: &str) -> Self {
+        ViewOffset::After(token.to_string())
+    }
+}
+
+impl From<String> for ViewOffset {
+    fn from(token: String) -> Self {
+        ViewOffset::After(token)
+    }
+}
+
+/// JsonPage is a page of the entries of a list or a map.
+///
+/// List entries are json values, map entries are `[key, value]` pairs in key order.
+/// The `next` token stays valid while the container changes: list tokens are item ids
+/// and map tokens are keys, so pages do not skip or repeat entries when items are
+/// inserted or deleted before the token.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JsonPage {
+    pub path: String,
+    pub kind: String,
+    /// number of entries in the container
+    pub total: usize,
+    /// position of the first entry of the page
+    pub offset: usize,
+    pub entries: Vec<Value>,
+    /// token for the next page, None on the last page
+    pub next: Option<String>,
+}
+
+impl Doc {
+    /// Paginated json of the list or map at the slash separated path, e.g. `todos/3/tags`.
+    /// The empty path is the document root.
+    pub fn json_view(
+        &self,
+        path: &str,
+        offset: impl Into<ViewOffset>,
+        limit: usize,
+    ) -> Result<JsonPage, String> {
+        let container = self.resolve_path(path)?;
+        let offset = offset.into();
+
+        let (start, entries, tokens, total): (usize, Vec<Value>, Vec<String>, usize) =
+            match &container {
+                Type::List(list) => {
+                    let items = list.unique_items();
+                    let start = match &offset {
+                        ViewOffset::Index(index) => *index,
+                        ViewOffset::After(token) => list_position(&container, &items, token)?,
+                    };
+
+                    let page: Vec<&Type> = items.iter().skip(start).take(limit).collect();
+                    let entries = page.iter().map(|item| item.to_json()).collect();
+                    let tokens = page.iter().map(|item| self.item_token(item)).collect();
+                    (start, entries, tokens, items.len())
+                }
+                Type::Map(map) => {
+                    let mut keys = map.keys();
+                    keys.sort();
+                    let start = match &offset {
+                        ViewOffset::Index(index) => *index,
+                        ViewOffset::After(token) => keys.partition_point(|key| key <= token),
+                    };
+
+                    let page: Vec<&String> = keys.iter().skip(start).take(limit).collect();
+                    let entries = page
+                        .iter()
+                        .map(|key| {
+                            let value = map.get(key.as_str()).map_or(Value::Null, |v| v.to_json());
+                            Value::Array(vec![Value::from(key.as_str()), value])
+                        })
+                        .collect();
+                    let tokens = page.iter().map(|key| key.to_string()).collect();
+                    (start, entries, tokens, keys.len())
+                }
+                _ => {
+                    return Err(format!(
+                        "{} at {} is not a list or a map",
+                        container.kind(),
+                        path
+                    ))
+                }
+            };
+
+        let next = match start + entries.len() < total {
+            true => tokens.last().cloned(),
+            false => None,
+        };
+
+        Ok(JsonPage {
+            path: path.to_string(),
+            kind: container.kind().to_string(),
+            total,
+            offset: start.min(total),
+            entries,
+            next,
+        })
+    }
+
+    // find the visible container at the path, list segments are indexes
+    fn resolve_path(&self, path: &str) -> Result<Type, String> {
+        let mut current = Type::Map(self.root.clone());
+        for segment in path.split('/').filter(|s| !s.is_empty()) {
+            let child = match &current {
+                Type::Map(map) => map.get(segment),
+                Type::List(list) => segment
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|index| list.get(index)),
+                _ => None,
+            };
+
+            current = child.ok_or_else(|| format!("path {} not found", path))?;
+        }
+
+        Ok(current)
+    }
+
+    // stable token of a list item, the client is written out to outlive client id mappings
+    fn item_token(&self, item: &Type) -> String {
+        id_token(&self.store.borrow(), item.id())
+    }
+}
+
+fn id_token(store: &DocStore, id: Id) -> String {
+    match store.state.get_client(&id.client) {
+        Some(client) => format!("{}:{}", client, id.clock),
+        None => format!("{}:{}", id.client, id.clock),
+    }
+}
+
+// position of the first visible item after the item of the token,
+// deleted items are still linked so tokens of deleted items keep working
+fn list_position(list: &Type, items: &[Type], token: &str) -> Result<usize, String> {
+    let clock = token
+        .rsplit_once(':')
+        .and_then(|(_, clock)| clock.parse::<ClockTick>().ok())
+        .ok_or_else(|| format!("invalid continuation token {}", token))?;
+
+    let all = list.item_ref().borrow().all_items();
+    let pos = {
+        let store = list.item_ref().store.upgrade().unwrap();
+        let store = store.borrow();
+        all.iter().position(|item| {
+            let id = item.id();
+            id.clock == clock && id_token(&store, id) == token
+        })
+    }
+    .ok_or_else(|| format!("unknown continuation token {}", token))?;
+
+    let index: HashMap<Id, usize> = items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| (item.id(), i))
+        .collect();
+
+    Ok(all[pos + 1..]
+        .iter()
+        .find_map(|item| index.get(&item.id()).copied())
+        .unwrap_or(items.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_json_view_pages() {
+        let doc = Doc::default();
+        let todos = doc.list();
+        doc.set("todos", todos.clone());
+        for i in 0..5 {
+            todos.append(doc.atom(format!("todo {}", i)));
+        }
+        doc.set("title", doc.atom("list"));
+        doc.commit();
+
+        let page = doc.json_view("todos", 0, 2).unwrap();
+        assert_eq!(page.total, 5);
+        assert_eq!(page.entries, vec![json!("todo 0"), json!("todo 1")]);
+
+        // the token survives a delete of the last item of the page
+        todos.get(1usize).unwrap().delete();
+        doc.commit();
+
+        let page = doc.json_view("todos", page.next.unwrap(), 2).unwrap();
+        assert_eq!(page.offset, 1);
+        assert_eq!(page.entries, vec![json!("todo 2"), json!("todo 3")]);
+
+        let page = doc.json_view("todos", page.next.unwrap(), 2).unwrap();
+        assert_eq!(page.entries, vec![json!("todo 4")]);
+        assert_eq!(page.next, None);
+
+        let root = doc.json_view("", "title", 10).unwrap();
+        assert_eq!(
+            root.entries,
+            vec![json!(["todos", ["todo 0", "todo 2", "todo 3", "todo 4"]])]
+        );
+        assert!(doc.json_view("title", 0, 10).is_err());
+    }
+}
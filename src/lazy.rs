@@ -0,0 +1,406 @@
+use hashbrown::{HashMap, HashSet};
+use miniz_oxide::deflate::compress_to_vec;
+use miniz_oxide::inflate::decompress_to_vec;
+
+use crate::codec_v1::{DecoderV1, EncoderV1};
+use crate::decoder::{Decode, DecodeContext};
+use crate::diff::Diff;
+use crate::doc::{Doc, DocMeta};
+use crate::encoder::{Encode, EncodeContext, Encoder};
+use crate::id::{Id, WithId};
+use crate::item::ItemData;
+use crate::store::{DeleteItemStore, DocStore, ItemDataStore};
+use crate::subtree::{dependencies, DiffIndex};
+
+/// LazyStore keeps the root entries of a lazily loaded document as encoded diffs,
+/// an entry is decoded and applied when its key is first read
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub(crate) struct LazyStore {
+    meta: Option<DocMeta>,
+    root: Option<Id>,
+    // keys of the entries, the entries tied together share a buffer
+    keys: HashMap<String, usize>,
+    entries: HashMap<usize, Vec<u8>>,
+    // origins of the root entries in other entries, restored once the entry is applied
+    origins: HashMap<Id, Origins>,
+    // an entry is being applied, the apply must not pull the other entries
+    hydrating: bool,
+}
+
+// left and right origin of an item
+type Origins = (Option<Id>, Option<Id>);
+
+impl LazyStore {
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    // the map is the root of a document with pending entries
+    pub(crate) fn is_lazy_root(&self, id: &Id) -> bool {
+        !self.entries.is_empty() && self.root == Some(*id)
+    }
+
+    pub(crate) fn meta(&self) -> Option<DocMeta> {
+        self.meta.clone()
+    }
+
+    // put the origins of the applied root entries back, diffs of the document keep them
+    fn restore_origins(&mut self, store: &DocStore) {
+        self.origins.retain(|id, (left_id, right_id)| {
+            let Some(item) = store.find(id) else {
+                return true;
+            };
+            let item = item.item_ref();
+            let mut item = item.borrow_mut();
+            item.data.left_id = *left_id;
+            item.data.right_id = *right_id;
+            false
+        });
+    }
+
+    fn insert(&mut self, keys: &[String], diff: &Diff) {
+        let mut e = EncoderV1::new();
+        diff.encode(&mut e, &mut EncodeContext::default());
+
+        let entry = self.entries.len();
+        self.entries.insert(entry, compress_to_vec(&e.buffer(), 6));
+        for key in keys {
+            self.keys.insert(key.clone(), entry);
+        }
+    }
+
+    // take the entry of the key, all entries without a key
+    fn take(&mut self, key: Option<&str>) -> Vec<Diff> {
+        let bufs: Vec<Vec<u8>> = match key {
+            Some(key) => {
+                let Some(entry) = self.keys.get(key).copied() else {
+                    return vec![];
+                };
+                self.keys.retain(|_, e| *e != entry);
+                self.entries.remove(&entry).into_iter().collect()
+            }
+            None => {
+                self.keys.clear();
+                self.entries.drain().map(|(_, buf)| buf).collect()
+            }
+        };
+
+        bufs.iter().map(|buf| decode_entry(buf)).collect()
+    }
+}
+
+fn decode_entry(buf: &[u8]) -> Diff {
+    // the buffer is written by LazyStore::insert, a broken buffer is a bug
+    let buf = decompress_to_vec(buf).expect("lazy entry buffer is corrupted");
+    Diff::decode(&mut DecoderV1::new(buf), &DecodeContext::default())
+        .expect("lazy entry buffer is corrupted")
+}
+
+// root entry keys of the diff items, items outside the root entries have no key
+struct EntryKeys<'a> {
+    index: DiffIndex<'a>,
+    diff: &'a Diff,
+    root: Id,
+    parents: HashMap<Id, Option<Id>>,
+    keys: HashMap<Id, Option<String>>,
+}
+
+impl<'a> EntryKeys<'a> {
+    // the codec drops the parent of items with an origin, the siblings share the parent
+    fn parent_of(&mut self, item: &'a ItemData) -> Option<Id> {
+        let mut path = vec![];
+        let mut current = Some(item);
+        let mut parent = None;
+        while let Some(item) = current {
+            if let Some(known) = self.parents.get(&item.id) {
+                parent = *known;
+                break;
+            }
+            if item.parent_id.is_some() {
+                parent = item.parent_id;
+                path.push(item.id);
+                break;
+            }
+
+            path.push(item.id);
+            current = [item.left_id, item.right_id]
+                .iter()
+                .flatten()
+                .find_map(|id| self.index.find(id));
+        }
+
+        for id in path {
+            self.parents.insert(id, parent);
+        }
+        parent
+    }
+
+    fn key_of(&mut self, item: &'a ItemData) -> Option<String> {
+        let mut path = vec![];
+        let mut current = Some(item);
+        let mut key = None;
+        while let Some(item) = current {
+            if let Some(known) = self.keys.get(&item.id) {
+                key = known.clone();
+                break;
+            }
+
+            path.push(item.id);
+            let parent = self.parent_of(item);
+            if parent == Some(self.root) {
+                key = item
+                    .field
+                    .and_then(|f| self.diff.fields.get_field(&f).cloned());
+                break;
+            }
+            current = parent.and_then(|id| self.index.find(&id));
+        }
+
+        for id in path {
+            self.keys.insert(id, key.clone());
+        }
+        key
+    }
+}
+
+// split the diff into the root entries and the rest, the entries that depend on each
+// other, e.g. through a move, are kept together under all their keys
+fn partition(diff: &Diff) -> Option<(Diff, Vec<(Vec<String>, Diff)>, HashMap<Id, Origins>)> {
+    let root = diff.get_root()?.id;
+    let mut keys = EntryKeys {
+        index: DiffIndex::new(diff),
+        diff,
+        root,
+        parents: HashMap::new(),
+        keys: HashMap::new(),
+    };
+
+    let items: Vec<&ItemData> = diff
+        .items
+        .iter()
+        .flat_map(|(_, store)| store.iter().map(|(_, item)| item))
+        .collect();
+
+    // merged keys point to the key they were merged into
+    let mut merged: HashMap<String, String> = HashMap::new();
+    let resolve = |merged: &HashMap<String, String>, mut key: String| {
+        while let Some(next) = merged.get(&key) {
+            key = next.clone();
+        }
+        key
+    };
+
+    let mut entry_of: Vec<(&ItemData, Option<String>)> = vec![];
+    for item in items {
+        let key = keys.key_of(item);
+        // the root entries are ordered by their origins but do not depend on each other
+        let entry = keys.parent_of(item) == Some(root);
+        let origin = |dep: &Id| entry && [item.left_id, item.right_id].contains(&Some(*dep));
+        if let Some(key) = &key {
+            for dep in dependencies(item).iter().filter(|dep| !origin(dep)) {
+                let other = keys.index.find(dep).and_then(|dep| keys.key_of(dep));
+                if let Some(other) = other {
+                    let (a, b) = (resolve(&merged, key.clone()), resolve(&merged, other));
+                    if a != b {
+                        merged.insert(b, a);
+                    }
+                }
+            }
+        }
+        entry_of.push((item, key));
+    }
+
+    let empty = |changes| Diff {
+        changes,
+        ..diff.clone_meta()
+    };
+    let mut shell = empty(diff.changes.clone());
+    let mut entries: HashMap<String, Diff> = HashMap::new();
+    let mut targets: HashMap<Id, String> = HashMap::new();
+    let mut origins = HashMap::new();
+    for (item, key) in entry_of {
+        match key.map(|key| resolve(&merged, key)) {
+            Some(key) => {
+                // an entry is applied alone, the origins in other entries are dropped until
+                // the entry is applied, the order of the root entries does not matter
+                let entry = keys.parent_of(item) == Some(root);
+                let mut item = item.clone();
+                if entry {
+                    let mut inside = |id: Option<Id>| {
+                        let other = id.and_then(|id| keys.index.find(&id));
+                        let other = other.and_then(|other| keys.key_of(other));
+                        id.filter(|_| {
+                            other.map(|other| resolve(&merged, other)) == Some(key.clone())
+                        })
+                    };
+                    let (left_id, right_id) = (inside(item.left_id), inside(item.right_id));
+                    if (left_id, right_id) != (item.left_id, item.right_id) {
+                        origins.insert(item.id, (item.left_id, item.right_id));
+                        item.left_id = left_id;
+                        item.right_id = right_id;
+                        item.parent_id = Some(root);
+                    }
+                }
+
+                targets.insert(item.id, key.clone());
+                entries
+                    .entry(key)
+                    .or_insert_with(|| empty(Default::default()))
+                    .items
+                    .insert(item);
+            }
+            None => shell.items.insert(item.clone()),
+        }
+    }
+
+    for (_, store) in diff.deletes.iter() {
+        for (_, delete) in store.iter() {
+            let key = keys
+                .index
+                .find(&delete.target())
+                .and_then(|item| targets.get(&item.id));
+            match key.and_then(|key| entries.get_mut(key)) {
+                Some(entry) => entry.deletes.insert(delete.clone()),
+                None => shell.deletes.insert(delete.clone()),
+            }
+        }
+    }
+
+    let mut groups: HashMap<String, Vec<String>> = HashMap::new();
+    let all_keys: HashSet<String> = merged.keys().chain(entries.keys()).cloned().collect();
+    for key in all_keys {
+        groups
+            .entry(resolve(&merged, key.clone()))
+            .or_default()
+            .push(key);
+    }
+    let entries = entries
+        .into_iter()
+        .map(|(key, entry)| (groups.remove(&key).unwrap_or_default(), entry))
+        .collect();
+
+    Some((shell, entries, origins))
+}
+
+impl Diff {
+    // an empty diff with the document, clients and fields of the diff
+    fn clone_meta(&self) -> Diff {
+        Diff {
+            created_by: self.created_by.clone(),
+            doc_id: self.doc_id.clone(),
+            fields: self.fields.clone(),
+            state: self.state.clone(),
+            changes: Default::default(),
+            items: ItemDataStore::default(),
+            deletes: DeleteItemStore::default(),
+            features: self.features.clone(),
+        }
+    }
+}
+
+impl Doc {
+    /// Create a document from the diff without materializing the root entries. An entry
+    /// is decoded from the diff when it is first read or written through its key, reading
+    /// the whole document, diffs and remote changes decode all the entries first.
+    pub fn from_diff_lazy(diff: &Diff) -> Option<Doc> {
+        let (shell, entries, origins) = partition(diff)?;
+        let doc = Doc::from(&shell)?;
+
+        {
+            let mut store = doc.store.borrow_mut();
+            store.lazy.meta = Some(doc.meta.clone());
+            store.lazy.root = Some(doc.root.id());
+            store.lazy.origins = origins;
+            for (keys, entry) in entries.iter() {
+                store.lazy.insert(keys, entry);
+            }
+        }
+
+        Some(doc)
+    }
+
+    /// Number of root entries not decoded yet
+    pub fn lazy_entries(&self) -> usize {
+        self.store.borrow().lazy.len()
+    }
+
+    /// Decode the root entries of a lazily loaded document
+    pub fn hydrate_all(&self) {
+        self.hydrate(None);
+    }
+
+    // apply the entry of the key, all entries without a key
+    pub(crate) fn hydrate(&self, key: Option<&str>) {
+        let diffs = match self.store.try_borrow_mut() {
+            Ok(mut store) if !store.lazy.is_empty() && !store.lazy.hydrating => {
+                store.lazy.hydrating = true;
+                store.lazy.take(key)
+            }
+            _ => return,
+        };
+
+        for diff in diffs.iter() {
+            self.apply(diff);
+        }
+        let mut store = self.store_mut("hydrate");
+        let mut lazy = std::mem::take(&mut store.lazy);
+        lazy.restore_origins(&store);
+        lazy.hydrating = false;
+        store.lazy = lazy;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::state::ClientState;
+    use crate::sync::equal_docs;
+    use crate::types::Type;
+
+    use super::*;
+
+    #[test]
+    fn test_from_diff_lazy() {
+        let doc = Doc::default();
+        let pages = doc.map();
+        doc.set("pages", pages.clone());
+        let body = doc.text();
+        pages.set("body", body.clone());
+        body.append(doc.string("hello"));
+        let todos = doc.list();
+        doc.set("todos", todos.clone());
+        todos.append(doc.atom("milk"));
+        doc.set("title", doc.atom("draft"));
+        doc.set("title", doc.atom("notes"));
+        doc.commit();
+
+        // the moved value ties the entries together
+        let archive = doc.map();
+        doc.set("archive", archive.clone());
+        let todo: Type = todos.borrow().as_list()[0].clone();
+        todo.move_to_key(&archive, "done");
+        doc.commit();
+
+        let diff = doc.diff(ClientState::default());
+        let lazy = Doc::from_diff_lazy(&diff).unwrap();
+        assert_eq!(lazy.lazy_entries(), 3);
+        let before = lazy.memory_stats().hot_items;
+
+        assert_eq!(lazy.get("title").unwrap().to_json(), "notes");
+        assert_eq!(lazy.lazy_entries(), 2);
+        let body = lazy.get("pages").unwrap().get("body").unwrap();
+        assert_eq!(body.text_content(), "hello");
+        assert_eq!(lazy.lazy_entries(), 1);
+        assert!(lazy.memory_stats().hot_items > before);
+
+        // reading the whole document decodes the rest
+        assert_eq!(lazy.get("archive").unwrap().to_json()["done"], "milk");
+        assert_eq!(lazy.lazy_entries(), 0);
+        assert!(equal_docs(&doc, &lazy));
+    }
+}
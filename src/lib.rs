@@ -10,66 +10,203 @@
 #![allow(unused_must_use)]
 #![allow(clippy::derived_hash_with_manual_eq)]
 
+pub use crate::activity::*;
+pub use crate::annotation::*;
+pub use crate::apply_stats::*;
+pub use crate::attribution::*;
+pub use crate::awareness::*;
+pub use crate::bridge::*;
 pub use crate::change::*;
+pub use crate::change_budget::*;
+pub use crate::checkpoint::*;
+pub use crate::checksum::*;
+pub use crate::chunk::*;
+pub use crate::coalesce::*;
+pub use crate::cold::*;
+#[cfg(any(test, feature = "conformance"))]
+pub use crate::conformance::*;
+pub use crate::content_eq::*;
+pub use crate::dag_metrics::*;
 pub use crate::diff::*;
 pub use crate::diffstore::*;
 pub use crate::doc::*;
+pub use crate::draft::*;
+pub use crate::event::*;
+pub use crate::features::*;
+pub use crate::frame::*;
+pub use crate::frontier::*;
+pub use crate::gc::*;
+pub use crate::health::*;
+pub use crate::history::*;
 pub use crate::id::*;
+pub use crate::id_alloc::*;
+pub use crate::id_set::*;
+pub use crate::inspect::*;
 pub use crate::item::*;
+pub use crate::journal::*;
+pub use crate::json::*;
+pub use crate::json_export::*;
+pub use crate::json_view::*;
+pub use crate::limits::*;
+pub use crate::mark::*;
+pub use crate::mark_inherit::*;
+pub use crate::ncounter::*;
+pub use crate::nkv::*;
+pub use crate::nproxy::*;
 pub use crate::nstring::*;
 pub use crate::ntext::*;
+pub use crate::observe::*;
+pub use crate::patch::*;
+pub use crate::persist::*;
+pub use crate::preview::*;
+pub use crate::provenance::*;
+pub use crate::raw::*;
+pub use crate::redact::*;
+pub use crate::refs::*;
+pub use crate::registry::*;
+pub use crate::relative_position::*;
+pub use crate::retention::*;
 pub use crate::richtext::*;
+pub use crate::schema::*;
+pub use crate::snapshot::*;
+pub use crate::soak::*;
+pub use crate::sql::*;
 pub use crate::state::*;
 pub use crate::sync::*;
+pub use crate::tally::*;
+pub use crate::template::*;
+pub use crate::text_change::*;
+pub use crate::text_delta::*;
+pub use crate::text_mark::*;
+pub use crate::text_offset::*;
+pub use crate::transaction::*;
+pub use crate::transfer::*;
+pub use crate::trash::*;
 pub use crate::types::*;
+pub use crate::undo_redo::*;
 pub use crate::utils::*;
+pub use crate::weight::*;
+pub use crate::write_token::*;
+pub use crate::xml::*;
 
 use crate::index::*;
 
+mod activity;
+mod anchor;
+mod annotation;
+mod apply_stats;
+mod attribution;
+mod awareness;
 mod bimapid;
+mod bridge;
+mod cbor;
 mod change;
+mod change_budget;
 mod change_btree;
 mod change_list;
 mod change_sorter;
 mod change_store;
+mod checkpoint;
+mod checksum;
+mod chunk;
+mod coalesce;
 pub mod codec_v1;
+pub mod codec_v2;
+mod cold;
+#[cfg(any(test, feature = "conformance"))]
+mod conformance;
+mod content_eq;
 mod crdt_fugue;
 mod crdt_yata;
 mod cycle;
 mod dag;
+mod dag_metrics;
 pub mod decoder;
 mod delete;
 mod diff;
 pub mod diffstore;
 mod doc;
+mod draft;
 pub mod encoder;
+mod event;
+mod features;
+mod format;
+mod frame;
 mod frontier;
+mod gc;
 mod hash;
+mod health;
+mod history;
 mod id;
+mod id_alloc;
+mod id_set;
 mod id_store;
 mod index;
 mod index_map;
+mod inspect;
+mod invariants;
 mod item;
+mod journal;
 mod json;
+mod json_export;
+mod json_stream;
+mod json_view;
+mod lazy;
+mod limits;
+mod line_index;
 mod mark;
+mod mark_inherit;
 mod natom;
+mod ncounter;
+mod nkv;
 mod nlist;
 mod nmap;
 mod nmark;
 mod nmove;
+mod nproxy;
 mod nstring;
 mod ntext;
 mod ntree;
+mod observe;
+mod packed;
+mod patch;
 mod persist;
+mod preview;
+mod provenance;
 mod queue_store;
+mod raw;
+mod redact;
+mod refs;
+mod registry;
+mod relative_position;
+mod retention;
 mod richtext;
+mod schema;
+mod snapshot;
+mod soak;
+mod sql;
 mod state;
 mod store;
+mod subtree;
 mod sync;
 mod table;
+mod tally;
+mod template;
+pub mod testing;
+mod text_change;
+mod text_delta;
+mod text_mark;
+mod text_offset;
+mod tombstone;
 mod transaction;
+mod transfer;
+mod trash;
 mod tx;
 mod types;
 mod undo_redo;
+mod unique;
 mod utils;
 mod version;
+mod weight;
+mod write_token;
+mod xml;
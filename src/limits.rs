@@ -0,0 +1,188 @@
+use std::fmt::{Display, Formatter};
+
+use serde::Serialize;
+
+use crate::apply_stats::{ApplyStats, ApplyWarning};
+use crate::decoder::DecodeContext;
+use crate::diff::Diff;
+use crate::doc::Doc;
+
+/// DiffLimit names a bounded part of a diff
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+pub enum DiffLimit {
+    Clients,
+    Changes,
+    Items,
+    Pending,
+}
+
+/// LimitError is the structured rejection of a diff over one of the limits
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct LimitError {
+    pub limit: DiffLimit,
+    pub count: usize,
+    pub max: usize,
+}
+
+impl Display for LimitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "diff limit exceeded: {:?} {} > {}",
+            self.limit, self.count, self.max
+        )
+    }
+}
+
+/// DiffLimits bound the diffs a document decodes and applies, so a crafted diff that
+/// declares millions of clients or changes is rejected before it allocates the stores.
+/// All limits are off by default.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct DiffLimits {
+    pub max_clients: Option<usize>,
+    /// changes of all the clients
+    pub max_changes: Option<usize>,
+    /// items and deletes of all the clients
+    pub max_items: Option<usize>,
+    /// items parked with unmet dependencies, kept by the document between applies
+    pub max_pending: Option<usize>,
+}
+
+impl DiffLimits {
+    pub fn with_max_clients(mut self, max: usize) -> Self {
+        self.max_clients = Some(max);
+        self
+    }
+
+    pub fn with_max_changes(mut self, max: usize) -> Self {
+        self.max_changes = Some(max);
+        self
+    }
+
+    pub fn with_max_items(mut self, max: usize) -> Self {
+        self.max_items = Some(max);
+        self
+    }
+
+    pub fn with_max_pending(mut self, max: usize) -> Self {
+        self.max_pending = Some(max);
+        self
+    }
+
+    pub fn check(&self, limit: DiffLimit, count: usize) -> Result<(), LimitError> {
+        let max = match limit {
+            DiffLimit::Clients => self.max_clients,
+            DiffLimit::Changes => self.max_changes,
+            DiffLimit::Items => self.max_items,
+            DiffLimit::Pending => self.max_pending,
+        };
+
+        match max {
+            Some(max) if count > max => Err(LimitError { limit, count, max }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check the sizes of a decoded diff
+    pub fn check_diff(&self, diff: &Diff) -> Result<(), LimitError> {
+        self.check(DiffLimit::Clients, diff.state.clients.size() as usize)?;
+
+        let changes = diff.changes.iter().map(|(_, s)| s.size()).sum();
+        self.check(DiffLimit::Changes, changes)?;
+
+        let items = diff.items.size() + diff.deletes.size();
+        self.check(DiffLimit::Items, items as usize)
+    }
+}
+
+impl DecodeContext {
+    /// Context that stops decoding as soon as a declared size is over the limits
+    pub fn with_limits(limits: DiffLimits) -> Self {
+        DecodeContext {
+            limits,
+            ..DecodeContext::default()
+        }
+    }
+
+    // check a declared size, for the decoders
+    pub(crate) fn check_limit(&self, limit: DiffLimit, count: usize) -> Result<(), String> {
+        self.limits.check(limit, count).map_err(|e| e.to_string())
+    }
+}
+
+impl Doc {
+    /// Set the limits checked by `apply`, over limit diffs are rejected
+    pub fn set_diff_limits(&self, limits: DiffLimits) {
+        self.store_mut("set_diff_limits").diff_limits = limits;
+    }
+
+    pub fn diff_limits(&self) -> DiffLimits {
+        self.store.borrow().diff_limits
+    }
+
+    // drop the parked items when there are too many of them, they are not part of the
+    // document state so the sender delivers them again on the next sync
+    pub(crate) fn bound_pending(&self, stats: &mut ApplyStats) {
+        let mut store = self.store_mut("bound_pending");
+        let pending = store.pending.items.size() + store.pending.delete_items.size();
+        if let Err(err) = store
+            .diff_limits
+            .check(DiffLimit::Pending, pending as usize)
+        {
+            store.pending = Default::default();
+            stats.pending = 0;
+            stats.warn(ApplyWarning::LimitExceeded(err));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec_v1::{DecoderV1, EncoderV1};
+    use crate::decoder::Decode;
+    use crate::doc::CloneDeep;
+    use crate::encoder::{Encode, EncodeContext, Encoder};
+    use crate::state::ClientState;
+
+    use super::*;
+
+    #[test]
+    fn test_diff_limits() {
+        let d1 = Doc::default();
+        d1.commit();
+        let d2 = d1.clone_deep();
+        d2.update_client();
+
+        let list = d1.list();
+        d1.set("list", list.clone());
+        for i in 0..10 {
+            list.append(d1.atom(i.to_string()));
+        }
+        d1.commit();
+        let diff = d1.diff(ClientState::default());
+
+        let mut e = EncoderV1::new();
+        diff.encode(&mut e, &mut EncodeContext::default());
+        let limits = DiffLimits::default().with_max_items(5);
+        let mut d = DecoderV1::new(e.buffer());
+        let err = Diff::decode(&mut d, &DecodeContext::with_limits(limits)).unwrap_err();
+        assert!(err.contains("Items"));
+
+        d2.set_diff_limits(limits);
+        let stats = d2.apply(&diff);
+        assert!(stats.is_rejected());
+        assert!(matches!(
+            stats.warnings.as_slice(),
+            [ApplyWarning::LimitExceeded(LimitError {
+                limit: DiffLimit::Items,
+                max: 5,
+                ..
+            })]
+        ));
+        assert!(d2.get("list").is_none());
+
+        d2.set_diff_limits(DiffLimits::default().with_max_clients(10));
+        assert!(!d2.apply(&diff).is_rejected());
+        assert_eq!(d2.get("list").unwrap().size(), 10);
+    }
+}
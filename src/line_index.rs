@@ -0,0 +1,219 @@
+use hashbrown::HashMap;
+
+use crate::id::{ClockTick, Id, WithId};
+use crate::item::ItemIterator;
+use crate::ntext::NText;
+use crate::store::DocStore;
+
+/// LineIndex maps between the byte offsets and the lines of a text.
+///
+/// The index is rebuilt on the first lookup after an edit. Only the string runs not seen
+/// before are scanned for newlines, the content of a run never changes.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub(crate) struct LineIndex {
+    // offsets of the line starts, the first line starts at 0
+    starts: Vec<u32>,
+    size: u32,
+    // newline offsets in every string run by run id and size, runs are split by edits
+    runs: HashMap<(Id, u32), Vec<u32>>,
+    // store clock the index was built at
+    clock: Option<ClockTick>,
+}
+
+impl LineIndex {
+    #[inline]
+    fn is_valid(&self, clock: ClockTick) -> bool {
+        self.clock == Some(clock)
+    }
+
+    #[inline]
+    pub(crate) fn invalidate(&mut self) {
+        self.clock = None;
+    }
+
+    fn update(&mut self, text: &NText, clock: ClockTick) {
+        let mut runs = HashMap::new();
+        self.starts = vec![0];
+        self.size = 0;
+
+        for item in text.visible_item_iter() {
+            let size = item.size();
+            let key = (item.id(), size);
+            let newlines = self.runs.remove(&key).unwrap_or_else(|| {
+                if !item.kind().is_string() {
+                    return vec![];
+                }
+                item.text_content()
+                    .bytes()
+                    .enumerate()
+                    .filter(|(_, byte)| *byte == b'\n')
+                    .map(|(offset, _)| offset as u32)
+                    .collect()
+            });
+
+            let base = self.size;
+            self.starts
+                .extend(newlines.iter().map(|offset| base + offset + 1));
+            self.size += size;
+            runs.insert(key, newlines);
+        }
+
+        self.runs = runs;
+        self.clock = Some(clock);
+    }
+
+    #[inline]
+    fn line_count(&self) -> u32 {
+        self.starts.len() as u32
+    }
+
+    #[inline]
+    fn offset_of_line(&self, line: u32) -> Option<u32> {
+        self.starts.get(line as usize).copied()
+    }
+
+    fn line_of_offset(&self, offset: u32) -> Option<u32> {
+        if offset > self.size {
+            return None;
+        }
+
+        Some(self.starts.partition_point(|start| *start <= offset) as u32 - 1)
+    }
+}
+
+impl DocStore {
+    // remote diffs and rollbacks change texts without moving the clock forward
+    pub(crate) fn invalidate_line_indexes(&mut self) {
+        self.line_indexes
+            .values_mut()
+            .for_each(LineIndex::invalidate);
+    }
+}
+
+impl NText {
+    /// Keep a line index for the text, code editors address it by line and column
+    /// without scanning the text on every lookup
+    pub fn enable_line_index(&self) {
+        if let Some(store) = self.store.upgrade() {
+            store
+                .borrow_mut()
+                .line_indexes
+                .entry(self.id())
+                .or_default();
+        }
+    }
+
+    pub fn disable_line_index(&self) {
+        if let Some(store) = self.store.upgrade() {
+            store.borrow_mut().line_indexes.remove(&self.id());
+        }
+    }
+
+    /// Number of lines, an empty text has a single line
+    pub fn line_count(&self) -> u32 {
+        self.with_line_index(|index| index.line_count())
+    }
+
+    /// Byte offset of the first character of the zero based line
+    pub fn offset_of_line(&self, line: u32) -> Option<u32> {
+        self.with_line_index(|index| index.offset_of_line(line))
+    }
+
+    /// Zero based line holding the byte offset, the end of the text is on the last line
+    pub fn line_of_offset(&self, offset: u32) -> Option<u32> {
+        self.with_line_index(|index| index.line_of_offset(offset))
+    }
+
+    // look up the cached index, texts without an enabled index are scanned every time
+    fn with_line_index<R>(&self, f: impl FnOnce(&LineIndex) -> R) -> R {
+        self.thaw();
+        let Some(store) = self.store.upgrade() else {
+            let mut index = LineIndex::default();
+            index.update(self, 0);
+            return f(&index);
+        };
+
+        let (cached, clock) = {
+            let mut store = store.borrow_mut();
+            (store.line_indexes.remove(&self.id()), store.clock)
+        };
+        let enabled = cached.is_some();
+        let mut index = cached.unwrap_or_default();
+        if !index.is_valid(clock) {
+            index.update(self, clock);
+        }
+
+        let result = f(&index);
+        if enabled {
+            store.borrow_mut().line_indexes.insert(self.id(), index);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doc::{CloneDeep, Doc};
+    use crate::state::ClientState;
+    use crate::Type;
+
+    #[test]
+    fn test_line_index() {
+        let doc = Doc::default();
+        let text = doc.text();
+        doc.set("text", text.clone());
+        text.enable_line_index();
+        assert_eq!(text.line_count(), 1);
+
+        text.append(doc.string("fn main() {\n}\n"));
+        assert_eq!(text.line_count(), 3);
+        assert_eq!(text.offset_of_line(1), Some(12));
+        assert_eq!(text.line_of_offset(11), Some(0));
+        assert_eq!(text.line_of_offset(12), Some(1));
+        assert_eq!(text.line_of_offset(14), Some(2));
+        assert_eq!(text.line_of_offset(15), None);
+
+        // a local edit splitting the run
+        text.insert(12, doc.string("    run();\n"));
+        assert_eq!(text.line_count(), 4);
+        assert_eq!(text.offset_of_line(2), Some(23));
+        doc.commit();
+
+        // a remote edit
+        let remote = doc.clone_deep();
+        remote.update_client();
+        remote
+            .get("text")
+            .unwrap()
+            .insert(0, remote.string("// main\n"));
+        remote.commit();
+        doc.apply(&remote.diff(ClientState::default()));
+        assert_eq!(text.line_count(), 5);
+        assert_eq!(text.offset_of_line(1), Some(8));
+        assert_eq!(text.line_of_offset(8), Some(1));
+    }
+
+    #[test]
+    fn test_line_index_after_backspace() {
+        let doc = Doc::default();
+        let text = doc.text();
+        doc.set("text", text.clone());
+        text.enable_line_index();
+        let chars: Vec<Type> = ["a", "\n", "b", "\n", "c"]
+            .map(|c| Type::from(doc.string(c)))
+            .into();
+        for c in &chars {
+            text.append(c.clone());
+        }
+        doc.commit();
+
+        chars[4].delete();
+        assert_eq!(text.line_count(), 3);
+
+        // the backspace extends the pending delete without a new clock tick
+        chars[3].delete();
+        assert_eq!(text.line_count(), 2);
+        assert_eq!(text.offset_of_line(2), None);
+    }
+}
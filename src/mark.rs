@@ -3,21 +3,39 @@ use serde_json::Value;
 
 use crate::decoder::{Decode, DecodeContext, Decoder};
 use crate::encoder::{Encode, EncodeContext, Encoder};
-use crate::id::IdRange;
+use crate::id::{Id, IdRange};
 
 #[derive(Debug, Clone, Default, Eq, PartialEq)]
 pub(crate) struct MarkContent {
     pub(crate) range: IdRange,
     pub(crate) data: Mark,
+    // start and end of a text mark, see NText::mark
+    pub(crate) anchors: Option<(MarkAnchor, MarkAnchor)>,
 }
 
 impl MarkContent {
     pub(crate) fn new(range: IdRange, data: Mark) -> Self {
-        Self { range, data }
+        Self {
+            range,
+            data,
+            anchors: None,
+        }
+    }
+
+    pub(crate) fn anchored(data: Mark, start: MarkAnchor, end: MarkAnchor) -> Self {
+        Self {
+            range: IdRange::default(),
+            data,
+            anchors: Some((start, end)),
+        }
     }
 
+    // an anchored mark takes a single tick, a range mark as many as the marked range
     pub(crate) fn size(&self) -> u32 {
-        self.range.size()
+        match self.anchors {
+            Some(_) => 1,
+            None => self.range.size(),
+        }
     }
 
     pub(crate) fn split(&self, offset: u32) -> (MarkContent, MarkContent) {
@@ -90,7 +108,14 @@ impl MarkContent {
     }
 
     pub(crate) fn get_key(&self) -> String {
-        match self.data {
+        self.data.key()
+    }
+}
+
+impl Mark {
+    /// Name of the mark, marks with the same name replace each other
+    pub fn key(&self) -> String {
+        match self {
             Mark::Bold => "bold".to_string(),
             Mark::Italic => "italic".to_string(),
             Mark::Underline => "underline".to_string(),
@@ -101,7 +126,7 @@ impl MarkContent {
             Mark::Color(_) => "color".to_string(),
             Mark::Background(_) => "background".to_string(),
             Mark::Link(_) => "link".to_string(),
-            Mark::Custom(ref name, _) => name.to_string(),
+            Mark::Custom(name, _) => name.to_string(),
             Mark::Id(_) => "id".to_string(),
             Mark::None => "_".to_string(),
         }
@@ -162,6 +187,14 @@ impl Encode for MarkContent {
     fn encode<T: Encoder>(&self, e: &mut T, ctx: &mut EncodeContext) {
         self.range.encode(e, ctx);
         self.data.encode(e, ctx);
+        match &self.anchors {
+            Some((start, end)) => {
+                e.u8(1);
+                start.encode(e, ctx);
+                end.encode(e, ctx);
+            }
+            None => e.u8(0),
+        }
     }
 }
 
@@ -169,12 +202,73 @@ impl Decode for MarkContent {
     fn decode<D: Decoder>(d: &mut D, ctx: &DecodeContext) -> Result<MarkContent, String> {
         let range = IdRange::decode(d, ctx)?;
         let data = Mark::decode(d, ctx)?;
-        Ok(MarkContent::new(range, data))
+        let anchors = match d.u8()? {
+            0 => None,
+            _ => Some((MarkAnchor::decode(d, ctx)?, MarkAnchor::decode(d, ctx)?)),
+        };
+
+        Ok(MarkContent {
+            range,
+            data,
+            anchors,
+        })
     }
 }
 
+/// MarkAnchor is an edge of a text mark, the gap before or after a character.
+/// An anchor without a character is the start or the end of the text.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub(crate) struct MarkAnchor {
+    pub(crate) id: Option<Id>,
+    pub(crate) after: bool,
+}
+
+impl MarkAnchor {
+    #[inline]
+    pub(crate) fn before(id: Id) -> Self {
+        Self {
+            id: Some(id),
+            after: false,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn after(id: Id) -> Self {
+        Self {
+            id: Some(id),
+            after: true,
+        }
+    }
+}
+
+impl Encode for MarkAnchor {
+    fn encode<T: Encoder>(&self, e: &mut T, ctx: &mut EncodeContext) {
+        let flags = self.id.is_some() as u8 | (self.after as u8) << 1;
+        e.u8(flags);
+        if let Some(id) = &self.id {
+            id.encode(e, ctx);
+        }
+    }
+}
+
+impl Decode for MarkAnchor {
+    fn decode<D: Decoder>(d: &mut D, ctx: &DecodeContext) -> Result<MarkAnchor, String> {
+        let flags = d.u8()?;
+        let id = match flags & 1 {
+            0 => None,
+            _ => Some(Id::decode(d, ctx)?),
+        };
+
+        Ok(MarkAnchor {
+            id,
+            after: flags & 2 != 0,
+        })
+    }
+}
+
+/// Mark is a formatting or annotation mark of a string or container
 #[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
-pub(crate) enum Mark {
+pub enum Mark {
     Bold,
     Italic,
     Underline,
@@ -220,11 +314,60 @@ impl Serialize for Mark {
 }
 
 impl Encode for Mark {
-    fn encode<T: Encoder>(&self, e: &mut T, ctx: &mut EncodeContext) {}
+    fn encode<T: Encoder>(&self, e: &mut T, _ctx: &mut EncodeContext) {
+        match self {
+            Mark::Bold => e.u8(0),
+            Mark::Italic => e.u8(1),
+            Mark::Underline => e.u8(2),
+            Mark::StrikeThrough => e.u8(3),
+            Mark::Code => e.u8(4),
+            Mark::Subscript => e.u8(5),
+            Mark::Superscript => e.u8(6),
+            Mark::Color(color) => {
+                e.u8(7);
+                e.string(color);
+            }
+            Mark::Background(color) => {
+                e.u8(8);
+                e.string(color);
+            }
+            Mark::Link(url) => {
+                e.u8(9);
+                e.string(url);
+            }
+            Mark::Custom(name, json) => {
+                e.u8(10);
+                e.string(name);
+                e.string(json);
+            }
+            Mark::None => e.u8(11),
+            Mark::Id(id) => {
+                e.u8(12);
+                e.u32(*id);
+            }
+        }
+    }
 }
 
 impl Decode for Mark {
-    fn decode<D: Decoder>(d: &mut D, ctx: &DecodeContext) -> Result<Mark, String> {
-        todo!()
+    fn decode<D: Decoder>(d: &mut D, _ctx: &DecodeContext) -> Result<Mark, String> {
+        let mark = match d.u8()? {
+            0 => Mark::Bold,
+            1 => Mark::Italic,
+            2 => Mark::Underline,
+            3 => Mark::StrikeThrough,
+            4 => Mark::Code,
+            5 => Mark::Subscript,
+            6 => Mark::Superscript,
+            7 => Mark::Color(d.string()?),
+            8 => Mark::Background(d.string()?),
+            9 => Mark::Link(d.string()?),
+            10 => Mark::Custom(d.string()?, d.string()?),
+            11 => Mark::None,
+            12 => Mark::Id(d.u32()?),
+            flag => return Err(format!("invalid mark flag: {}", flag)),
+        };
+
+        Ok(mark)
     }
 }
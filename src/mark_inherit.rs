@@ -0,0 +1,214 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use hashbrown::HashSet;
+
+use crate::doc::Doc;
+use crate::id::WithId;
+use crate::item::{Content, ItemKind};
+use crate::mark::{Mark, MarkContent};
+use crate::nmark::NMark;
+use crate::types::Type;
+
+/// InheritRule decides which new children take over a mark of an enclosing container
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub enum InheritRule {
+    /// the mark stays on the container
+    #[default]
+    Never,
+    /// every new child inherits the mark
+    Always,
+    /// only new children of the given kinds inherit the mark
+    Kinds(BTreeSet<ItemKind>),
+}
+
+impl InheritRule {
+    #[inline]
+    fn allows(&self, kind: ItemKind) -> bool {
+        match self {
+            InheritRule::Never => false,
+            InheritRule::Always => true,
+            InheritRule::Kinds(kinds) => kinds.contains(&kind),
+        }
+    }
+}
+
+/// MarkInheritance keeps the inheritance rules of the marks by mark name, e.g. `bold` or a custom mark name.
+/// Marks without a rule are not inherited.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct MarkInheritance {
+    rules: BTreeMap<String, InheritRule>,
+}
+
+impl MarkInheritance {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_rule(mut self, mark: impl Into<String>, rule: InheritRule) -> Self {
+        self.rules.insert(mark.into(), rule);
+        self
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Marks a new child of the given kind inherits from its ancestors,
+    /// `ancestors` holds the marks of every ancestor starting with the parent.
+    /// The mark of the closest ancestor wins when the same mark is set on several ancestors.
+    pub(crate) fn inherited(&self, ancestors: &[Vec<Mark>], kind: ItemKind) -> Vec<Mark> {
+        let mut seen = HashSet::new();
+        let mut marks = vec![];
+
+        for mark in ancestors.iter().flatten() {
+            let key = mark.key();
+            if !seen.insert(key.clone()) {
+                continue;
+            }
+
+            if self.rules.get(&key).is_some_and(|rule| rule.allows(kind)) {
+                marks.push(mark.clone());
+            }
+        }
+
+        marks
+    }
+}
+
+impl Type {
+    // marks set on the container itself, the anchored marks of a text mark its characters
+    fn container_marks(&self) -> Vec<Mark> {
+        self.mark_items()
+            .iter()
+            .filter_map(|item| match item.as_mark()?.content() {
+                Content::Mark(content) if content.anchors.is_none() => Some(content.data),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // mark a child inserted locally into this container with the marks the inheritance
+    // rules pass down from the container and its ancestors. Only strings and containers
+    // keep marks, the marks are regular items and reach the remote replicas with the child.
+    pub(crate) fn inherit_marks(&self, child: &Type) {
+        let Some(store) = self.store().upgrade() else {
+            return;
+        };
+        let rules = store.borrow().mark_inheritance.clone();
+        if rules.is_empty() {
+            return;
+        }
+
+        let mut ancestors = vec![];
+        let mut container = Some(self.clone());
+        while let Some(parent) = container {
+            ancestors.push(parent.container_marks());
+            container = parent.parent();
+        }
+
+        for mark in rules.inherited(&ancestors, child.kind()) {
+            match child {
+                Type::String(string) => string.add_mark(mark),
+                Type::Map(_) | Type::List(_) | Type::Text(_) => {
+                    let id = store.borrow_mut().next_id();
+                    let content = MarkContent::new(child.id().into(), mark);
+                    NMark::new(id, Content::Mark(content), self.store()).attach(child);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Doc {
+    /// Set the rules used to copy container marks onto new children at insert time
+    pub fn set_mark_inheritance(&self, rules: MarkInheritance) {
+        self.store_mut("set_mark_inheritance").mark_inheritance = rules;
+    }
+
+    pub fn mark_inheritance(&self) -> MarkInheritance {
+        self.store.borrow().mark_inheritance.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doc::CloneDeep;
+    use crate::state::ClientState;
+
+    use super::*;
+
+    #[test]
+    fn test_inherited_marks() {
+        let rules = MarkInheritance::new()
+            .with_rule("color", InheritRule::Always)
+            .with_rule(
+                "quote",
+                InheritRule::Kinds([ItemKind::Text, ItemKind::List].into()),
+            );
+
+        let quote = Mark::Custom("quote".to_string(), "{}".to_string());
+        let ancestors = vec![
+            vec![Mark::Color("red".to_string()), Mark::Bold],
+            vec![quote.clone(), Mark::Color("blue".to_string())],
+        ];
+
+        assert_eq!(
+            rules.inherited(&ancestors, ItemKind::Text),
+            vec![Mark::Color("red".to_string()), quote]
+        );
+        assert_eq!(
+            rules.inherited(&ancestors, ItemKind::Atom),
+            vec![Mark::Color("red".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_inherit_marks_on_insert() {
+        let doc = Doc::default();
+        doc.commit();
+        let remote = doc.clone_deep();
+        remote.update_client();
+
+        doc.set_mark_inheritance(
+            MarkInheritance::new()
+                .with_rule("color", InheritRule::Always)
+                .with_rule(
+                    "quote",
+                    InheritRule::Kinds([ItemKind::Text, ItemKind::List].into()),
+                ),
+        );
+
+        let quote = Mark::Custom("quote".to_string(), "{}".to_string());
+        let red = Mark::Color("red".to_string());
+        let block = doc.map();
+        doc.set("block", block.clone());
+        Type::from(block.clone()).add_mark(quote.clone());
+        Type::from(block.clone()).add_mark(red.clone());
+
+        let body = doc.text();
+        block.set("body", body.clone());
+        let title = doc.atom("title");
+        block.set("title", title.clone());
+        let hello = doc.string("hello");
+        body.append(hello.clone());
+        doc.commit();
+
+        let marks = |item: Option<Type>| {
+            let mut marks: Vec<Mark> = item.unwrap().marks().into_iter().map(|(_, m)| m).collect();
+            marks.sort_by_key(Mark::key);
+            marks
+        };
+        assert_eq!(marks(Some(body.into())), vec![red.clone(), quote.clone()]);
+        assert!(marks(Some(title.into())).is_empty());
+        assert_eq!(marks(Some(hello.into())), vec![red.clone()]);
+
+        // the inherited marks reach the remote replicas with the children
+        remote.apply(&doc.diff(ClientState::default()));
+        let body = remote.get("block").and_then(|block| block.get("body"));
+        assert_eq!(marks(body.clone()), vec![red.clone(), quote]);
+        let hello = body.and_then(|body| body.item_ref().borrow().as_list().first().cloned());
+        assert_eq!(marks(hello), vec![red]);
+    }
+}
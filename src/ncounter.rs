@@ -0,0 +1,183 @@
+use std::ops::Deref;
+
+use serde::ser::SerializeStruct;
+use serde::Serialize;
+
+use crate::id::{Id, IdRange, WithId, WithIdRange};
+use crate::item::{Any, Content, ItemData, ItemKind, ItemRef};
+use crate::natom::NAtom;
+use crate::store::WeakStoreRef;
+use crate::types::Type;
+
+/// NCounter is changed by increments from many replicas, e.g. likes, votes or stock counts.
+/// Every increment is a delta item appended to the counter, the value is the sum of the
+/// visible deltas so concurrent increments commute and an undone increment drops out.
+#[derive(Clone, Debug)]
+pub struct NCounter {
+    pub(crate) item: ItemRef,
+}
+
+impl NCounter {
+    pub(crate) fn new(id: Id, store: WeakStoreRef) -> Self {
+        let data = ItemData {
+            kind: ItemKind::Counter,
+            id,
+            ..ItemData::default()
+        };
+        Self {
+            item: ItemRef::new(data.into(), store),
+        }
+    }
+
+    /// Sum of the increments, the sum wraps around on overflow like on every replica
+    pub fn value(&self) -> i64 {
+        self.borrow()
+            .as_list()
+            .iter()
+            .map(|delta| delta_of(&delta.content()))
+            .fold(0, i64::wrapping_add)
+    }
+
+    pub fn increment(&self, by: i64) {
+        if by == 0 {
+            return;
+        }
+
+        let store = self.store.upgrade().unwrap();
+        let id = store.borrow_mut().next_id();
+        let delta: Type = NAtom::new(id, Content::Embed(Any::I64(by)), self.store.clone()).into();
+        store.borrow_mut().insert(delta.clone());
+
+        delta.set_parent(Some(self.into()));
+        self.item.append(delta);
+    }
+
+    #[inline]
+    pub fn decrement(&self, by: i64) {
+        self.increment(by.wrapping_neg())
+    }
+
+    #[inline]
+    pub(crate) fn size(&self) -> u32 {
+        1
+    }
+
+    #[inline]
+    pub(crate) fn content(&self) -> Content {
+        Content::Embed(Any::I64(self.value()))
+    }
+
+    #[inline]
+    pub(crate) fn delete(&self) {
+        self.item.delete(1);
+    }
+
+    #[inline]
+    pub(crate) fn item_ref(&self) -> ItemRef {
+        self.item.clone()
+    }
+
+    #[inline]
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        self.value().into()
+    }
+}
+
+// increment held by a delta item of a counter
+pub(crate) fn delta_of(content: &Content) -> i64 {
+    match content {
+        Content::Embed(Any::I64(delta)) => *delta,
+        _ => 0,
+    }
+}
+
+impl Serialize for NCounter {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::ser::Serializer,
+    {
+        let size = self.borrow().serialize_size() + 1;
+        let mut counter = serializer.serialize_struct("Counter", size)?;
+
+        self.serialize_with(&mut counter)?;
+
+        counter.serialize_field("value", &self.value())?;
+
+        counter.end()
+    }
+}
+
+impl WithId for NCounter {
+    #[inline]
+    fn id(&self) -> Id {
+        self.item.borrow().id()
+    }
+}
+
+impl WithIdRange for NCounter {
+    #[inline]
+    fn range(&self) -> IdRange {
+        self.borrow().id().range(1)
+    }
+}
+
+impl Deref for NCounter {
+    type Target = ItemRef;
+
+    fn deref(&self) -> &Self::Target {
+        &self.item
+    }
+}
+
+impl From<ItemRef> for NCounter {
+    fn from(item: ItemRef) -> Self {
+        Self { item }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec_v1::EncoderV1;
+    use crate::decoder::{Decode, DecodeContext};
+    use crate::diff::Diff;
+    use crate::doc::Doc;
+    use crate::encoder::{Encode, EncodeContext, Encoder};
+    use crate::state::ClientState;
+
+    use super::*;
+
+    #[test]
+    fn test_counter_merge() {
+        let d1 = Doc::default();
+        let likes = d1.counter();
+        d1.set("likes", likes.clone());
+        likes.increment(3);
+        d1.commit();
+
+        // the counter syncs through the encoded diff
+        let mut e = EncoderV1::new();
+        d1.diff(ClientState::default())
+            .encode(&mut e, &mut EncodeContext::default());
+        let diff = Diff::decode(&mut e.decoder(), &DecodeContext::default()).unwrap();
+        let d2 = Doc::new(d1.meta.clone());
+        d2.apply(&diff);
+        d2.update_client();
+        let Some(Type::Counter(remote)) = d2.get("likes") else {
+            panic!("counter not synced");
+        };
+        assert_eq!(remote.value(), 3);
+
+        // concurrent changes all count
+        likes.increment(2);
+        d1.commit();
+        remote.increment(5);
+        remote.decrement(1);
+        d2.commit();
+
+        d1.apply(&d2.diff(d1.state()));
+        d2.apply(&d1.diff(d2.state()));
+        assert_eq!(likes.value(), 9);
+        assert_eq!(remote.value(), 9);
+        assert_eq!(d1.get("likes").unwrap().to_json(), serde_json::json!(9));
+    }
+}
@@ -0,0 +1,268 @@
+use std::rc::Rc;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::doc::Doc;
+use crate::id::{Client, Id, WithId};
+use crate::item::{Content, ItemKind};
+use crate::nmap::NMap;
+use crate::types::Type;
+
+/// KeyChange is emitted when a key of a key-value store is set or removed
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyChange {
+    pub key: String,
+    /// the new value, None when the key was removed
+    pub value: Option<Value>,
+    /// true for changes committed by the local client
+    pub local: bool,
+}
+
+/// LastWrite tells who wrote the current value of a key
+#[derive(Debug, Clone, PartialEq)]
+pub struct LastWrite {
+    pub client: Option<Client>,
+    /// id of the value item
+    pub id: Id,
+}
+
+/// NKeyValue is a settings style facade over a root map.
+///
+/// Values are stored as json encoded atoms, so any serde type can be kept under a key
+/// and a concurrent write of the same key is resolved by the map as a whole value.
+#[derive(Clone, Debug)]
+pub struct NKeyValue {
+    doc: Doc,
+    name: String,
+    map: NMap,
+}
+
+impl NKeyValue {
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sorted keys with a value
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys = self.map.keys();
+        keys.sort();
+        keys
+    }
+
+    #[inline]
+    pub fn contains(&self, key: &str) -> bool {
+        self.map.get(key).is_some()
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, String> {
+        self.get_json(key)
+            .map(|value| serde_json::from_value(value).map_err(|e| e.to_string()))
+            .transpose()
+    }
+
+    pub fn get_json(&self, key: &str) -> Option<Value> {
+        self.map.get(key).map(|item| value_of(&item))
+    }
+
+    /// Set the value of the key, setting an equal value is a no-op
+    pub fn set<T: Serialize>(&self, key: &str, value: &T) -> Result<(), String> {
+        let value = serde_json::to_value(value).map_err(|e| e.to_string())?;
+        self.set_json(key, value);
+        Ok(())
+    }
+
+    pub fn set_json(&self, key: &str, value: Value) {
+        if self.get_json(key).as_ref() == Some(&value) {
+            return;
+        }
+
+        self.map.set(key, self.doc.atom(value.to_string()));
+    }
+
+    /// Remove the key, returns false when the key has no value
+    pub fn remove(&self, key: &str) -> bool {
+        match self.map.get(key) {
+            Some(item) => {
+                item.delete();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Client and item id of the last write of the key
+    pub fn last_write(&self, key: &str) -> Option<LastWrite> {
+        let id = self.map.get(key)?.id();
+        let client = self
+            .doc
+            .store
+            .borrow()
+            .state
+            .get_client(&id.client)
+            .cloned();
+
+        Some(LastWrite { client, id })
+    }
+
+    /// All the keys as a json object
+    pub fn to_json(&self) -> Value {
+        let entries = self
+            .keys()
+            .into_iter()
+            .filter_map(|key| self.get_json(&key).map(|value| (key, value)))
+            .collect::<Map<String, Value>>();
+
+        Value::Object(entries)
+    }
+
+    /// Set every key of the json object, returns the number of changed keys.
+    /// Keys missing in the object are kept.
+    pub fn import(&self, json: &Value) -> Result<usize, String> {
+        let Value::Object(entries) = json else {
+            return Err("key-value import expects a json object".to_string());
+        };
+
+        let mut changed = 0;
+        for (key, value) in entries {
+            if self.get_json(key).as_ref() != Some(value) {
+                self.set_json(key, value.clone());
+                changed += 1;
+            }
+        }
+
+        Ok(changed)
+    }
+
+    /// Observe the changes of a key, None observes all the keys.
+    /// Returns a token to remove the observer with `Doc::unobserve_path`.
+    pub fn observe(&self, key: Option<&str>, listener: impl Fn(&KeyChange) + 'static) -> u32 {
+        let map = self.map.clone();
+        let prefix = format!("{}/", self.name);
+        let key = key.map(|key| key.to_string());
+        let listener = Rc::new(listener);
+
+        self.doc
+            .observe_path(&format!("{}/*", self.name), move |event| {
+                let Some(changed) = event.path.strip_prefix(&prefix) else {
+                    return;
+                };
+                if key.as_ref().is_some_and(|key| key != changed) {
+                    return;
+                }
+
+                listener(&KeyChange {
+                    key: changed.to_string(),
+                    value: map.get(changed).map(|item| value_of(&item)),
+                    local: event.local,
+                });
+            })
+    }
+}
+
+// json value of a stored item, atoms written by other code are read as they are
+fn value_of(item: &Type) -> Value {
+    if item.kind() == ItemKind::Atom {
+        if let Content::String(s) = item.content() {
+            return serde_json::from_str(&s).unwrap_or(Value::String(s));
+        }
+    }
+
+    item.to_json()
+}
+
+impl Doc {
+    /// Key-value store kept in the root map under the name, created when missing
+    pub fn key_value(&self, name: &str) -> Result<NKeyValue, String> {
+        let map = match self.get(name) {
+            Some(Type::Map(map)) => map,
+            Some(other) => return Err(format!("{} is a {}, not a map", name, other.kind())),
+            None => {
+                let map = self.map();
+                self.set(name, map.clone());
+                map
+            }
+        };
+
+        Ok(NKeyValue {
+            doc: self.clone(),
+            name: name.to_string(),
+            map,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use crate::doc::CloneDeep;
+    use crate::state::ClientState;
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Editor {
+        font_size: u32,
+        theme: String,
+    }
+
+    #[test]
+    fn test_key_value_settings() {
+        let d1 = Doc::default();
+        let settings = d1.key_value("settings").unwrap();
+        let editor = Editor {
+            font_size: 14,
+            theme: "dark".to_string(),
+        };
+        settings.set("editor", &editor).unwrap();
+        settings.set("autosave", &true).unwrap();
+        d1.commit();
+
+        assert_eq!(settings.get::<Editor>("editor").unwrap(), Some(editor));
+        assert_eq!(settings.get::<u32>("missing").unwrap(), None);
+        assert!(settings.get::<u32>("editor").is_err());
+
+        let d2 = d1.clone_deep();
+        let client = d2.update_client();
+        let remote = d2.key_value("settings").unwrap();
+
+        let changes = Rc::new(RefCell::new(vec![]));
+        let seen = changes.clone();
+        settings.observe(Some("autosave"), move |change| {
+            seen.borrow_mut().push(change.clone())
+        });
+
+        let changed = remote
+            .import(&json!({"autosave": false, "language": "en"}))
+            .unwrap();
+        assert_eq!(changed, 2);
+        d2.commit();
+        d1.apply(&d2.diff(ClientState::default()));
+
+        assert_eq!(
+            settings.to_json(),
+            json!({
+                "autosave": false,
+                "editor": {"font_size": 14, "theme": "dark"},
+                "language": "en",
+            })
+        );
+        assert_eq!(
+            changes.borrow().as_slice(),
+            &[KeyChange {
+                key: "autosave".to_string(),
+                value: Some(json!(false)),
+                local: false,
+            }]
+        );
+
+        let write = settings.last_write("autosave").unwrap();
+        assert_eq!(write.client, Some(client));
+    }
+}
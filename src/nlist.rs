@@ -1,13 +1,12 @@
-use crate::cycle::creates_cycle;
 use crate::id::{Id, IdRange, WithId, WithIdRange, WithTarget};
 use crate::index::{BTreeIndex, IBTree, ItemIndexMap};
 use crate::item::{
-    ContainerKind, Content, ItemData, ItemIterator, ItemKey, ItemKind, ItemRef, Linked, StartEnd,
-    WithIndex,
+    ContainerKind, Content, ItemData, ItemKey, ItemKind, ItemRef, Linked, StartEnd, WithIndex,
 };
 use crate::nmove::NMove;
 use crate::store::WeakStoreRef;
 use crate::types::Type;
+use fractional_index::FractionalIndex;
 use log::warn;
 use serde::ser::{Serialize, SerializeStruct};
 use std::cell::RefCell;
@@ -52,23 +51,47 @@ impl NList {
 
     /// move the item to the offset position in the new parent list
     pub(crate) fn move_to(&self, offset: u32, target: &Type) {
-        if creates_cycle(&self.into(), target) {
-            warn!("can not move nodes within, creates cycle");
-            return;
+        // the item at the offset is found before the target is hidden at its old place
+        let next = self.list.borrow().at_index(offset).cloned();
+        if let Some(mover) = NMove::for_target(&self.into(), target) {
+            match next {
+                _ if offset == 0 => self.prepend(mover),
+                Some(next) => next.insert_before(mover),
+                None => self.append(mover),
+            }
         }
+    }
 
-        let id = self.store.upgrade().unwrap().borrow_mut().next_id();
-        let mover: Type = NMove::new(id, target.clone(), self.store.clone()).into();
+    /// move `len` items starting at `start` to `dest_index`, the destination index is counted
+    /// before the items are removed. The moves join the pending change of the document.
+    pub fn move_range(&self, start: u32, len: u32, dest_index: u32) {
+        let items = self.borrow().as_list();
+        let size = items.len() as u32;
+        if len == 0 || start >= size || start + len > size {
+            warn!("move_range: invalid range {}..{}", start, start + len);
+            return;
+        }
 
-        target.item_ref().mark_moved();
+        // moving the block into itself does not change the order
+        if dest_index >= start && dest_index <= start + len {
+            return;
+        }
 
-        self.store
-            .upgrade()
-            .unwrap()
-            .borrow_mut()
-            .add_mover(target.id(), mover.clone());
+        // moved items are listed through their movers, move the actual targets
+        let block = items[start as usize..(start + len) as usize]
+            .iter()
+            .map(|item| item.item_ref().get_target().unwrap_or(item.clone()))
+            .collect::<Vec<_>>();
 
-        self.insert(offset, mover);
+        if let Some(anchor) = items.get(dest_index as usize) {
+            for item in block.iter() {
+                self.move_before(anchor, item);
+            }
+        } else {
+            for item in block.iter() {
+                self.move_to(self.size(), item);
+            }
+        }
     }
 }
 
@@ -88,12 +111,18 @@ impl NList {
 
     #[inline]
     pub fn size(&self) -> u32 {
+        if self.has_unique_key() {
+            return self.unique_items().len() as u32;
+        }
         self.list.borrow().size() as u32
     }
 
     #[inline]
     pub fn get(&self, key: impl Into<ItemKey>) -> Option<Type> {
         if let ItemKey::Number(offset) = key.into() {
+            if self.has_unique_key() {
+                return self.unique_items().get(offset as usize).cloned();
+            }
             return self.list.borrow().at_index(offset).map(|v| v.clone());
         }
         None
@@ -106,8 +135,7 @@ impl NList {
 
     #[inline]
     pub fn content(&self) -> Content {
-        let types = self.borrow().as_list();
-        Content::Types(types)
+        Content::Types(self.unique_items())
     }
 
     #[inline]
@@ -146,6 +174,7 @@ impl NList {
             Type::add_frac_index(&item);
             self.on_insert(&item);
         }
+        Type::from(self).inherit_marks(&item);
     }
 
     /// append an item to the end of the list
@@ -155,6 +184,7 @@ impl NList {
         self.item.append(item.clone());
         Type::add_frac_index(&item);
         self.on_insert(&item);
+        Type::from(self).inherit_marks(&item);
     }
 
     pub fn insert(&self, offset: u32, item: impl Into<Type>) {
@@ -175,13 +205,108 @@ impl NList {
             };
 
             if let Some(next) = next {
-                next.insert_before(item);
+                next.insert_before(item.clone());
+                Type::from(self).inherit_marks(&item);
             } else {
                 self.append(item);
             }
         }
     }
 
+    /// insert the items at the offset in a single pass. The neighbors are looked up once and
+    /// the items get evenly spread fractional indexes, so the index keys stay short and the
+    /// index tree is updated in bulk.
+    pub fn insert_batch(&self, offset: u32, items: Vec<impl Into<Type>>) {
+        let mut items = items.into_iter().map(Into::into);
+        let Some(first) = items.next() else {
+            return;
+        };
+        self.insert(offset, first.clone());
+
+        let parent: Type = self.into();
+        let mut last = first.clone();
+        let rest: Vec<Type> = items
+            .map(|item: Type| {
+                item.set_parent(Some(parent.clone()));
+                last.insert_after(item.clone());
+                last = item.clone();
+                item
+            })
+            .collect();
+        rest.iter().for_each(|item| parent.inherit_marks(item));
+
+        let upper = last.right().map(|next| next.index());
+        if !spread_indexes(&rest, first.index(), upper) {
+            // the neighbors are not ordered, index the items one by one and let the list repair
+            for item in rest.iter() {
+                Type::add_frac_index(item);
+                self.on_insert(item);
+            }
+            return;
+        }
+
+        self.list.borrow_mut().extend(rest);
+    }
+
+    /// append the items to the end of the list, see `insert_batch`
+    pub fn extend(&self, items: impl IntoIterator<Item = impl Into<Type>>) {
+        let items: Vec<Type> = items.into_iter().map(Into::into).collect();
+        let size = self.list.borrow().size();
+        self.insert_batch(size, items);
+    }
+
+    /// insert the item right after the sibling with the id, without an index lookup.
+    /// A deleted sibling keeps its slot in the list, so the item lands where the sibling was.
+    pub fn insert_after_id(&self, id: &Id, item: impl Into<Type>) -> Result<(), String> {
+        let anchor = self.anchor(id)?;
+        let item = item.into();
+
+        item.set_parent(Some(self.into()));
+        anchor.insert_after(item.clone());
+        Type::from(self).on_insert(&item);
+        Type::from(self).inherit_marks(&item);
+
+        Ok(())
+    }
+
+    /// insert the item right before the sibling with the id, see `insert_after_id`
+    pub fn insert_before_id(&self, id: &Id, item: impl Into<Type>) -> Result<(), String> {
+        let anchor = self.anchor(id)?;
+        let item = item.into();
+
+        // the item takes the parent of the anchor and is indexed by the insert
+        anchor.insert_before(item.clone());
+        Type::from(self).inherit_marks(&item);
+
+        Ok(())
+    }
+
+    // the slot of the sibling in this list, a moved sibling is found at its active mover
+    // when the mover is in this list and at its original slot otherwise
+    fn anchor(&self, id: &Id) -> Result<Type, String> {
+        let store = self
+            .store
+            .upgrade()
+            .ok_or("list is detached from the document")?;
+        let (item, mover) = {
+            let store = store.borrow();
+            let mover = store
+                .moves
+                .get(id)
+                .and_then(|movers| movers.last().cloned());
+            (store.find(id), mover)
+        };
+
+        let item = item.ok_or_else(|| format!("item {} not found", id))?;
+        let mover = mover.filter(|_| item.is_moved());
+
+        mover
+            .into_iter()
+            .chain([item])
+            .find(|slot| slot.parent().is_some_and(|p| p.id() == self.id()))
+            .ok_or_else(|| format!("item {} is not in the list", id))
+    }
+
     fn fugue_append(&self, offset: u32, item: impl Into<Type>) {}
     fn fugue_prepend(&self, offset: u32, item: impl Into<Type>) {}
     fn fugue_insert(&self, offset: u32, item: impl Into<Type>) {}
@@ -211,7 +336,7 @@ impl NList {
 
     pub(crate) fn to_json(&self) -> serde_json::Value {
         let mut json = self.borrow().to_json();
-        let items = self.borrow().as_list();
+        let items = self.unique_items();
 
         let content = items.iter().map(|item| item.to_json()).collect();
 
@@ -220,9 +345,41 @@ impl NList {
 }
 
 impl NList {
-    #[inline]
+    // index the inserted child, local and remote inserts both end up here
     pub(crate) fn on_insert(&self, child: &Type) {
         self.list.borrow_mut().insert(child.clone());
+        self.repair_index(child);
+    }
+
+    // reindex the right neighbors of the item until the indexes follow the list order again,
+    // integration can place an item next to neighbors whose indexes leave no room between them
+    fn repair_index(&self, item: &Type) {
+        let mut prev = item.index();
+        let mut current = item.right();
+
+        while let Some(item) = current {
+            let index = item.index();
+            if index > prev {
+                break;
+            }
+
+            let next = item.right();
+            let new_index = next
+                .as_ref()
+                .and_then(|next| FractionalIndex::new_between(&prev, &next.index()))
+                .unwrap_or_else(|| FractionalIndex::new_after(&prev));
+
+            let mut list = self.list.borrow_mut();
+            // an equal index was taken over by the inserted item
+            if list.btree.get(&index).is_some_and(|t| t.id() == item.id()) {
+                list.btree.remove(&index);
+            }
+            item.item_ref().borrow_mut().index = new_index.clone();
+            list.btree.insert(new_index.clone(), item.clone());
+
+            prev = new_index;
+            current = next;
+        }
     }
 }
 
@@ -235,7 +392,8 @@ impl Serialize for NList {
         self.serialize_with(&mut s)?;
 
         let content = self
-            .visible_item_iter()
+            .unique_items()
+            .iter()
             .map(|item| serde_json::to_value(item).unwrap_or_default())
             .collect::<Vec<_>>();
 
@@ -260,15 +418,54 @@ impl WithIdRange for NList {
 }
 
 impl From<ItemRef> for NList {
+    // share the index of the stored list, a list of its own would start with an empty index
     #[inline]
     fn from(item: ItemRef) -> Self {
+        let stored = item.store.upgrade().and_then(|store| {
+            match store.try_borrow().ok()?.find(&item.id())? {
+                Type::List(list) => Some(list.list),
+                _ => None,
+            }
+        });
+
         Self {
             item,
-            ..Default::default()
+            list: stored.unwrap_or_default(),
         }
     }
 }
 
+// give the items ordered indexes after the lower bound and before the upper bound, the
+// indexes between two bounds are assigned by bisection to keep them short. Returns false
+// when the bounds leave no room.
+fn spread_indexes(items: &[Type], lower: FractionalIndex, upper: Option<FractionalIndex>) -> bool {
+    let Some(upper) = upper else {
+        let mut index = lower;
+        for item in items {
+            index = FractionalIndex::new_after(&index);
+            item.item_ref().borrow_mut().index = index.clone();
+        }
+        return true;
+    };
+
+    let mut ranges = vec![(0, items.len(), lower, upper)];
+    while let Some((from, to, lower, upper)) = ranges.pop() {
+        if from >= to {
+            continue;
+        }
+
+        let mid = from + (to - from) / 2;
+        let Some(index) = FractionalIndex::new_between(&lower, &upper) else {
+            return false;
+        };
+        items[mid].item_ref().borrow_mut().index = index.clone();
+        ranges.push((from, mid, lower, index.clone()));
+        ranges.push((mid + 1, to, index, upper));
+    }
+
+    true
+}
+
 #[cfg(test)]
 mod test {
     use crate::doc::Doc;
@@ -368,4 +565,100 @@ content:
 
         // println!("{}", serde_yaml::to_string(doc).unwrap());
     }
+
+    #[test]
+    fn test_remote_insert_index() {
+        use crate::doc::CloneDeep;
+        use crate::item::WithIndex;
+        use crate::state::ClientState;
+        use crate::types::Type;
+
+        let d1 = Doc::default();
+        let list = d1.list();
+        d1.set("list", list.clone());
+        list.append(d1.atom("a"));
+        list.append(d1.atom("d"));
+        d1.commit();
+
+        let d2 = d1.clone_deep();
+        d2.update_client();
+        let remote = d2.get("list").unwrap().as_list().unwrap();
+
+        // neighbors without room between them are reindexed on integration
+        let a = list.get(0usize).unwrap();
+        let d = list.get(1usize).unwrap();
+        list.list.borrow_mut().btree.remove(&d.index());
+        d.item_ref().borrow_mut().index = a.index();
+
+        remote.insert(1, d2.atom("b"));
+        remote.insert(2, d2.atom("c"));
+        d2.commit();
+        d1.apply(&d2.diff(ClientState::default()));
+
+        let items: Vec<Type> = (0..list.size()).filter_map(|i| list.get(i)).collect();
+        let json: Vec<_> = items.iter().map(|item| item.to_json()).collect();
+        assert_eq!(json, vec!["a", "b", "c", "d"]);
+        assert!(items.windows(2).all(|w| w[0].index() < w[1].index()));
+    }
+
+    #[test]
+    fn test_insert_by_id() {
+        use crate::doc::CloneDeep;
+        use crate::id::WithId;
+        use crate::state::ClientState;
+        use crate::sync::equal_docs;
+
+        let d1 = Doc::default();
+        let list = d1.list();
+        d1.set("list", list.clone());
+        let a = d1.atom("a");
+        let b = d1.atom("b");
+        let c = d1.atom("c");
+        list.append(a.clone());
+        list.append(b.clone());
+        list.append(c.clone());
+        d1.commit();
+        let d2 = d1.clone_deep();
+
+        list.insert_after_id(&b.id(), d1.atom("x")).unwrap();
+        list.insert_before_id(&a.id(), d1.atom("y")).unwrap();
+
+        // the slot of a deleted sibling is still a valid anchor
+        c.delete();
+        list.insert_after_id(&c.id(), d1.atom("z")).unwrap();
+        assert!(list.insert_after_id(&list.id(), b.clone()).is_err());
+        d1.commit();
+
+        let json = d1.get("list").unwrap().to_json();
+        assert_eq!(json, serde_json::json!(["y", "a", "b", "x", "z"]));
+
+        d2.apply(&d1.diff(ClientState::default()));
+        assert!(equal_docs(&d1, &d2));
+    }
+
+    #[test]
+    fn test_insert_batch() {
+        use crate::state::ClientState;
+        use crate::sync::equal_docs;
+        use crate::types::Type;
+
+        let d1 = Doc::default();
+        let list = d1.list();
+        d1.set("list", list.clone());
+        list.extend(["a", "e"].map(|s| d1.atom(s)));
+        list.insert_batch(1, ["b", "c", "d"].map(|s| d1.atom(s)).to_vec());
+        list.extend((0..100).map(|i| d1.atom(i.to_string())));
+        list.insert_batch(0, Vec::<Type>::new());
+        d1.commit();
+
+        assert_eq!(list.size(), 105);
+        let json = list.to_json();
+        assert_eq!(json.as_array().unwrap()[..5], ["a", "b", "c", "d", "e"]);
+        assert_eq!(json[104], "99");
+        assert_eq!(list.borrow().as_list().len(), 105);
+
+        let d2 = Doc::new(d1.meta.clone());
+        d2.apply(&d1.diff(ClientState::default()));
+        assert!(equal_docs(&d1, &d2));
+    }
 }
@@ -4,10 +4,12 @@ use std::ops::Deref;
 use serde::ser::SerializeStruct;
 use serde::Serialize;
 
-use crate::id::{Id, IdRange, WithId, WithIdRange};
+use crate::doc::Doc;
+use crate::id::{Id, IdRange, WithId, WithIdRange, WithTarget};
 use crate::item::{Content, ItemData, ItemIterator, ItemKey, ItemKind, ItemRef, Linked, StartEnd};
 use crate::mark::{Mark, MarkContent};
 use crate::nmark::NMark;
+use crate::nmove::NMove;
 use crate::store::WeakStoreRef;
 use crate::types::Type;
 
@@ -31,6 +33,7 @@ impl NMap {
 
     /// size of the map
     pub(crate) fn size(&self) -> u32 {
+        self.hydrate(None);
         let item = self.borrow();
         let map = item.as_map(&self.store);
         map.len() as u32
@@ -75,32 +78,75 @@ impl NMap {
             .id();
 
         let mark = NMark::new(id, Content::Mark(content), self.store.clone());
-
-        // self.item_ref().add_mark(mark);
+        mark.attach(&self.clone().into());
     }
 
     pub(crate) fn get(&self, key: impl Into<ItemKey>) -> Option<Type> {
-        let item = self.borrow();
         let key = key.into().as_string();
-        let map = self.visible_children();
+        self.hydrate(Some(&key));
+        let map = self.linked_children();
         let item = map.get(&key);
 
         item.cloned()
     }
 
     pub(crate) fn set(&self, field: impl Into<String>, item: impl Into<Type>) {
+        let field = field.into();
         let item = item.into();
+        self.hydrate(Some(&field));
+        if self.coalesce(&field, &item) {
+            return;
+        }
+
         let item_ref = item.item_ref();
         let store = item_ref.store.upgrade().unwrap();
-        let field_id = store.borrow_mut().get_field_id(&field.into());
+        let field_id = store.borrow_mut().get_field_id(&field);
         item.set_parent(Some(self.into()));
         item_ref.borrow_mut().data.field = Some(field_id);
-        self.item_ref().append(item);
+        self.item_ref().append(item.clone());
+        Type::from(self).inherit_marks(&item);
+    }
+
+    // a fresh atom set on a coalesced key replaces the content of the atom set earlier in
+    // the same pending change, the change keeps a single item for the key
+    fn coalesce(&self, field: &str, item: &Type) -> bool {
+        if item.kind() != ItemKind::Atom || item.parent_id().is_some() {
+            return false;
+        }
+        let Some(store) = self.store.upgrade() else {
+            return false;
+        };
+        let Some(prev) = self.linked_children().remove(field) else {
+            return false;
+        };
+
+        let mut store = store.borrow_mut();
+        if prev.kind() != ItemKind::Atom
+            || !store.coalesced_keys.contains(field)
+            || !store.is_pending(&prev.id())
+            || !store.retract_last(&item.id())
+        {
+            return false;
+        }
+
+        prev.item_ref().set_content(item.content());
+        true
+    }
+
+    /// move the target under the key, a moved value keeps its id and concurrent edits of
+    /// the value follow it
+    pub(crate) fn move_to(&self, key: &str, target: &Type) {
+        let target = target.item_ref().get_target().unwrap_or(target.clone());
+        if let Some(mover) = NMove::for_target(&self.into(), &target) {
+            self.set(key, mover);
+        }
     }
 
     pub(crate) fn remove(&self, key: ItemKey) {
-        let map = self.visible_children();
-        let value = map.get(&key.as_string());
+        let key = key.as_string();
+        self.hydrate(Some(&key));
+        let map = self.linked_children();
+        let value = map.get(&key);
         if let Some(value) = value {
             value.delete();
         }
@@ -120,6 +166,7 @@ impl NMap {
     }
 
     pub(crate) fn clear(&self) {
+        self.hydrate(None);
         let item = self.borrow();
         let map = item.as_map(&self.store);
         for item in map.values() {
@@ -127,7 +174,13 @@ impl NMap {
         }
     }
 
-    fn visible_children(&self) -> HashMap<String, Type> {
+    pub(crate) fn visible_children(&self) -> HashMap<String, Type> {
+        self.hydrate(None);
+        self.linked_children()
+    }
+
+    // visible children of the linked items, the lazy entries are left out
+    fn linked_children(&self) -> HashMap<String, Type> {
         let mut curr = self.start();
         let mut map = HashMap::new();
         while let Some(item) = curr {
@@ -143,6 +196,27 @@ impl NMap {
         map
     }
 
+    // decode the lazy entry of the key, all entries without a key, when the map is the
+    // root of a lazily loaded document
+    fn hydrate(&self, key: Option<&str>) {
+        let Some(store) = self.store.upgrade() else {
+            return;
+        };
+        let meta = match store.try_borrow() {
+            Ok(s) if s.lazy.is_lazy_root(&self.id()) => s.lazy.meta(),
+            _ => return,
+        };
+
+        if let Some(meta) = meta {
+            let doc = Doc {
+                meta,
+                root: self.clone(),
+                store,
+            };
+            doc.hydrate(key);
+        }
+    }
+
     #[inline]
     pub(crate) fn delete(&self) {
         self.item.delete(1);
@@ -166,6 +240,7 @@ impl Serialize for NMap {
     where
         S: serde::ser::Serializer,
     {
+        self.hydrate(None);
         let mut s = serializer.serialize_struct("Doc", self.borrow().serialize_size() + 1)?;
         self.serialize_with(&mut s)?;
 
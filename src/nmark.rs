@@ -5,6 +5,7 @@ use serde_json::Value;
 
 use crate::id::{Id, IdRange, Split, WithId, WithIdRange};
 use crate::item::{Content, ItemData, ItemKind, ItemRef};
+use crate::mark::Mark;
 use crate::store::WeakStoreRef;
 use crate::types::Type;
 
@@ -33,10 +34,33 @@ impl NMark {
         Self { item }
     }
 
+    // a mark takes as many ticks as the marked range
     pub(crate) fn size(&self) -> u32 {
-        // let marks = self.borrow().get_marks();
+        match &self.item_ref().borrow().data.content {
+            Content::Mark(mark) => mark.size(),
+            _ => 1,
+        }
+    }
 
-        1 //marks.len() as u32
+    pub(crate) fn mark(&self) -> Option<Mark> {
+        match &self.item_ref().borrow().data.content {
+            Content::Mark(mark) => Some(mark.data.clone()),
+            _ => None,
+        }
+    }
+
+    // marks are kept by the store and point to the marked item with the parent id,
+    // they are not linked into the children of the marked item
+    pub(crate) fn attach(&self, target: &Type) {
+        {
+            let mut item = self.item.borrow_mut();
+            item.data.parent_id = Some(target.id());
+            item.parent = Some(target.clone());
+        }
+
+        if let Some(store) = self.store.upgrade() {
+            store.borrow_mut().insert(Type::Mark(self.clone()));
+        }
     }
 
     pub(crate) fn item_ref(&self) -> ItemRef {
@@ -98,7 +122,7 @@ impl WithId for NMark {
 
 impl WithIdRange for NMark {
     fn range(&self) -> IdRange {
-        self.borrow().id().range(1)
+        self.borrow().id().range(self.size())
     }
 }
 
@@ -110,8 +134,13 @@ impl From<ItemRef> for NMark {
 
 #[cfg(test)]
 mod tests {
-    use crate::doc::Doc;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::doc::{CloneDeep, Doc};
+    use crate::event::Event;
     use crate::mark::Mark;
+    use crate::types::Type;
 
     #[test]
     fn test_nmark() {
@@ -138,4 +167,44 @@ mod tests {
         let yaml = serde_yaml::to_string(&s1).unwrap();
         println!("{}", yaml);
     }
+
+    #[test]
+    fn test_remove_marks() {
+        let doc = Doc::default();
+        let map = doc.map();
+        doc.set("map", map.clone());
+        let map = Type::from(map);
+        map.add_mark(Mark::Bold);
+        map.add_mark(Mark::Color("red".into()));
+        map.add_mark(Mark::Bold);
+        doc.commit();
+
+        let marks = map.marks();
+        assert_eq!(marks.len(), 3);
+        assert_eq!(marks[1].1, Mark::Color("red".into()));
+
+        // marks are integrated by the replicas
+        let remote = doc.clone_deep();
+        assert_eq!(remote.get("map").unwrap().marks().len(), 3);
+
+        let events = Rc::new(RefCell::new(vec![]));
+        let seen = events.clone();
+        doc.observe_path("map", move |event| {
+            seen.borrow_mut().extend(event.events.clone())
+        });
+
+        map.remove_mark_by_id(&marks[1].0).unwrap();
+        assert!(map.remove_mark_by_id(&marks[1].0).is_err());
+        assert_eq!(map.clear_marks("bold"), 2);
+        doc.commit();
+
+        assert!(map.marks().is_empty());
+        let removed: Vec<_> = events
+            .borrow()
+            .iter()
+            .filter(|event| matches!(event, Event::MarkDelete { .. }))
+            .cloned()
+            .collect();
+        assert_eq!(removed.len(), 3);
+    }
 }
@@ -1,9 +1,11 @@
+use crate::cycle::creates_cycle;
 use crate::id::{Id, IdRange, WithId, WithIdRange, WithTarget};
 use crate::item::{Content, ItemData, ItemIterator, ItemKey, ItemKind, ItemRef};
 use crate::nlist::NList;
 use crate::store::WeakStoreRef;
 use crate::Type;
 use fake::Opt;
+use log::warn;
 use serde::ser::SerializeStruct;
 use serde::Serialize;
 use serde_json::Value;
@@ -31,6 +33,25 @@ impl NMove {
         Self { item }
     }
 
+    // register a mover of the target into the parent, the target is hidden at its old
+    // position. None when the parent is within the target.
+    pub(crate) fn for_target(parent: &Type, target: &Type) -> Option<Type> {
+        if creates_cycle(parent, target) {
+            warn!("can not move nodes within, creates cycle");
+            return None;
+        }
+
+        let store = parent.store().upgrade()?;
+        let id = store.borrow_mut().next_id();
+        let mover: Type = NMove::new(id, target.clone(), parent.store()).into();
+
+        target.item_ref().mark_moved();
+        store.borrow_mut().add_mover(target.id(), mover.clone());
+        store.borrow_mut().insert(mover.clone());
+
+        Some(mover)
+    }
+
     #[inline]
     pub(crate) fn item_ref(&self) -> ItemRef {
         self.item.clone()
@@ -113,8 +134,7 @@ impl NMove {
     #[inline]
     pub(crate) fn to_json(&self) -> serde_json::Value {
         if let Some(target) = self.get_target().as_ref() {
-            serde_json::Value::Null
-            // target.to_json()
+            target.to_json()
         } else {
             serde_json::Value::Null
         }
@@ -261,4 +281,83 @@ mod tests {
         assert_eq!(get_list_text(&l1), vec![] as Vec<String>);
         assert_eq!(get_list_text(&l2), vec!["a", "c", "b", "d"]);
     }
+
+    #[test]
+    fn test_move_range_within_list() {
+        let doc = Doc::default();
+        let list = doc.list();
+        doc.set("list", list.clone());
+
+        let a = doc.atom("a");
+        let b = doc.atom("b");
+        let c = doc.atom("c");
+        let d = doc.atom("d");
+        let e = doc.atom("e");
+        append!(list, a, b, c, d, e);
+        doc.commit();
+
+        list.move_range(1, 2, 4);
+        assert_eq!(get_list_text(&list), vec!["a", "d", "b", "c", "e"]);
+        // the moves are left to the caller to commit
+        let store = doc.store.borrow();
+        assert!(store.commited_clock < store.clock);
+        drop(store);
+
+        list.move_range(2, 2, 0);
+        assert_eq!(get_list_text(&list), vec!["b", "c", "a", "d", "e"]);
+
+        list.move_range(0, 2, 5);
+        assert_eq!(get_list_text(&list), vec!["a", "d", "e", "b", "c"]);
+    }
+
+    #[test]
+    fn test_move_map_key() {
+        let doc = Doc::default();
+        let map = doc.map();
+        let archive = doc.map();
+        doc.set("map", map.clone());
+        doc.set("archive", archive.clone());
+
+        let todo = doc.map();
+        map.set("draft", todo.clone());
+        todo.set("title", doc.atom("milk"));
+
+        // rename the key, the value keeps its id
+        let value = map.get("draft").unwrap();
+        value.move_to_key(&map, "todo");
+        assert!(map.get("draft").is_none());
+        let mover = map.get("todo").unwrap();
+        assert_eq!(mover.item_ref().get_target().unwrap().id(), todo.id());
+        assert_eq!(
+            map.to_json(),
+            serde_json::json!({"todo": {"title": "milk"}})
+        );
+
+        // a map can not move into its own value
+        let parent: Type = map.clone().into();
+        parent.move_to_key(&todo, "parent");
+        assert!(todo.get("parent").is_none());
+
+        mover.move_to_key(&archive, "done");
+        assert_eq!(map.size(), 0);
+        assert_eq!(archive.to_json()["done"]["title"], "milk");
+    }
+
+    #[test]
+    fn test_move_text_range() {
+        let doc = Doc::default();
+        let text = doc.text();
+        doc.set("text", text.clone());
+        text.append(doc.string("hello brave world"));
+
+        text.move_range(6, 6, 17);
+        assert_eq!(text.text_content(), "hello worldbrave ");
+        assert_eq!(text.size(), 17);
+
+        text.move_range(11, 6, 0);
+        assert_eq!(text.text_content(), "brave hello world");
+
+        text.insert(17, doc.string("!"));
+        assert_eq!(text.text_content(), "brave hello world!");
+    }
 }
@@ -0,0 +1,178 @@
+use std::ops::Deref;
+
+use serde::ser::SerializeStruct;
+use serde::Serialize;
+
+use crate::id::{Id, IdRange, WithId, WithIdRange, WithTarget};
+use crate::item::{Content, ItemData, ItemKind, ItemRef};
+use crate::store::WeakStoreRef;
+use crate::types::Type;
+
+/// NProxy is a weak reference to another item of the document, e.g. a mention of a block.
+/// The proxy holds the id of the target only, deleting the proxy keeps the target and a
+/// deleted target makes the proxy unresolvable.
+#[derive(Clone, Debug)]
+pub struct NProxy {
+    pub(crate) item: ItemRef,
+}
+
+impl NProxy {
+    pub(crate) fn new(id: Id, target: &Type, store: WeakStoreRef) -> Self {
+        let data = ItemData {
+            id,
+            kind: ItemKind::Proxy,
+            content: Content::Id(target.id()),
+            ..ItemData::default()
+        };
+
+        let item = ItemRef::new(data.into(), store);
+        item.set_target(target.clone());
+
+        Self { item }
+    }
+
+    /// Id of the referenced item
+    pub fn target_id(&self) -> Option<Id> {
+        match self.borrow().data.content {
+            Content::Id(id) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// The referenced item, None when it was deleted or is not in the document
+    pub fn resolve(&self) -> Option<Type> {
+        let target = match self.get_target() {
+            Some(target) => target,
+            None => self.store.upgrade()?.borrow().find(&self.target_id()?)?,
+        };
+
+        (!target.is_deleted()).then_some(target)
+    }
+
+    #[inline]
+    pub(crate) fn size(&self) -> u32 {
+        1
+    }
+
+    #[inline]
+    pub(crate) fn content(&self) -> Content {
+        self.borrow().data.content.clone()
+    }
+
+    #[inline]
+    pub(crate) fn delete(&self) {
+        self.item.delete(1);
+    }
+
+    #[inline]
+    pub(crate) fn item_ref(&self) -> ItemRef {
+        self.item.clone()
+    }
+
+    /// Id of the live target, the target is not inlined as it may contain the proxy
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        match self.resolve() {
+            Some(target) => target.id().to_string().into(),
+            None => serde_json::Value::Null,
+        }
+    }
+}
+
+impl Serialize for NProxy {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::ser::Serializer,
+    {
+        let size = self.borrow().serialize_size() + 1;
+        let mut proxy = serializer.serialize_struct("Proxy", size)?;
+
+        self.serialize_with(&mut proxy)?;
+
+        proxy.serialize_field("target", &self.to_json())?;
+
+        proxy.end()
+    }
+}
+
+impl WithId for NProxy {
+    #[inline]
+    fn id(&self) -> Id {
+        self.item.borrow().id()
+    }
+}
+
+impl WithIdRange for NProxy {
+    #[inline]
+    fn range(&self) -> IdRange {
+        self.borrow().id().range(1)
+    }
+}
+
+impl Deref for NProxy {
+    type Target = ItemRef;
+
+    fn deref(&self) -> &Self::Target {
+        &self.item
+    }
+}
+
+impl From<ItemRef> for NProxy {
+    fn from(item: ItemRef) -> Self {
+        Self { item }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec_v1::EncoderV1;
+    use crate::decoder::{Decode, DecodeContext};
+    use crate::diff::Diff;
+    use crate::doc::Doc;
+    use crate::encoder::{Encode, EncodeContext, Encoder};
+    use crate::state::ClientState;
+
+    use super::*;
+
+    #[test]
+    fn test_proxy_resolve() {
+        let d1 = Doc::default();
+        let blocks = d1.list();
+        d1.set("blocks", blocks.clone());
+        let block = d1.atom("intro");
+        blocks.append(block.clone());
+        let target: Type = block.into();
+        let mention = d1.proxy(&target);
+        d1.set("mention", mention.clone());
+        d1.commit();
+        assert_eq!(mention.resolve().map(|t| t.id()), Some(target.id()));
+
+        // the proxy survives the encoded diff
+        let mut e = EncoderV1::new();
+        d1.diff(ClientState::default())
+            .encode(&mut e, &mut EncodeContext::default());
+        let diff = Diff::decode(&mut e.decoder(), &DecodeContext::default()).unwrap();
+        let d2 = Doc::new(d1.meta.clone());
+        d2.apply(&diff);
+        d2.update_client();
+        let Some(Type::Proxy(remote)) = d2.get("mention") else {
+            panic!("proxy not synced");
+        };
+        assert_eq!(remote.target_id(), Some(target.id()));
+        assert_eq!(remote.resolve().unwrap().to_json(), "intro");
+
+        // deleting the proxy keeps the target
+        mention.delete();
+        d1.commit();
+        assert!(d1.get("mention").is_none());
+        assert!(target.is_visible());
+
+        // a deleted target does not resolve
+        let Some(Type::List(list)) = d2.get("blocks") else {
+            panic!("list not synced");
+        };
+        list.borrow().as_list()[0].delete();
+        d2.commit();
+        assert!(remote.resolve().is_none());
+        assert_eq!(remote.to_json(), serde_json::Value::Null);
+    }
+}
@@ -39,6 +39,7 @@ impl NString {
     pub(crate) fn size(&self) -> u32 {
         match self.borrow().content {
             Content::String(ref s) => s.len() as u32,
+            Content::Tombstone(size) => size,
             _ => panic!("NString has invalid content"),
         }
     }
@@ -64,8 +65,7 @@ impl NString {
             .id();
 
         let mark = NMark::new(id, Content::Mark(content), self.store.clone());
-
-        // self.item_ref().add_mark(mark);
+        mark.attach(&self.clone().into());
     }
 
     #[inline]
@@ -73,6 +73,35 @@ impl NString {
         self.item.clone()
     }
 
+    /// Split the string into chunks of at most `max` bytes, the chunks keep the original id range.
+    /// Chunk boundaries are moved back to the nearest char boundary.
+    pub(crate) fn chunk(&self, max: u32) -> usize {
+        let mut count = 0;
+        let mut item = self.clone();
+        while item.size() > max {
+            let offset = match item.content() {
+                Content::String(s) => (1..=max as usize)
+                    .rev()
+                    .find(|i| s.is_char_boundary(*i))
+                    .unwrap_or(0),
+                _ => 0,
+            };
+
+            if offset == 0 {
+                break;
+            }
+
+            match item.split(offset as u32) {
+                Ok((_, Type::String(right))) => item = right,
+                _ => break,
+            }
+
+            count += 1;
+        }
+
+        count
+    }
+
     pub(crate) fn to_json(&self) -> Value {
         let mut map = serde_json::Map::new();
 
@@ -106,6 +135,11 @@ impl Split for NString {
         //     right_item.item_ref().borrow_mut().add_mark(r);
         // }
 
+        // the halves stay deleted or moved like the split string
+        let flags = self.item_ref().borrow().flags;
+        left_item.item_ref().borrow_mut().flags = flags;
+        right_item.item_ref().borrow_mut().flags = flags;
+
         left_item.set_right(right_item.clone());
         right_item.set_left(left_item.clone());
         left_item.set_parent(self.item_ref().borrow().parent.clone());
@@ -124,6 +158,8 @@ impl Split for NString {
         if let Some(right) = right {
             right.set_left(right_item.clone());
             right_item.set_right(right);
+        } else if let Some(parent) = self.item_ref().borrow().parent.clone() {
+            parent.set_end(right_item.clone());
         }
 
         self.store
@@ -228,4 +264,22 @@ mod test {
 
         print_yaml(&text);
     }
+
+    #[test]
+    fn test_chunk_large_string() {
+        let doc = Doc::default();
+        let text = doc.text();
+        doc.set("text", text.clone());
+
+        text.append(doc.string("hello world"));
+        assert_eq!(text.borrow().items().len(), 1);
+
+        doc.set_max_string_size(Some(4));
+        assert_eq!(doc.normalize_strings(), 2);
+        assert_eq!(text.borrow().items().len(), 3);
+
+        text.append(doc.string("0123456789"));
+        assert_eq!(text.borrow().items().len(), 6);
+        assert_eq!(text.text_content(), "hello world0123456789");
+    }
 }
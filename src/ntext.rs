@@ -1,11 +1,14 @@
 use std::io::BufRead;
 use std::ops::Deref;
 
+use log::warn;
 use serde::ser::SerializeStruct;
 use serde::Serialize;
 
-use crate::id::{Id, IdRange, Split, WithId, WithIdRange};
+use crate::id::{ClockTick, Id, IdRange, Split, WithId, WithIdRange, WithTarget};
 use crate::item::{Content, ItemData, ItemIterator, ItemKind, ItemRef};
+use crate::nmove::NMove;
+use crate::nstring::NString;
 use crate::store::WeakStoreRef;
 use crate::types::Type;
 
@@ -20,6 +23,7 @@ impl NText {
 
 impl NText {
     pub(crate) fn slice(&self, start: u32, end: u32) -> Vec<Type> {
+        self.thaw();
         let start = self.find_at_offset(start);
         let end = self.find_at_offset(end);
 
@@ -40,9 +44,17 @@ impl NText {
 
 impl NText {
     pub(crate) fn new(id: Id, store: WeakStoreRef) -> Self {
+        Self::with_kind(id, ItemKind::Text, store)
+    }
+
+    pub(crate) fn new_plain(id: Id, store: WeakStoreRef) -> Self {
+        Self::with_kind(id, ItemKind::PlaintText, store)
+    }
+
+    fn with_kind(id: Id, kind: ItemKind, store: WeakStoreRef) -> Self {
         let data = ItemData {
             id,
-            kind: ItemKind::Text,
+            kind,
             ..ItemData::default()
         };
 
@@ -51,7 +63,97 @@ impl NText {
         }
     }
 
+    /// Plain texts hold strings without marks
+    #[inline]
+    pub fn is_plain(&self) -> bool {
+        self.kind().is_plaintext()
+    }
+
+    /// Replace the plain text with a rich text of the same content. The rich text takes
+    /// the place of the plain text in the parent and the plain text is deleted, the edits
+    /// go into the pending change. The strings are copied, item ids can not change kind.
+    pub fn upgrade_from_plaintext(plain: &NText) -> Result<NText, String> {
+        if !plain.is_plain() {
+            return Err(format!("text {} is not a plain text", plain.id()));
+        }
+
+        plain.convert(ItemKind::Text)
+    }
+
+    /// Replace the rich text with a plain text of the same content, the marks are dropped
+    pub fn downgrade_to_plaintext(&self) -> Result<NText, String> {
+        if self.is_plain() {
+            return Err(format!("text {} is already a plain text", self.id()));
+        }
+
+        self.convert(ItemKind::PlaintText)
+    }
+
+    fn convert(&self, kind: ItemKind) -> Result<NText, String> {
+        let this = Type::from(self.clone());
+        if this.is_deleted() {
+            return Err(format!("text {} is deleted", self.id()));
+        }
+        let parent = this
+            .parent()
+            .ok_or_else(|| format!("text {} has no parent", self.id()))?;
+        let store = self
+            .store
+            .upgrade()
+            .ok_or("text is detached from the document")?;
+
+        let content = self.text_content();
+        let (text, string) = {
+            let mut store = store.borrow_mut();
+            let text = NText::with_kind(store.next_id(), kind, self.store.clone());
+            store.insert(text.clone());
+
+            let string = (!content.is_empty()).then(|| {
+                let id = store.next_id_range(content.len() as ClockTick).start_id();
+                let string = NString::new(id, content, self.store.clone());
+                store.insert(string.clone());
+                string
+            });
+
+            (text, string)
+        };
+
+        match parent {
+            Type::Map(_) => {
+                let field = this
+                    .field()
+                    .ok_or_else(|| format!("text {} has no field", self.id()))?;
+                parent.set(field, text.clone());
+            }
+            _ => this.insert_before(text.clone().into()),
+        }
+
+        if let Some(string) = string {
+            text.append(string);
+        }
+        this.delete();
+
+        Ok(text)
+    }
+
+    // decode the string items if the text was frozen by the cold store,
+    // internal operations that hold the store thaw the texts they touch up front
+    pub(crate) fn thaw(&self) {
+        let Some(store) = self.store.upgrade() else {
+            return;
+        };
+
+        let Ok(mut store) = store.try_borrow_mut() else {
+            return;
+        };
+
+        if store.cold.is_frozen(&self.id()) {
+            store.thaw_text(&self.clone().into());
+        }
+    }
+
     pub(crate) fn clear(&self) {
+        self.thaw();
         self.item_ref()
             .borrow()
             .items()
@@ -60,30 +162,39 @@ impl NText {
     }
 
     pub(crate) fn content(&self) -> Content {
+        self.thaw();
         let items = self.borrow().as_list();
         Content::Types(items)
     }
 
     pub(crate) fn size(&self) -> u32 {
+        self.thaw();
         self.visible_item_iter()
-            .fold(0, |acc, item| acc + item.size())
+            .fold(0, |acc, item| acc + visible_size(&item))
     }
 
     pub fn append(&self, item: impl Into<Type>) {
+        self.thaw();
         let item = item.into();
         assert!(item.kind().is_string());
         self.item.append(item.clone());
         item.set_parent(Some(self.into()));
+        Type::from(self).inherit_marks(&item);
+        self.chunk_item(&item);
     }
 
     pub fn prepend(&self, item: impl Into<Type>) {
+        self.thaw();
         let item = item.into();
         assert!(item.kind().is_string());
-        self.item.prepend(item);
+        self.item.prepend(item.clone());
+        Type::from(self).inherit_marks(&item);
+        self.chunk_item(&item);
     }
 
     /// Insert string in text
     pub fn insert(&self, offset: u32, item: impl Into<Type>) {
+        self.thaw();
         let item = item.into();
 
         assert!(item.kind().is_string());
@@ -98,17 +209,30 @@ impl NText {
 
             if let Some(target) = target {
                 if offset == 0 {
-                    target.insert_before(item);
-                } else if offset >= target.size() {
-                    target.insert_after(item);
+                    target.insert_before(item.clone());
+                } else if offset >= target.size() || target.kind() == ItemKind::Move {
+                    // a moved span is not split, the string goes after it
+                    target.insert_after(item.clone());
                 } else {
                     let items = target.split(offset);
-                    items.0.insert_after(item);
+                    items.0.insert_after(item.clone());
                 }
+
+                Type::from(self).inherit_marks(&item);
+                self.chunk_item(&item);
             }
         }
     }
 
+    // split a large inserted string into chunks of the configured max string size,
+    // later edits inside the string then only touch a small item
+    fn chunk_item(&self, item: &Type) {
+        let max = self.store.upgrade().unwrap().borrow().max_string_size;
+        if let (Some(max), Type::String(string)) = (max, item) {
+            string.chunk(max);
+        }
+    }
+
     // find item string child at offset
     fn find_at_offset(&self, offset: u32) -> (Option<Type>, u32) {
         let items = self.borrow().as_list();
@@ -120,7 +244,7 @@ impl NText {
         }
 
         for item in self.item.visible_item_iter() {
-            let size = item.size();
+            let size = visible_size(&item);
             if target_offset + size > offset {
                 target = Some(item);
                 break;
@@ -140,6 +264,7 @@ impl NText {
     }
 
     pub(crate) fn to_json(&self) -> serde_json::Value {
+        self.thaw();
         let items = self.borrow().as_list();
         let items: Vec<_> = items.iter().map(|item| item.to_json()).collect();
 
@@ -148,10 +273,108 @@ impl NText {
 
     // raw un marked text content
     pub(crate) fn text_content(&self) -> String {
+        self.thaw();
         self.visible_item_iter()
-            .map(|item| item.text_content())
+            .map(|item| match item.get_target() {
+                Some(target) => target.item_ref().text_content(),
+                None => item.text_content(),
+            })
             .collect()
     }
+
+    /// move `len` characters starting at `start` to `dest`, the destination offset is counted
+    /// before the characters are removed. The moved strings keep their ids, so concurrent
+    /// edits and marks of the span follow it.
+    pub fn move_range(&self, start: u32, len: u32, dest: u32) {
+        let size = self.size();
+        if len == 0 || start + len > size || dest > size {
+            warn!("move_range: invalid range {}..{}", start, start + len);
+            return;
+        }
+
+        // moving the span into itself does not change the text
+        if dest >= start && dest <= start + len {
+            return;
+        }
+
+        let store = self.store.upgrade().unwrap();
+        // flush the pending local edits so that the moves form their own change
+        store.borrow_mut().commit();
+
+        let anchor = self.split_at(dest);
+        let parent: Type = self.into();
+        for piece in self.span(start, start + len) {
+            let target = piece.item_ref().get_target().unwrap_or(piece);
+            let Some(mover) = NMove::for_target(&parent, &target) else {
+                continue;
+            };
+            match &anchor {
+                Some(anchor) => anchor.insert_before(mover),
+                None => {
+                    self.item.append(mover.clone());
+                    mover.set_parent(Some(parent.clone()));
+                }
+            }
+        }
+
+        store.borrow_mut().commit();
+    }
+
+    // the visible item starting at the offset, a string is split when the offset falls in it
+    fn split_at(&self, offset: u32) -> Option<Type> {
+        let mut start = 0;
+        for item in self.visible_item_iter() {
+            let item = Type::from(item);
+            let size = visible_size(&item.item_ref());
+            if offset < start + size {
+                return match (offset - start, &item) {
+                    (0, _) | (_, Type::Move(_)) => Some(item),
+                    (at, _) => Some(item.split(at).1),
+                };
+            }
+            start += size;
+        }
+
+        None
+    }
+
+    // the visible items of the range, the strings are split at the ends of the range
+    fn span(&self, offset: u32, end: u32) -> Vec<Type> {
+        let items: Vec<Type> = self.visible_item_iter().map(Type::from).collect();
+        let mut span = vec![];
+        let mut start = 0;
+        for item in items {
+            let (from, to) = (start, start + visible_size(&item.item_ref()));
+            start = to;
+            if to <= offset || from >= end {
+                continue;
+            }
+            if let Type::Move(_) = item {
+                span.push(item);
+                continue;
+            }
+
+            let mut piece = item;
+            if from < offset {
+                piece = piece.split(offset - from).1;
+            }
+            let from = from.max(offset);
+            if to > end {
+                piece = piece.split(end - from).0;
+            }
+            span.push(piece);
+        }
+
+        span
+    }
+}
+
+// size of a text child, a mover counts the moved string
+fn visible_size(item: &ItemRef) -> u32 {
+    match item.get_target() {
+        Some(target) => target.size(),
+        None => item.size(),
+    }
 }
 
 impl Serialize for NText {
@@ -159,6 +382,7 @@ impl Serialize for NText {
     where
         S: serde::ser::Serializer,
     {
+        self.thaw();
         let mut s = serializer.serialize_struct("Text", self.borrow().serialize_size() + 1)?;
         self.borrow().serialize_with(&mut s)?;
 
@@ -203,6 +427,12 @@ impl From<ItemRef> for NText {
 #[cfg(test)]
 mod tests {
     use crate::doc::Doc;
+    use crate::id::WithId;
+    use crate::state::ClientState;
+    use crate::sync::equal_docs;
+    use crate::types::Type;
+
+    use super::NText;
 
     #[test]
     fn test_text() {
@@ -222,6 +452,34 @@ mod tests {
         println!("{}", yaml);
     }
 
+    #[test]
+    fn test_upgrade_plaintext() {
+        let doc = Doc::default();
+        let plain = doc.plain_text();
+        doc.set("note", plain.clone());
+        plain.append(doc.string("hello "));
+        plain.append(doc.string("world"));
+        doc.commit();
+        assert!(NText::upgrade_from_plaintext(&doc.text()).is_err());
+
+        let rich = NText::upgrade_from_plaintext(&plain).unwrap();
+        doc.commit();
+        assert!(!rich.is_plain());
+        assert_eq!(rich.text_content(), "hello world");
+        match doc.get("note") {
+            Some(Type::Text(text)) => assert_eq!(text.id(), rich.id()),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let plain = rich.downgrade_to_plaintext().unwrap();
+        doc.commit();
+        assert!(plain.is_plain());
+        assert_eq!(plain.text_content(), "hello world");
+
+        let d2 = Doc::from(&doc.diff(ClientState::default())).unwrap();
+        assert!(equal_docs(&doc, &d2));
+    }
+
     #[test]
     fn test_insert_between_string() {
         let doc = Doc::default();
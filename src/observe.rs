@@ -0,0 +1,856 @@
+use std::cell::RefMut;
+use std::collections::VecDeque;
+use std::fmt::{Debug, Formatter};
+use std::rc::Rc;
+
+use hashbrown::{HashMap, HashSet};
+
+use crate::bimapid::ClientId;
+use crate::codec_v1::EncoderV1;
+use crate::doc::Doc;
+use crate::encoder::{Encode, EncodeContext, Encoder};
+use crate::event::{item_events, DocEvent, Event};
+use crate::id::{Client, Id, IdRange, WithId};
+use crate::item::ItemKind;
+use crate::state::ClientState;
+use crate::store::DocStore;
+use crate::types::Type;
+
+type PathListener = Rc<dyn Fn(&PathEvent)>;
+type DeferredTask = Rc<dyn Fn(&Doc)>;
+type UpdateListener = Rc<dyn Fn(&[u8])>;
+
+/// PathEvent is emitted for a container path that matches an observed pattern
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathEvent {
+    /// slash separated path of the changed container, e.g. `todos/2/done`
+    pub path: String,
+    /// id of the item at the path
+    pub id: Id,
+    /// true for changes committed by the local client
+    pub local: bool,
+    /// changes of the value at the path, empty when the change is further down
+    pub events: Vec<Event>,
+}
+
+// a glob pattern with the listener subscribed to it
+#[derive(Clone)]
+struct PathObserver {
+    token: u32,
+    pattern: Vec<String>,
+    listener: PathListener,
+}
+
+// work postponed until the running listeners return
+#[derive(Clone)]
+enum Deferred {
+    Notify(Vec<(Id, ClientId)>, bool),
+    Task(DeferredTask),
+}
+
+/// PathObservers keeps the key-path subscriptions of a document
+#[derive(Clone, Default)]
+pub(crate) struct PathObservers {
+    observers: Vec<PathObserver>,
+    token: u32,
+    // depth of the running callbacks, nested notifications and commits wait in the queue
+    running: u32,
+    deferred: VecDeque<Deferred>,
+}
+
+impl PathObservers {
+    fn add(&mut self, pattern: &str, listener: PathListener) -> u32 {
+        self.token += 1;
+        self.observers.push(PathObserver {
+            token: self.token,
+            pattern: split_path(pattern),
+            listener,
+        });
+
+        self.token
+    }
+
+    fn remove(&mut self, token: u32) {
+        self.observers.retain(|o| o.token != token);
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.observers.is_empty()
+    }
+}
+
+impl Debug for PathObservers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.observers.iter().map(|o| o.pattern.join("/")))
+            .finish()
+    }
+}
+
+impl PartialEq for PathObservers {
+    fn eq(&self, other: &Self) -> bool {
+        self.observers.len() == other.observers.len()
+            && self
+                .observers
+                .iter()
+                .zip(other.observers.iter())
+                .all(|(a, b)| a.token == b.token && a.pattern == b.pattern)
+    }
+}
+
+impl Eq for PathObservers {}
+
+/// UpdateListeners get the encoded change of every local commit
+#[derive(Clone, Default)]
+pub(crate) struct UpdateListeners {
+    listeners: Vec<(u32, UpdateListener)>,
+    token: u32,
+}
+
+impl UpdateListeners {
+    fn add(&mut self, listener: UpdateListener) -> u32 {
+        self.token += 1;
+        self.listeners.push((self.token, listener));
+
+        self.token
+    }
+
+    fn remove(&mut self, token: u32) {
+        self.listeners.retain(|(t, _)| *t != token);
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.listeners.is_empty()
+    }
+}
+
+impl Debug for UpdateListeners {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.listeners.iter().map(|(token, _)| token))
+            .finish()
+    }
+}
+
+impl PartialEq for UpdateListeners {
+    fn eq(&self, other: &Self) -> bool {
+        let tokens = |l: &Self| l.listeners.iter().map(|(t, _)| *t).collect::<Vec<_>>();
+        tokens(self) == tokens(other)
+    }
+}
+
+impl Eq for UpdateListeners {}
+
+impl DocStore {
+    // changed ids are collected only for path observers and cached checksums
+    #[inline]
+    pub(crate) fn tracks_changes(&self) -> bool {
+        !self.path_observers.is_empty() || !self.emitter.is_empty() || !self.checksums.is_empty()
+    }
+
+    // ids of the items inserted or deleted in the local clock range, when anyone tracks changes
+    pub(crate) fn changed_ids(&self, range: IdRange) -> Vec<Id> {
+        if !self.tracks_changes() {
+            return vec![];
+        }
+
+        let items = self.items.get_by_range(range).into_iter().map(|i| i.id());
+        let deletes = self
+            .deletes
+            .get_by_range(range)
+            .into_iter()
+            .map(|d| d.target());
+
+        items.chain(deletes).collect()
+    }
+}
+
+impl Type {
+    /// Observe the changes of the children of the container, see `Doc::observe_deep`.
+    /// Returns a token to remove the observer with `Doc::unobserve`, None for a type
+    /// of a dropped document.
+    pub fn observe(&self, listener: impl Fn(&DocEvent) + 'static) -> Option<u32> {
+        let store = self.store().upgrade()?;
+        let token = store.borrow_mut().emitter.add_listener(self.id(), listener);
+        Some(token)
+    }
+}
+
+// leaves the running callbacks, also when a callback panics. The work deferred by a
+// panicking callback is dropped with it.
+struct Running<'a> {
+    doc: &'a Doc,
+}
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        let Ok(mut store) = self.doc.store.try_borrow_mut() else {
+            return;
+        };
+        let observers = &mut store.path_observers;
+        observers.running -= 1;
+        if observers.running == 0 && std::thread::panicking() {
+            observers.deferred.clear();
+        }
+    }
+}
+
+impl Doc {
+    /// Observe the containers matching a slash separated glob pattern like `todos/*/done`.
+    /// `*` matches a single path segment or a part of it, `**` matches any number of segments.
+    /// A change inside a container is reported for the container and all its ancestors.
+    /// Returns a token to remove the observer.
+    pub fn observe_path(&self, pattern: &str, listener: impl Fn(&PathEvent) + 'static) -> u32 {
+        self.store_mut("observe_path")
+            .path_observers
+            .add(pattern, Rc::new(listener))
+    }
+
+    pub fn unobserve_path(&self, token: u32) {
+        self.store_mut("unobserve_path")
+            .path_observers
+            .remove(token);
+    }
+
+    /// Observe every change of the document, the listener runs after each commit and
+    /// apply with the events of the changed containers. Returns a token to remove the
+    /// observer with `Doc::unobserve`.
+    pub fn observe_deep(&self, listener: impl Fn(&DocEvent) + 'static) -> u32 {
+        self.store_mut("observe_deep")
+            .emitter
+            .add_deep_listener(listener)
+    }
+
+    /// Remove an observer added by `Doc::observe_deep` or `Type::observe`
+    pub fn unobserve(&self, token: u32) {
+        self.store_mut("unobserve").emitter.remove_listener(token);
+    }
+
+    /// Listen to the local commits, the listener gets the encoded diff of the committed
+    /// change only, e.g. to broadcast small updates to the other sites. Returns a token
+    /// to remove the listener with `Doc::unobserve_update`.
+    pub fn on_update(&self, listener: impl Fn(&[u8]) + 'static) -> u32 {
+        self.store_mut("on_update")
+            .update_listeners
+            .add(Rc::new(listener))
+    }
+
+    pub fn unobserve_update(&self, token: u32) {
+        self.store_mut("unobserve_update")
+            .update_listeners
+            .remove(token);
+    }
+
+    // encode the change committed since the version and hand it to the update listeners
+    pub(crate) fn emit_update(&self, before: ClientState) {
+        let listeners = self.store.borrow().update_listeners.listeners.clone();
+        if listeners.is_empty() {
+            return;
+        }
+
+        let mut e = EncoderV1::new();
+        self.diff(before)
+            .encode(&mut e, &mut EncodeContext::default());
+        let update = e.buffer();
+        self.run_callbacks(|| {
+            for (_, listener) in listeners {
+                listener(&update);
+            }
+        });
+    }
+
+    /// Run the task once the running callbacks return, right away when no callback is
+    /// running. Listeners change the document through it so that every listener of a
+    /// change sees the same document. Changes committed directly from a callback are
+    /// applied but committed and reported after the current callbacks too.
+    pub fn defer(&self, task: impl Fn(&Doc) + 'static) {
+        let mut store = self.store_mut("defer");
+        if store.path_observers.running > 0 {
+            let task = Deferred::Task(Rc::new(task));
+            store.path_observers.deferred.push_back(task);
+        } else {
+            drop(store);
+            task(self);
+        }
+    }
+
+    // mutable store for the public entry points. Callbacks run without a store borrow, a
+    // change made while the store is borrowed still fails with a clear message instead of
+    // a bare BorrowMutError.
+    pub(crate) fn store_mut(&self, op: &str) -> RefMut<'_, DocStore> {
+        match self.store.try_borrow_mut() {
+            Ok(store) => store,
+            Err(_) => panic!(
+                "{} re-entered the document during an iteration, change the document after it",
+                op
+            ),
+        }
+    }
+
+    // true while listeners or visitors run, their commits wait for them to return
+    pub(crate) fn in_callback(&self) -> bool {
+        self.store.borrow().path_observers.running > 0
+    }
+
+    // run the user callbacks of an operation, the work deferred by the callbacks runs once
+    // the outermost callbacks return
+    pub(crate) fn run_callbacks<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.store_mut("run_callbacks").path_observers.running += 1;
+        let running = Running { doc: self };
+        let result = f();
+        drop(running);
+
+        if !self.in_callback() {
+            self.run_deferred();
+        }
+
+        result
+    }
+
+    fn run_deferred(&self) {
+        loop {
+            let next = self
+                .store_mut("run_deferred")
+                .path_observers
+                .deferred
+                .pop_front();
+            match next {
+                Some(Deferred::Notify(changed, local)) => self.notify_paths(changed, local),
+                Some(Deferred::Task(task)) => task(self),
+                None => break,
+            }
+        }
+    }
+
+    // report the changed items to the matching path observers, the changes made by the
+    // listeners are reported once the listeners of this change return
+    pub(crate) fn notify_paths(
+        &self,
+        changed: impl IntoIterator<Item = (Id, ClientId)>,
+        local: bool,
+    ) {
+        let changed: Vec<(Id, ClientId)> = changed.into_iter().collect();
+        {
+            let mut store = self.store_mut("notify");
+            if store.path_observers.is_empty() && store.emitter.is_empty() {
+                return;
+            }
+            let observers = &mut store.path_observers;
+            if observers.running > 0 {
+                observers
+                    .deferred
+                    .push_back(Deferred::Notify(changed, local));
+                return;
+            }
+        }
+
+        self.run_callbacks(|| self.dispatch_paths(changed, local));
+    }
+
+    fn dispatch_paths(&self, changed: Vec<(Id, ClientId)>, local: bool) {
+        let (observers, emitter, items) = {
+            let store = self.store.borrow();
+            if store.path_observers.is_empty() && store.emitter.is_empty() {
+                return;
+            }
+
+            // an item inserted and deleted by the same change is seen twice and has no events
+            let mut counts: HashMap<Id, usize> = HashMap::new();
+            changed
+                .iter()
+                .for_each(|(id, _)| *counts.entry(*id).or_default() += 1);
+
+            let items: Vec<(Type, bool, Option<Client>)> = changed
+                .iter()
+                .filter_map(|(id, client)| {
+                    let count = counts.remove(id)?;
+                    let client = store.state.get_client(client).cloned();
+                    store.find(id).map(|item| (item, count == 1, client))
+                })
+                .collect();
+
+            (
+                store.path_observers.observers.clone(),
+                store.emitter.clone(),
+                items,
+            )
+        };
+
+        // collect the changed containers with all the ancestors, once per path
+        let root = self.root.id();
+        let mut paths = HashSet::new();
+        let mut events = vec![];
+        let mut changes: HashMap<Vec<String>, Vec<Event>> = HashMap::new();
+        let mut doc_events = vec![];
+        for (item, changed, client) in items {
+            let item_changes = match changed {
+                true => item_events(&item),
+                false => vec![],
+            };
+
+            // the listeners of the changed container get the events of its children
+            if !emitter.is_empty() && !item_changes.is_empty() {
+                let container = match item.kind() {
+                    ItemKind::String | ItemKind::Mark => reported_at(item.clone()),
+                    _ => item.parent(),
+                };
+                if let Some(container) = container {
+                    let path = self.path_of(&container, &root);
+                    if !path.is_empty() || container.id() == root {
+                        doc_events.extend(item_changes.iter().map(|event| DocEvent {
+                            path: path.join("/"),
+                            target: container.id(),
+                            client: client.clone(),
+                            local,
+                            event: event.clone(),
+                        }));
+                    }
+                }
+            }
+
+            // strings are reported as a change of the text they belong to,
+            // marks as a change of the marked item
+            let Some(item) = reported_at(item) else {
+                continue;
+            };
+
+            let mut path = self.path_of(&item, &root);
+            if !path.is_empty() && !item_changes.is_empty() {
+                changes
+                    .entry(path.clone())
+                    .or_default()
+                    .extend(item_changes);
+            }
+
+            let mut id = item.id();
+            let mut current = Some(item);
+
+            while let Some(item) = current {
+                if !path.is_empty() && paths.insert(path.clone()) {
+                    events.push((path.clone(), id));
+                }
+
+                path.pop();
+                current = item.parent().filter(|p| p.id() != root);
+                id = current.as_ref().map_or(root, |p| p.id());
+            }
+        }
+
+        for (path, id) in events {
+            let segments: Vec<&str> = path.iter().map(|s| s.as_str()).collect();
+            let event = PathEvent {
+                path: path.join("/"),
+                id,
+                local,
+                events: changes.remove(&path).unwrap_or_default(),
+            };
+
+            for observer in observers.iter() {
+                if glob_match(&observer.pattern, &segments) {
+                    (observer.listener)(&event);
+                }
+            }
+        }
+
+        for event in doc_events {
+            for listener in emitter.listeners(&event.target) {
+                listener(&event);
+            }
+        }
+    }
+
+    // path segments from the root to the item, empty for detached items
+    pub(crate) fn path_of(&self, item: &Type, root: &Id) -> Vec<String> {
+        let mut segments = vec![];
+        let mut current = item.clone();
+
+        while let Some(parent) = current.parent() {
+            let segment = match parent.kind() {
+                ItemKind::List => list_index(&parent, &current).to_string(),
+                _ => match current.item_ref().borrow().data.field {
+                    Some(_) => current.field().unwrap_or_default(),
+                    None => return vec![],
+                },
+            };
+            segments.push(segment);
+
+            if parent.id() == *root {
+                break;
+            }
+            current = parent;
+        }
+
+        segments.reverse();
+        segments
+    }
+}
+
+// index of the item among the visible items of the list, deleted items take the index of the next item
+pub(crate) fn list_index(list: &Type, item: &Type) -> usize {
+    let id = item.id();
+    list.item_ref()
+        .borrow()
+        .all_items()
+        .iter()
+        .take_while(|i| i.id() != id)
+        .filter(|i| i.is_visible())
+        .count()
+}
+
+pub(crate) fn split_path(path: &str) -> Vec<String> {
+    path.split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect()
+}
+
+// the container a change of the item is reported at
+fn reported_at(item: Type) -> Option<Type> {
+    match item.kind() {
+        ItemKind::String | ItemKind::Mark => reported_at(item.parent()?),
+        _ => Some(item),
+    }
+}
+
+pub(crate) fn glob_match(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skip| glob_match(rest, &path[skip..]))
+        }
+        Some((first, rest)) => match path.split_first() {
+            Some((segment, path)) => segment_match(first, segment) && glob_match(rest, path),
+            None => false,
+        },
+    }
+}
+
+// match a single segment, `*` matches any run of characters
+fn segment_match(pattern: &str, segment: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == segment,
+        Some((prefix, rest)) => {
+            let Some(segment) = segment.strip_prefix(prefix) else {
+                return false;
+            };
+
+            (0..=segment.len())
+                .filter(|i| segment.is_char_boundary(*i))
+                .any(|i| segment_match(rest, &segment[i..]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use crate::codec_v1::DecoderV1;
+    use crate::decoder::{Decode, DecodeContext};
+    use crate::diff::Diff;
+    use crate::doc::CloneDeep;
+    use crate::mark::Mark;
+    use crate::raw::RawFilter;
+    use crate::sync::equal_docs;
+    use crate::text_mark::Expand;
+
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        let pattern = split_path("todos/*/done");
+        assert!(glob_match(&pattern, &["todos", "1", "done"]));
+        assert!(!glob_match(&pattern, &["todos", "1", "title"]));
+        assert!(!glob_match(&pattern, &["todos", "done"]));
+
+        let pattern = split_path("**/done");
+        assert!(glob_match(&pattern, &["done"]));
+        assert!(glob_match(&pattern, &["todos", "1", "done"]));
+
+        assert!(segment_match("to*s", "todos"));
+        assert!(!segment_match("to*s", "todo"));
+    }
+
+    #[test]
+    fn test_observe_path() {
+        let doc = Doc::default();
+        let todos = doc.list();
+        doc.set("todos", todos.clone());
+
+        let todo = doc.map();
+        todos.append(todo.clone());
+        todo.set("title", doc.atom("write tests"));
+        doc.commit();
+
+        let events = Rc::new(RefCell::new(vec![]));
+        let seen = events.clone();
+        doc.observe_path("todos/*/done", move |e| {
+            seen.borrow_mut().push(e.path.clone())
+        });
+
+        todo.set("title", doc.atom("write more tests"));
+        doc.commit();
+        assert!(events.borrow().is_empty());
+
+        todo.set("done", doc.atom("yes"));
+        doc.commit();
+        assert_eq!(*events.borrow(), vec!["todos/0/done".to_string()]);
+    }
+
+    #[test]
+    fn test_observe_typed_events() {
+        let doc = Doc::default();
+        let todos = doc.list();
+        doc.set("todos", todos.clone());
+        let notes = doc.text();
+        doc.set("notes", notes.clone());
+        doc.set("title", doc.atom("draft"));
+        doc.commit();
+
+        let events = Rc::new(RefCell::new(vec![]));
+        let seen = events.clone();
+        doc.observe_path("*", move |e| seen.borrow_mut().extend(e.events.clone()));
+        let seen = events.clone();
+        doc.observe_path("todos/*", move |e| {
+            seen.borrow_mut().extend(e.events.clone())
+        });
+
+        doc.set("title", doc.atom("final"));
+        todos.append(doc.atom("a"));
+        let hello = doc.string("hello");
+        notes.append(hello.clone());
+        Type::from(hello).add_mark(Mark::Bold);
+        doc.commit();
+
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                Event::MapSet {
+                    key: "title".to_string(),
+                    old: Some("draft".into()),
+                    new: Some("final".into()),
+                },
+                Event::ListInsert {
+                    index: 0,
+                    values: vec!["a".into()],
+                },
+                Event::TextInsert {
+                    offset: 0,
+                    text: "hello".to_string(),
+                    marks: vec!["bold".to_string()],
+                },
+                Event::MarkInsert {
+                    name: "bold".to_string(),
+                    value: true.into(),
+                },
+            ]
+        );
+
+        events.borrow_mut().clear();
+        todos.get(0usize).unwrap().delete();
+        doc.commit();
+        assert_eq!(
+            *events.borrow(),
+            vec![Event::ListDelete { index: 0, len: 1 }]
+        );
+
+        // the inserted text takes the expanding text mark
+        notes.mark(0, 5, Mark::Italic, Expand::After).unwrap();
+        doc.commit();
+        events.borrow_mut().clear();
+        notes.append(doc.string("!"));
+        doc.commit();
+        assert_eq!(
+            *events.borrow(),
+            vec![Event::TextInsert {
+                offset: 5,
+                text: "!".to_string(),
+                marks: vec!["italic".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_changes_from_listeners_are_deferred() {
+        let doc = Doc::default();
+        let seen = Rc::new(RefCell::new(vec![]));
+        let (events, d) = (seen.clone(), doc.clone());
+        doc.observe_path("*", move |e| {
+            events.borrow_mut().push(e.path.clone());
+            match e.path.as_str() {
+                "a" => {
+                    d.set("b", d.atom("b"));
+                    d.commit();
+                }
+                "b" => d.defer(|d| {
+                    d.set("d", d.atom("d"));
+                    d.commit();
+                }),
+                _ => {}
+            }
+        });
+
+        doc.set("a", doc.atom("a"));
+        doc.set("c", doc.atom("c"));
+        doc.commit();
+
+        // every listener of a change runs before the changes made by the listeners
+        assert_eq!(*seen.borrow(), vec!["a", "c", "b", "d"]);
+        assert!(doc.get("d").is_some());
+
+        // without a running listener the task runs right away
+        doc.defer(|d| d.set("e", d.atom("e")));
+        assert!(doc.get("e").is_some());
+    }
+
+    #[test]
+    fn test_observe_deep_and_type() {
+        let doc = Doc::default();
+        let todos = doc.list();
+        doc.set("todos", todos.clone());
+        let notes = doc.text();
+        doc.set("notes", notes.clone());
+        doc.commit();
+
+        let deep = Rc::new(RefCell::new(vec![]));
+        let seen = deep.clone();
+        let token = doc.observe_deep(move |e| seen.borrow_mut().push(e.clone()));
+        let typed = Rc::new(RefCell::new(vec![]));
+        let seen = typed.clone();
+        Type::from(todos.clone())
+            .observe(move |e| seen.borrow_mut().push(e.event.clone()))
+            .unwrap();
+
+        todos.append(doc.atom("a"));
+        notes.append(doc.string("hi"));
+        doc.commit();
+
+        let client = {
+            let store = doc.store.borrow();
+            store.state.get_client(&store.client).cloned()
+        };
+        {
+            let events = deep.borrow();
+            assert_eq!(events.len(), 2);
+            let todo = events.iter().find(|e| e.path == "todos").unwrap();
+            assert_eq!(todo.target, todos.id());
+            assert_eq!(todo.client, client);
+            assert!(todo.local);
+            assert!(matches!(todo.event, Event::ListInsert { index: 0, .. }));
+            assert!(events.iter().any(|e| e.path == "notes"));
+        }
+        assert_eq!(typed.borrow().len(), 1);
+
+        // remote changes carry the remote client
+        let remote = doc.clone_deep();
+        remote.update_client();
+        remote.get("todos").unwrap().append(remote.atom("b"));
+        remote.commit();
+        deep.borrow_mut().clear();
+        doc.apply(&remote.diff(doc.state()));
+        {
+            let events = deep.borrow();
+            assert_eq!(events.len(), 1);
+            assert!(!events[0].local);
+            assert!(events[0].client.is_some());
+            assert_ne!(events[0].client, client);
+        }
+        assert_eq!(typed.borrow().len(), 2);
+
+        doc.unobserve(token);
+        todos.append(doc.atom("c"));
+        doc.commit();
+        assert_eq!(deep.borrow().len(), 1);
+        assert_eq!(typed.borrow().len(), 3);
+    }
+
+    #[test]
+    fn test_update_stream() {
+        let doc = Doc::default();
+        let updates = Rc::new(RefCell::new(vec![]));
+        let sent = updates.clone();
+        let token = doc.on_update(move |update| sent.borrow_mut().push(update.to_vec()));
+
+        doc.set("title", doc.atom("draft"));
+        doc.commit();
+        doc.set("note", doc.atom("milk"));
+        doc.commit();
+        doc.commit();
+        Type::Map(doc.root.clone()).remove("title".into());
+        doc.commit();
+        assert_eq!(updates.borrow().len(), 3);
+
+        // every update holds the committed change only
+        let decode = |buf: &Vec<u8>| {
+            Diff::decode(&mut DecoderV1::new(buf.clone()), &DecodeContext::default()).unwrap()
+        };
+        let second = decode(&updates.borrow()[1]);
+        assert_eq!(second.items.size(), 1);
+        assert!(second.deletes.is_empty());
+        let third = decode(&updates.borrow()[2]);
+        assert!(third.items.is_empty());
+        assert_eq!(third.deletes.size(), 1);
+
+        let replica = Doc::new(doc.meta.clone());
+        for update in updates.borrow().iter() {
+            replica.apply(&decode(update));
+        }
+        assert!(equal_docs(&doc, &replica));
+
+        doc.unobserve_update(token);
+        doc.set("title", doc.atom("notes"));
+        doc.commit();
+        assert_eq!(updates.borrow().len(), 3);
+    }
+
+    #[test]
+    fn test_commit_from_visitor_is_deferred() {
+        let doc = Doc::default();
+        doc.set("a", doc.atom("a"));
+        doc.commit();
+
+        let seen = Rc::new(RefCell::new(vec![]));
+        let paths = seen.clone();
+        doc.observe_path("*", move |e| paths.borrow_mut().push(e.path.clone()));
+
+        let mut visited = 0;
+        let atoms = RawFilter::default().kind(ItemKind::Atom);
+        doc.raw_items(&atoms, |_| {
+            visited += 1;
+            doc.set("b", doc.atom("b"));
+            doc.commit();
+            // the change is committed after the visit
+            assert!(seen.borrow().is_empty());
+        });
+
+        // the visit sees the items as they were when it started
+        assert_eq!(visited, 1);
+        assert_eq!(*seen.borrow(), vec!["b"]);
+        let store = doc.store.borrow();
+        assert_eq!(store.commited_clock, store.clock);
+    }
+
+    #[test]
+    fn test_panicking_listener_resets_the_callbacks() {
+        let doc = Doc::default();
+        let seen = Rc::new(RefCell::new(vec![]));
+        let paths = seen.clone();
+        doc.observe_path("*", move |e| {
+            if e.path == "a" {
+                panic!("listener failed");
+            }
+            paths.borrow_mut().push(e.path.clone());
+        });
+
+        doc.set("a", doc.atom("a"));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| doc.commit()));
+        assert!(result.is_err());
+        assert!(!doc.in_callback());
+
+        doc.set("b", doc.atom("b"));
+        doc.commit();
+        assert_eq!(*seen.borrow(), vec!["b"]);
+    }
+}
@@ -0,0 +1,283 @@
+use serde_json::{Number, Value};
+
+use crate::decoder::{Decode, DecodeContext, Decoder};
+use crate::encoder::{Encode, EncodeContext, Encoder};
+use crate::item::{Any, Content};
+
+const KIND_F32: u8 = 1;
+const KIND_F64: u8 = 2;
+const KIND_I64: u8 = 3;
+
+/// PackedArray is a typed numeric array stored in a compact form.
+///
+/// Integers are delta encoded as zigzag varints, floats are xor-ed with the previous value
+/// and only the changed bytes are kept, so slowly changing series (embeddings, telemetry)
+/// take a fraction of the size of an array of `Any` numbers.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PackedArray {
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+    I64(Vec<i64>),
+}
+
+impl PackedArray {
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            PackedArray::F32(values) => values.len(),
+            PackedArray::F64(values) => values.len(),
+            PackedArray::I64(values) => values.len(),
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> Value {
+        // non finite floats have no json form
+        let float = |f: f64| Number::from_f64(f).map_or(Value::Null, Value::Number);
+        let values = match self {
+            PackedArray::F32(values) => values.iter().map(|f| float(*f as f64)).collect(),
+            PackedArray::F64(values) => values.iter().map(|f| float(*f)).collect(),
+            PackedArray::I64(values) => values.iter().map(|i| Value::from(*i)).collect(),
+        };
+
+        Value::Array(values)
+    }
+
+    fn kind(&self) -> u8 {
+        match self {
+            PackedArray::F32(_) => KIND_F32,
+            PackedArray::F64(_) => KIND_F64,
+            PackedArray::I64(_) => KIND_I64,
+        }
+    }
+
+    fn pack(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        match self {
+            PackedArray::F32(values) => {
+                let bits = values.iter().map(|f| f.to_bits() as u64);
+                pack_xor(bits, 4, &mut buf);
+            }
+            PackedArray::F64(values) => pack_xor(values.iter().map(|f| f.to_bits()), 8, &mut buf),
+            PackedArray::I64(values) => {
+                let mut prev = 0i64;
+                for value in values {
+                    write_varint(zigzag(value.wrapping_sub(prev)), &mut buf);
+                    prev = *value;
+                }
+            }
+        }
+
+        buf
+    }
+
+    fn unpack(kind: u8, len: usize, buf: &[u8]) -> Result<PackedArray, String> {
+        let mut pos = 0;
+        match kind {
+            KIND_F32 => {
+                let bits = unpack_xor(len, 4, buf, &mut pos)?;
+                Ok(PackedArray::F32(
+                    bits.into_iter().map(|b| f32::from_bits(b as u32)).collect(),
+                ))
+            }
+            KIND_F64 => {
+                let bits = unpack_xor(len, 8, buf, &mut pos)?;
+                Ok(PackedArray::F64(
+                    bits.into_iter().map(f64::from_bits).collect(),
+                ))
+            }
+            KIND_I64 => {
+                let mut values = Vec::with_capacity(len);
+                let mut prev = 0i64;
+                for _ in 0..len {
+                    prev = prev.wrapping_add(unzigzag(read_varint(buf, &mut pos)?));
+                    values.push(prev);
+                }
+                Ok(PackedArray::I64(values))
+            }
+            _ => Err(format!("unknown packed array kind: {}", kind)),
+        }
+    }
+}
+
+impl Encode for PackedArray {
+    fn encode<T: Encoder>(&self, e: &mut T, _cx: &mut EncodeContext) {
+        e.u8(self.kind());
+        e.u32(self.len() as u32);
+        e.bytes(&self.pack());
+    }
+}
+
+impl Decode for PackedArray {
+    fn decode<T: Decoder>(d: &mut T, _ctx: &DecodeContext) -> Result<Self, String> {
+        let kind = d.u8()?;
+        let len = d.u32()? as usize;
+        let buf = d.bytes()?;
+        PackedArray::unpack(kind, len, &buf)
+    }
+}
+
+// every value is xor-ed with the previous one, a header byte keeps the number of
+// leading and trailing zero bytes of the xor and only the bytes in between are written
+fn pack_xor(values: impl Iterator<Item = u64>, width: u32, buf: &mut Vec<u8>) {
+    let mut prev = 0u64;
+    for bits in values {
+        let xor = bits ^ prev;
+        prev = bits;
+
+        if xor == 0 {
+            buf.push(0xFF);
+            continue;
+        }
+
+        let leading = (xor.leading_zeros() / 8).saturating_sub(8 - width) as u8;
+        let trailing = (xor.trailing_zeros() / 8) as u8;
+        buf.push((leading << 4) | trailing);
+
+        let bytes = xor.to_le_bytes();
+        buf.extend_from_slice(&bytes[trailing as usize..(width as u8 - leading) as usize]);
+    }
+}
+
+fn unpack_xor(len: usize, width: u32, buf: &[u8], pos: &mut usize) -> Result<Vec<u64>, String> {
+    let mut values = Vec::with_capacity(len);
+    let mut prev = 0u64;
+    for _ in 0..len {
+        let header = *buf.get(*pos).ok_or("packed array is truncated")?;
+        *pos += 1;
+
+        if header != 0xFF {
+            let (leading, trailing) = ((header >> 4) as usize, (header & 0x0F) as usize);
+            let end = (width as usize)
+                .checked_sub(leading)
+                .filter(|end| *end > trailing)
+                .ok_or("packed array is corrupted")?;
+
+            let size = end - trailing;
+            let chunk = buf
+                .get(*pos..*pos + size)
+                .ok_or("packed array is truncated")?;
+            *pos += size;
+
+            let mut bytes = [0u8; 8];
+            bytes[trailing..end].copy_from_slice(chunk);
+            prev ^= u64::from_le_bytes(bytes);
+        }
+
+        values.push(prev);
+    }
+
+    Ok(values)
+}
+
+#[inline]
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+#[inline]
+fn unzigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+fn write_varint(mut value: u64, buf: &mut Vec<u8>) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn read_varint(buf: &[u8], pos: &mut usize) -> Result<u64, String> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *buf.get(*pos).ok_or("packed array is truncated")?;
+        *pos += 1;
+        value |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err("packed array varint is too long".to_string())
+}
+
+impl From<Vec<f32>> for Content {
+    fn from(values: Vec<f32>) -> Self {
+        Any::Packed(PackedArray::F32(values)).into()
+    }
+}
+
+impl From<Vec<f64>> for Content {
+    fn from(values: Vec<f64>) -> Self {
+        Any::Packed(PackedArray::F64(values)).into()
+    }
+}
+
+impl From<Vec<i64>> for Content {
+    fn from(values: Vec<i64>) -> Self {
+        Any::Packed(PackedArray::I64(values)).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::codec_v1::{DecoderV1, EncoderV1};
+
+    use super::*;
+
+    fn roundtrip(array: &PackedArray) -> (PackedArray, usize) {
+        let mut e = EncoderV1::new();
+        array.encode(&mut e, &mut EncodeContext::default());
+        let buf = e.buffer();
+        let size = buf.len();
+
+        let mut d = DecoderV1::new(buf);
+        (
+            PackedArray::decode(&mut d, &DecodeContext::default()).unwrap(),
+            size,
+        )
+    }
+
+    #[test]
+    fn test_packed_roundtrip() {
+        let series: Vec<i64> = (0..1000).map(|i| 1_700_000_000 + i * 5).collect();
+        let array = PackedArray::I64(series);
+        let (decoded, size) = roundtrip(&array);
+        assert_eq!(decoded, array);
+        assert!(size < 1100);
+
+        let embedding: Vec<f32> = (0..256).map(|i| (i as f32 * 0.01).sin()).collect();
+        let array = PackedArray::F32(embedding);
+        assert_eq!(roundtrip(&array).0, array);
+
+        let array = PackedArray::F64(vec![1.5, 1.5, -0.0, f64::MAX, 1e-300]);
+        assert_eq!(roundtrip(&array).0, array);
+
+        assert_eq!(
+            PackedArray::I64(vec![-1, 2, 3]).to_json(),
+            json!([-1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn test_packed_atom_sync() {
+        let d1 = crate::Doc::default();
+        d1.set("embedding", d1.atom(vec![0.25f32, 0.5, 0.75]));
+        d1.commit();
+
+        // send the diff through the codec
+        let mut e = EncoderV1::new();
+        let diff = d1.diff(crate::ClientState::default());
+        diff.encode(&mut e, &mut EncodeContext::default());
+        let mut d = DecoderV1::new(e.buffer());
+        let diff = crate::Diff::decode(&mut d, &DecodeContext::default()).unwrap();
+
+        let d2 = crate::Doc::from(&diff).unwrap();
+        assert_eq!(
+            d2.get("embedding").unwrap().to_json(),
+            json!([0.25, 0.5, 0.75])
+        );
+    }
+}
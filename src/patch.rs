@@ -0,0 +1,199 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::change::ChangeStore;
+use crate::codec_v1::{DecoderV1, EncoderV1};
+use crate::decoder::{Decode, DecodeContext};
+use crate::diff::Diff;
+use crate::doc::Doc;
+use crate::encoder::{Encode, EncodeContext, Encoder};
+use crate::id::WithId;
+use crate::preview::{json_changes, JsonChange, PreviewReport};
+use crate::state::ClientState;
+use crate::store::{DeleteItemStore, ItemDataStore};
+
+/// DocPatch is a self-contained, reviewable set of changes between two document versions.
+///
+/// The previews and the json changes are for the reviewer, the encoded diff is what
+/// `Doc::apply_patch` applies, so a patch can be stored or sent as plain json.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DocPatch {
+    pub name: Option<String>,
+    /// client clocks of the versions, by client
+    pub from: Map<String, Value>,
+    pub to: Map<String, Value>,
+    /// json of the document at the versions
+    pub before: Value,
+    pub after: Value,
+    pub changes: Vec<JsonChange>,
+    /// base64 of the encoded diff
+    pub diff: String,
+}
+
+impl DocPatch {
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn to_json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+
+    pub fn from_json(json: &Value) -> Result<DocPatch, String> {
+        DocPatch::deserialize(json).map_err(|e| e.to_string())
+    }
+
+    /// Decode the diff carried by the patch
+    pub fn diff(&self) -> Result<Diff, String> {
+        let bytes = STANDARD.decode(&self.diff).map_err(|e| e.to_string())?;
+        let mut d = DecoderV1::try_new(bytes)?;
+        Diff::decode(&mut d, &DecodeContext::default())
+    }
+}
+
+impl Doc {
+    /// Export the changes between two versions of the document as a patch.
+    /// The versions are expected to be taken with `Doc::version` after a commit.
+    pub fn export_patch(&self, from: &ClientState, to: &ClientState) -> Result<DocPatch, String> {
+        let full = self.diff(ClientState::default());
+        let until_from = diff_until(&full, from);
+        let until_to = diff_until(&full, to);
+
+        let diff = until_to.diff(from);
+        if diff.items.is_empty() && diff.deletes.is_empty() {
+            return Err("no changes between the versions".to_string());
+        }
+
+        let before = preview(&until_from);
+        let after = preview(&until_to);
+        let mut changes = vec![];
+        json_changes(String::new(), Some(&before), Some(&after), &mut changes);
+
+        let mut e = EncoderV1::new();
+        diff.encode(&mut e, &mut EncodeContext::default());
+
+        Ok(DocPatch {
+            name: None,
+            from: version_json(from),
+            to: version_json(to),
+            before,
+            after,
+            changes,
+            diff: STANDARD.encode(e.buffer()),
+        })
+    }
+
+    /// Apply the patch, a dry run only reports what would change.
+    /// Patches of other documents or with unsupported features are refused.
+    pub fn apply_patch(&self, patch: &DocPatch, dry_run: bool) -> Result<PreviewReport, String> {
+        let diff = patch.diff()?;
+        let report = self.preview_apply(&diff);
+        if !report.is_valid() {
+            return Err(report.errors.join(", "));
+        }
+
+        if !dry_run {
+            self.apply(&diff);
+        }
+
+        Ok(report)
+    }
+}
+
+// the part of the diff seen at the version
+fn diff_until(diff: &Diff, version: &ClientState) -> Diff {
+    let seen = |client, clock| version.get(&client).is_some_and(|c| clock <= *c);
+
+    let mut items = ItemDataStore::default();
+    for (client, store) in diff.items.iter() {
+        store
+            .iter()
+            .filter(|(id, _)| seen(*client, id.clock))
+            .for_each(|(_, item)| items.insert(item.clone()));
+    }
+
+    let mut deletes = DeleteItemStore::default();
+    for (client, store) in diff.deletes.iter() {
+        store
+            .iter()
+            .filter(|(_, item)| seen(*client, item.id().clock))
+            .for_each(|(_, item)| deletes.insert(item.clone()));
+    }
+
+    let mut changes = ChangeStore::default();
+    for (client, store) in diff.changes.iter() {
+        store
+            .iter()
+            .filter(|change| seen(*client, change.end))
+            .for_each(|change| changes.insert(*change));
+    }
+
+    let mut part = Diff::from(
+        diff.doc_id.clone(),
+        diff.created_by.clone(),
+        diff.fields.clone(),
+        changes,
+        diff.state.clone(),
+        items,
+        deletes,
+    );
+    part.features = diff.features.clone();
+
+    part
+}
+
+fn version_json(version: &ClientState) -> Map<String, Value> {
+    version
+        .clients()
+        .into_iter()
+        .map(|(client, clock)| (client.to_string(), Value::from(clock)))
+        .collect()
+}
+
+// json of the document built from the diff, empty before the document was created
+fn preview(diff: &Diff) -> Value {
+    Doc::from(diff).map_or(Value::Object(Map::new()), |doc| doc.root.to_json())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::doc::CloneDeep;
+
+    use super::*;
+
+    #[test]
+    fn test_export_and_apply_patch() {
+        let d1 = Doc::default();
+        d1.set("title", d1.atom("draft"));
+        d1.commit();
+        let reviewed = d1.clone_deep();
+        let from = d1.version();
+
+        d1.set("title", d1.atom("final"));
+        d1.set("tags", d1.list());
+        d1.commit();
+
+        let patch = d1
+            .export_patch(&from, &d1.version())
+            .unwrap()
+            .with_name("rename title");
+        assert_eq!(patch.before, json!({"title": "draft"}));
+        assert_eq!(patch.after, json!({"title": "final", "tags": []}));
+
+        // the patch survives a json roundtrip
+        let patch = DocPatch::from_json(&patch.to_json()).unwrap();
+        assert_eq!(patch.name.as_deref(), Some("rename title"));
+
+        let report = reviewed.apply_patch(&patch, true).unwrap();
+        assert!(!report.changes.is_empty());
+        assert_eq!(reviewed.get("title").unwrap().to_json(), json!("draft"));
+
+        reviewed.apply_patch(&patch, false).unwrap();
+        assert_eq!(reviewed.get("title").unwrap().to_json(), json!("final"));
+    }
+}
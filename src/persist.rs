@@ -1,12 +1,16 @@
 use std::cell::RefCell;
 use std::default::Default;
+use std::fs::{File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 use std::rc::{Rc, Weak};
 
 use crate::bimapid::FieldMap;
 use crate::change::ChangeStore;
+use crate::codec_v1::{DecoderV1, EncoderV1};
 use crate::decoder::{Decode, DecodeContext, Decoder};
 use crate::diff::Diff;
-use crate::doc::DocId;
+use crate::doc::{Doc, DocId};
 use crate::encoder::{Encode, EncodeContext, Encoder};
 use crate::item::{Content, ItemKind};
 use crate::state::ClientState;
@@ -204,3 +208,176 @@ impl DocStoreDataTransaction {
 
     fn rollback(&mut self) {}
 }
+
+// header of a doc file, followed by the change frames
+const MAGIC: &[u8; 8] = b"NITRODOC";
+// length and crc of the payload
+const FRAME_HEADER: usize = 8;
+
+/// DocFile is an append-only file of document changes.
+///
+/// Every change is a frame of the payload length, the crc32 of the payload and the encoded
+/// diff. A frame is synced to disk before `append_change` returns. A crash while appending
+/// leaves a torn or corrupt frame at the end of the file, `open` truncates the file to the
+/// last complete frame.
+#[derive(Debug)]
+pub struct DocFile {
+    path: PathBuf,
+    file: File,
+    // bytes dropped from the tail by `open`
+    truncated: u64,
+}
+
+impl DocFile {
+    /// Open or create the file, a torn tail of an earlier crash is truncated
+    pub fn open(path: impl AsRef<Path>) -> Result<DocFile, String> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(|e| e.to_string())?;
+        let bytes = std::fs::read(&path).map_err(|e| e.to_string())?;
+
+        // a crash while the file was created leaves a part of the header only
+        let end = if bytes.len() < MAGIC.len() && MAGIC.starts_with(&bytes) {
+            file.set_len(0).map_err(|e| e.to_string())?;
+            file.write_all(MAGIC).map_err(|e| e.to_string())?;
+            MAGIC.len()
+        } else if bytes.starts_with(MAGIC) {
+            frames(&bytes).1
+        } else {
+            return Err(format!("{} is not a doc file", path.display()));
+        };
+
+        let truncated = bytes.len().saturating_sub(end) as u64;
+        if truncated > 0 {
+            file.set_len(end as u64).map_err(|e| e.to_string())?;
+        }
+        file.sync_all().map_err(|e| e.to_string())?;
+        file.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+
+        Ok(DocFile {
+            path,
+            file,
+            truncated,
+        })
+    }
+
+    /// Append the diff of a change, the first change of a file is a diff with the
+    /// document root, e.g. the diff of a new document from the default state
+    pub fn append_change(&mut self, diff: &Diff) -> Result<(), String> {
+        let mut e = EncoderV1::new();
+        diff.encode(&mut e, &mut EncodeContext::default());
+        let payload = e.buffer();
+
+        let mut frame = Vec::with_capacity(FRAME_HEADER + payload.len());
+        frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&crc32(&payload).to_le_bytes());
+        frame.extend_from_slice(&payload);
+
+        self.file.write_all(&frame).map_err(|e| e.to_string())?;
+        self.file.sync_data().map_err(|e| e.to_string())
+    }
+
+    /// Load the document from the changes of the file
+    pub fn load(&self) -> Result<Doc, String> {
+        let bytes = std::fs::read(&self.path).map_err(|e| e.to_string())?;
+        let mut changes = frames(&bytes).0.into_iter().map(|payload| {
+            let mut d = DecoderV1::try_new(payload.to_vec())?;
+            Diff::decode(&mut d, &DecodeContext::default())
+        });
+
+        let first = changes.next().ok_or("doc file has no changes")??;
+        let doc = Doc::from(&first).ok_or("first change of the doc file has no document root")?;
+        for diff in changes {
+            doc.apply(&diff?);
+        }
+
+        Ok(doc)
+    }
+
+    /// Bytes of a torn tail truncated when the file was opened
+    #[inline]
+    pub fn truncated(&self) -> u64 {
+        self.truncated
+    }
+}
+
+// payloads of the complete frames and the end of the last one
+fn frames(bytes: &[u8]) -> (Vec<&[u8]>, usize) {
+    let mut payloads = vec![];
+    let mut offset = MAGIC.len();
+    while let Some(header) = bytes.get(offset..offset + FRAME_HEADER) {
+        let size = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(header[4..].try_into().unwrap());
+        let start = offset + FRAME_HEADER;
+        match bytes.get(start..start + size) {
+            Some(payload) if crc32(payload) == crc => payloads.push(payload),
+            _ => break,
+        }
+        offset = start + size;
+    }
+
+    (payloads, offset.min(bytes.len()))
+}
+
+// crc-32 (ieee) of the bytes
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sync::equal_docs;
+
+    use super::*;
+
+    #[test]
+    fn test_doc_file_recovers_torn_tail() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let path = std::env::temp_dir().join(format!("nitro-{}.doc", uuid::Uuid::new_v4()));
+        let doc = Doc::default();
+        doc.set("title", doc.atom("first"));
+        doc.commit();
+
+        let mut file = DocFile::open(&path).unwrap();
+        file.append_change(&doc.diff(ClientState::default()))
+            .unwrap();
+        let state = doc.state();
+        let list = doc.list();
+        doc.set("list", list.clone());
+        list.append(doc.atom("a"));
+        doc.commit();
+        file.append_change(&doc.diff(state)).unwrap();
+        drop(file);
+
+        // a crash in the middle of the next frame
+        let mut torn = OpenOptions::new().append(true).open(&path).unwrap();
+        torn.write_all(&[200, 0, 0, 0, 1, 2]).unwrap();
+        drop(torn);
+
+        let file = DocFile::open(&path).unwrap();
+        assert_eq!(file.truncated(), 6);
+        assert!(equal_docs(&doc, &file.load().unwrap()));
+        drop(file);
+
+        // the file is clean after the recovery
+        let file = DocFile::open(&path).unwrap();
+        assert_eq!(file.truncated(), 0);
+        assert!(equal_docs(&doc, &file.load().unwrap()));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,246 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::diff::Diff;
+use crate::doc::{CloneDeep, Doc};
+use crate::id::{Id, WithId};
+use crate::types::Type;
+
+/// PreviewReport describes what applying a diff would do to a document
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PreviewReport {
+    /// json values that would change
+    pub changes: Vec<JsonChange>,
+    /// remote edits that clash with concurrent local edits
+    pub conflicts: Vec<PreviewConflict>,
+    /// reasons the diff can not be applied at all
+    pub errors: Vec<String>,
+}
+
+impl PreviewReport {
+    /// true when the diff can be applied
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    #[inline]
+    pub fn has_conflicts(&self) -> bool {
+        !self.conflicts.is_empty()
+    }
+}
+
+/// JsonChange is a single changed value in the json view of the document
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonChange {
+    /// json pointer to the changed value
+    pub path: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PreviewConflict {
+    /// id of the remote item in the local id space
+    pub id: Id,
+    pub reason: String,
+}
+
+impl Doc {
+    /// Apply the diff on a temporary copy of the document and report the outcome.
+    /// The document itself is never modified.
+    pub fn preview_apply(&self, diff: &Diff) -> PreviewReport {
+        let mut report = PreviewReport::default();
+
+        if diff.doc_id != self.id() {
+            report.errors.push(format!(
+                "diff belongs to document {:?}, expected {:?}",
+                diff.doc_id,
+                self.id()
+            ));
+        }
+
+        if let Err(err) = diff.features.check() {
+            report.errors.push(err);
+        }
+
+        if !report.is_valid() {
+            return report;
+        }
+
+        report.conflicts = self.find_conflicts(diff);
+
+        let overlay = self.clone_deep();
+        let before = overlay.root.to_json();
+        overlay.apply(diff);
+        let after = overlay.root.to_json();
+
+        json_changes(
+            String::new(),
+            Some(&before),
+            Some(&after),
+            &mut report.changes,
+        );
+
+        report
+    }
+
+    // find remote items that are inserted into locally deleted items
+    // or that overwrite a map key written concurrently by the local site
+    fn find_conflicts(&self, diff: &Diff) -> Vec<PreviewConflict> {
+        let adjusted = diff.adjust(&self.store_mut("find_conflicts"));
+        let mut conflicts = vec![];
+
+        // the adjusted state is merged with the local state, the remote site saw a local
+        // item only if the state of the original diff covers it
+        let seen = |id: Id| {
+            let store = self.store.borrow();
+            let client = store.state.get_client(&id.client);
+            let client = client.and_then(|client| diff.state.get_client_id(client));
+            client
+                .and_then(|client| diff.state.get(client))
+                .is_some_and(|clock| id.clock <= *clock)
+        };
+
+        for (_, store) in adjusted.items.iter() {
+            for (_, data) in store.iter() {
+                // already integrated items can not conflict
+                if self.find_by_id(&data.id).is_some() {
+                    continue;
+                }
+
+                let parent = adjusted.parent_id(&self.store.borrow(), data);
+                let parent = parent.and_then(|id| self.find_by_id(&id));
+                match parent {
+                    Some(parent) if parent.is_deleted() => conflicts.push(PreviewConflict {
+                        id: data.id,
+                        reason: "insert into a deleted item".to_string(),
+                    }),
+                    Some(Type::Map(map)) => {
+                        let field = data
+                            .field
+                            .and_then(|f| adjusted.fields.get_field(&f).cloned());
+                        let current = field.as_ref().and_then(|f| map.get(f.clone()));
+                        if let (Some(field), Some(current)) = (field, current) {
+                            if !seen(current.id()) {
+                                conflicts.push(PreviewConflict {
+                                    id: data.id,
+                                    reason: format!("concurrent write to key {}", field),
+                                })
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        conflicts
+    }
+}
+
+pub(crate) fn json_changes(
+    path: String,
+    before: Option<&Value>,
+    after: Option<&Value>,
+    changes: &mut Vec<JsonChange>,
+) {
+    match (before, after) {
+        (Some(Value::Object(b)), Some(Value::Object(a))) => {
+            for (key, value) in b.iter() {
+                json_changes(
+                    format!("{}/{}", path, escape_token(key)),
+                    Some(value),
+                    a.get(key),
+                    changes,
+                );
+            }
+            for (key, value) in a.iter().filter(|(key, _)| !b.contains_key(*key)) {
+                json_changes(
+                    format!("{}/{}", path, escape_token(key)),
+                    None,
+                    Some(value),
+                    changes,
+                );
+            }
+        }
+        (Some(Value::Array(b)), Some(Value::Array(a))) => {
+            for i in 0..b.len().max(a.len()) {
+                json_changes(format!("{}/{}", path, i), b.get(i), a.get(i), changes);
+            }
+        }
+        (b, a) if b != a => changes.push(JsonChange {
+            path,
+            before: b.cloned(),
+            after: a.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+// json pointer reference token of an object key, see RFC 6901
+fn escape_token(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::state::ClientState;
+
+    use super::*;
+
+    #[test]
+    fn test_preview_apply_does_not_modify_doc() {
+        let d1 = Doc::default();
+        d1.set("a", d1.atom("a"));
+        d1.commit();
+
+        let d2 = d1.clone_deep();
+        d2.update_client();
+
+        d2.set("b", d2.atom("b"));
+        d2.commit();
+
+        let report = d1.preview_apply(&d2.diff(ClientState::default()));
+
+        assert!(report.is_valid());
+        assert_eq!(report.changes.len(), 1);
+        assert_eq!(report.changes[0].path, "/b");
+        assert!(d1.get("b").is_none());
+    }
+
+    #[test]
+    fn test_preview_concurrent_key_write() {
+        let d1 = Doc::default();
+        d1.set("a", d1.atom("a"));
+        d1.commit();
+
+        let d2 = d1.clone_deep();
+        d2.update_client();
+
+        d1.set("k", d1.atom("local"));
+        d1.commit();
+
+        d2.set("k", d2.atom("remote"));
+        d2.commit();
+
+        let report = d1.preview_apply(&d2.diff(ClientState::default()));
+        assert!(report.has_conflicts());
+    }
+
+    #[test]
+    fn test_preview_escapes_json_pointer() {
+        let d1 = Doc::default();
+        d1.commit();
+
+        let d2 = d1.clone_deep();
+        d2.update_client();
+
+        d2.set("a/b~c", d2.atom("x"));
+        d2.commit();
+
+        let report = d1.preview_apply(&d2.diff(ClientState::default()));
+        assert_eq!(report.changes.len(), 1);
+        assert_eq!(report.changes[0].path, "/a~1b~0c");
+    }
+}
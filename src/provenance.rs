@@ -0,0 +1,256 @@
+use std::collections::BTreeSet;
+
+use hashbrown::HashMap;
+
+use crate::apply_stats::ApplyStats;
+use crate::bimapid::ClientId;
+use crate::change::{ChangeId, ChangeStore};
+use crate::diff::Diff;
+use crate::doc::Doc;
+use crate::id::{ClockTick, Id, WithId};
+use crate::state::ClientState;
+use crate::store::{DeleteItemStore, DocStore, ItemDataStore};
+
+/// OriginFilter lists the change origins a sync provider does not propagate
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct OriginFilter {
+    // held back from the receiver, e.g. local drafts
+    blocked: BTreeSet<String>,
+    // already known to the receiver, e.g. the upstream the diff is sent to
+    skipped: BTreeSet<String>,
+}
+
+impl OriginFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hold back the changes of the origin and the changes depending on them
+    pub fn block(mut self, origin: impl Into<String>) -> Self {
+        self.blocked.insert(origin.into());
+        self
+    }
+
+    /// Leave out the changes of the origin, the receiver already has them
+    pub fn skip(mut self, origin: impl Into<String>) -> Self {
+        self.skipped.insert(origin.into());
+        self
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.blocked.is_empty() && self.skipped.is_empty()
+    }
+
+    /// Changes without an origin are always allowed
+    #[inline]
+    pub fn allows(&self, origin: Option<&str>) -> bool {
+        origin.map_or(true, |origin| {
+            !self.blocked.contains(origin) && !self.skipped.contains(origin)
+        })
+    }
+}
+
+impl DocStore {
+    // tag the changes of the client starting at or after the clock
+    pub(crate) fn tag_changes(&mut self, client: ClientId, start: ClockTick, origin: String) {
+        for change in self.changes_since(client, start) {
+            self.origins.insert(change, origin.clone());
+        }
+    }
+
+    // changes of the client starting at or after the clock
+    pub(crate) fn changes_since(&self, client: ClientId, start: ClockTick) -> Vec<ChangeId> {
+        let Some(changes) = self.changes.id_store(&client) else {
+            return vec![];
+        };
+
+        changes
+            .iter()
+            .filter(|change| change.start >= start)
+            .cloned()
+            .collect()
+    }
+}
+
+impl Doc {
+    /// Commit the pending change tagged with the origin, a change violating the document
+    /// schema is rolled back and the violations are returned
+    pub fn commit_with_origin(&self, origin: impl Into<String>) -> Result<(), Vec<String>> {
+        let (client, start) = {
+            let store = self.store.borrow();
+            (store.client, store.commited_clock)
+        };
+        self.try_commit()?;
+
+        let mut store = self.store_mut("commit_with_origin");
+        store.tag_changes(client, start, origin.into());
+
+        Ok(())
+    }
+
+    /// Apply a remote diff and tag the changes it integrated with the origin, e.g. the
+    /// upstream the diff came from
+    pub fn apply_with_origin(&self, diff: &Diff, origin: impl Into<String>) -> ApplyStats {
+        let before = self.version();
+        let stats = self.apply(diff);
+
+        let origin = origin.into();
+        let mut store = self.store_mut("apply_with_origin");
+        let clients: Vec<ClientId> = store.changes.iter().map(|(client, _)| *client).collect();
+        for client in clients {
+            let known = before.get(&client).map_or(0, |clock| clock + 1);
+            store.tag_changes(client, known, origin.clone());
+        }
+
+        stats
+    }
+
+    /// Origin of the change holding the id
+    pub fn origin_of(&self, id: &Id) -> Option<String> {
+        let store = self.store.borrow();
+        let change = store.changes.get(id)?;
+        store.origins.get(change).cloned()
+    }
+
+    /// Diff of the changes since the state whose origin passes the filter.
+    ///
+    /// A client clock has no holes, so the changes of a client after a blocked change are
+    /// held back too, as are the changes that depend on a held back change. They are sent
+    /// once the filter lets the blocking change through. Skipped changes are left out
+    /// without holding back anything.
+    pub fn diff_filtered(&self, state: impl Into<ClientState>, filter: &OriginFilter) -> Diff {
+        let mut diff = self.diff(state);
+        if filter.is_empty() {
+            return diff;
+        }
+
+        // the first held back clock of every client and the skipped changes
+        let mut cut: HashMap<ClientId, ClockTick> = HashMap::new();
+        let mut skipped = ChangeStore::default();
+        {
+            let store = self.store.borrow();
+            for (change, origin) in store.origins.iter() {
+                if filter.blocked.contains(origin) {
+                    let entry = cut.entry(change.client).or_insert(change.start);
+                    *entry = (*entry).min(change.start);
+                } else if filter.skipped.contains(origin) {
+                    skipped.insert(*change);
+                }
+            }
+        }
+
+        let held = |cut: &HashMap<ClientId, ClockTick>, id: &Id| {
+            cut.get(&id.client).is_some_and(|clock| id.clock >= *clock)
+        };
+
+        // hold back everything depending on a held back id until nothing changes
+        let mut changed = !cut.is_empty();
+        while changed {
+            changed = false;
+            let mut hold = |cut: &mut HashMap<ClientId, ClockTick>, id: Id| {
+                if !held(cut, &id) {
+                    cut.insert(id.client, id.clock);
+                    changed = true;
+                }
+            };
+
+            for (_, items) in diff.items.iter() {
+                for (_, item) in items.iter() {
+                    let range = item.id.range(item.ticks());
+                    if held(&cut, &range.end_id()) || item.deps().iter().any(|id| held(&cut, id)) {
+                        hold(&mut cut, item.id);
+                    }
+                }
+            }
+            for (_, deletes) in diff.deletes.iter() {
+                for (id, delete) in deletes.iter() {
+                    if held(&cut, &delete.target()) {
+                        hold(&mut cut, *id);
+                    }
+                }
+            }
+        }
+
+        let kept = |id: &Id| !held(&cut, id) && !skipped.contains(id);
+        let mut items = ItemDataStore::default();
+        for (_, store) in diff.items.iter() {
+            store
+                .iter()
+                .filter(|(id, _)| kept(id))
+                .for_each(|(_, item)| items.insert(item.clone()));
+        }
+        let mut deletes = DeleteItemStore::default();
+        for (_, store) in diff.deletes.iter() {
+            store
+                .iter()
+                .filter(|(id, _)| kept(id))
+                .for_each(|(_, delete)| deletes.insert(delete.clone()));
+        }
+        let mut changes = ChangeStore::default();
+        for (_, store) in diff.changes.iter() {
+            store
+                .iter()
+                .filter(|change| kept(&change.id()))
+                .for_each(|change| changes.insert(*change));
+        }
+
+        // the receiver must not think it has seen the held back changes
+        for (client, clock) in cut.iter() {
+            if diff.state.get(client).is_some_and(|seen| *seen >= *clock) {
+                diff.state.state.update(*client, clock.saturating_sub(1));
+            }
+        }
+
+        diff.items = items;
+        diff.deletes = deletes;
+        diff.changes = changes;
+        diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doc::CloneDeep;
+
+    use super::*;
+
+    #[test]
+    fn test_filter_change_origins() {
+        let hub = Doc::default();
+        hub.commit();
+        let spoke = hub.clone_deep();
+        spoke.update_client();
+
+        spoke.set("from-spoke", spoke.atom("s"));
+        spoke.commit();
+        let stats = hub.apply_with_origin(&spoke.diff(hub.version()), "spoke");
+        assert_eq!(stats.integrated, 1);
+        let id = hub.get("from-spoke").unwrap().id();
+        assert_eq!(hub.origin_of(&id), Some("spoke".to_string()));
+
+        // changes received from the spoke are not echoed back
+        hub.set("from-hub", hub.atom("h"));
+        hub.commit();
+        let filter = OriginFilter::new().skip("spoke").block("local-draft");
+        let full = hub.diff(ClientState::default());
+        let diff = hub.diff_filtered(ClientState::default(), &filter);
+        assert_eq!(diff.items.size() + 1, full.items.size());
+        spoke.apply(&diff);
+        assert!(spoke.get("from-hub").is_some());
+
+        // drafts stay local, later changes of the client wait for them
+        hub.set("draft", hub.atom("d"));
+        hub.commit_with_origin("local-draft").unwrap();
+        hub.set("after", hub.atom("a"));
+        hub.commit();
+
+        spoke.apply(&hub.diff_filtered(spoke.version(), &filter));
+        assert!(spoke.get("draft").is_none());
+        assert!(spoke.get("after").is_none());
+
+        spoke.apply(&hub.diff_filtered(spoke.version(), &OriginFilter::new()));
+        assert!(spoke.get("draft").is_some());
+        assert!(spoke.get("after").is_some());
+    }
+}
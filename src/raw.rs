@@ -0,0 +1,158 @@
+use crate::doc::Doc;
+use crate::id::{Id, WithId};
+use crate::item::{Content, ItemData, ItemKind};
+use crate::store::DocStore;
+
+/// RawItem is a borrowed view of the data of a single item
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RawItem<'a> {
+    pub id: Id,
+    pub kind: ItemKind,
+    /// id of the container holding the item
+    pub parent: Option<Id>,
+    /// map key of the item
+    pub field: Option<&'a str>,
+    pub content: &'a Content,
+    pub deleted: bool,
+}
+
+/// RawFilter selects the items visited by `Doc::raw_items`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RawFilter {
+    kinds: Vec<ItemKind>,
+    parent: Option<Id>,
+    deleted: bool,
+}
+
+impl RawFilter {
+    /// Visit only the items of the kind, can be repeated to visit several kinds
+    pub fn kind(mut self, kind: ItemKind) -> Self {
+        self.kinds.push(kind);
+        self
+    }
+
+    /// Visit only the direct children of the container
+    pub fn parent(mut self, parent: Id) -> Self {
+        self.parent = Some(parent);
+        self
+    }
+
+    /// Visit the deleted items too
+    pub fn with_deleted(mut self) -> Self {
+        self.deleted = true;
+        self
+    }
+
+    fn matches(&self, item: &RawItem) -> bool {
+        (self.kinds.is_empty() || self.kinds.contains(&item.kind))
+            && (self.parent.is_none() || self.parent == item.parent)
+            && (self.deleted || !item.deleted)
+    }
+}
+
+impl Doc {
+    /// Visit the data of the items matching the filter in id order, for search indexes and
+    /// analytics that do not need the document tree. Items of frozen texts are decoded
+    /// without thawing the texts. The visitor sees the items as they were when the visit
+    /// started, the changes it commits are applied after the visit.
+    pub fn raw_items(&self, filter: &RawFilter, mut visit: impl FnMut(&RawItem)) {
+        let items = {
+            let store = self.store.borrow();
+            let mut items = vec![];
+            let mut keep = |data: &ItemData, parent: Option<Id>, deleted: bool| {
+                let raw = raw_item(&store, data, parent, deleted);
+                if filter.matches(&raw) {
+                    let field = raw.field.map(|field| field.to_string());
+                    items.push((data.clone(), parent, field, deleted));
+                }
+            };
+
+            for (_, items) in store.items.iter() {
+                for (_, item) in items.iter() {
+                    let item = item.item_ref();
+                    let item = item.borrow();
+                    // the codec drops the parent id of items with a left origin, the link is kept
+                    let parent = item.parent.as_ref().map(|p| p.id());
+                    keep(&item.data, parent, item.is_deleted());
+                }
+            }
+
+            for (flags, data) in store.cold.flagged_items() {
+                keep(&data, data.parent_id, flags & 0x01 == 0x01);
+            }
+
+            items
+        };
+
+        // the store is released, the visitor can change the document
+        self.run_callbacks(|| {
+            for (data, parent, field, deleted) in items.iter() {
+                visit(&RawItem {
+                    id: data.id,
+                    kind: data.kind,
+                    parent: *parent,
+                    field: field.as_deref(),
+                    content: &data.content,
+                    deleted: *deleted,
+                });
+            }
+        });
+    }
+}
+
+fn raw_item<'a>(
+    store: &'a DocStore,
+    data: &'a ItemData,
+    parent: Option<Id>,
+    deleted: bool,
+) -> RawItem<'a> {
+    RawItem {
+        id: data.id,
+        kind: data.kind,
+        parent,
+        field: data
+            .field
+            .and_then(|field| store.fields.get_field(&field))
+            .map(|field| field.as_str()),
+        content: &data.content,
+        deleted,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_raw_items() {
+        let doc = Doc::default();
+        let notes = doc.text();
+        doc.set("notes", notes.clone());
+        notes.append(doc.string("hello "));
+        notes.append(doc.string("world"));
+        doc.set("title", doc.atom("draft"));
+        doc.commit();
+        doc.get("title").unwrap().delete();
+        doc.commit();
+
+        let mut words = vec![];
+        let filter = RawFilter::default().kind(ItemKind::String);
+        doc.raw_items(&filter, |item| {
+            if let Content::String(s) = item.content {
+                words.push(s.clone());
+            }
+        });
+        assert_eq!(words, vec!["hello ".to_string(), "world".to_string()]);
+
+        let mut fields = vec![];
+        let filter = RawFilter::default().parent(doc.root.id());
+        doc.raw_items(&filter, |item| {
+            fields.push(item.field.map(|f| f.to_string()))
+        });
+        assert_eq!(fields, vec![Some("notes".to_string())]);
+
+        let mut count = 0;
+        doc.raw_items(&filter.with_deleted(), |_| count += 1);
+        assert_eq!(count, 2);
+    }
+}
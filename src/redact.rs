@@ -0,0 +1,259 @@
+use std::collections::BTreeSet;
+
+use hashbrown::HashMap;
+
+use crate::bimapid::ClientId;
+use crate::diff::Diff;
+use crate::doc::Doc;
+use crate::id::{Id, IdRange, WithId};
+use crate::item::{Content, ItemData, ItemKind, Linked};
+use crate::state::ClientState;
+use crate::store::{DeleteItemStore, DocStore, ItemDataStore};
+use crate::types::Type;
+
+/// RedactPolicy lists the subtrees a receiver is not allowed to read
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct RedactPolicy {
+    // roots of the hidden subtrees
+    items: BTreeSet<Id>,
+    // hidden keys of the maps, every value ever set for the key is hidden
+    keys: BTreeSet<(Id, String)>,
+}
+
+impl RedactPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hide the item and everything inserted into it
+    pub fn hide(mut self, item: &Type) -> Self {
+        self.items.insert(item.id());
+        self
+    }
+
+    /// Hide the values of the key of the map, including the values set later
+    pub fn hide_key(mut self, map: &Type, key: impl Into<String>) -> Self {
+        self.keys.insert((map.id(), key.into()));
+        self
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty() && self.keys.is_empty()
+    }
+
+    // the item is the root of a hidden subtree
+    fn hides(&self, item: &Type) -> bool {
+        if self.items.contains(&item.id()) {
+            return true;
+        }
+        if self.keys.is_empty() {
+            return false;
+        }
+
+        let Some(parent) = item.parent().filter(|p| p.kind() == ItemKind::Map) else {
+            return false;
+        };
+        let has_field = item.item_ref().borrow().data.field.is_some();
+        has_field
+            .then(|| item.field())
+            .flatten()
+            .is_some_and(|key| self.keys.contains(&(parent.id(), key)))
+    }
+
+    // the item or one of its ancestors is hidden
+    fn covers(&self, item: &Type) -> bool {
+        let mut current = Some(item.clone());
+        while let Some(item) = current {
+            if self.hides(&item) {
+                return true;
+            }
+            current = item.parent();
+        }
+
+        false
+    }
+}
+
+// ranges of the items left out of the diffs, by client
+#[derive(Default)]
+struct Redacted {
+    items: HashMap<ClientId, Vec<IdRange>>,
+}
+
+impl Redacted {
+    // the hidden subtrees and the moves of hidden items, over the whole history
+    // so that every diff with the policy leaves out the same items
+    fn new(store: &DocStore, all: &Diff, policy: &RedactPolicy) -> Self {
+        let mut redacted = Self::default();
+        let mut rest = vec![];
+        for (_, items) in all.items.iter() {
+            for (_, item) in items.iter() {
+                match store.find(&item.id) {
+                    Some(found) if policy.covers(&found) => redacted.insert(item),
+                    _ => rest.push(item),
+                }
+            }
+        }
+
+        // an item can not be integrated without its parent and move target
+        let mut changed = true;
+        while changed {
+            changed = false;
+            rest.retain(|item| {
+                let target = match (&item.kind, &item.content) {
+                    (ItemKind::Move, Content::Id(target)) => Some(*target),
+                    _ => None,
+                };
+                let hidden = item
+                    .parent_id
+                    .iter()
+                    .chain(target.iter())
+                    .any(|id| redacted.contains(id));
+                if hidden {
+                    redacted.insert(item);
+                    changed = true;
+                }
+                !hidden
+            });
+        }
+
+        redacted
+    }
+
+    fn insert(&mut self, item: &ItemData) {
+        let range = item.id.range(item.ticks());
+        self.items.entry(range.client).or_default().push(range);
+    }
+
+    fn contains(&self, id: &Id) -> bool {
+        self.items
+            .get(&id.client)
+            .is_some_and(|ranges| ranges.iter().any(|range| range.contains(id)))
+    }
+
+    // an origin pointing at a hidden item is replaced with the closest sibling on the same
+    // side that is not hidden. Only siblings with a lower id are taken, so the replaced
+    // origins never point at each other
+    fn visible_origins(&self, store: &DocStore, item: &ItemData) -> (Option<Id>, Option<Id>) {
+        // the left origin is the last tick of a string run
+        let sibling = |origin: Option<Id>, left: bool| {
+            let origin = origin?;
+            if !self.contains(&origin) {
+                return Some(origin);
+            }
+
+            let next = |item: &Type| if left { item.left() } else { item.right() };
+            let mut current = store.find(&origin).and_then(|hidden| next(&hidden));
+            while let Some(sibling) = current {
+                if !self.contains(&sibling.id()) && sibling.end_id() < item.id {
+                    return Some(if left { sibling.end_id() } else { sibling.id() });
+                }
+                current = next(&sibling);
+            }
+
+            None
+        };
+
+        let left = sibling(item.left_id, true);
+        let right = sibling(item.right_id, false);
+
+        (left, right)
+    }
+}
+
+impl Doc {
+    /// Diff of the changes since the state without the subtrees hidden by the policy.
+    ///
+    /// The other items keep their place, an origin pointing at a hidden item is replaced
+    /// with the closest sibling of the hidden item that is not hidden. Moves of hidden items
+    /// and deletes of hidden items are left out. The state of the diff still covers the
+    /// hidden items, so a receiver never asks for them again; send a full diff when the
+    /// policy grants access later.
+    pub fn diff_redacted(&self, state: impl Into<ClientState>, policy: &RedactPolicy) -> Diff {
+        let mut diff = self.diff(state);
+        if policy.is_empty() {
+            return diff;
+        }
+
+        let redacted = {
+            let all = self.diff(ClientState::default());
+            Redacted::new(&self.store.borrow(), &all, policy)
+        };
+
+        let mut items = ItemDataStore::default();
+        for (_, store) in diff.items.iter() {
+            for (id, item) in store.iter() {
+                if redacted.contains(id) {
+                    continue;
+                }
+                let mut item = item.clone();
+                let origins = redacted.visible_origins(&self.store.borrow(), &item);
+                // the optimized diff finds the parent through the replaced origins
+                if origins != (item.left_id, item.right_id) && item.parent_id.is_none() {
+                    item.parent_id = self.find_by_id(&item.id).and_then(|i| i.parent_id());
+                }
+                (item.left_id, item.right_id) = origins;
+                items.insert(item);
+            }
+        }
+        let mut deletes = DeleteItemStore::default();
+        for (_, store) in diff.deletes.iter() {
+            store
+                .iter()
+                .filter(|(_, delete)| !redacted.contains(&delete.target()))
+                .for_each(|(_, delete)| deletes.insert(delete.clone()));
+        }
+
+        diff.items = items;
+        diff.deletes = deletes;
+        diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_redacted() {
+        let doc = Doc::default();
+        let user = doc.map();
+        doc.set("user", user.clone());
+        user.set("name", doc.atom("alice"));
+        user.set("ssn", doc.atom("123"));
+        let notes = doc.list();
+        doc.set("notes", notes.clone());
+        let secret = doc.map();
+        notes.append(secret.clone());
+        secret.set("body", doc.atom("hidden"));
+        notes.append(doc.atom("after secret"));
+        notes.prepend(doc.atom("public"));
+        doc.commit();
+
+        let policy = RedactPolicy::new()
+            .hide_key(&user.clone().into(), "ssn")
+            .hide(&secret.clone().into());
+
+        let reader = Doc::new(doc.meta.clone());
+        reader.apply(&doc.diff_redacted(ClientState::default(), &policy));
+        let reader_user = reader.get("user").unwrap();
+        assert_eq!(reader_user.get("name").unwrap().to_json(), "alice");
+        assert!(reader_user.get("ssn").is_none());
+
+        // the item inserted after the secret keeps its place
+        let reader_notes = reader.get("notes").unwrap();
+        assert_eq!(reader_notes.size(), 2);
+        assert_eq!(reader_notes.get(0u32).unwrap().to_json(), "public");
+        assert_eq!(reader_notes.get(1u32).unwrap().to_json(), "after secret");
+
+        // later values of a hidden key stay hidden
+        user.set("ssn", doc.atom("456"));
+        user.set("email", doc.atom("a@b.c"));
+        doc.commit();
+        reader.apply(&doc.diff_redacted(reader.state(), &policy));
+        assert!(reader_user.get("ssn").is_none());
+        assert_eq!(reader_user.get("email").unwrap().to_json(), "a@b.c");
+        assert!(reader.check_invariants().is_ok());
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+
+use crate::doc::{Doc, DocId};
+use crate::id::{Id, WithId};
+use crate::types::Type;
+
+/// DocRef points at a document or at an item of a document
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct DocRef {
+    pub doc_id: DocId,
+    /// None for the document itself
+    pub item: Option<Id>,
+}
+
+impl DocRef {
+    pub fn doc(doc_id: DocId) -> Self {
+        Self { doc_id, item: None }
+    }
+
+    pub fn item(doc_id: DocId, id: Id) -> Self {
+        Self {
+            doc_id,
+            item: Some(id),
+        }
+    }
+}
+
+impl Display for DocRef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.item {
+            Some(id) => write!(f, "{}/{}", self.doc_id.to_string(), id),
+            None => write!(f, "{}", self.doc_id.to_string()),
+        }
+    }
+}
+
+/// DeletePolicy decides what happens when a referenced target is deleted
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum DeletePolicy {
+    /// refuse to delete the target
+    #[default]
+    Block,
+    /// drop the references to the target
+    Invalidate,
+    /// keep the references and report the target as orphaned
+    Orphan,
+}
+
+/// RefEvent reports a reference affected by a delete
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RefEvent {
+    Invalidated { from: DocRef, to: DocRef },
+    Orphaned { from: DocRef, to: DocRef },
+}
+
+/// RefRegistry counts the references between the documents of a workspace, so that
+/// deleting a referenced document or item does not leave dangling references behind.
+#[derive(Debug, Clone, Default)]
+pub struct RefRegistry {
+    policy: DeletePolicy,
+    // referrers of every target with the number of references they hold
+    refs: BTreeMap<DocRef, BTreeMap<DocRef, u32>>,
+    // targets deleted while referenced with the orphan policy
+    orphans: BTreeSet<DocRef>,
+}
+
+impl RefRegistry {
+    pub fn new(policy: DeletePolicy) -> Self {
+        Self {
+            policy,
+            ..Self::default()
+        }
+    }
+
+    #[inline]
+    pub fn policy(&self) -> DeletePolicy {
+        self.policy
+    }
+
+    #[inline]
+    pub fn set_policy(&mut self, policy: DeletePolicy) {
+        self.policy = policy;
+    }
+
+    /// Record a reference held by `from` to `to`
+    pub fn add_ref(&mut self, from: DocRef, to: DocRef) {
+        *self.refs.entry(to).or_default().entry(from).or_default() += 1;
+    }
+
+    /// Drop a reference recorded by `add_ref`
+    pub fn remove_ref(&mut self, from: &DocRef, to: &DocRef) {
+        let Some(referrers) = self.refs.get_mut(to) else {
+            return;
+        };
+        if let Some(count) = referrers.get_mut(from) {
+            *count -= 1;
+            if *count == 0 {
+                referrers.remove(from);
+            }
+        }
+        if referrers.is_empty() {
+            self.refs.remove(to);
+            self.orphans.remove(to);
+        }
+    }
+
+    /// Number of references to the target
+    pub fn ref_count(&self, to: &DocRef) -> u32 {
+        self.refs
+            .get(to)
+            .map_or(0, |referrers| referrers.values().sum())
+    }
+
+    pub fn referrers(&self, to: &DocRef) -> Vec<DocRef> {
+        self.refs
+            .get(to)
+            .map(|referrers| referrers.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// The target was deleted while referenced with the orphan policy
+    #[inline]
+    pub fn is_orphaned(&self, to: &DocRef) -> bool {
+        self.orphans.contains(to)
+    }
+
+    /// Apply the delete policy to the deleted targets. References held by the deleted
+    /// targets themselves do not count and are dropped with them. Nothing changes when
+    /// the policy blocks the delete.
+    pub fn on_delete(&mut self, targets: &[DocRef]) -> Result<Vec<RefEvent>, String> {
+        let deleted: BTreeSet<&DocRef> = targets.iter().collect();
+        let is_deleted = |from: &DocRef| {
+            deleted.contains(from) || deleted.contains(&DocRef::doc(from.doc_id.clone()))
+        };
+
+        let mut external = vec![];
+        for to in targets {
+            for from in self.referrers(to) {
+                if !is_deleted(&from) {
+                    external.push((from, to.clone()));
+                }
+            }
+        }
+
+        if self.policy == DeletePolicy::Block {
+            if let Some((from, to)) = external.first() {
+                return Err(format!("{} is referenced by {}", to, from));
+            }
+        }
+
+        // the deleted holders release their references
+        for referrers in self.refs.values_mut() {
+            referrers.retain(|from, _| !is_deleted(from));
+        }
+
+        let events = external
+            .into_iter()
+            .map(|(from, to)| match self.policy {
+                DeletePolicy::Orphan => {
+                    self.orphans.insert(to.clone());
+                    RefEvent::Orphaned { from, to }
+                }
+                _ => RefEvent::Invalidated { from, to },
+            })
+            .collect();
+
+        if self.policy != DeletePolicy::Orphan {
+            for to in targets {
+                self.refs.remove(to);
+            }
+        }
+        self.refs.retain(|_, referrers| !referrers.is_empty());
+        self.orphans.retain(|to| self.refs.contains_key(to));
+
+        Ok(events)
+    }
+
+    /// Apply the delete policy to a whole document and the items referenced in it
+    pub fn on_delete_doc(&mut self, doc_id: &DocId) -> Result<Vec<RefEvent>, String> {
+        let targets: Vec<DocRef> = self
+            .refs
+            .keys()
+            .filter(|to| &to.doc_id == doc_id && to.item.is_some())
+            .cloned()
+            .chain([DocRef::doc(doc_id.clone())])
+            .collect();
+
+        self.on_delete(&targets)
+    }
+}
+
+impl Doc {
+    /// Delete the item after applying the delete policy of the registry to the item and
+    /// its descendants. A blocked delete leaves the document untouched.
+    pub fn delete_referenced(
+        &self,
+        item: &Type,
+        registry: &mut RefRegistry,
+    ) -> Result<Vec<RefEvent>, String> {
+        let mut ids = vec![];
+        subtree_ids(item, &mut ids);
+        let targets: Vec<DocRef> = ids
+            .into_iter()
+            .map(|id| DocRef::item(self.id(), id))
+            .collect();
+
+        let events = registry.on_delete(&targets)?;
+        item.delete();
+
+        Ok(events)
+    }
+}
+
+// ids of the item and its visible descendants
+fn subtree_ids(item: &Type, ids: &mut Vec<Id>) {
+    ids.push(item.id());
+    for child in item.item_ref().borrow().items() {
+        subtree_ids(&child, ids);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delete_referenced_items() {
+        let doc = Doc::default();
+        let list = doc.list();
+        doc.set("list", list.clone());
+        let target = doc.atom("target");
+        list.append(target.clone());
+        doc.commit();
+
+        let other = Doc::default();
+        let holder = other.atom("link");
+        other.set("link", holder.clone());
+        other.commit();
+
+        let from = DocRef::item(other.id(), holder.id());
+        let to = DocRef::item(doc.id(), target.id());
+        let mut registry = RefRegistry::default();
+        registry.add_ref(from.clone(), to.clone());
+        assert_eq!(registry.ref_count(&to), 1);
+
+        // the list holds the referenced atom
+        let list = Type::from(list);
+        assert!(doc.delete_referenced(&list, &mut registry).is_err());
+        assert!(doc.get("list").is_some());
+
+        registry.set_policy(DeletePolicy::Orphan);
+        let events = doc.delete_referenced(&list, &mut registry).unwrap();
+        assert_eq!(
+            events,
+            vec![RefEvent::Orphaned {
+                from: from.clone(),
+                to: to.clone()
+            }]
+        );
+        assert!(registry.is_orphaned(&to));
+
+        // deleting the holding document releases its references
+        registry.set_policy(DeletePolicy::Block);
+        assert_eq!(registry.on_delete_doc(&other.id()), Ok(vec![]));
+        assert_eq!(registry.ref_count(&to), 0);
+        assert!(!registry.is_orphaned(&to));
+    }
+}
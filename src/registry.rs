@@ -0,0 +1,280 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Debug, Formatter};
+use std::rc::Rc;
+
+use crate::bimapid::ClientMapper;
+use crate::doc::{Doc, DocId};
+use crate::id::{Client, ClockTick, Id, WithId};
+use crate::item::{Content, ItemKind};
+use crate::natom::NAtom;
+use crate::refs::DocRef;
+use crate::types::Type;
+
+const LINK_SCHEME: &str = "nitro://";
+
+/// DocLink points at an item of another document. The item is kept with the client
+/// instead of the client id of the linking document, so the link resolves the same
+/// on every replica. A link is stored as an atom of the form
+/// `nitro://<doc id>/<client hex>/<clock>`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct DocLink {
+    pub doc_id: DocId,
+    pub client: Client,
+    pub clock: ClockTick,
+}
+
+impl DocLink {
+    /// Read the link from an atom created by `Doc::link_to`
+    pub fn from_item(item: &Type) -> Option<DocLink> {
+        if item.kind() != ItemKind::Atom {
+            return None;
+        }
+        let Content::String(uri) = item.content() else {
+            return None;
+        };
+
+        let mut parts = uri.strip_prefix(LINK_SCHEME)?.split('/');
+        let doc_id = DocId::from_str(parts.next()?).ok()?;
+        let client = parts.next()?;
+        let clock = parts.next()?.parse().ok()?;
+        if parts.next().is_some() || client.len() % 2 != 0 || client.is_empty() {
+            return None;
+        }
+        let bytes = (0..client.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(client.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+
+        Some(DocLink {
+            doc_id,
+            client: Client::from_bytes(&bytes),
+            clock,
+        })
+    }
+
+    /// The linked item as a reference of the linked document
+    pub fn doc_ref(&self, doc: &Doc) -> Option<DocRef> {
+        let store = doc.store.borrow();
+        let client = store.state.clients.get_client_id(&self.client)?;
+
+        Some(DocRef::item(
+            self.doc_id.clone(),
+            Id::new(*client, self.clock),
+        ))
+    }
+
+    fn to_uri(&self) -> String {
+        let client: String = self
+            .client
+            .as_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        format!(
+            "{}{}/{}/{}",
+            LINK_SCHEME,
+            self.doc_id.to_string(),
+            client,
+            self.clock
+        )
+    }
+}
+
+impl Doc {
+    /// Create an atom linking to the item of the other document, the atom is set in this
+    /// document like any other value and resolved through a `DocRegistry`
+    pub fn link_to(&self, doc: &Doc, item: &Type) -> Result<NAtom, String> {
+        let id = item.id();
+        if !item.item_ref().store.ptr_eq(&Rc::downgrade(&doc.store)) {
+            return Err(format!("{} is not an item of {}", id, doc.id().to_string()));
+        }
+
+        let client = doc
+            .store
+            .borrow()
+            .state
+            .clients
+            .get_client(&id.client)
+            .cloned()
+            .ok_or_else(|| format!("unknown client of {}", id))?;
+        let link = DocLink {
+            doc_id: doc.id(),
+            client,
+            clock: id.clock,
+        };
+
+        Ok(self.atom(link.to_uri()))
+    }
+}
+
+/// RegistryEvent reports the documents coming and going, a `Requested` document was
+/// needed to resolve a link and can be fetched and inserted by the application
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RegistryEvent {
+    Requested(DocId),
+    Loaded(DocId),
+    Unloaded(DocId),
+}
+
+/// DocRegistry holds the open documents of a workspace and resolves the links between them
+#[derive(Default)]
+pub struct DocRegistry {
+    docs: BTreeMap<DocId, Doc>,
+    // linked documents not loaded yet, every document is requested once
+    requested: BTreeSet<DocId>,
+    listeners: Vec<(u32, Box<dyn Fn(&RegistryEvent)>)>,
+    next_token: u32,
+}
+
+impl DocRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the document, returns the document it replaced
+    pub fn insert(&mut self, doc: Doc) -> Option<Doc> {
+        let id = doc.id();
+        self.requested.remove(&id);
+        let prev = self.docs.insert(id.clone(), doc);
+        self.emit(RegistryEvent::Loaded(id));
+
+        prev
+    }
+
+    pub fn remove(&mut self, doc_id: &DocId) -> Option<Doc> {
+        let doc = self.docs.remove(doc_id)?;
+        self.emit(RegistryEvent::Unloaded(doc_id.clone()));
+
+        Some(doc)
+    }
+
+    #[inline]
+    pub fn get(&self, doc_id: &DocId) -> Option<&Doc> {
+        self.docs.get(doc_id)
+    }
+
+    #[inline]
+    pub fn contains(&self, doc_id: &DocId) -> bool {
+        self.docs.contains_key(doc_id)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    pub fn doc_ids(&self) -> Vec<DocId> {
+        self.docs.keys().cloned().collect()
+    }
+
+    /// Linked documents requested but not inserted yet
+    pub fn requested(&self) -> Vec<DocId> {
+        self.requested.iter().cloned().collect()
+    }
+
+    /// The visible linked item, a document that is not loaded is requested and the link
+    /// resolves once the document is inserted
+    pub fn resolve(&mut self, link: &DocLink) -> Option<Type> {
+        let Some(doc) = self.docs.get(&link.doc_id) else {
+            if self.requested.insert(link.doc_id.clone()) {
+                self.emit(RegistryEvent::Requested(link.doc_id.clone()));
+            }
+            return None;
+        };
+
+        let id = link.doc_ref(doc)?.item?;
+        doc.find_by_id(&id).filter(|item| item.is_visible())
+    }
+
+    /// Listen to the registry events, returns the token to unsubscribe with
+    pub fn subscribe(&mut self, listener: impl Fn(&RegistryEvent) + 'static) -> u32 {
+        self.next_token += 1;
+        self.listeners.push((self.next_token, Box::new(listener)));
+
+        self.next_token
+    }
+
+    pub fn unsubscribe(&mut self, token: u32) -> bool {
+        let len = self.listeners.len();
+        self.listeners.retain(|(t, _)| *t != token);
+
+        self.listeners.len() != len
+    }
+
+    fn emit(&self, event: RegistryEvent) {
+        for (_, listener) in self.listeners.iter() {
+            listener(&event);
+        }
+    }
+}
+
+impl Debug for DocRegistry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DocRegistry")
+            .field("docs", &self.docs.keys().collect::<Vec<_>>())
+            .field("requested", &self.requested)
+            .field("listeners", &self.listeners.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use crate::doc::CloneDeep;
+
+    use super::*;
+
+    #[test]
+    fn test_doc_registry_links() {
+        let pages = Doc::default();
+        let page = pages.map();
+        pages.set("intro", page.clone());
+        page.set("title", pages.atom("hello"));
+        pages.commit();
+
+        let index = Doc::default();
+        let link = index.link_to(&pages, &page.clone().into()).unwrap();
+        index.set("first", link);
+        index.commit();
+        assert!(index.link_to(&index, &page.clone().into()).is_err());
+
+        let events = Rc::new(RefCell::new(vec![]));
+        let mut registry = DocRegistry::new();
+        let seen = events.clone();
+        registry.subscribe(move |e| seen.borrow_mut().push(e.clone()));
+
+        // the link survives the sync of the linking document
+        registry.insert(index.clone_deep());
+        let copy = registry.get(&index.id()).unwrap();
+        let link = DocLink::from_item(&copy.get("first").unwrap()).unwrap();
+        assert!(registry.resolve(&link).is_none());
+        assert!(registry.resolve(&link).is_none());
+        assert_eq!(registry.requested(), vec![pages.id()]);
+
+        // the replica of the linked document resolves the same item
+        registry.insert(pages.clone_deep());
+        let item = registry.resolve(&link).unwrap();
+        assert_eq!(item.get("title").unwrap().to_json(), "hello");
+        assert!(registry.requested().is_empty());
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                RegistryEvent::Loaded(index.id()),
+                RegistryEvent::Requested(pages.id()),
+                RegistryEvent::Loaded(pages.id()),
+            ]
+        );
+
+        registry.remove(&pages.id());
+        assert!(registry.resolve(&link).is_none());
+        assert_eq!(registry.len(), 1);
+    }
+}
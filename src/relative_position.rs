@@ -0,0 +1,126 @@
+use std::cmp::Ordering;
+
+use serde::Serialize;
+
+use crate::annotation::char_id;
+use crate::doc::Doc;
+use crate::id::{Id, WithId};
+use crate::item::{ItemIterator, Linked};
+use crate::ntext::NText;
+use crate::types::Type;
+
+/// RelativePosition is a caret position in a text that survives concurrent edits.
+///
+/// The position is kept as the id of the character right of it, that is the id of its
+/// string item plus the offset of the character in the item. Inserts before the position
+/// shift it, a deleted character keeps the position where the character was.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct RelativePosition {
+    /// id of the text
+    pub text: Id,
+    /// id of the character after the position, None at the end of the text
+    pub item: Option<Id>,
+}
+
+impl RelativePosition {
+    /// Position before the character at the index, the index is the same as in `NText::insert`.
+    /// The index at the size of the text sticks to the end of the text.
+    pub fn from_index(text: &NText, index: u32) -> Option<Self> {
+        let item = match index.cmp(&text.size()) {
+            Ordering::Less => Some(char_id(text, index)?),
+            Ordering::Equal => None,
+            Ordering::Greater => return None,
+        };
+
+        Some(Self {
+            text: text.id(),
+            item,
+        })
+    }
+
+    /// Index of the position in the text of the document, None when the text or the
+    /// character is not known to the document or the text was deleted
+    pub fn resolve(&self, doc: &Doc) -> Option<u32> {
+        let Some(Type::Text(text)) = doc.find_by_id(&self.text) else {
+            return None;
+        };
+        if Type::from(text.clone()).is_deleted() {
+            return None;
+        }
+
+        text.thaw();
+        let Some(id) = self.item else {
+            return Some(text.size());
+        };
+
+        let mut offset = 0;
+        for item in text.item_iter() {
+            let visible = item.is_visible();
+            let size = item.size();
+            // the ids of the item end right before the next item starts
+            if size > 0 && item.id().range(size).contains(&id) {
+                return match visible {
+                    true => Some(offset + id.clock - item.id().clock),
+                    false => Some(offset),
+                };
+            }
+            if visible {
+                offset += item.size();
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::state::ClientState;
+
+    use super::*;
+
+    #[test]
+    fn test_relative_position() {
+        let doc = Doc::default();
+        let list = doc.list();
+        doc.set("list", list.clone());
+        list.append(doc.atom("first"));
+        let text = doc.text();
+        list.append(text.clone());
+        text.append(doc.string("hello world"));
+        doc.commit();
+
+        let caret = RelativePosition::from_index(&text, 6).unwrap();
+        let end = RelativePosition::from_index(&text, 11).unwrap();
+        assert!(RelativePosition::from_index(&text, 12).is_none());
+
+        // a remote insert before the caret shifts it
+        let remote = Doc::new(doc.meta.clone());
+        remote.update_client();
+        remote.apply(&doc.diff(ClientState::default()));
+        let Some(Type::Text(remote_text)) = remote.find_by_id(&text.id()) else {
+            panic!("text not synced");
+        };
+        remote_text.insert(0, remote.string(">> "));
+        remote.commit();
+        doc.apply(&remote.diff(doc.state()));
+        assert_eq!(caret.resolve(&doc), Some(9));
+        assert_eq!(caret.resolve(&remote), Some(9));
+        assert_eq!(end.resolve(&doc), Some(14));
+
+        // moving the text keeps the positions
+        Type::from(text.clone()).move_to(list.clone(), 0);
+        assert_eq!(caret.resolve(&doc), Some(9));
+
+        // a deleted character keeps the place of the caret
+        text.insert(9, doc.string("big "));
+        doc.commit();
+        assert_eq!(caret.resolve(&doc), Some(13));
+        let world = RelativePosition::from_index(&text, 14).unwrap();
+        let hello = RelativePosition::from_index(&text, 3).unwrap();
+        text.clear();
+        assert_eq!(hello.resolve(&doc), Some(0));
+        assert_eq!(world.resolve(&doc), Some(0));
+        assert_eq!(end.resolve(&doc), Some(0));
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::checkpoint::Checkpoint;
+use crate::doc::Doc;
+use crate::gc::GcStats;
+use crate::id::{Id, IdRange};
+use crate::state::ClientState;
+
+/// RetentionPolicy keeps the full history of a document for a period, the history older
+/// than the period is compacted by `Doc::enforce_retention`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct RetentionPolicy {
+    keep_for: Duration,
+}
+
+impl RetentionPolicy {
+    pub fn new(keep_for: Duration) -> Self {
+        Self { keep_for }
+    }
+
+    pub fn keep_days(days: u64) -> Self {
+        Self::new(Duration::from_secs(days * 24 * 60 * 60))
+    }
+
+    #[inline]
+    pub fn keep_for(&self) -> Duration {
+        self.keep_for
+    }
+}
+
+// versions the document reached with the time in seconds they were reached at, oldest first
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub(crate) struct HistoryTimeline {
+    entries: Vec<(u64, ClientState)>,
+}
+
+impl HistoryTimeline {
+    pub(crate) fn record(&mut self, at: u64, version: &ClientState) {
+        let last = self.entries.last();
+        if last.is_some_and(|(_, last)| last == version) {
+            return;
+        }
+
+        // a clock going backwards keeps the entries in order
+        let at = last.map_or(at, |(last, _)| at.max(*last));
+        self.entries.push((at, version.clone()));
+    }
+
+    // latest version reached at or before the time
+    pub(crate) fn version_at(&self, at: u64) -> Option<&ClientState> {
+        let index = self.entries.partition_point(|(time, _)| *time <= at);
+        index.checked_sub(1).map(|index| &self.entries[index].1)
+    }
+
+    // drop the entries superseded by the version reached at the time
+    pub(crate) fn prune(&mut self, at: u64) {
+        let index = self.entries.partition_point(|(time, _)| *time <= at);
+        self.entries.drain(..index.saturating_sub(1));
+    }
+
+    // time of the first recorded version with the id
+    pub(crate) fn seen_at(&self, id: &Id) -> Option<u64> {
+        self.entries
+            .iter()
+            .find(|(_, version)| version.get(&id.client).is_some_and(|c| *c >= id.clock))
+            .map(|(at, _)| *at)
+    }
+
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// LegalHolds are the named versions exempt from compaction, the text visible at a held
+/// version is kept until the hold is released
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub(crate) struct LegalHolds {
+    holds: BTreeMap<String, ClientState>,
+}
+
+impl LegalHolds {
+    // the range was inserted and not yet deleted by the delete at some held version
+    pub(crate) fn protects(&self, range: &IdRange, delete: &Id) -> bool {
+        let seen = |version: &ClientState, id: &Id| {
+            version
+                .get(&id.client)
+                .is_some_and(|clock| id.clock <= *clock)
+        };
+
+        self.holds
+            .values()
+            .any(|version| seen(version, &range.start_id()) && !seen(version, delete))
+    }
+}
+
+impl Doc {
+    /// Exempt the document at the checkpoint from compaction until the hold is released.
+    /// Holds are kept by the replica, they are not synced or persisted with the changes.
+    pub fn place_legal_hold(
+        &self,
+        name: impl Into<String>,
+        checkpoint: &Checkpoint,
+    ) -> Result<(), String> {
+        if checkpoint.id() != &self.id() {
+            return Err(format!(
+                "checkpoint of document {} can not be held by {}",
+                checkpoint.id().to_string(),
+                self.id().to_string()
+            ));
+        }
+
+        let mut store = self.store_mut("place_legal_hold");
+        let version = checkpoint.version().as_per(&store.state);
+        store.holds.holds.insert(name.into(), version);
+
+        Ok(())
+    }
+
+    /// Release the hold, the held history is compacted by the next pass
+    pub fn release_legal_hold(&self, name: &str) -> bool {
+        self.store_mut("release_legal_hold").holds.holds.remove(name).is_some()
+    }
+
+    /// Names of the legal holds in name order
+    pub fn legal_holds(&self) -> Vec<String> {
+        self.store.borrow().holds.holds.keys().cloned().collect()
+    }
+
+    /// Compact the history older than the policy keeps, the versions under a legal hold
+    /// stay readable. See `Doc::gc`.
+    pub fn enforce_retention(&self, policy: &RetentionPolicy) -> GcStats {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        self.enforce_retention_at(policy, now)
+    }
+
+    pub(crate) fn enforce_retention_at(&self, policy: &RetentionPolicy, now: u64) -> GcStats {
+        let cutoff = now.saturating_sub(policy.keep_for.as_secs());
+        let version = {
+            let mut store = self.store_mut("enforce_retention_at");
+            let Some(version) = store.history.version_at(cutoff).cloned() else {
+                return GcStats::default();
+            };
+            store.history.prune(cutoff);
+            version
+        };
+
+        self.gc(&version)
+    }
+
+    // remember the time the document reached its current version
+    pub(crate) fn record_history(&self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut store = self.store_mut("record_history");
+        let version = store.state.clone();
+        store.history.record(now, &version);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    #[test]
+    fn test_retention_with_legal_hold() {
+        let doc = Doc::default();
+        let text = doc.text();
+        doc.set("text", text.clone());
+        let strings: Vec<_> = ["ab", "cd", "ef"]
+            .iter()
+            .map(|content| doc.string(*content))
+            .collect();
+        strings
+            .iter()
+            .for_each(|string| text.append(string.clone()));
+        doc.commit();
+
+        let held = doc.checkpoint();
+        doc.place_legal_hold("case-1", &held).unwrap();
+        assert_eq!(doc.legal_holds(), vec!["case-1".to_string()]);
+
+        // inserted and deleted after the held version
+        let late = doc.string("gh");
+        text.append(late.clone());
+        doc.commit();
+        strings[..2].iter().for_each(|string| string.delete());
+        late.delete();
+        doc.commit();
+        assert_eq!(text.text_content(), "ef");
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let policy = RetentionPolicy::keep_days(90);
+
+        // the history is within the retention period
+        assert_eq!(doc.enforce_retention_at(&policy, now), GcStats::default());
+
+        // the text visible at the held version is kept
+        let stats = doc.enforce_retention_at(&policy, now + 91 * DAY);
+        assert_eq!(stats.collected, 1);
+        assert_eq!(stats.freed, 2);
+        assert_eq!(doc.store.borrow().history.len(), 1);
+
+        assert!(doc.release_legal_hold("case-1"));
+        let stats = doc.enforce_retention_at(&policy, now + 92 * DAY);
+        assert_eq!(stats.collected, 2);
+        assert_eq!(stats.freed, 4);
+        assert_eq!(text.text_content(), "ef");
+        assert!(doc.check_invariants().is_ok());
+    }
+}
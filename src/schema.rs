@@ -0,0 +1,457 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use hashbrown::HashMap;
+
+use crate::diff::Diff;
+use crate::doc::Doc;
+use crate::id::{Id, IdRange, WithId};
+use crate::item::ItemKind;
+use crate::observe::{glob_match, split_path};
+use crate::types::Type;
+
+/// ContainerSchema constrains the children of the containers at a path
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ContainerSchema {
+    // allowed child kinds, empty allows any kind
+    kinds: BTreeSet<ItemKind>,
+    max_len: Option<usize>,
+    // allowed map keys, empty allows any key
+    keys: BTreeSet<String>,
+}
+
+impl ContainerSchema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_kinds(mut self, kinds: &[ItemKind]) -> Self {
+        self.kinds.extend(kinds.iter().copied());
+        self
+    }
+
+    /// Most children of a list or keys of a map, the length of a text is in bytes
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = Some(max_len);
+        self
+    }
+
+    /// Allow only the keys in a map
+    pub fn with_keys(mut self, keys: &[&str]) -> Self {
+        self.keys.extend(keys.iter().map(|key| key.to_string()));
+        self
+    }
+
+    // check a child of the container, `len` is the container length with the child
+    fn validate(&self, kind: ItemKind, key: Option<&str>, len: usize) -> Result<(), String> {
+        if !self.kinds.is_empty() && !self.kinds.contains(&kind) {
+            return Err(format!("{:?} is not allowed", kind));
+        }
+        if let Some(key) = key.filter(|key| !self.keys.is_empty() && !self.keys.contains(*key)) {
+            return Err(format!("key {} is not allowed", key));
+        }
+        match self.max_len {
+            Some(max_len) if len > max_len => {
+                Err(format!("length {} is over the limit of {}", len, max_len))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// DocSchema declares the structure a document must keep.
+///
+/// Local changes are validated at commit time, a violating change is rolled back.
+/// Remote diffs are validated against the schema before integration,
+/// a diff with a violating item is quarantined instead of applied.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct DocSchema {
+    // expected kind of the root fields
+    fields: BTreeMap<String, ItemKind>,
+    // allowed child kinds of a container, containers without an entry accept any child
+    children: BTreeMap<ItemKind, BTreeSet<ItemKind>>,
+    // reject root fields that are not declared
+    strict: bool,
+    // constraints of the containers at a path pattern
+    containers: Vec<(Vec<String>, ContainerSchema)>,
+}
+
+impl DocSchema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declare the kind of a root field
+    pub fn with_field(mut self, name: impl Into<String>, kind: ItemKind) -> Self {
+        self.fields.insert(name.into(), kind);
+        self
+    }
+
+    /// Restrict the child kinds of a container kind
+    pub fn with_children(mut self, parent: ItemKind, kinds: &[ItemKind]) -> Self {
+        self.children
+            .entry(parent)
+            .or_default()
+            .extend(kinds.iter().copied());
+        self
+    }
+
+    /// Reject root fields that are not declared in the schema
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    /// Constrain the containers at the path, the path is a pattern like `Doc::observe_path`
+    /// takes, e.g. `todos/*/tags`. An empty path is the root map.
+    pub fn with_container(mut self, path: &str, container: ContainerSchema) -> Self {
+        self.containers.push((split_path(path), container));
+        self
+    }
+
+    // check a child of the container at the path against the container constraints
+    fn validate_container(
+        &self,
+        path: &[&str],
+        kind: ItemKind,
+        key: Option<&str>,
+        len: usize,
+    ) -> Result<(), String> {
+        let containers = self.containers.iter();
+        for (_, container) in containers.filter(|(pattern, _)| glob_match(pattern, path)) {
+            container
+                .validate(kind, key, len)
+                .map_err(|err| format!("/{}: {}", path.join("/"), err))?;
+        }
+
+        Ok(())
+    }
+
+    /// Check an item against the schema, `field` is set for root fields only
+    pub(crate) fn validate(
+        &self,
+        kind: ItemKind,
+        parent: ItemKind,
+        field: Option<&str>,
+    ) -> Result<(), String> {
+        if let Some(field) = field {
+            match self.fields.get(field) {
+                Some(expected) if *expected != kind => {
+                    return Err(format!(
+                        "root field {} must be {:?}, found {:?}",
+                        field, expected, kind
+                    ));
+                }
+                None if self.strict => {
+                    return Err(format!("root field {} is not declared", field));
+                }
+                _ => return Ok(()),
+            }
+        }
+
+        // strings belong to texts only, the crdt can not handle them anywhere else
+        if kind.is_string() != (parent.is_text() || parent.is_plaintext()) {
+            return Err(format!("{:?} can not be a child of {:?}", kind, parent));
+        }
+
+        match self.children.get(&parent) {
+            Some(allowed) if !allowed.contains(&kind) => {
+                Err(format!("{:?} can not be a child of {:?}", kind, parent))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// QuarantinedDiff is a remote diff that was not applied because it violates the document schema
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct QuarantinedDiff {
+    pub diff: Diff,
+    pub errors: Vec<String>,
+}
+
+impl Doc {
+    /// Set the schema local changes and remote diffs are validated against, None disables
+    /// the validation
+    pub fn set_schema(&self, schema: Option<DocSchema>) {
+        self.store_mut("set_schema").schema = schema;
+    }
+
+    pub fn schema(&self) -> Option<DocSchema> {
+        self.store.borrow().schema.clone()
+    }
+
+    /// Diffs rejected by the schema validation
+    pub fn quarantine(&self) -> Vec<QuarantinedDiff> {
+        self.store.borrow().quarantine.clone()
+    }
+
+    /// Remove and return the quarantined diffs
+    pub fn take_quarantine(&self) -> Vec<QuarantinedDiff> {
+        std::mem::take(&mut self.store_mut("take_quarantine").quarantine)
+    }
+
+    // check the child of an attached container against the container constraints
+    fn validate_child(
+        &self,
+        schema: &DocSchema,
+        parent: &Type,
+        child: ItemKind,
+        key: Option<&str>,
+        len: usize,
+    ) -> Result<(), String> {
+        let root = self.root.id();
+        let path = self.path_of(parent, &root);
+        if path.is_empty() && parent.id() != root {
+            return Ok(());
+        }
+
+        let path: Vec<&str> = path.iter().map(|s| s.as_str()).collect();
+        schema.validate_container(&path, child, key, len)
+    }
+
+    // validate the items of the pending change against the schema
+    pub(crate) fn validate_pending(&self) -> Result<(), Vec<String>> {
+        // a re-entered store is reported by the commit
+        let Ok(store) = self.store.try_borrow() else {
+            return Ok(());
+        };
+        let Some(schema) = store.schema.clone() else {
+            return Ok(());
+        };
+        if store.clock == store.commited_clock {
+            return Ok(());
+        }
+        let range = IdRange::new(store.client, store.commited_clock, store.clock);
+        let items = store.items.get_by_range(range);
+        drop(store);
+
+        let root = self.root.id();
+        let mut errors = vec![];
+        for item in items {
+            let kind = item.kind();
+            if kind.is_move() || kind.is_mark() || kind.is_proxy() || !item.is_visible() {
+                continue;
+            }
+            let Some(parent) = item.parent() else {
+                continue;
+            };
+
+            let key = match parent.kind() {
+                ItemKind::Map => item.field(),
+                _ => None,
+            };
+            let field = key.as_deref().filter(|_| parent.id() == root);
+            let len = parent.size() as usize;
+            let valid = schema
+                .validate(kind, parent.kind(), field)
+                .and_then(|_| self.validate_child(&schema, &parent, kind, key.as_deref(), len));
+            if let Err(err) = valid {
+                errors.push(format!("{:?}: {}", item.id(), err));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    // validate the new items of an adjusted diff against the schema
+    pub(crate) fn validate_diff(&self, diff: &Diff) -> Result<(), Vec<String>> {
+        let Some(schema) = self.store.borrow().schema.clone() else {
+            return Ok(());
+        };
+
+        // kinds of the items in the diff, parents can arrive in the same diff
+        let kinds: HashMap<Id, ItemKind> = diff
+            .items
+            .iter()
+            .flat_map(|(_, store)| store.iter().map(|(id, data)| (*id, data.kind)))
+            .collect();
+
+        let root = self.root.id();
+        let mut errors = vec![];
+        let parent_of = |data| diff.parent_id(&self.store.borrow(), data);
+
+        // children added to the existing containers, a map counts its new keys only
+        let mut added: HashMap<Id, usize> = HashMap::new();
+        for (_, store) in diff.items.iter() {
+            for (id, data) in store.iter() {
+                let structural = !data.kind.is_move() && !data.kind.is_mark();
+                let Some(parent) = parent_of(data).and_then(|id| self.find_by_id(&id)) else {
+                    continue;
+                };
+                if !structural || data.kind.is_proxy() || self.find_by_id(id).is_some() {
+                    continue;
+                }
+                let key = data.field.and_then(|f| diff.fields.get_field(&f).cloned());
+                if let (Type::Map(map), Some(key)) = (&parent, key) {
+                    if map.keys().contains(&key) {
+                        continue;
+                    }
+                }
+                *added.entry(parent.id()).or_default() += 1;
+            }
+        }
+
+        for (_, store) in diff.items.iter() {
+            for (id, data) in store.iter() {
+                // moves, marks and proxies do not change the structure
+                if data.kind.is_move() || data.kind.is_mark() || data.kind.is_proxy() {
+                    continue;
+                }
+
+                if self.find_by_id(id).is_some() {
+                    continue;
+                }
+
+                let Some(parent_id) = parent_of(data) else {
+                    continue;
+                };
+
+                // items with a missing parent wait in the pending store
+                let parent = kinds
+                    .get(&parent_id)
+                    .copied()
+                    .or_else(|| self.find_by_id(&parent_id).map(|p| p.kind()));
+                let Some(parent) = parent else {
+                    continue;
+                };
+
+                let field = (parent_id == root)
+                    .then(|| data.field.and_then(|f| diff.fields.get_field(&f).cloned()))
+                    .flatten();
+
+                if let Err(err) = schema.validate(data.kind, parent, field.as_deref()) {
+                    errors.push(format!("{:?}: {}", id, err));
+                    continue;
+                }
+
+                // containers arriving with the diff have no path yet
+                let Some(container) = self.find_by_id(&parent_id) else {
+                    continue;
+                };
+                let key = match parent {
+                    ItemKind::Map => data.field.and_then(|f| diff.fields.get_field(&f).cloned()),
+                    _ => None,
+                };
+                let len = container.size() as usize + added.get(&parent_id).copied().unwrap_or(0);
+                if let Err(err) =
+                    self.validate_child(&schema, &container, data.kind, key.as_deref(), len)
+                {
+                    errors.push(format!("{:?}: {}", id, err));
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doc::CloneDeep;
+    use crate::state::ClientState;
+
+    use super::*;
+
+    #[test]
+    fn test_schema_quarantines_invalid_diff() {
+        let d1 = Doc::default();
+        d1.commit();
+
+        let d2 = d1.clone_deep();
+        d2.update_client();
+
+        d1.set_schema(Some(
+            DocSchema::new()
+                .with_field("title", ItemKind::Atom)
+                .with_field("items", ItemKind::List)
+                .strict(),
+        ));
+
+        d2.set("title", d2.atom("hello"));
+        d2.commit();
+        d1.apply(&d2.diff(ClientState::default()));
+        assert!(d1.get("title").is_some());
+        assert!(d1.quarantine().is_empty());
+
+        d2.set("items", d2.map());
+        d2.commit();
+        d1.apply(&d2.diff(ClientState::default()));
+        assert!(d1.get("items").is_none());
+
+        let quarantine = d1.take_quarantine();
+        assert_eq!(quarantine.len(), 1);
+        assert_eq!(quarantine[0].errors.len(), 1);
+        assert!(d1.quarantine().is_empty());
+    }
+
+    #[test]
+    fn test_schema_container_rules() {
+        let d1 = Doc::default();
+        let todos = d1.list();
+        d1.set("todos", todos.clone());
+        let meta = d1.map();
+        d1.set("meta", meta.clone());
+        d1.commit();
+
+        let d2 = d1.clone_deep();
+        d2.update_client();
+
+        let schema = DocSchema::new()
+            .with_container(
+                "todos",
+                ContainerSchema::new()
+                    .with_kinds(&[ItemKind::Atom])
+                    .with_max_len(2),
+            )
+            .with_container("meta", ContainerSchema::new().with_keys(&["title"]));
+        d1.set_schema(Some(schema.clone()));
+        d2.set_schema(Some(schema));
+
+        todos.append(d1.atom("a"));
+        meta.set("title", d1.atom("notes"));
+        assert!(d1.try_commit().is_ok());
+
+        // a violating change is rolled back as a whole
+        todos.append(d1.map());
+        let errors = d1.try_commit().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(todos.size(), 1);
+
+        todos.append(d1.atom("b"));
+        todos.append(d1.atom("c"));
+        assert!(d1.try_commit().is_err());
+        meta.set("author", d1.atom("me"));
+        d1.commit();
+        assert!(meta.get("author").is_none());
+        assert_eq!(
+            d1.root.to_json(),
+            serde_json::json!({"todos": ["a"], "meta": {"title": "notes"}})
+        );
+
+        // transactions and tagged commits report the violations
+        assert!(d1.transact(|tx| todos.append(tx.map())).is_err());
+        todos.append(d1.map());
+        assert_eq!(d1.commit_with_origin("import").unwrap_err().len(), 1);
+        assert_eq!(todos.size(), 1);
+
+        // remote items over the limit of an existing container are quarantined
+        let Some(Type::List(remote)) = d2.get("todos") else {
+            panic!("todos not synced");
+        };
+        d2.set_schema(None);
+        remote.append(d2.atom("x"));
+        remote.append(d2.atom("y"));
+        d2.commit();
+        d1.apply(&d2.diff(d1.version()));
+        assert_eq!(todos.size(), 1);
+        assert_eq!(d1.take_quarantine().len(), 1);
+    }
+}
@@ -0,0 +1,275 @@
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::doc::DocId;
+use crate::id::WithTarget;
+use crate::item::Content;
+use crate::state::ClientFrontier;
+use crate::types::Type;
+use crate::Doc;
+
+/// DocSnapshot is an immutable copy of the visible document tree.
+///
+/// Unlike the document it holds no reference to the store, every node lives in a flat arena
+/// so the snapshot is `Send + Sync` and can be moved to worker threads for export or indexing
+/// while the document keeps changing on the main thread.
+#[derive(Debug, Clone)]
+pub struct DocSnapshot {
+    inner: Arc<SnapshotArena>,
+}
+
+#[derive(Debug)]
+struct SnapshotArena {
+    id: DocId,
+    version: ClientFrontier,
+    // the root map is always the first node
+    nodes: Vec<SnapshotNode>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum SnapshotNode {
+    Map(Vec<(String, usize)>),
+    List(Vec<usize>),
+    Text(String),
+    Binary(Vec<u8>),
+    Value(Value),
+}
+
+impl DocSnapshot {
+    pub(crate) fn new(doc: &Doc) -> Self {
+        let mut nodes = vec![];
+        push_type(&mut nodes, &Type::from(doc.root.clone()));
+
+        Self {
+            inner: Arc::new(SnapshotArena {
+                id: doc.id(),
+                version: doc.version().into(),
+                nodes,
+            }),
+        }
+    }
+
+    pub(crate) fn from_nodes(id: DocId, version: ClientFrontier, nodes: Vec<SnapshotNode>) -> Self {
+        Self {
+            inner: Arc::new(SnapshotArena { id, version, nodes }),
+        }
+    }
+
+    /// Id of the document the snapshot was taken from
+    #[inline]
+    pub fn id(&self) -> &DocId {
+        &self.inner.id
+    }
+
+    /// Document version at the time of the snapshot
+    #[inline]
+    pub fn version(&self) -> &ClientFrontier {
+        &self.inner.version
+    }
+
+    /// Root map of the snapshot
+    #[inline]
+    pub fn root(&self) -> SnapshotRef<'_> {
+        SnapshotRef {
+            arena: &self.inner,
+            index: 0,
+        }
+    }
+
+    /// Get a value from the root map
+    pub fn get(&self, key: &str) -> Option<SnapshotRef<'_>> {
+        self.root().get(key)
+    }
+
+    /// Number of nodes in the snapshot
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.inner.nodes.len()
+    }
+
+    pub fn to_json(&self) -> Value {
+        self.root().to_json()
+    }
+}
+
+/// SnapshotRef points to a single node of a snapshot
+#[derive(Debug, Clone, Copy)]
+pub struct SnapshotRef<'a> {
+    arena: &'a SnapshotArena,
+    index: usize,
+}
+
+impl<'a> SnapshotRef<'a> {
+    #[inline]
+    fn node(&self) -> &'a SnapshotNode {
+        &self.arena.nodes[self.index]
+    }
+
+    fn at(&self, index: usize) -> SnapshotRef<'a> {
+        SnapshotRef {
+            arena: self.arena,
+            index,
+        }
+    }
+
+    /// Get a value by key if the node is a map
+    pub fn get(&self, key: &str) -> Option<SnapshotRef<'a>> {
+        match self.node() {
+            SnapshotNode::Map(entries) => entries
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, index)| self.at(*index)),
+            _ => None,
+        }
+    }
+
+    /// Get a value by position if the node is a list
+    pub fn at_index(&self, index: usize) -> Option<SnapshotRef<'a>> {
+        match self.node() {
+            SnapshotNode::List(items) => items.get(index).map(|index| self.at(*index)),
+            _ => None,
+        }
+    }
+
+    /// Keys of a map node, empty for other nodes
+    pub fn keys(&self) -> Vec<&'a str> {
+        match self.node() {
+            SnapshotNode::Map(entries) => entries.iter().map(|(k, _)| k.as_str()).collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Children of a map or list node
+    pub fn children(&self) -> Vec<SnapshotRef<'a>> {
+        match self.node() {
+            SnapshotNode::Map(entries) => entries.iter().map(|(_, i)| self.at(*i)).collect(),
+            SnapshotNode::List(items) => items.iter().map(|i| self.at(*i)).collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Text content of a text or string node
+    pub fn text(&self) -> Option<&'a str> {
+        match self.node() {
+            SnapshotNode::Text(text) => Some(text.as_str()),
+            _ => None,
+        }
+    }
+
+    /// Binary content of a binary atom
+    pub fn binary(&self) -> Option<&'a [u8]> {
+        match self.node() {
+            SnapshotNode::Binary(bytes) => Some(bytes.as_slice()),
+            _ => None,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        match self.node() {
+            SnapshotNode::Map(entries) => {
+                let mut map = serde_json::Map::new();
+                for (key, index) in entries {
+                    map.insert(key.clone(), self.at(*index).to_json());
+                }
+                Value::Object(map)
+            }
+            SnapshotNode::List(items) => {
+                Value::Array(items.iter().map(|i| self.at(*i).to_json()).collect())
+            }
+            SnapshotNode::Text(text) => Value::String(text.clone()),
+            SnapshotNode::Binary(bytes) => Content::Binary(bytes.clone()).to_json(),
+            SnapshotNode::Value(value) => value.clone(),
+        }
+    }
+}
+
+// push the type and its visible children into the arena, returns the index of the node
+fn push_type(nodes: &mut Vec<SnapshotNode>, item: &Type) -> usize {
+    let index = nodes.len();
+    // reserve the slot so that parents always come before their children
+    nodes.push(SnapshotNode::Value(Value::Null));
+
+    let node = match item {
+        Type::Map(map) => {
+            let mut entries = vec![];
+            for key in map.keys() {
+                if let Some(value) = map.get(key.clone()) {
+                    entries.push((key, push_type(nodes, &value)));
+                }
+            }
+            SnapshotNode::Map(entries)
+        }
+        Type::List(list) => {
+            let items = list.borrow().as_list();
+            SnapshotNode::List(items.iter().map(|item| push_type(nodes, item)).collect())
+        }
+        Type::Text(text) => SnapshotNode::Text(text.text_content()),
+        Type::String(string) => content_node(string.content()),
+        Type::Atom(atom) => content_node(atom.content()),
+        Type::Move(mover) => match mover.get_target() {
+            Some(target) => {
+                // the target is visible at the moved position only
+                nodes.pop();
+                return push_type(nodes, &target);
+            }
+            None => SnapshotNode::Value(Value::Null),
+        },
+        Type::Counter(counter) => SnapshotNode::Value(counter.value().into()),
+        Type::Proxy(proxy) => SnapshotNode::Value(proxy.to_json()),
+        Type::Mark(_) | Type::Identity => SnapshotNode::Value(Value::Null),
+    };
+
+    nodes[index] = node;
+
+    index
+}
+
+pub(crate) fn content_node(content: Content) -> SnapshotNode {
+    match content {
+        Content::String(s) => SnapshotNode::Text(s),
+        Content::Binary(b) => SnapshotNode::Binary(b),
+        content => SnapshotNode::Value(content.to_json()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_snapshot_is_send() {
+        fn assert_send<T: Send + Sync>() {}
+        assert_send::<DocSnapshot>();
+    }
+
+    #[test]
+    fn test_snapshot_on_worker_thread() {
+        let doc = Doc::default();
+        let list = doc.list();
+        doc.set("list", list.clone());
+        list.append(doc.atom("a"));
+        list.append(doc.atom(vec![1u8, 2, 3]));
+
+        let text = doc.text();
+        doc.set("text", text.clone());
+        text.append(doc.string("hello"));
+
+        let snapshot = doc.snapshot();
+
+        // keep editing while the worker reads the snapshot
+        list.append(doc.atom("b"));
+
+        let handle = thread::spawn(move || {
+            let list = snapshot.get("list").unwrap();
+            assert_eq!(list.children().len(), 2);
+            assert_eq!(list.at_index(1).unwrap().binary(), Some(&[1u8, 2, 3][..]));
+            snapshot.get("text").unwrap().text().map(|s| s.to_string())
+        });
+
+        assert_eq!(handle.join().unwrap(), Some("hello".to_string()));
+        assert_eq!(doc.get("list").unwrap().size(), 3);
+    }
+}
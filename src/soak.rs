@@ -0,0 +1,305 @@
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::doc::{CloneDeep, Doc};
+use crate::item::Item;
+use crate::store::DocStore;
+use crate::types::Type;
+
+/// AllocatorHook reports the bytes in use on the heap, e.g. read from a counting
+/// global allocator installed by the test binary
+pub trait AllocatorHook {
+    fn allocated(&self) -> usize;
+}
+
+impl<F: Fn() -> usize> AllocatorHook for F {
+    fn allocated(&self) -> usize {
+        self()
+    }
+}
+
+/// SoakConfig describes a soak run over several replicas of a document
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoakConfig {
+    pub docs: usize,
+    pub ops: usize,
+    /// check the invariants and sample the memory every this many ops
+    pub check_every: usize,
+    /// seed of the random ops, a failing run is replayed with the same seed
+    pub seed: u64,
+}
+
+impl Default for SoakConfig {
+    fn default() -> Self {
+        Self {
+            docs: 3,
+            ops: 1_000_000,
+            check_every: 10_000,
+            seed: 0,
+        }
+    }
+}
+
+impl SoakConfig {
+    pub fn new(docs: usize, ops: usize) -> Self {
+        Self {
+            docs: docs.max(1),
+            ops,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_check_every(mut self, check_every: usize) -> Self {
+        self.check_every = check_every.max(1);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+/// MemorySample is the heap in use after a number of ops
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct MemorySample {
+    pub ops: usize,
+    pub allocated: usize,
+}
+
+/// SoakReport sums up a soak run
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct SoakReport {
+    pub ops: usize,
+    pub commits: usize,
+    pub syncs: usize,
+    pub checks: usize,
+    /// samples of the allocator hook, empty without a hook
+    pub memory: Vec<MemorySample>,
+    /// heap still in use after the documents are dropped, compared to the start
+    pub retained: Option<isize>,
+    /// document stores alive after the documents are dropped
+    pub leaked_stores: usize,
+    /// container items alive after the documents are dropped
+    pub leaked_items: usize,
+}
+
+impl SoakReport {
+    /// Heap growth from the first to the last sample
+    pub fn growth(&self) -> Option<isize> {
+        let first = self.memory.first()?;
+        let last = self.memory.last()?;
+        Some(last.allocated as isize - first.allocated as isize)
+    }
+
+    #[inline]
+    pub fn has_leaks(&self) -> bool {
+        self.leaked_stores > 0 || self.leaked_items > 0
+    }
+}
+
+/// Soak runs random edits on replicas of a document and syncs them, the invariants are
+/// checked periodically. After the run the documents are dropped and the store and
+/// items still alive are reported as leaks, cycles in the item links keep them alive.
+pub struct Soak {
+    config: SoakConfig,
+    hook: Option<Box<dyn AllocatorHook>>,
+}
+
+impl Soak {
+    pub fn new(config: SoakConfig) -> Self {
+        Self { config, hook: None }
+    }
+
+    pub fn with_allocator_hook(mut self, hook: impl AllocatorHook + 'static) -> Self {
+        self.hook = Some(Box::new(hook));
+        self
+    }
+
+    pub fn run(&self) -> Result<SoakReport, String> {
+        let mut report = SoakReport::default();
+        let mut rng = StdRng::seed_from_u64(self.config.seed);
+        let baseline = self.allocated();
+
+        let (stores, items) = {
+            let docs = self.replicas();
+            for op in 1..=self.config.ops {
+                let index = rng.gen_range(0..docs.len());
+                random_op(&docs[index], &mut rng);
+                report.ops += 1;
+
+                if rng.gen_ratio(1, 8) {
+                    docs[index].commit();
+                    report.commits += 1;
+                }
+
+                if rng.gen_ratio(1, 32) {
+                    let peer = rng.gen_range(0..docs.len());
+                    docs[index].commit();
+                    docs[peer].apply(&docs[index].diff(docs[peer].state()));
+                    report.syncs += 1;
+                }
+
+                if op % self.config.check_every == 0 || op == self.config.ops {
+                    self.check(&docs, op, &mut report)?;
+                }
+            }
+
+            // watch the stores and containers while the documents are dropped
+            let stores: Vec<Weak<RefCell<DocStore>>> =
+                docs.iter().map(|doc| Rc::downgrade(&doc.store)).collect();
+            let items: Vec<Weak<RefCell<Item>>> = docs
+                .iter()
+                .flat_map(containers)
+                .map(|item| Rc::downgrade(&item.item_ref().item))
+                .collect();
+
+            (stores, items)
+        };
+
+        report.leaked_stores = stores.iter().filter(|s| s.upgrade().is_some()).count();
+        report.leaked_items = items.iter().filter(|i| i.upgrade().is_some()).count();
+        report.retained = baseline
+            .zip(self.allocated())
+            .map(|(before, after)| after as isize - before as isize);
+
+        Ok(report)
+    }
+
+    // replicas of a document with a text, a list and a map
+    fn replicas(&self) -> Vec<Doc> {
+        let doc = Doc::default();
+        doc.set("text", doc.text());
+        doc.set("list", doc.list());
+        doc.set("map", doc.map());
+        doc.commit();
+
+        let mut docs = vec![];
+        for _ in 1..self.config.docs.max(1) {
+            let replica = doc.clone_deep();
+            replica.update_client();
+            docs.push(replica);
+        }
+        docs.insert(0, doc);
+
+        docs
+    }
+
+    // sync all replicas and check the invariants
+    fn check(&self, docs: &[Doc], op: usize, report: &mut SoakReport) -> Result<(), String> {
+        docs.iter().for_each(|doc| doc.commit());
+        for (i, from) in docs.iter().enumerate() {
+            for (j, to) in docs.iter().enumerate() {
+                if i != j {
+                    to.apply(&from.diff(to.state()));
+                }
+            }
+        }
+
+        for (index, doc) in docs.iter().enumerate() {
+            doc.check_invariants().map_err(|errors| {
+                format!(
+                    "invariants of doc {} are broken after {} ops (seed {}):\n{}",
+                    index,
+                    op,
+                    self.config.seed,
+                    errors.join("\n")
+                )
+            })?;
+        }
+
+        if let Some(allocated) = self.allocated() {
+            report.memory.push(MemorySample { ops: op, allocated });
+        }
+        report.checks += 1;
+
+        Ok(())
+    }
+
+    #[inline]
+    fn allocated(&self) -> Option<usize> {
+        self.hook.as_ref().map(|hook| hook.allocated())
+    }
+}
+
+// insert or delete at a random position of a random container
+fn random_op(doc: &Doc, rng: &mut StdRng) {
+    let (Some(text), Some(list), Some(map)) = (doc.get("text"), doc.get("list"), doc.get("map"))
+    else {
+        return;
+    };
+
+    match rng.gen_range(0..5) {
+        0 => {
+            let content: String = (0..rng.gen_range(1..8))
+                .map(|_| rng.gen_range(b'a'..=b'z') as char)
+                .collect();
+            text.insert(rng.gen_range(0..=text.size()), doc.string(content));
+        }
+        1 => {
+            let strings = text.item_ref().borrow().items();
+            if let Some(Type::String(string)) = pick(&strings, rng) {
+                string.delete();
+            }
+        }
+        2 => list.insert(rng.gen_range(0..=list.size()), doc.atom(rng.gen::<u32>())),
+        3 => {
+            let items = list.item_ref().borrow().items();
+            if let Some(item) = pick(&items, rng) {
+                item.delete();
+            }
+        }
+        _ => map.set(
+            format!("key{}", rng.gen_range(0..8)),
+            doc.atom(rng.gen::<u32>()),
+        ),
+    }
+}
+
+fn pick<'a>(items: &'a [Type], rng: &mut StdRng) -> Option<&'a Type> {
+    if items.is_empty() {
+        None
+    } else {
+        items.get(rng.gen_range(0..items.len()))
+    }
+}
+
+fn containers(doc: &Doc) -> Vec<Type> {
+    let mut items = vec![Type::from(doc.root.clone())];
+    items.extend(
+        ["text", "list", "map"]
+            .into_iter()
+            .filter_map(|key| doc.get(key)),
+    );
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    #[test]
+    fn test_soak_without_leaks() {
+        let samples = Rc::new(Cell::new(0));
+        let counter = samples.clone();
+        let config = SoakConfig::new(3, 2_000).with_check_every(500).with_seed(7);
+        let report = Soak::new(config)
+            .with_allocator_hook(move || {
+                counter.set(counter.get() + 1);
+                counter.get()
+            })
+            .run()
+            .unwrap();
+
+        assert_eq!(report.ops, 2_000);
+        assert_eq!(report.checks, 4);
+        assert_eq!(report.memory.len(), 4);
+        assert_eq!(samples.get(), 6);
+        assert!(!report.has_leaks(), "{:?}", report);
+    }
+}
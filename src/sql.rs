@@ -0,0 +1,247 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::doc::Doc;
+use crate::id::{Id, WithId};
+use crate::state::ClientState;
+use crate::types::Type;
+
+/// SqlTable maps the rows of a root container to a table.
+///
+/// Every map item inside the container is a row, the row key is the global id of the map item
+/// and the mapped fields of the map are the columns.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct SqlTable {
+    name: String,
+    key_column: String,
+    // map field -> column
+    columns: BTreeMap<String, String>,
+}
+
+impl SqlTable {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            key_column: "id".to_string(),
+            columns: BTreeMap::new(),
+        }
+    }
+
+    /// Column holding the row key, `id` by default
+    pub fn with_key_column(mut self, column: impl Into<String>) -> Self {
+        self.key_column = column.into();
+        self
+    }
+
+    /// Map a field of the row maps to a column
+    pub fn with_column(mut self, field: impl Into<String>, column: impl Into<String>) -> Self {
+        self.columns.insert(field.into(), column.into());
+        self
+    }
+}
+
+/// SqlStatement is a parameterized statement, parameters are numbered `$1`, `$2`, ...
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SqlStatement {
+    pub sql: String,
+    pub params: Vec<Value>,
+}
+
+/// SqlProjection keeps a sql mirror of the document containers up to date.
+/// Call `process` after every commit or apply to get the statements for the changes since the last call.
+#[derive(Debug, Clone, Default)]
+pub struct SqlProjection {
+    // root field -> table
+    tables: BTreeMap<String, SqlTable>,
+    version: ClientState,
+}
+
+impl SqlProjection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start from the current document version, existing rows are not exported
+    pub fn from_doc(doc: &Doc) -> Self {
+        Self {
+            version: doc.version(),
+            ..Self::default()
+        }
+    }
+
+    /// Mirror the rows of the root container `field` into the table
+    pub fn with_table(mut self, field: impl Into<String>, table: SqlTable) -> Self {
+        self.tables.insert(field.into(), table);
+        self
+    }
+
+    /// Upserts for the changed rows and deletes for the deleted rows since the last call
+    pub fn process(&mut self, doc: &Doc) -> Vec<SqlStatement> {
+        let diff = doc.diff(self.version.clone());
+        self.version = doc.version();
+
+        let mut changed: Vec<Id> = vec![];
+        for (_, store) in diff.items.iter() {
+            changed.extend(store.iter().map(|(id, _)| *id));
+        }
+        for (_, store) in diff.deletes.iter() {
+            changed.extend(store.iter().map(|(_, item)| item.target()));
+        }
+
+        // changed rows, once per row in id order
+        let mut rows = BTreeMap::new();
+        for id in changed {
+            let Some(item) = doc.find_by_id(&id) else {
+                continue;
+            };
+            if let Some((field, row)) = self.row_of(doc, item) {
+                rows.insert(row.id(), (field, row));
+            }
+        }
+
+        rows.into_values()
+            .filter_map(|(field, row)| {
+                let table = &self.tables[&field];
+                let key = Value::String(global_id(doc, &row.id()));
+
+                if row.is_deleted() {
+                    return Some(delete(table, key));
+                }
+
+                let map = row.as_map()?;
+                let values = table
+                    .columns
+                    .iter()
+                    .map(|(field, column)| {
+                        let value = map.get(field.clone()).map(|v| v.to_json());
+                        (column.as_str(), value.unwrap_or(Value::Null))
+                    })
+                    .collect();
+
+                Some(upsert(table, key, values))
+            })
+            .collect()
+    }
+
+    // the row map holding the item and the root field of the mapped container
+    fn row_of(&self, doc: &Doc, item: Type) -> Option<(String, Type)> {
+        let root = doc.root.id();
+        let mut current = item;
+
+        loop {
+            let parent = current.parent()?;
+            let grand = parent.parent()?;
+            if grand.id() == root {
+                let field = parent.field()?;
+                if self.tables.contains_key(&field) && current.as_map().is_some() {
+                    return Some((field, current));
+                }
+                return None;
+            }
+            current = parent;
+        }
+    }
+}
+
+// id of the item that is the same on every site
+fn global_id(doc: &Doc, id: &Id) -> String {
+    let store = doc.store.borrow();
+    match store.state.get_client(&id.client) {
+        Some(client) => format!("{}:{}", client, id.clock),
+        None => id.to_string(),
+    }
+}
+
+fn upsert(table: &SqlTable, key: Value, values: Vec<(&str, Value)>) -> SqlStatement {
+    let mut columns = vec![quote(&table.key_column)];
+    let mut params = vec![key];
+    for (column, value) in values {
+        columns.push(quote(column));
+        params.push(value);
+    }
+
+    let placeholders: Vec<String> = (1..=params.len()).map(|i| format!("${}", i)).collect();
+    let updates: Vec<String> = columns[1..]
+        .iter()
+        .map(|c| format!("{} = excluded.{}", c, c))
+        .collect();
+    let conflict = if updates.is_empty() {
+        "DO NOTHING".to_string()
+    } else {
+        format!("DO UPDATE SET {}", updates.join(", "))
+    };
+
+    SqlStatement {
+        sql: format!(
+            "INSERT INTO {} ({}) VALUES ({}) ON CONFLICT ({}) {}",
+            quote(&table.name),
+            columns.join(", "),
+            placeholders.join(", "),
+            columns[0],
+            conflict
+        ),
+        params,
+    }
+}
+
+fn delete(table: &SqlTable, key: Value) -> SqlStatement {
+    SqlStatement {
+        sql: format!(
+            "DELETE FROM {} WHERE {} = $1",
+            quote(&table.name),
+            quote(&table.key_column)
+        ),
+        params: vec![key],
+    }
+}
+
+// quote an identifier, embedded quotes are doubled
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_project_rows() {
+        let doc = Doc::default();
+        let todos = doc.list();
+        doc.set("todos", todos.clone());
+        let mut projection = SqlProjection::from_doc(&doc).with_table(
+            "todos",
+            SqlTable::new("todos")
+                .with_column("title", "title")
+                .with_column("done", "is_done"),
+        );
+
+        let todo = doc.map();
+        todos.append(todo.clone());
+        todo.set("title", doc.atom("write tests"));
+        doc.commit();
+
+        let statements = projection.process(&doc);
+        assert_eq!(statements.len(), 1);
+        assert_eq!(
+            statements[0].sql,
+            "INSERT INTO \"todos\" (\"id\", \"is_done\", \"title\") VALUES ($1, $2, $3) \
+             ON CONFLICT (\"id\") DO UPDATE SET \"is_done\" = excluded.\"is_done\", \"title\" = excluded.\"title\""
+        );
+        assert_eq!(statements[0].params[1], Value::Null);
+        assert_eq!(statements[0].params[2], json!("write tests"));
+
+        assert!(projection.process(&doc).is_empty());
+
+        todo.delete();
+        doc.commit();
+
+        let statements = projection.process(&doc);
+        assert_eq!(statements.len(), 1);
+        assert_eq!(statements[0].sql, "DELETE FROM \"todos\" WHERE \"id\" = $1");
+    }
+}
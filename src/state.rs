@@ -8,6 +8,7 @@ use crate::bimapid::{ClientId, ClientMap, ClientMapper};
 use crate::decoder::{Decode, DecodeContext, Decoder};
 use crate::encoder::{Encode, EncodeContext, Encoder};
 use crate::id::ClockTick;
+use crate::limits::DiffLimit;
 use crate::Client;
 use serde::ser::SerializeStruct;
 use serde::{Serialize, Serializer};
@@ -51,6 +52,14 @@ impl ClientFrontier {
         hash_str
     }
 
+    // every change seen by the other frontier is seen by this one
+    pub(crate) fn covers(&self, other: &ClientFrontier) -> bool {
+        other
+            .frontier
+            .iter()
+            .all(|(client, clock)| self.frontier.get(client).is_some_and(|c| c >= clock))
+    }
+
     pub(crate) fn short_hash(&self) -> String {
         let hash = self.hash();
         hash.chars().take(8).collect()
@@ -342,6 +351,7 @@ impl Encode for ClientIdState {
 impl Decode for ClientIdState {
     fn decode<D: Decoder>(d: &mut D, ctx: &DecodeContext) -> Result<ClientIdState, String> {
         let len = d.u32()? as usize;
+        ctx.check_limit(DiffLimit::Clients, len)?;
         let mut clients = BTreeMap::new();
         for _ in 0..len {
             let client = d.u32()?;
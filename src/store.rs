@@ -1,17 +1,35 @@
+use crate::activity::ActivityTracker;
+use crate::anchor::AnchorRefs;
 use crate::bimapid::{ClientId, Field, FieldId, FieldMap};
 use crate::change::{ChangeId, ChangeStore};
+use crate::change_budget::ChangeBudget;
+use crate::checksum::ChecksumTree;
+use crate::cold::ColdStore;
 use crate::dag::{ChangeDag, ChangeNode};
 use crate::decoder::{Decode, DecodeContext, Decoder};
 use crate::delete::DeleteItem;
 use crate::diff::Diff;
 use crate::doc::DocId;
 use crate::encoder::{Encode, EncodeContext, Encoder};
+use crate::event::DocEvent;
+use crate::features::FeatureSet;
 use crate::frontier::Frontier;
-use crate::id::{ClockTick, Id, IdRange, Split, WithId, WithIdRange};
+use crate::id::{ClockTick, Id, IdRange, Split, WithId, WithIdRange, WithTarget};
+use crate::id_alloc::IdAllocation;
 use crate::id_store::ClientIdStore;
-use crate::item::{ItemData, ItemKind, ItemRef};
+use crate::item::{Content, ItemData, ItemKind, ItemRef};
+use crate::lazy::LazyStore;
+use crate::limits::{DiffLimit, DiffLimits};
+use crate::line_index::LineIndex;
+use crate::mark_inherit::MarkInheritance;
+use crate::observe::{PathObservers, UpdateListeners};
+use crate::retention::{HistoryTimeline, LegalHolds};
+use crate::schema::{DocSchema, QuarantinedDiff};
 use crate::state::ClientState;
+use crate::text_offset::OffsetIndex;
+use crate::transaction::ChangeMeta;
 use crate::types::Type;
+use crate::unique::UniqueKeys;
 use crate::{print_yaml, Client};
 use bimap::BiMap;
 use hashbrown::{HashMap, HashSet};
@@ -28,80 +46,85 @@ pub(crate) type StoreRef = Rc<RefCell<DocStore>>;
 pub(crate) type WeakStoreRef = Weak<RefCell<DocStore>>;
 
 // Listener is a tuple of a token and a listener function
-type Listener = (u32, Rc<dyn Fn(&Type)>);
+type Listener = (u32, Rc<dyn Fn(&DocEvent)>);
 
-/// TypeEmitter is a store for the types that are dirty and need to be emitted
+/// TypeEmitter keeps the listeners of `Type::observe` by the observed container
+/// and the listeners of `Doc::observe_deep`
 #[derive(Clone, Default)]
-struct TypeEmitter {
-    pub(crate) dirty: HashSet<Id>,
+pub(crate) struct TypeEmitter {
     pub(crate) store: HashMap<Id, Vec<Listener>>,
+    pub(crate) deep: Vec<Listener>,
     token: u32,
 }
 
 impl TypeEmitter {
-    pub(crate) fn new() -> Self {
-        TypeEmitter {
-            dirty: HashSet::new(),
-            store: HashMap::new(),
-            token: 0,
-        }
-    }
-
     pub(crate) fn add_listener<F>(&mut self, id: Id, listener: F) -> u32
     where
-        F: Fn(&Type) + 'static,
+        F: Fn(&DocEvent) + 'static,
     {
-        let token = self.token;
         self.token += 1;
-
         let entry = self.store.entry(id).or_default();
-        entry.push((token, Rc::new(listener)));
+        entry.push((self.token, Rc::new(listener)));
 
-        token
+        self.token
     }
 
-    pub(crate) fn remove_listener(&mut self, id: &Id, token: u32) {
-        if let Some(listeners) = self.store.get_mut(id) {
-            listeners.retain(|(t, _)| *t != token);
-            if listeners.is_empty() {
-                self.store.remove(id);
-            }
-        }
-    }
+    pub(crate) fn add_deep_listener<F>(&mut self, listener: F) -> u32
+    where
+        F: Fn(&DocEvent) + 'static,
+    {
+        self.token += 1;
+        self.deep.push((self.token, Rc::new(listener)));
 
-    pub(crate) fn emit(&mut self, item: &Type) {
-        if let Some(listeners) = self.store.get(&item.id()) {
-            for (_, listener) in listeners.iter() {
-                listener(item);
-            }
-        }
+        self.token
     }
 
-    pub(crate) fn add_dirty(&mut self, id: Id) {
-        self.dirty.insert(id);
+    pub(crate) fn remove_listener(&mut self, token: u32) {
+        self.deep.retain(|(t, _)| *t != token);
+        self.store.retain(|_, listeners| {
+            listeners.retain(|(t, _)| *t != token);
+            !listeners.is_empty()
+        });
     }
 
-    pub(crate) fn reset_dirty(&mut self) {
-        self.dirty.clear();
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.store.is_empty() && self.deep.is_empty()
     }
 
-    pub(crate) fn remove_dirty(&mut self, id: &Id) {
-        self.dirty.remove(id);
+    // listeners of a change of the container, the container listeners come first
+    pub(crate) fn listeners(&self, id: &Id) -> Vec<Rc<dyn Fn(&DocEvent)>> {
+        self.store
+            .get(id)
+            .into_iter()
+            .flatten()
+            .chain(self.deep.iter())
+            .map(|(_, listener)| listener.clone())
+            .collect()
     }
 
-    /// publish will emit all dirty items to the listeners
-    pub(crate) fn publish(&mut self, store: &TypeStore) {}
+    fn tokens(&self) -> Vec<u32> {
+        let mut tokens: Vec<u32> = self
+            .store
+            .values()
+            .flatten()
+            .chain(self.deep.iter())
+            .map(|(token, _)| *token)
+            .collect();
+        tokens.sort();
+        tokens
+    }
 }
 
 impl Debug for TypeEmitter {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        todo!()
+        f.debug_list().entries(self.tokens()).finish()
     }
 }
 
 impl PartialEq<Self> for TypeEmitter {
     fn eq(&self, other: &Self) -> bool {
-        todo!()
+        self.tokens() == other.tokens()
     }
 }
 
@@ -112,6 +135,53 @@ impl Eq for TypeEmitter {}
 pub(crate) struct DocStore {
     pub(crate) doc_id: DocId,
     pub(crate) created_by: Client,
+    // capabilities used by the document, see FeatureSet
+    pub(crate) features: FeatureSet,
+    // inserted strings larger than this are split into chunks
+    pub(crate) max_string_size: Option<u32>,
+    // per client counters of the applied remote operations
+    pub(crate) activity: ActivityTracker,
+    // remote diffs are validated against the schema before integration
+    pub(crate) schema: Option<DocSchema>,
+    pub(crate) quarantine: Vec<QuarantinedDiff>,
+    // idle texts kept as frozen buffers
+    pub(crate) cold: ColdStore,
+    // root entries of a lazily loaded document
+    pub(crate) lazy: LazyStore,
+    // key-path subscriptions
+    pub(crate) path_observers: PathObservers,
+    // listeners of the encoded local changes
+    pub(crate) update_listeners: UpdateListeners,
+    // uniqueness keys of the lists acting as sets
+    pub(crate) unique: UniqueKeys,
+    // check the invariants after every commit and apply
+    pub(crate) strict: bool,
+    // marks copied from containers onto new children
+    pub(crate) mark_inheritance: MarkInheritance,
+    // oversized local commits are split into several changes
+    pub(crate) change_budget: ChangeBudget,
+    // cached container checksums, dropped when the container changes
+    pub(crate) checksums: HashMap<Id, ChecksumTree>,
+    // bounds of the remote diffs
+    pub(crate) diff_limits: DiffLimits,
+    // map keys keeping a single value per change, see Doc::coalesce_values
+    pub(crate) coalesced_keys: HashSet<String>,
+    // ids referenced by registered anchors, their tombstones are never purged
+    pub(crate) anchors: AnchorRefs,
+    // versions exempt from compaction, see Doc::place_legal_hold
+    pub(crate) holds: LegalHolds,
+    // times the versions were reached at, see Doc::enforce_retention
+    pub(crate) history: HistoryTimeline,
+    // picks the clients of the local changes, see Doc::set_id_allocator
+    pub(crate) id_allocation: IdAllocation,
+    // origin tags of the changes, see OriginFilter
+    pub(crate) origins: HashMap<ChangeId, String>,
+    // user metadata of the changes committed by Doc::transact
+    pub(crate) change_meta: HashMap<ChangeId, ChangeMeta>,
+    // line indexes of the texts, see NText::enable_line_index
+    pub(crate) line_indexes: HashMap<Id, LineIndex>,
+    // offset indexes of the texts, see NText::convert_offset
+    pub(crate) offset_indexes: HashMap<Id, OffsetIndex>,
 
     pub(crate) client: ClientId,
     pub(crate) clock: ClockTick,
@@ -128,6 +198,8 @@ pub(crate) struct DocStore {
 
     pub(crate) items: TypeStore,
     pub(crate) movers: TypeStore,
+    // marks are kept aside, they are not linked into the children of the marked items
+    pub(crate) marks: TypeStore,
     pub(crate) deletes: DeleteItemStore,
 
     pub(crate) pending: PendingStore,
@@ -135,7 +207,8 @@ pub(crate) struct DocStore {
     pub(crate) changes: ChangeStore,
     pub(crate) dag: ChangeDag,
 
-    emitter: TypeEmitter,
+    // listeners of Type::observe and Doc::observe_deep
+    pub(crate) emitter: TypeEmitter,
 }
 
 impl DocStore {
@@ -164,6 +237,27 @@ impl DocStore {
 
         // println!("change_id: {:?}", change_id);
 
+        // oversized commits are split into a chain of changes
+        let mut prev = None;
+        let mut start = change_id.start;
+        for end in self.change_splits(change_id.into()) {
+            let part = ChangeId::new(client_id, start, end);
+            self.commit_change(part, prev);
+            prev = Some(part);
+            start = end + 1;
+        }
+
+        // the committed movers are no longer pending
+        self.commited_clock = self.clock;
+        for mover in self.movers.get_by_range(change_id) {
+            if let Some(target) = mover.item_ref().get_target() {
+                self.sort_movers(target.id());
+            }
+        }
+    }
+
+    // insert the change and connect it to the change dag, `prev` is the previous part of a split commit
+    fn commit_change(&mut self, change_id: ChangeId, prev: Option<ChangeId>) {
         // find the highest change dependency for the change
 
         let mut deps = HashSet::new();
@@ -171,21 +265,33 @@ impl DocStore {
         // track if change has a move item
         let mut moves = false;
         // update the deps for the inserted items
-        self.items.get_by_range(change_id).iter().map(|item| {
+        for item in self.items.get_by_range(change_id) {
             let data = item.data();
             moves |= data.kind == ItemKind::Move;
             deps.extend(data.deps())
-        });
+        }
 
         // update the deps for the change deletes
-        self.deletes
-            .get_by_range(change_id)
-            .iter()
-            .map(|item| deps.insert(item.target()));
+        for item in self.deletes.get_by_range(change_id) {
+            deps.insert(item.target());
+        }
+
+        // the containers changed by the local change are hot
+        for item in self.items.get_by_range(change_id) {
+            if let Some(parent_id) = item.parent_id() {
+                self.cold.touch(parent_id);
+            }
+        }
+        for item in self.deletes.get_by_range(change_id) {
+            if let Some(parent_id) = self.find(&item.target()).and_then(|t| t.parent_id()) {
+                self.cold.touch(parent_id);
+            }
+        }
 
         // connect the new change with the change dependencies
         // this will create the change DAG
         let mut change_ids = HashSet::new();
+        change_ids.extend(prev);
         for dep in deps {
             if let Some(change) = self.changes.get(&dep) {
                 // avoid creating self circular dependency
@@ -202,10 +308,6 @@ impl DocStore {
             ChangeNode::new(change_id, parents).with_mover(moves),
             &self.state.clients,
         );
-
-        self.commited_clock = self.clock;
-
-        self.emitter.publish(&self.items);
     }
 
     // rollback the uncommited items from the store
@@ -217,26 +319,55 @@ impl DocStore {
 
         let range = IdRange::new(self.client, self.commited_clock, self.clock + 1);
 
+        // undo the deletes first, they may target the pending items
+        let deleted = self.deletes.get_by_range(range);
+        deleted
+            .iter()
+            .rev()
+            .for_each(|delete| self.remove_deleter(&delete.id()));
+
         // find all items within the clock tick
         let items = self.items.get_by_range(range);
         items.iter().rev().for_each(|item| self.remove(&item.id()));
 
-        let deleted = self.deletes.get_by_range(range);
-        items
-            .iter()
-            .rev()
-            .for_each(|item| self.remove_deleter(&item.id()));
+        // give the clock ticks of the change back
+        self.clock = self.commited_clock;
+        self.state
+            .state
+            .update(self.client, self.commited_clock.saturating_sub(1));
     }
 
     pub(crate) fn add_mover(&mut self, target_id: Id, mover: Type) {
-        let entry = self.moves.entry(target_id).or_default();
-        // mark the last mover as moved so that it will be treated as an invisible item
-        if let Some(last) = entry.last() {
-            last.item_ref().mark_moved();
+        mover.item_ref().mark_active();
+        self.moves.entry(target_id).or_default().push(mover);
+        self.sort_movers(target_id);
+    }
+
+    // concurrent movers of a target reach the replicas in any order, keep them ordered by
+    // clock and client so the same mover wins everywhere. The pending local movers stay on
+    // top until they are committed.
+    fn sort_movers(&mut self, target_id: Id) {
+        let Some(mut movers) = self.moves.remove(&target_id) else {
+            return;
+        };
+
+        movers.sort_by_cached_key(|mover| {
+            let id = mover.id();
+            let pending = id.client == self.client && id.clock >= self.commited_clock;
+            (
+                pending,
+                id.clock,
+                self.state.get_client(&id.client).cloned(),
+            )
+        });
+
+        // mark the lower movers as moved so that they will be treated as invisible items
+        if let Some((last, rest)) = movers.split_last() {
+            rest.iter().for_each(|mover| mover.item_ref().mark_moved());
+            last.item_ref().unmark_moved();
         }
 
-        mover.item_ref().mark_active();
-        entry.push(mover);
+        self.moves.insert(target_id, movers);
     }
 
     /// remove the last mover for the given target id
@@ -268,7 +399,16 @@ impl DocStore {
     }
 
     #[inline]
-    pub(crate) fn remove_deleter(&mut self, id: &Id) {}
+    // drop a pending delete, the deleted items become visible again
+    pub(crate) fn remove_deleter(&mut self, id: &Id) {
+        let Some(delete) = self.deletes.remove(id) else {
+            return;
+        };
+
+        for item in self.items.get_by_range(*delete.range()) {
+            item.item_ref().borrow_mut().unmark_deleted();
+        }
+    }
 
     #[inline]
     pub(crate) fn get_field_id(&mut self, field: &Field) -> u32 {
@@ -288,6 +428,8 @@ impl DocStore {
     pub(crate) fn update_client(&mut self, client: &Client, clock: ClockTick) -> ClientId {
         self.client = self.state.clients.get_or_insert(client);
         self.clock = clock.max(1);
+        // the new client has no pending change
+        self.commited_clock = self.clock;
 
         self.client
     }
@@ -299,6 +441,9 @@ impl DocStore {
 
     #[inline]
     pub(crate) fn next_id(&mut self) -> Id {
+        self.begin_change();
+        self.reserve_ticks(1);
+
         let id = Id::new(self.client, self.clock);
         self.clock += 1;
 
@@ -307,15 +452,47 @@ impl DocStore {
 
     #[inline]
     pub(crate) fn next_id_range(&mut self, size: ClockTick) -> IdRange {
+        self.begin_change();
+        self.reserve_ticks(size);
+
         let id = IdRange::new(self.client, self.clock, self.clock + size - 1);
         self.clock += size;
 
         id
     }
 
+    /// make sure the current client can allocate `size` more clock ticks.
+    ///
+    /// the clock is a 32-bit counter, when it is about to wrap around the pending change is
+    /// committed and the store switches to a fresh client starting at clock 1. a change
+    /// never spans two clients, so an operation allocating ids past the overflow ends up
+    /// in two regular changes and the remote sites integrate both.
+    pub(crate) fn reserve_ticks(&mut self, size: ClockTick) {
+        if self.clock.checked_add(size).is_some() {
+            return;
+        }
+
+        self.commit();
+
+        let client = Client::default();
+        log::warn!(
+            "clock overflow for client {}, switching to new client {}",
+            self.client,
+            client
+        );
+
+        self.update_client(&client, 1);
+    }
+
+    /// number of clock ticks the current client can still allocate before re-client
+    #[inline]
+    pub(crate) fn clock_headroom(&self) -> ClockTick {
+        ClockTick::MAX - self.clock
+    }
+
     #[inline]
     pub(crate) fn contains(&self, id: &Id) -> bool {
-        self.items.get(id).is_some()
+        self.find(id).is_some()
     }
 
     #[inline]
@@ -337,6 +514,23 @@ impl DocStore {
         if item.kind() == ItemKind::Move {
             self.movers.insert(item.clone())
         }
+        if item.kind() == ItemKind::Mark {
+            self.marks.insert(item.clone())
+        }
+        // remote proxies and movers find their target by id
+        if let Type::Proxy(proxy) = &item {
+            if let Some(target) = proxy.target_id().and_then(|id| self.find(&id)) {
+                proxy.set_target(target);
+            }
+        }
+        if let Type::Move(mover) = &item {
+            let content = mover.borrow().data.content.clone();
+            if let (None, Content::Id(id)) = (mover.get_target(), content) {
+                if let Some(target) = self.find(&id) {
+                    mover.set_target(target);
+                }
+            }
+        }
         self.items.insert(item);
 
         self.state.update(id_range.client, id_range.end);
@@ -345,7 +539,12 @@ impl DocStore {
     pub(crate) fn remove(&mut self, id: &Id) {
         if let Some(item) = self.items.get(id) {
             if item.id().eq(id) {
-                item.disconnect();
+                // marks are not linked into the children of the marked item
+                if item.kind() == ItemKind::Mark {
+                    self.marks.remove(id);
+                } else {
+                    item.disconnect();
+                }
                 self.items.remove(id);
                 // retract the clock
                 if self.client == id.client && self.clock == item.range().end {
@@ -358,15 +557,95 @@ impl DocStore {
         }
     }
 
+    // drop the links between the items, the links are strong and form cycles that
+    // would keep the items alive after the document is dropped
+    pub(crate) fn unlink_items(&mut self) {
+        for (_, items) in self.items.iter() {
+            for (_, item) in items.iter() {
+                let item = item.item_ref();
+                let mut item = item.borrow_mut();
+                item.parent = None;
+                item.target = None;
+                item.left = None;
+                item.right = None;
+                item.start = None;
+                item.end = None;
+            }
+        }
+    }
+
+    // the id belongs to the uncommitted change of the local client
+    #[inline]
+    pub(crate) fn is_pending(&self, id: &Id) -> bool {
+        id.client == self.client && id.clock >= self.commited_clock && id.clock < self.clock
+    }
+
+    // drop the last allocated item of the pending change and give its clock tick back,
+    // items allocated before it can not be dropped without leaving a hole in the clock
+    pub(crate) fn retract_last(&mut self, id: &Id) -> bool {
+        if !self.is_pending(id) || id.clock + 1 != self.clock {
+            return false;
+        }
+
+        self.items.remove(id);
+        self.clock = id.clock;
+        self.state
+            .state
+            .update(self.client, id.clock.saturating_sub(1));
+
+        true
+    }
+
     #[inline]
     pub(crate) fn insert_delete(&mut self, item: DeleteItem) -> &mut DocStore {
+        // the version covers the delete like an inserted item
+        self.state.update(item.id().client, item.id().clock);
         self.deletes.insert(item);
         self
     }
 
+    // record a local delete. Characters deleted right before or after the characters of
+    // the previous operation of the pending change extend that delete, so a backspace run
+    // ends up as a single delete item
+    pub(crate) fn delete_range(&mut self, range: IdRange) {
+        // an extended delete leaves the clock as it is, the text indexes can not see it
+        self.invalidate_line_indexes();
+        self.invalidate_offset_indexes();
+
+        if self.clock > self.commited_clock {
+            let last = Id::new(self.client, self.clock - 1);
+            let extends = self.deletes.get(&last).is_some_and(|delete| {
+                self.same_text(&delete.target(), &range.start_id()) && delete.clone().extend(&range)
+            });
+            if let (true, Some(delete)) = (extends, self.deletes.get_mut(&last)) {
+                delete.extend(&range);
+                return;
+            }
+        }
+
+        let id = self.next_id();
+        self.insert_delete(DeleteItem::new(id, range));
+    }
+
+    // both ids are strings of the same text
+    fn same_text(&self, a: &Id, b: &Id) -> bool {
+        match (self.find(a), self.find(b)) {
+            (Some(a), Some(b)) => {
+                a.kind().is_string() && b.kind().is_string() && a.parent_id() == b.parent_id()
+            }
+            _ => false,
+        }
+    }
+
     // replace the item with two items, used for splitting items
     #[inline]
     pub(crate) fn replace(&mut self, item: &Type, items: (Type, Type)) -> &mut DocStore {
+        // the halves of a split string are found by their own ranges
+        if item.kind() == ItemKind::String {
+            let range = |item: &Type| item.id().range(item.size());
+            self.id_map
+                .replace(range(item), (range(&items.0), range(&items.1)));
+        }
         self.items.replace(item, items);
         self
     }
@@ -389,7 +668,21 @@ impl DocStore {
     pub(crate) fn diff(&self, id: DocId, created_by: Client, state: ClientState) -> Diff {
         let state = state.as_per(&self.state);
 
-        let items = self.items.diff(&state);
+        let mut items = self.items.diff(&state);
+
+        // frozen items are not materialized but still belong to the document
+        if !self.cold.is_empty() {
+            let mut frozen = ItemDataStore::default();
+            self.cold
+                .items()
+                .into_iter()
+                .for_each(|data| frozen.insert(data));
+            for (_, store) in frozen.diff(&state).iter() {
+                store
+                    .iter()
+                    .for_each(|(_, data)| items.insert(data.clone()));
+            }
+        }
 
         let deletes = self.deletes.diff(&state);
 
@@ -410,7 +703,7 @@ impl DocStore {
             .iter()
             .any(|(_, store)| store.iter().any(|(_, item)| item.kind().is_move()));
 
-        Diff::from(
+        let mut diff = Diff::from(
             id,
             created_by,
             self.fields.clone(),
@@ -418,7 +711,10 @@ impl DocStore {
             state,
             items,
             deletes,
-        )
+        );
+        diff.features = self.features.clone();
+
+        diff
     }
 }
 
@@ -433,6 +729,10 @@ pub(crate) struct ReadyStore {
 
 impl ReadyStore {
     pub(crate) fn insert(&mut self, item: ItemData) {
+        // origins may point inside a ready string run
+        if let Content::String(s) = &item.content {
+            self.id_range_map.insert(item.id.range(s.len() as u32));
+        }
         self.items_exists.insert(item.id());
         self.queue.push_back(item.clone());
         self.items.insert(item);
@@ -900,6 +1200,57 @@ impl<T: ClientStoreEntry> ClientStore<T> {
     }
 }
 
+impl ItemDataStore {
+    // insert the parts of the item not covered by the stored items, two diffs can hold
+    // the same string split at different offsets
+    pub(crate) fn insert_missing(&mut self, item: ItemData) {
+        // ItemData::range reaches one tick past the end of a string
+        let range = item.id.range(item.ticks());
+        let store = self.store(&range.client);
+
+        // stored ranges overlapping the item, the one starting before it may reach into it
+        let mut covered: Vec<IdRange> = store
+            .map
+            .range(..=range.end_id())
+            .rev()
+            .map(|(_, stored)| stored.id.range(stored.ticks()))
+            .take_while(|stored| stored.end >= range.start)
+            .collect();
+        if covered.is_empty() {
+            store.insert(item);
+            return;
+        }
+        // only strings span several ticks
+        if item.kind != ItemKind::String {
+            return;
+        }
+
+        covered.reverse();
+        let mut gaps = vec![];
+        let mut start = range.start;
+        for stored in covered {
+            if stored.start > start {
+                gaps.push((start, stored.start - 1));
+            }
+            start = start.max(stored.end + 1);
+        }
+        if start <= range.end {
+            gaps.push((start, range.end));
+        }
+
+        for (start, end) in gaps {
+            let mut part = item.clone();
+            if start > range.start {
+                part = part.split(start - range.start).unwrap().1;
+            }
+            if end < range.end {
+                part = part.split(end - start + 1).unwrap().0;
+            }
+            store.insert(part);
+        }
+    }
+}
+
 impl<T: ClientStoreEntry> IntoIterator for ClientStore<T> {
     type Item = (ClientId, ItemStore<T>);
     type IntoIter = std::collections::btree_map::IntoIter<ClientId, ItemStore<T>>;
@@ -934,10 +1285,14 @@ impl<T: ClientStoreEntry> Encode for ClientStore<T> {
 impl<T: ClientStoreEntry> Decode for ClientStore<T> {
     fn decode<D: Decoder>(d: &mut D, cx: &DecodeContext) -> Result<ClientStore<T>, String> {
         let len = d.u32()? as usize;
+        cx.check_limit(DiffLimit::Clients, len)?;
         let mut items = BTreeMap::new();
+        let mut size = 0;
         for _ in 0..len {
             let client = d.u32()?;
             let store = ItemStore::decode(d, cx)?;
+            size += store.size();
+            cx.check_limit(DiffLimit::Items, size)?;
             items.insert(client, store);
         }
 
@@ -1078,6 +1433,7 @@ impl<T: ItemStoreEntry> Encode for ItemStore<T> {
 impl<T: ItemStoreEntry> Decode for ItemStore<T> {
     fn decode<D: Decoder>(d: &mut D, cx: &DecodeContext) -> Result<ItemStore<T>, String> {
         let len = d.u32()? as usize;
+        cx.check_limit(DiffLimit::Items, len)?;
         let mut data = BTreeMap::new();
         for _ in 0..len {
             let value = T::decode(d, cx)?;
@@ -1213,6 +1569,25 @@ mod tests {
         assert_eq!(map.get(&Id::new(1, 9)).unwrap(), &Id::new(1, 8).into());
     }
 
+    #[test]
+    fn test_clock_overflow_switches_client() {
+        let doc = crate::doc::Doc::default();
+        doc.commit();
+        let client = doc.store.borrow().client;
+
+        doc.store.borrow_mut().clock = ClockTick::MAX - 1;
+        doc.store.borrow_mut().commited_clock = ClockTick::MAX - 1;
+        let a = doc.atom("a");
+        assert_eq!(a.id().client, client);
+
+        // the next tick would wrap around, the pending change is committed and the store
+        // moves to a new client
+        let b = doc.atom("b");
+        assert_ne!(b.id().client, client);
+        assert_eq!(b.id().clock, 1);
+        assert!(doc.store.borrow().changes.get(&a.id()).is_some());
+    }
+
     #[test]
     fn test_encode_decode_client_id_store() {
         let mut store = ItemStore::default();
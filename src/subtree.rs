@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+
+use hashbrown::{HashMap, HashSet};
+
+use crate::bimapid::ClientId;
+use crate::diff::Diff;
+use crate::doc::Doc;
+use crate::id::{ClockTick, Id, WithId};
+use crate::item::{Content, ItemData, ItemKind};
+use crate::state::ClientState;
+use crate::store::{DeleteItemStore, ItemDataStore};
+use crate::types::Type;
+
+// items of a diff by client and start clock
+pub(crate) struct DiffIndex<'a> {
+    items: HashMap<ClientId, BTreeMap<ClockTick, &'a ItemData>>,
+}
+
+impl<'a> DiffIndex<'a> {
+    pub(crate) fn new(diff: &'a Diff) -> Self {
+        let mut items: HashMap<ClientId, BTreeMap<ClockTick, &'a ItemData>> = HashMap::new();
+        for (client, store) in diff.items.iter() {
+            let entry = items.entry(*client).or_default();
+            for (id, item) in store.iter() {
+                entry.insert(id.clock, item);
+            }
+        }
+
+        Self { items }
+    }
+
+    // the item holding the id
+    pub(crate) fn find(&self, id: &Id) -> Option<&'a ItemData> {
+        let (_, item) = self.items.get(&id.client)?.range(..=id.clock).next_back()?;
+        item.id.range(item.ticks()).contains(id).then_some(*item)
+    }
+}
+
+// items the item can not be integrated without
+pub(crate) fn dependencies(item: &ItemData) -> Vec<Id> {
+    let mut deps = item.deps();
+    if let (ItemKind::Move, Content::Id(target)) = (&item.kind, &item.content) {
+        deps.push(*target);
+    }
+
+    deps
+}
+
+// the item is the root or inside it
+fn is_inside(item: &Type, root: &Id) -> bool {
+    let mut current = Some(item.clone());
+    while let Some(item) = current {
+        if item.id() == *root {
+            return true;
+        }
+        current = item.parent();
+    }
+
+    false
+}
+
+impl Doc {
+    /// Diff of the changes since the state inside the container, e.g. a single page of a
+    /// workspace. The items the receiver can not integrate without are part of the diff
+    /// too: the ancestors of the container and the origins of the items.
+    ///
+    /// The state is the version the receiver synced the subtree at, the receiver's own
+    /// state has gaps after a subtree sync. The state of the returned diff is the version
+    /// to pass for the next diff of the subtree.
+    pub fn diff_subtree(&self, root: &Type, state: impl Into<ClientState>) -> Diff {
+        let mut diff = self.diff(state);
+        let root = root.id();
+
+        let included: HashSet<Id> = {
+            let store = self.store.borrow();
+            let index = DiffIndex::new(&diff);
+
+            let mut queue: Vec<&ItemData> = diff
+                .items
+                .iter()
+                .flat_map(|(_, items)| items.iter().map(|(_, item)| item))
+                .filter(|item| store.find(&item.id).is_some_and(|i| is_inside(&i, &root)))
+                .collect();
+
+            let mut included = HashSet::new();
+            while let Some(item) = queue.pop() {
+                if !included.insert(item.id) {
+                    continue;
+                }
+                queue.extend(dependencies(item).iter().filter_map(|id| index.find(id)));
+            }
+
+            included
+        };
+
+        let mut items = ItemDataStore::default();
+        for (_, store) in diff.items.iter() {
+            store
+                .iter()
+                .filter(|(id, _)| included.contains(&**id))
+                .for_each(|(_, item)| items.insert(item.clone()));
+        }
+
+        // deletes of the items inside the container, the receiver has the other items
+        // of the container from an earlier diff
+        let mut deletes = DeleteItemStore::default();
+        {
+            let store = self.store.borrow();
+            for (_, pending) in diff.deletes.iter() {
+                pending
+                    .iter()
+                    .filter(|(_, delete)| {
+                        store
+                            .find(&delete.target())
+                            .is_some_and(|item| is_inside(&item, &root))
+                    })
+                    .for_each(|(_, delete)| deletes.insert(delete.clone()));
+            }
+        }
+
+        diff.items = items;
+        diff.deletes = deletes;
+        diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_subtree() {
+        let doc = Doc::default();
+        let pages = doc.map();
+        doc.set("pages", pages.clone());
+        let first = doc.map();
+        pages.set("first", first.clone());
+        let text = doc.text();
+        first.set("body", text.clone());
+        text.append(doc.string("hello"));
+        let second = doc.list();
+        pages.set("second", second.clone());
+        second.append(doc.atom("item"));
+        doc.commit();
+
+        let reader = Doc::new(doc.meta.clone());
+        let diff = doc.diff_subtree(&first.clone().into(), ClientState::default());
+        reader.apply(&diff);
+        let reader_pages = reader.get("pages").unwrap();
+        let body = reader_pages.get("first").unwrap().get("body").unwrap();
+        assert_eq!(body.text_content(), "hello");
+        assert!(reader_pages.get("second").is_none());
+
+        // later edits of the subtree only
+        text.append(doc.string(" world"));
+        second.append(doc.atom("other"));
+        doc.commit();
+        let diff = doc.diff_subtree(&first.clone().into(), diff.state.clone());
+        assert_eq!(diff.items.size(), 1);
+        reader.apply(&diff);
+        assert_eq!(body.text_content(), "hello world");
+        assert!(reader_pages.get("second").is_none());
+        assert!(reader.check_invariants().is_ok());
+    }
+}
@@ -1,5 +1,18 @@
+use std::time::Duration;
+
+use hashbrown::HashMap;
+use rand::Rng;
+use serde_columnar::Itertools;
+
+use crate::change::{sort_changes, ChangeId, ChangeStore};
+use crate::codec_v1::{DecoderV1, EncoderV1};
+use crate::decoder::{Decode, DecodeContext, Decoder};
+use crate::diff::Diff;
 use crate::doc::Doc;
+use crate::encoder::{Encode, EncodeContext, Encoder};
 use crate::print_yaml;
+use crate::state::ClientState;
+use crate::store::{DeleteItemStore, ItemDataStore};
 
 pub fn equal_docs(d1: &Doc, d2: &Doc) -> bool {
     let left = serde_json::to_string(d1).unwrap();
@@ -47,14 +60,399 @@ pub fn sync_first_doc(d1: &Doc, d2: &Doc) {
     d1.apply(&diff1);
 }
 
+/// RetryPolicy describes how a transport reconnects or resends after a failure.
+///
+/// The delay grows by `factor` from `base` up to `max`, `jitter` spreads the delays
+/// of many clients so that they do not reconnect at the same time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub base: Duration,
+    pub max: Duration,
+    pub factor: f64,
+    /// give up after this many attempts, None retries forever
+    pub max_attempts: Option<u32>,
+    /// fraction of the delay that is randomized, 0.0 to 1.0
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            base: Duration::from_millis(100),
+            max: Duration::from_secs(30),
+            factor: 2.0,
+            max_attempts: None,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self {
+            base,
+            max,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_factor(mut self, factor: f64) -> Self {
+        self.factor = factor.max(1.0);
+        self
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Delay before the given attempt without jitter, attempts start at 0.
+    /// None when the policy gave up.
+    pub fn delay(&self, attempt: u32) -> Option<Duration> {
+        if self.max_attempts.is_some_and(|max| attempt >= max) {
+            return None;
+        }
+
+        let delay = self.base.as_secs_f64() * self.factor.powi(attempt.min(i32::MAX as u32) as i32);
+        Some(Duration::from_secs_f64(delay.min(self.max.as_secs_f64())))
+    }
+
+    pub fn backoff(&self) -> Backoff {
+        Backoff::new(self.clone())
+    }
+}
+
+/// Backoff keeps the attempt count of a RetryPolicy, reset it after a successful exchange
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    policy: RetryPolicy,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self { policy, attempt: 0 }
+    }
+
+    #[inline]
+    pub fn attempts(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Delay to wait before the next attempt, None when the policy gave up
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        let delay = self.policy.delay(self.attempt)?;
+        self.attempt += 1;
+
+        if self.policy.jitter == 0.0 {
+            return Some(delay);
+        }
+
+        let spread = delay.as_secs_f64() * self.policy.jitter;
+        let jitter = rand::thread_rng().gen_range(-spread..=spread);
+        Some(Duration::from_secs_f64(
+            (delay.as_secs_f64() + jitter).max(0.0),
+        ))
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+/// ResumableSync tracks the document version the remote side acknowledged.
+///
+/// After a reconnect the next update starts from the last acknowledged version,
+/// the acked version can be persisted to resume across restarts.
+#[derive(Debug, Clone, Default)]
+pub struct ResumableSync {
+    acked: ClientState,
+    // version of the update waiting for an ack
+    in_flight: Option<ClientState>,
+}
+
+impl ResumableSync {
+    pub fn new(acked: ClientState) -> Self {
+        Self {
+            acked,
+            in_flight: None,
+        }
+    }
+
+    #[inline]
+    pub fn acked(&self) -> &ClientState {
+        &self.acked
+    }
+
+    #[inline]
+    pub fn is_waiting(&self) -> bool {
+        self.in_flight.is_some()
+    }
+
+    /// Changes the remote side has not acknowledged yet, None when there is nothing to send
+    pub fn next_update(&mut self, doc: &Doc) -> Option<Diff> {
+        let diff = doc.diff(self.acked.clone());
+        if diff.items.is_empty() && diff.deletes.is_empty() {
+            return None;
+        }
+
+        self.in_flight = Some(doc.version());
+        Some(diff)
+    }
+
+    /// The remote side received the last update
+    pub fn ack(&mut self) {
+        if let Some(version) = self.in_flight.take() {
+            self.acked = version;
+        }
+    }
+
+    /// The connection dropped before the ack, the next update resends the changes
+    pub fn reset(&mut self) {
+        self.in_flight = None;
+    }
+}
+
+/// SyncMessage is a message of the two phase sync handshake. Both sides open with
+/// `Step1` carrying their version, the other side answers with the changes it is missing
+/// in `Step2`. Changes made after the handshake travel as `Update`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SyncMessage {
+    Step1(ClientState),
+    Step2(Diff),
+    Update(Diff),
+}
+
+impl SyncMessage {
+    fn tag(&self) -> u8 {
+        match self {
+            SyncMessage::Step1(_) => 0,
+            SyncMessage::Step2(_) => 1,
+            SyncMessage::Update(_) => 2,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut e = EncoderV1::new();
+        self.encode(&mut e, &mut EncodeContext::default());
+        e.buffer()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<SyncMessage, String> {
+        if bytes.is_empty() {
+            return Err("empty sync message".to_string());
+        }
+
+        let mut d = DecoderV1::try_new(bytes.to_vec())?;
+        SyncMessage::decode(&mut d, &DecodeContext::default())
+    }
+}
+
+impl Encode for SyncMessage {
+    fn encode<E: Encoder>(&self, e: &mut E, ctx: &mut EncodeContext) {
+        e.u8(self.tag());
+        match self {
+            SyncMessage::Step1(state) => state.encode(e, ctx),
+            SyncMessage::Step2(diff) | SyncMessage::Update(diff) => diff.encode(e, ctx),
+        }
+    }
+}
+
+impl Decode for SyncMessage {
+    fn decode<D: Decoder>(d: &mut D, ctx: &DecodeContext) -> Result<SyncMessage, String> {
+        match d.u8()? {
+            0 => Ok(SyncMessage::Step1(ClientState::decode(d, ctx)?)),
+            1 => Ok(SyncMessage::Step2(Diff::decode(d, ctx)?)),
+            2 => Ok(SyncMessage::Update(Diff::decode(d, ctx)?)),
+            tag => Err(format!("unknown sync message: {}", tag)),
+        }
+    }
+}
+
+/// SyncProtocol runs the sync handshake of a document with one peer, e.g. a websocket
+/// connection of a server. Feed the received bytes to `receive` and send the returned
+/// messages back, local changes are sent with `update` once the peer's version is known.
+#[derive(Debug, Clone)]
+pub struct SyncProtocol {
+    doc: Doc,
+    // version of the peer as far as it is known, None before its Step1
+    remote: Option<ClientState>,
+    // the peer sent the changes it had
+    synced: bool,
+}
+
+impl SyncProtocol {
+    pub fn new(doc: &Doc) -> Self {
+        Self {
+            doc: doc.clone(),
+            remote: None,
+            synced: false,
+        }
+    }
+
+    /// The message opening the handshake
+    pub fn start(&self) -> SyncMessage {
+        SyncMessage::Step1(self.doc.version())
+    }
+
+    /// Both sides have the changes of the other side
+    #[inline]
+    pub fn is_synced(&self) -> bool {
+        self.synced && self.remote.is_some()
+    }
+
+    /// Handle a message of the peer, returns the messages to send back
+    pub fn handle(&mut self, message: SyncMessage) -> Result<Vec<SyncMessage>, String> {
+        match message {
+            SyncMessage::Step1(state) => {
+                let diff = self.doc.diff(state);
+                self.remote = Some(self.doc.version());
+                Ok(vec![SyncMessage::Step2(diff)])
+            }
+            SyncMessage::Step2(diff) => {
+                self.doc.try_apply(&diff)?;
+                self.synced = true;
+                Ok(vec![])
+            }
+            SyncMessage::Update(diff) => {
+                self.doc.try_apply(&diff)?;
+                Ok(vec![])
+            }
+        }
+    }
+
+    /// Handle the encoded message of the peer, returns the encoded messages to send back
+    pub fn receive(&mut self, bytes: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+        let responses = self.handle(SyncMessage::from_bytes(bytes)?)?;
+        Ok(responses.iter().map(|m| m.to_bytes()).collect())
+    }
+
+    /// Update with the local changes the peer has not seen, None before the handshake
+    /// or when nothing changed
+    pub fn update(&mut self) -> Option<SyncMessage> {
+        let remote = self.remote.as_ref()?;
+        let diff = self.doc.diff(remote.clone());
+        if diff.items.is_empty() && diff.deletes.is_empty() {
+            return None;
+        }
+
+        self.remote = Some(self.doc.version());
+        Some(SyncMessage::Update(diff))
+    }
+}
+
+/// Split a diff into diffs that encode to about `max_size` bytes each.
+///
+/// The diff is split at change boundaries and the parts are in causal order,
+/// they must be applied in the returned order. A single change larger than `max_size`
+/// is not split and is returned as its own part.
+pub fn split_diff(diff: &Diff, max_size: usize) -> Vec<Diff> {
+    // diffs without change ids are applied as a whole
+    if diff.changes.size() <= 1 || encoded_size(diff) <= max_size {
+        return vec![diff.clone()];
+    }
+
+    let (changes, _) = diff.changes();
+
+    let mut parents = HashMap::new();
+    for (id, change) in &changes {
+        let deps = change
+            .deps
+            .iter()
+            .filter(|dep| !id.contains(dep))
+            .filter_map(|dep| diff.changes.get(dep).cloned())
+            .filter(|dep| changes.contains_key(dep))
+            .unique()
+            .collect::<Vec<ChangeId>>();
+        parents.insert(*id, deps);
+    }
+
+    let mut parts = vec![];
+    let mut part = DiffPart::default();
+    for change_id in sort_changes(parents) {
+        let Some(change) = changes.get(&change_id) else {
+            continue;
+        };
+
+        let size = change.items.iter().map(encoded_size).sum::<usize>()
+            + change.delete.iter().map(encoded_size).sum::<usize>();
+        if !part.is_empty() && part.size + size > max_size {
+            parts.push(part.into_diff(diff));
+            part = DiffPart::default();
+        }
+
+        part.changes.insert(change_id);
+        change
+            .items
+            .iter()
+            .for_each(|item| part.items.insert(item.clone()));
+        change
+            .delete
+            .iter()
+            .for_each(|item| part.deletes.insert(item.clone()));
+        part.size += size;
+    }
+
+    if !part.is_empty() {
+        parts.push(part.into_diff(diff));
+    }
+
+    parts
+}
+
+#[derive(Default)]
+struct DiffPart {
+    changes: ChangeStore,
+    items: ItemDataStore,
+    deletes: DeleteItemStore,
+    size: usize,
+}
+
+impl DiffPart {
+    fn is_empty(&self) -> bool {
+        self.changes.size() == 0
+    }
+
+    fn into_diff(self, diff: &Diff) -> Diff {
+        let mut part = Diff::from(
+            diff.doc_id.clone(),
+            diff.created_by.clone(),
+            diff.fields.clone(),
+            self.changes,
+            diff.state.clone(),
+            self.items,
+            self.deletes,
+        );
+        part.features = diff.features.clone();
+
+        part
+    }
+}
+
+pub(crate) fn encoded_size(value: &impl Encode) -> usize {
+    let mut e = EncoderV1::new();
+    value.encode(&mut e, &mut EncodeContext::default());
+    e.buffer().len()
+}
+
 #[cfg(test)]
 mod test {
     use crate::doc::{CloneDeep, Doc};
     use crate::print_yaml;
-    use crate::sync::{equal_docs, sync_docs, SyncDirection};
+    use crate::state::ClientState;
+    use crate::sync::{
+        equal_docs, split_diff, sync_docs, ResumableSync, RetryPolicy, SyncDirection, SyncMessage,
+        SyncProtocol,
+    };
     use rand::prelude::SliceRandom;
     use rand::Rng;
     use serde_json::json;
+    use std::time::Duration;
 
     #[test]
     fn test_sync_lr() {
@@ -280,6 +678,97 @@ mod test {
         // assert!(equal_docs(&doc1, &doc2));
     }
 
+    #[test]
+    fn test_retry_backoff() {
+        let policy = RetryPolicy::new(Duration::from_millis(100), Duration::from_secs(1))
+            .with_max_attempts(6)
+            .with_jitter(0.0);
+        let mut backoff = policy.backoff();
+
+        let delays = std::iter::from_fn(|| backoff.next_delay()).collect::<Vec<_>>();
+        assert_eq!(
+            delays,
+            vec![100, 200, 400, 800, 1000, 1000]
+                .into_iter()
+                .map(Duration::from_millis)
+                .collect::<Vec<_>>()
+        );
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn test_resumable_split_sync() {
+        let d1 = Doc::default();
+        let d2 = d1.clone_deep();
+        d2.update_client();
+
+        let list = d1.list();
+        d1.set("list", list.clone());
+        d1.commit();
+        for i in 0..20 {
+            list.append(d1.atom(format!("item {}", i)));
+            d1.commit();
+        }
+
+        let mut sync = ResumableSync::new(ClientState::default());
+        assert!(sync.next_update(&d1).is_some());
+        assert!(sync.is_waiting());
+
+        // the connection dropped, the update is sent again in small parts
+        sync.reset();
+        let update = sync.next_update(&d1).unwrap();
+        let parts = split_diff(&update, 64);
+        assert!(parts.len() > 1);
+        for part in &parts {
+            d2.apply(part);
+        }
+        sync.ack();
+
+        assert!(equal_docs(&d1, &d2));
+        assert!(sync.next_update(&d1).is_none());
+    }
+
+    #[test]
+    fn test_sync_protocol() {
+        let server = Doc::default();
+        let list = server.list();
+        server.set("list", list.clone());
+        list.append(server.atom("a"));
+        server.commit();
+
+        let client = Doc::new(server.meta.clone());
+        client.update_client();
+        client.set("title", client.atom("notes"));
+        client.commit();
+
+        let mut s = SyncProtocol::new(&server);
+        let mut c = SyncProtocol::new(&client);
+        assert!(c.update().is_none());
+
+        // both sides open the handshake and answer the other side
+        let to_server = c.start().to_bytes();
+        let to_client = s.start().to_bytes();
+        for reply in s.receive(&to_server).unwrap() {
+            c.receive(&reply).unwrap();
+        }
+        for reply in c.receive(&to_client).unwrap() {
+            s.receive(&reply).unwrap();
+        }
+        assert!(s.is_synced() && c.is_synced());
+        assert!(equal_docs(&server, &client));
+
+        list.append(server.atom("b"));
+        server.commit();
+        let update = s.update().unwrap();
+        assert!(s.update().is_none());
+        assert!(c.receive(&update.to_bytes()).unwrap().is_empty());
+        assert_eq!(client.get("list").unwrap().to_json(), list.to_json());
+
+        assert!(SyncMessage::from_bytes(&[9]).is_err());
+    }
+
     // #[test]
     // fn test_inser
 }
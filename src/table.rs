@@ -1,4 +1,5 @@
 use crate::bimapid::ClientId;
+use crate::item::{ItemKind, ItemKindFlags, ItemSide, ItemSideFlags};
 use crate::{Content, Id, ItemData};
 use serde::{Deserialize, Serialize};
 use serde_columnar::columnar;
@@ -24,28 +25,19 @@ struct Data {
     #[columnar(strategy = "DeltaRle")]
     right_id_clock: u32,
     #[columnar(strategy = "Rle")]
-    target_id: ClientId,
-    #[columnar(strategy = "DeltaRle")]
-    target_id_clock: u32,
-    #[columnar(strategy = "Rle")]
-    mover_id: ClientId,
-    #[columnar(strategy = "DeltaRle")]
-    mover_id_clock: u32,
-    #[columnar(strategy = "Rle")]
-    content: String,
+    field: u32,
     #[columnar(strategy = "Rle")]
     flags: u8,
     #[columnar(strategy = "Rle")]
     kind_flag: u8,
 }
 
+/// Table keeps the items column by column, the contents are kept by the codec
 #[columnar(ser, de)]
 #[derive(Default, Debug, Clone)]
 pub(crate) struct Table {
     #[columnar(class = "vec")]
     pub(crate) data: Vec<Data>,
-    // #[columnar(strategy = "Rle")]
-    // pub(crate) content: Vec<String>,
 }
 
 impl Table {
@@ -53,18 +45,12 @@ impl Table {
         let mut data = Data {
             id_client: item.id.client,
             id_clock: item.id.clock,
+            field: item.field.unwrap_or_default(),
             kind_flag,
             flags: flag,
             ..Default::default()
         };
 
-        if !matches!(item.content, Content::Null) {
-            let content = serde_json::to_string(&item.content).unwrap();
-            // println!("content: {}", content);
-            data.content = content;
-            // self.content.push(content);
-        }
-
         if let Some(left_id) = item.left_id {
             data.left_id_client = left_id.client;
             data.left_id_clock = left_id.clock;
@@ -81,12 +67,49 @@ impl Table {
         self.data.push(data);
     }
 
-    pub(crate) fn buffer(&self) -> Vec<u8> {
-        let bytes = serde_columnar::to_vec(&self).unwrap();
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.data.len()
+    }
 
-        println!("size: {}", bytes.len());
+    #[inline]
+    pub(crate) fn has_content(&self, row: usize) -> bool {
+        self.data[row].flags & 0b1000 != 0
+    }
+
+    // the item of the row with the content, the ids are restored like codec_v1 does
+    pub(crate) fn item(&self, row: usize, content: Content) -> Result<ItemData, String> {
+        let data = &self.data[row];
+        let kind: ItemKind = ItemKindFlags::from_bits(data.kind_flag)
+            .ok_or_else(|| format!("table: invalid item kind {}", data.kind_flag))?
+            .into();
+        let side: ItemSide = ItemSideFlags::from_bits(data.flags >> 4)
+            .ok_or_else(|| format!("table: invalid item side {}", data.flags >> 4))?
+            .into();
+
+        let is_root = matches!(content, Content::Doc(_));
+        let has_left = !is_root && data.flags & 0b10 != 0;
+        let has_parent = side.is_none() && !is_root && data.flags & 0b10 == 0;
+
+        Ok(ItemData {
+            id: Id::new(data.id_client, data.id_clock),
+            kind,
+            content,
+            field: (data.flags & 0b100 != 0).then_some(data.field),
+            side,
+            left_id: has_left.then(|| Id::new(data.left_id_client, data.left_id_clock)),
+            parent_id: has_parent.then(|| Id::new(data.parent_id_client, data.parent_id_clock)),
+            right_id: (data.flags & 0b1 != 0)
+                .then(|| Id::new(data.right_id_client, data.right_id_clock)),
+        })
+    }
+
+    pub(crate) fn buffer(&self) -> Vec<u8> {
+        serde_columnar::to_vec(&self).unwrap()
+    }
 
-        bytes
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Table, String> {
+        serde_columnar::from_bytes(bytes).map_err(|e| e.to_string())
     }
 }
 
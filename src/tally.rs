@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+
+use crate::doc::Doc;
+use crate::item::{Linked, StartEnd};
+use crate::ncounter::NCounter;
+use crate::nmap::NMap;
+use crate::types::Type;
+
+/// NTally keeps a counter per key in a root map, e.g. the emoji reactions of a message.
+///
+/// Replicas adding the first count of a key at the same time both create a counter for it,
+/// the map keeps every counter of the key and the tally reads their sum so no count is lost.
+#[derive(Clone, Debug)]
+pub struct NTally {
+    doc: Doc,
+    name: String,
+    map: NMap,
+}
+
+impl NTally {
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn increment(&self, key: &str) -> Result<(), String> {
+        self.add(key, 1)
+    }
+
+    #[inline]
+    pub fn decrement(&self, key: &str) -> Result<(), String> {
+        self.add(key, -1)
+    }
+
+    /// Add to the count of the key, the key gets a counter on the first add
+    pub fn add(&self, key: &str, by: i64) -> Result<(), String> {
+        let counter = match self.map.get(key) {
+            Some(Type::Counter(counter)) => counter,
+            Some(other) => return Err(format!("{} is a {}, not a counter", key, other.kind())),
+            None => {
+                let counter = self.doc.counter();
+                self.map.set(key, counter.clone());
+                counter
+            }
+        };
+
+        counter.increment(by);
+        Ok(())
+    }
+
+    /// Count of the key, zero for a missing key
+    pub fn count(&self, key: &str) -> i64 {
+        self.counters()
+            .get(key)
+            .map(|counters| sum(counters))
+            .unwrap_or_default()
+    }
+
+    /// Keys with a non-zero count
+    pub fn counts(&self) -> BTreeMap<String, i64> {
+        self.counters()
+            .into_iter()
+            .map(|(key, counters)| (key, sum(&counters)))
+            .filter(|(_, count)| *count != 0)
+            .collect()
+    }
+
+    /// Remove the keys counted down to zero, returns the number of removed keys.
+    /// An add concurrent with the removal lands in a removed counter, compact when the
+    /// replicas are in sync.
+    pub fn compact(&self) -> usize {
+        let mut removed = 0;
+        for counters in self.counters().into_values() {
+            if sum(&counters) == 0 {
+                counters.iter().for_each(|counter| counter.delete());
+                removed += 1;
+            }
+        }
+
+        removed
+    }
+
+    // visible counters of every key, the concurrently created ones included
+    fn counters(&self) -> BTreeMap<String, Vec<NCounter>> {
+        let mut counters: BTreeMap<String, Vec<NCounter>> = BTreeMap::new();
+        let mut curr = self.map.start();
+        while let Some(item) = curr {
+            if item.is_visible() {
+                if let (Some(key), Type::Counter(counter)) =
+                    (item.field(), Type::from(item.clone()))
+                {
+                    counters.entry(key).or_default().push(counter);
+                }
+            }
+            curr = item.right();
+        }
+
+        counters
+    }
+}
+
+fn sum(counters: &[NCounter]) -> i64 {
+    counters
+        .iter()
+        .map(|counter| counter.value())
+        .fold(0, i64::wrapping_add)
+}
+
+impl Doc {
+    /// Tally kept in the root map under the name, created when missing
+    pub fn tally(&self, name: &str) -> Result<NTally, String> {
+        let map = match self.get(name) {
+            Some(Type::Map(map)) => map,
+            Some(other) => return Err(format!("{} is a {}, not a map", name, other.kind())),
+            None => {
+                let map = self.map();
+                self.set(name, map.clone());
+                map
+            }
+        };
+
+        Ok(NTally {
+            doc: self.clone(),
+            name: name.to_string(),
+            map,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doc::CloneDeep;
+
+    use super::*;
+
+    #[test]
+    fn test_tally_reactions() {
+        let d1 = Doc::default();
+        let reactions = d1.tally("reactions").unwrap();
+        reactions.increment("👀").unwrap();
+        d1.commit();
+
+        let d2 = d1.clone_deep();
+        d2.update_client();
+        let remote = d2.tally("reactions").unwrap();
+
+        // both replicas react with a new emoji at the same time
+        reactions.increment("👍").unwrap();
+        reactions.decrement("👀").unwrap();
+        d1.commit();
+        remote.increment("👍").unwrap();
+        remote.add("🎉", 2).unwrap();
+        d2.commit();
+
+        d1.apply(&d2.diff(d1.state()));
+        d2.apply(&d1.diff(d2.state()));
+        let counts = BTreeMap::from([("🎉".to_string(), 2), ("👍".to_string(), 2)]);
+        assert_eq!(reactions.counts(), counts);
+        assert_eq!(remote.counts(), counts);
+        assert_eq!(reactions.count("👀"), 0);
+
+        assert_eq!(reactions.compact(), 1);
+        assert_eq!(reactions.compact(), 0);
+        assert_eq!(reactions.counts(), counts);
+        assert!(d1.get("reactions").unwrap().to_json().get("👀").is_none());
+
+        d1.set("title", d1.atom("hi"));
+        assert!(d1.tally("title").is_err());
+    }
+}
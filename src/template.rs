@@ -0,0 +1,163 @@
+use hashbrown::HashMap;
+
+use crate::doc::Doc;
+use crate::item::{Any, Content, ItemKind};
+use crate::types::Type;
+
+// embed key marking an atom as a slot
+const SLOT_KEY: &str = "$slot";
+
+/// Slot is a named placeholder in a template document, insert it with `Doc::atom`
+/// and replace it with `Doc::fill_slots`
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Slot {
+    pub name: String,
+    /// required slots must be filled, unfilled optional slots are removed
+    pub required: bool,
+}
+
+impl Slot {
+    pub fn required(name: impl Into<String>) -> Self {
+        Slot {
+            name: name.into(),
+            required: true,
+        }
+    }
+
+    pub fn optional(name: impl Into<String>) -> Self {
+        Slot {
+            name: name.into(),
+            required: false,
+        }
+    }
+
+    pub(crate) fn from_content(content: &Content) -> Option<Slot> {
+        let Content::Embed(Any::Map(entries)) = content else {
+            return None;
+        };
+
+        let name = entries.iter().find_map(|(key, value)| match value {
+            Any::String(name) if key == SLOT_KEY => Some(name.clone()),
+            _ => None,
+        })?;
+        let required = !entries
+            .iter()
+            .any(|(key, value)| key == "required" && *value == Any::False);
+
+        Some(Slot { name, required })
+    }
+}
+
+impl From<Slot> for Content {
+    fn from(slot: Slot) -> Self {
+        let required = if slot.required { Any::True } else { Any::False };
+        Content::Embed(Any::Map(vec![
+            (SLOT_KEY.to_string(), Any::String(slot.name)),
+            ("required".to_string(), required),
+        ]))
+    }
+}
+
+impl Doc {
+    /// Slots left in the document in document order, a slot used in several places is
+    /// listed once per place
+    pub fn slots(&self) -> Vec<Slot> {
+        let mut slots = vec![];
+        collect_slots(&Type::Map(self.root.clone()), &mut slots);
+        slots.into_iter().map(|(slot, _)| slot).collect()
+    }
+
+    /// Replace the slots with atoms of the values in a single change, unfilled optional
+    /// slots are removed. Nothing is changed when a required slot has no value.
+    /// Returns the number of filled slots.
+    pub fn fill_slots(&self, values: &HashMap<String, String>) -> Result<usize, String> {
+        let mut slots = vec![];
+        collect_slots(&Type::Map(self.root.clone()), &mut slots);
+
+        let mut missing: Vec<&str> = slots
+            .iter()
+            .filter(|(slot, _)| slot.required && !values.contains_key(&slot.name))
+            .map(|(slot, _)| slot.name.as_str())
+            .collect();
+        if !missing.is_empty() {
+            missing.sort();
+            missing.dedup();
+            return Err(format!("missing required slots: {}", missing.join(", ")));
+        }
+
+        let mut filled = 0;
+        for (slot, item) in &slots {
+            if let Some(value) = values.get(&slot.name) {
+                let parent = item.parent().ok_or("slot without a parent")?;
+                match parent {
+                    Type::Map(_) => {
+                        let field = item.field().ok_or("slot without a field")?;
+                        parent.set(field, self.atom(value.as_str()));
+                    }
+                    _ => item.insert_before(self.atom(value.as_str()).into()),
+                }
+                filled += 1;
+            }
+            item.delete();
+        }
+        self.commit();
+
+        Ok(filled)
+    }
+}
+
+fn collect_slots(container: &Type, slots: &mut Vec<(Slot, Type)>) {
+    let children = match container {
+        Type::Map(map) => {
+            let mut keys = map.keys();
+            keys.sort();
+            keys.into_iter().filter_map(|key| map.get(key)).collect()
+        }
+        Type::List(_) => container.item_ref().borrow().as_list(),
+        _ => vec![],
+    };
+
+    for child in children {
+        match child.kind() {
+            ItemKind::Map | ItemKind::List => collect_slots(&child, slots),
+            ItemKind::Atom => {
+                if let Some(slot) = Slot::from_content(&child.content()) {
+                    slots.push((slot, child));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill_slots() {
+        let doc = Doc::default();
+        doc.set("party", doc.atom(Slot::required("party")));
+        doc.set("note", doc.atom(Slot::optional("note")));
+        let items = doc.list();
+        doc.set("items", items.clone());
+        items.append(doc.atom(Slot::required("party")));
+        items.append(doc.atom(Slot::required("item")));
+        doc.commit();
+        assert_eq!(doc.slots().len(), 4);
+
+        let mut values = HashMap::new();
+        values.insert("party".to_string(), "ACME".to_string());
+        let err = doc.fill_slots(&values).unwrap_err();
+        assert!(err.contains("item"));
+        assert_eq!(doc.slots().len(), 4);
+
+        values.insert("item".to_string(), "anvil".to_string());
+        assert_eq!(doc.fill_slots(&values), Ok(3));
+        assert!(doc.slots().is_empty());
+        assert_eq!(doc.get("party").unwrap().to_json(), "ACME");
+        assert!(doc.get("note").is_none());
+        assert_eq!(items.size(), 2);
+        assert_eq!(items.get(1usize).unwrap().to_json(), "anvil");
+    }
+}
@@ -0,0 +1,439 @@
+//! Deterministic multi-client simulator to fuzz documents with.
+//!
+//! A `Simulator` runs random ops on virtual clients holding replicas of the same document,
+//! ships the committed changes as encoded diffs delivered in a random order and checks
+//! that all replicas converge. A run is replayed exactly from its seed.
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use uuid::Uuid;
+
+use crate::codec_v1::{DecoderV1, EncoderV1};
+use crate::decoder::{Decode, DecodeContext};
+use crate::diff::Diff;
+use crate::doc::{CloneDeep, Doc};
+use crate::encoder::{Encode, EncodeContext, Encoder};
+use crate::id::WithId;
+use crate::item::ItemKind;
+use crate::mark::Mark;
+use crate::nmap::NMap;
+use crate::text_mark::Expand;
+use crate::types::Type;
+use crate::Client;
+
+/// SimOp edits the document of a virtual client with the random source of the run
+pub type SimOp = Box<dyn Fn(&Doc, &mut StdRng)>;
+
+/// SimConfig describes a simulation run
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimConfig {
+    pub clients: usize,
+    pub ops: usize,
+    /// a client ships its changes every this many ops on average
+    pub sync_every: usize,
+    /// seed of the ops and the delivery order, a failing run is replayed with the same seed
+    pub seed: u64,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            clients: 3,
+            ops: 1_000,
+            sync_every: 16,
+            seed: 0,
+        }
+    }
+}
+
+impl SimConfig {
+    pub fn new(clients: usize, ops: usize) -> Self {
+        Self {
+            clients: clients.max(1),
+            ops,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_sync_every(mut self, sync_every: usize) -> Self {
+        self.sync_every = sync_every.max(1);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+/// SimReport sums up a simulation run
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct SimReport {
+    pub ops: usize,
+    pub commits: usize,
+    /// encoded diffs sent to the other clients
+    pub messages: usize,
+    /// messages that arrived after a message sent later
+    pub reordered: usize,
+}
+
+// an encoded diff on its way to a client
+struct Message {
+    to: usize,
+    seq: usize,
+    buf: Vec<u8>,
+}
+
+/// Simulator runs random ops on virtual clients and asserts their convergence. Without
+/// ops of its own it runs `random_insert`, `random_delete`, `random_move` and
+/// `random_mark` on every list, map and text of the document.
+pub struct Simulator {
+    config: SimConfig,
+    setup: Box<dyn Fn(&Doc)>,
+    ops: Vec<SimOp>,
+}
+
+impl Simulator {
+    pub fn new(config: SimConfig) -> Self {
+        Self {
+            config,
+            setup: Box::new(|doc| {
+                doc.set("text", doc.text());
+                doc.set("list", doc.list());
+                doc.set("map", doc.map());
+            }),
+            ops: vec![],
+        }
+    }
+
+    /// Build the initial document the clients start from, replaces the default text,
+    /// list and map
+    pub fn with_setup(mut self, setup: impl Fn(&Doc) + 'static) -> Self {
+        self.setup = Box::new(setup);
+        self
+    }
+
+    /// Add an op picked at random with the others
+    pub fn with_op(mut self, op: impl Fn(&Doc, &mut StdRng) + 'static) -> Self {
+        self.ops.push(Box::new(op));
+        self
+    }
+
+    pub fn run(&self) -> Result<SimReport, String> {
+        let mut report = SimReport::default();
+        let mut rng = StdRng::seed_from_u64(self.config.seed);
+        let docs = self.clients(&mut rng);
+        let mut queue: Vec<Message> = vec![];
+
+        for _ in 0..self.config.ops {
+            let client = rng.gen_range(0..docs.len());
+            self.random_op(&docs[client], &mut rng);
+            report.ops += 1;
+
+            if rng.gen_ratio(1, 4) {
+                docs[client].commit();
+                report.commits += 1;
+            }
+
+            if rng.gen_ratio(1, self.config.sync_every.max(1) as u32) {
+                docs[client].commit();
+                self.send(&docs, client, &mut queue, &mut report);
+            }
+
+            // deliver some of the messages in flight in a random order
+            if !queue.is_empty() && rng.gen_ratio(1, 2) {
+                let index = rng.gen_range(0..queue.len());
+                let message = queue.swap_remove(index);
+                deliver(&docs, message, &queue, &mut report)?;
+            }
+        }
+
+        // flush the changes, every client ends up with every change
+        for (client, doc) in docs.iter().enumerate() {
+            doc.commit();
+            self.send(&docs, client, &mut queue, &mut report);
+        }
+        queue.shuffle(&mut rng);
+        while let Some(message) = queue.pop() {
+            deliver(&docs, message, &queue, &mut report)?;
+        }
+
+        self.check(&docs)?;
+
+        Ok(report)
+    }
+
+    // clients with replicas of the document built by the setup. The clients break the ties
+    // of concurrent edits, they come from the seed so that a run can be replayed
+    fn clients(&self, rng: &mut StdRng) -> Vec<Doc> {
+        let client = |rng: &mut StdRng| Client::from(Uuid::from_u128(rng.gen()));
+        let doc = Doc::default();
+        doc.store.borrow_mut().update_client(&client(rng), 1);
+        (self.setup)(&doc);
+        doc.commit();
+
+        let mut docs = vec![doc];
+        for _ in 1..self.config.clients.max(1) {
+            let replica = docs[0].clone_deep();
+            replica.store.borrow_mut().update_client(&client(rng), 1);
+            docs.push(replica);
+        }
+
+        docs
+    }
+
+    fn random_op(&self, doc: &Doc, rng: &mut StdRng) {
+        if self.ops.is_empty() {
+            match rng.gen_range(0..8) {
+                0..=3 => random_insert(doc, rng),
+                4 | 5 => random_delete(doc, rng),
+                6 => random_move(doc, rng),
+                _ => random_mark(doc, rng),
+            }
+        } else {
+            let op = &self.ops[rng.gen_range(0..self.ops.len())];
+            op(doc, rng);
+        }
+    }
+
+    // queue the changes of the client the other clients have not seen
+    fn send(&self, docs: &[Doc], from: usize, queue: &mut Vec<Message>, report: &mut SimReport) {
+        for to in (0..docs.len()).filter(|to| *to != from) {
+            let diff = docs[from].diff(docs[to].version());
+            if diff.items.is_empty() && diff.deletes.is_empty() {
+                continue;
+            }
+
+            let mut e = EncoderV1::new();
+            diff.encode(&mut e, &mut EncodeContext::default());
+            queue.push(Message {
+                to,
+                seq: report.messages,
+                buf: e.buffer(),
+            });
+            report.messages += 1;
+        }
+    }
+
+    fn check(&self, docs: &[Doc]) -> Result<(), String> {
+        let seed = self.config.seed;
+        for (index, doc) in docs.iter().enumerate() {
+            doc.check_invariants().map_err(|errors| {
+                format!(
+                    "invariants of client {} are broken (seed {}):\n{}",
+                    index,
+                    seed,
+                    errors.join("\n")
+                )
+            })?;
+            // the item ids are local to each replica, compare the content
+            if docs[0].to_json() != doc.to_json() {
+                return Err(format!(
+                    "client {} diverged from client 0 (seed {}):\n{}\n{}",
+                    index,
+                    seed,
+                    docs[0].to_json(),
+                    doc.to_json()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn deliver(
+    docs: &[Doc],
+    message: Message,
+    queue: &[Message],
+    report: &mut SimReport,
+) -> Result<(), String> {
+    if queue
+        .iter()
+        .any(|m| m.to == message.to && m.seq < message.seq)
+    {
+        report.reordered += 1;
+    }
+
+    let diff = Diff::decode(
+        &mut DecoderV1::try_new(message.buf)?,
+        &DecodeContext::default(),
+    )?;
+    docs[message.to].apply(&diff);
+
+    Ok(())
+}
+
+/// Visible lists, maps and texts of the document, the root map included
+pub fn containers(doc: &Doc) -> Vec<Type> {
+    let mut containers = vec![];
+    let mut stack = vec![Type::from(doc.root.clone())];
+    while let Some(container) = stack.pop() {
+        let children = match &container {
+            Type::Map(map) => values(map),
+            Type::List(_) => container.item_ref().borrow().items(),
+            _ => vec![],
+        };
+        stack.extend(children.into_iter().filter(|child| {
+            matches!(
+                child.kind(),
+                ItemKind::List | ItemKind::Map | ItemKind::Text
+            )
+        }));
+        containers.push(container);
+    }
+
+    containers
+}
+
+/// Insert an atom into a list or a map, or a string into a text
+pub fn random_insert(doc: &Doc, rng: &mut StdRng) {
+    let Some(container) = pick(&containers(doc), rng).cloned() else {
+        return;
+    };
+
+    match container.kind() {
+        ItemKind::Text => {
+            let content: String = (0..rng.gen_range(1..6))
+                .map(|_| rng.gen_range(b'a'..=b'z') as char)
+                .collect();
+            container.insert(rng.gen_range(0..=container.size()), doc.string(content));
+        }
+        ItemKind::List => {
+            container.insert(
+                rng.gen_range(0..=container.size()),
+                doc.atom(rng.gen::<u32>()),
+            );
+        }
+        // the root keys are left to the setup
+        ItemKind::Map if container.id() != doc.root.id() => {
+            container.set(
+                format!("key{}", rng.gen_range(0..8)),
+                doc.atom(rng.gen::<u32>()),
+            );
+        }
+        _ => {}
+    }
+}
+
+/// Delete an atom or a string
+pub fn random_delete(doc: &Doc, rng: &mut StdRng) {
+    let leaves = leaves(doc);
+    if let Some(leaf) = pick(&leaves, rng) {
+        leaf.delete();
+    }
+}
+
+/// Move an atom to a random list or map
+pub fn random_move(doc: &Doc, rng: &mut StdRng) {
+    let atoms: Vec<Type> = leaves(doc)
+        .into_iter()
+        .filter(|leaf| leaf.kind() == ItemKind::Atom)
+        .collect();
+    let targets: Vec<Type> = containers(doc)
+        .into_iter()
+        .filter(|c| c.kind() == ItemKind::List || c.kind() == ItemKind::Map)
+        .filter(|c| c.id() != doc.root.id())
+        .collect();
+    let (Some(atom), Some(target)) = (pick(&atoms, rng), pick(&targets, rng)) else {
+        return;
+    };
+
+    match target.kind() {
+        ItemKind::List => atom.move_to(target.clone(), rng.gen_range(0..=target.size())),
+        _ => atom.move_to_key(target.clone(), &format!("key{}", rng.gen_range(0..8))),
+    }
+}
+
+/// Mark a random range of a text
+pub fn random_mark(doc: &Doc, rng: &mut StdRng) {
+    let texts: Vec<Type> = containers(doc)
+        .into_iter()
+        .filter(|c| c.kind() == ItemKind::Text && c.size() > 0)
+        .collect();
+    let Some(text) = pick(&texts, rng).and_then(|text| text.as_text()) else {
+        return;
+    };
+
+    let start = rng.gen_range(0..text.size());
+    let end = rng.gen_range(start + 1..=text.size());
+    let mark = match rng.gen_range(0..3) {
+        0 => Mark::Bold,
+        1 => Mark::Italic,
+        _ => Mark::Color(["red", "blue"][rng.gen_range(0..2)].into()),
+    };
+    // the range is inside the text, a failed mark leaves the text as it was
+    let _ = text.mark(start, end, mark, Expand::After);
+}
+
+// visible atoms and strings of the containers
+fn leaves(doc: &Doc) -> Vec<Type> {
+    containers(doc)
+        .iter()
+        .flat_map(|container| match container {
+            Type::Map(map) if container.id() != doc.root.id() => values(map),
+            Type::List(_) | Type::Text(_) => container.item_ref().borrow().items(),
+            _ => vec![],
+        })
+        .filter(|item| matches!(item.kind(), ItemKind::Atom | ItemKind::String))
+        .collect()
+}
+
+// the values of a map in key order, maps iterate their keys in a random order
+fn values(map: &NMap) -> Vec<Type> {
+    let mut entries: Vec<_> = map.visible_children().into_iter().collect();
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+    entries.into_iter().map(|(_, value)| value).collect()
+}
+
+fn pick<'a>(items: &'a [Type], rng: &mut StdRng) -> Option<&'a Type> {
+    if items.is_empty() {
+        None
+    } else {
+        items.get(rng.gen_range(0..items.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simulator_converges() {
+        for seed in 0..4 {
+            let config = SimConfig::new(3, 300).with_seed(seed).with_sync_every(8);
+            let report = Simulator::new(config).run().unwrap();
+            assert_eq!(report.ops, 300);
+            assert!(report.messages > 0);
+        }
+
+        // the same seed replays the same run
+        let run = |seed| {
+            Simulator::new(SimConfig::new(2, 100).with_seed(seed))
+                .run()
+                .unwrap()
+        };
+        assert_eq!(run(9), run(9));
+
+        // a custom composition with its own op
+        let config = SimConfig::new(2, 100).with_seed(3);
+        let report = Simulator::new(config)
+            .with_setup(|doc| {
+                let board = doc.map();
+                doc.set("board", board.clone());
+                board.set("cards", doc.list());
+            })
+            .with_op(random_insert)
+            .with_op(|doc, rng| {
+                if let Some(cards) = doc.get("board").and_then(|b| b.get("cards")) {
+                    let card = doc.map();
+                    cards.insert(rng.gen_range(0..=cards.size()), card.clone());
+                    card.set("title", doc.text());
+                }
+            })
+            .run()
+            .unwrap();
+        assert_eq!(report.ops, 100);
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::BTreeMap;
+
+use crate::doc::Doc;
+use crate::id::{Id, IdRange, WithId, WithIdRange};
+use crate::item::{Content, ItemKind};
+use crate::ntext::NText;
+use crate::state::ClientState;
+use crate::types::Type;
+
+/// TextChange is a changed range in the visible content of a text.
+///
+/// The offsets are only valid for the current text content, `left` and `right` are the ids of the
+/// characters around the range and stay valid while the text changes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextChange {
+    /// Id of the text the change belongs to
+    pub text: Id,
+    /// start offset of the changed range
+    pub start: u32,
+    /// end offset of the changed range (exclusive), equal to start for a pure delete
+    pub end: u32,
+    /// id of the character before the range
+    pub left: Option<Id>,
+    /// id of the character after the range
+    pub right: Option<Id>,
+    /// content of the changed range
+    pub content: String,
+}
+
+/// TextChangeHook receives the changed ranges of a text,
+/// spellcheckers and linters can use it to re-analyze only the changed parts.
+pub trait TextChangeHook {
+    fn on_text_change(&mut self, text: &NText, changes: &[TextChange]);
+}
+
+impl<F: FnMut(&NText, &[TextChange])> TextChangeHook for F {
+    fn on_text_change(&mut self, text: &NText, changes: &[TextChange]) {
+        self(text, changes)
+    }
+}
+
+/// TextChangeTracker remembers the document version seen by the hook.
+/// Call `process` after every commit or apply to feed the hook with the text changes since the last call.
+#[derive(Debug, Clone, Default)]
+pub struct TextChangeTracker {
+    version: ClientState,
+}
+
+impl TextChangeTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start tracking from the current document version, existing content is not reported
+    pub fn from_doc(doc: &Doc) -> Self {
+        Self {
+            version: doc.version(),
+        }
+    }
+
+    /// Collect the text changes since the last call and pass them to the hook
+    pub fn process(&mut self, doc: &Doc, hook: &mut impl TextChangeHook) {
+        let diff = doc.diff(self.version.clone());
+        self.version = doc.version();
+
+        let mut texts = BTreeMap::new();
+        let mut inserted = vec![];
+        let mut deleted = vec![];
+
+        for (_, store) in diff.items.iter() {
+            for (_, data) in store.iter() {
+                if data.kind != ItemKind::String {
+                    continue;
+                }
+
+                if let Content::String(s) = &data.content {
+                    inserted.push(data.id.range(s.len() as u32));
+                }
+                // the diff leaves out the parent of an item with a left origin
+                let parent = doc.find_by_id(&data.id).and_then(|item| item.parent());
+                if let Some(Type::Text(text)) = parent {
+                    texts.insert(text.id(), text);
+                }
+            }
+        }
+
+        for (_, store) in diff.deletes.iter() {
+            for (_, item) in store.iter() {
+                deleted.push(*item.range());
+                let parent = doc.find_by_id(&item.target()).and_then(|item| item.parent());
+                if let Some(Type::Text(text)) = parent {
+                    texts.insert(text.id(), text);
+                }
+            }
+        }
+
+        for text in texts.values() {
+            let changes = text_changes(text, &inserted, &deleted);
+            if !changes.is_empty() {
+                hook.on_text_change(text, &changes);
+            }
+        }
+    }
+}
+
+// find the changed ranges of the text for the inserted and deleted id ranges
+fn text_changes(text: &NText, inserted: &[IdRange], deleted: &[IdRange]) -> Vec<TextChange> {
+    text.thaw();
+    let mut content = String::new();
+    // visible string items with their offset in the text
+    let mut visible: Vec<(u32, IdRange)> = vec![];
+    let mut ranges: Vec<(u32, u32)> = vec![];
+
+    let mut offset = 0;
+    for item in text.borrow().all_items() {
+        if !item.kind().is_string() {
+            continue;
+        }
+
+        let range = item.range();
+        if !item.is_visible() {
+            if deleted.iter().any(|d| overlap(&range, d).is_some()) {
+                ranges.push((offset, offset));
+            }
+            continue;
+        }
+
+        for other in inserted {
+            if let Some((start, end)) = overlap(&range, other) {
+                ranges.push((
+                    offset + start - range.start,
+                    offset + end - range.start + 1,
+                ));
+            }
+        }
+
+        if let Content::String(s) = item.content() {
+            content.push_str(&s);
+        }
+        visible.push((offset, range));
+        offset += range.size();
+    }
+
+    ranges.sort();
+
+    // merge the overlapping and touching ranges
+    let mut merged: Vec<(u32, u32)> = vec![];
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    let char_id = |offset: u32| -> Option<Id> {
+        visible
+            .iter()
+            .find(|(start, range)| offset >= *start && offset < start + range.size())
+            .map(|(start, range)| Id::new(range.client, range.start + offset - start))
+    };
+
+    merged
+        .into_iter()
+        .map(|(start, end)| TextChange {
+            text: text.id(),
+            start,
+            end,
+            left: start.checked_sub(1).and_then(char_id),
+            right: char_id(end),
+            content: content
+                .get(start as usize..end as usize)
+                .unwrap_or_default()
+                .to_string(),
+        })
+        .collect()
+}
+
+// clock range shared by two id ranges of the same client
+fn overlap(a: &IdRange, b: &IdRange) -> Option<(u32, u32)> {
+    if a.client != b.client {
+        return None;
+    }
+
+    let start = a.start.max(b.start);
+    let end = a.end.min(b.end);
+
+    (start <= end).then_some((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_change_ranges() {
+        let doc = Doc::default();
+        let text = doc.text();
+        doc.set("text", text.clone());
+        text.append(doc.string("hello world"));
+        doc.commit();
+
+        let mut tracker = TextChangeTracker::from_doc(&doc);
+
+        text.insert(5, doc.string(" big"));
+        doc.commit();
+
+        let mut changes = vec![];
+        tracker.process(&doc, &mut |_: &NText, c: &[TextChange]| {
+            changes.extend_from_slice(c)
+        });
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!((changes[0].start, changes[0].end), (5, 9));
+        assert_eq!(changes[0].content, " big");
+        assert!(changes[0].left.is_some());
+        assert!(changes[0].right.is_some());
+
+        // nothing changed since the last call
+        let mut calls = 0;
+        tracker.process(&doc, &mut |_: &NText, _: &[TextChange]| calls += 1);
+        assert_eq!(calls, 0);
+    }
+}
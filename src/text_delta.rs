@@ -0,0 +1,295 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::id::ClockTick;
+use crate::item::ItemIterator;
+use crate::mark::Mark;
+use crate::nstring::NString;
+use crate::ntext::NText;
+use crate::text_mark::{is_removal, removal, Expand};
+use crate::types::Type;
+
+/// DeltaOp is an operation of a Quill delta. The lengths are in the unit of the text api,
+/// convert the offsets of a utf-16 editor with `NText::convert_offset`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DeltaOp {
+    Insert {
+        insert: String,
+        #[serde(default, skip_serializing_if = "Map::is_empty")]
+        attributes: Map<String, Value>,
+    },
+    Retain {
+        retain: u32,
+        #[serde(default, skip_serializing_if = "Map::is_empty")]
+        attributes: Map<String, Value>,
+    },
+    Delete {
+        delete: u32,
+    },
+}
+
+// delta attribute of the mark
+fn mark_attribute(mark: &Mark) -> (String, Value) {
+    let value = match mark {
+        Mark::Color(value) | Mark::Background(value) | Mark::Link(value) => value.as_str().into(),
+        Mark::Custom(_, json) => serde_json::from_str(json).unwrap_or(json.as_str().into()),
+        Mark::Id(id) => (*id).into(),
+        Mark::None => Value::Null,
+        _ => true.into(),
+    };
+
+    (mark.key(), value)
+}
+
+// mark of the delta attribute, null and false clear the marks with the name
+fn attribute_mark(name: &str, value: &Value) -> Mark {
+    match (name, value) {
+        (_, Value::Null | Value::Bool(false)) => removal(name),
+        ("bold", Value::Bool(true)) => Mark::Bold,
+        ("italic", Value::Bool(true)) => Mark::Italic,
+        ("underline", Value::Bool(true)) => Mark::Underline,
+        ("strikethrough", Value::Bool(true)) => Mark::StrikeThrough,
+        ("code", Value::Bool(true)) => Mark::Code,
+        ("subscript", Value::Bool(true)) => Mark::Subscript,
+        ("superscript", Value::Bool(true)) => Mark::Superscript,
+        ("color", Value::String(color)) => Mark::Color(color.clone()),
+        ("background", Value::String(color)) => Mark::Background(color.clone()),
+        ("link", Value::String(url)) => Mark::Link(url.clone()),
+        ("id", Value::Number(id)) if id.as_u64().is_some_and(|id| id <= u32::MAX as u64) => {
+            Mark::Id(id.as_u64().unwrap() as u32)
+        }
+        _ => Mark::Custom(name.to_string(), value.to_string()),
+    }
+}
+
+impl NText {
+    /// Content of the text as the insert operations of a Quill delta, one per run of
+    /// characters with the same marks
+    pub fn to_delta(&self) -> Vec<DeltaOp> {
+        let content = self.text_content();
+        let bytes = content.as_bytes();
+
+        self.mark_runs()
+            .into_iter()
+            .map(|(range, marks)| DeltaOp::Insert {
+                insert: String::from_utf8_lossy(&bytes[range.start as usize..range.end as usize])
+                    .into_owned(),
+                attributes: marks.iter().map(mark_attribute).collect(),
+            })
+            .collect()
+    }
+
+    /// Apply the operations of a Quill delta. Strings are split at the edges of the deleted
+    /// ranges only, and marks are added only where the attributes change the formatting.
+    /// Inserted text gets exactly the attributes of its operation. A delta reaching past
+    /// the end of the text is an error and nothing is applied.
+    pub fn apply_delta(&self, ops: &[DeltaOp]) -> Result<(), String> {
+        self.check_delta(ops)?;
+
+        let mut offset = 0;
+        for op in ops {
+            match op {
+                DeltaOp::Retain { retain, attributes } => {
+                    let range = offset..offset + retain;
+                    self.format(range.clone(), attributes, false)?;
+                    offset = range.end;
+                }
+                DeltaOp::Insert { insert, attributes } => {
+                    if insert.is_empty() {
+                        continue;
+                    }
+                    let string = self.new_string(insert)?;
+                    self.insert(offset, string);
+                    let range = offset..offset + insert.len() as u32;
+                    self.format(range.clone(), attributes, true)?;
+                    offset = range.end;
+                }
+                DeltaOp::Delete { delete } => self.delete_at(offset, *delete),
+            }
+        }
+
+        Ok(())
+    }
+
+    // the operations stay inside the text
+    fn check_delta(&self, ops: &[DeltaOp]) -> Result<(), String> {
+        let mut size = self.size();
+        let mut offset = 0;
+        for op in ops {
+            match op {
+                DeltaOp::Retain { retain: len, .. } | DeltaOp::Delete { delete: len } => {
+                    if offset + len > size {
+                        return Err(format!(
+                            "delta reaches past the end of the text at {}",
+                            offset + len
+                        ));
+                    }
+                    if let DeltaOp::Delete { .. } = op {
+                        size -= len;
+                    } else {
+                        offset += len;
+                    }
+                }
+                DeltaOp::Insert { insert, .. } => {
+                    size += insert.len() as u32;
+                    offset += insert.len() as u32;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // mark the range with the attributes, `exact` also clears the marks without an attribute
+    fn format(
+        &self,
+        range: Range<u32>,
+        attributes: &Map<String, Value>,
+        exact: bool,
+    ) -> Result<(), String> {
+        let runs: Vec<Vec<Mark>> = self
+            .mark_runs()
+            .into_iter()
+            .filter(|(run, _)| run.start < range.end && range.start < run.end)
+            .map(|(_, marks)| marks)
+            .collect();
+        let anywhere = |name: &str| runs.iter().flatten().any(|mark| mark.key() == name);
+
+        let mut marks: Vec<Mark> = attributes
+            .iter()
+            .map(|(name, value)| attribute_mark(name, value))
+            .collect();
+        if exact {
+            let mut names: Vec<String> = runs.iter().flatten().map(|mark| mark.key()).collect();
+            names.sort();
+            names.dedup();
+            names
+                .into_iter()
+                .filter(|name| !attributes.contains_key(name))
+                .for_each(|name| marks.push(removal(name)));
+        }
+
+        for mark in marks {
+            let changes = match is_removal(&mark) {
+                true => anywhere(&mark.key()),
+                false => !runs.iter().all(|marks| marks.contains(&mark)),
+            };
+            if changes {
+                let expand = Expand::default_for(&mark);
+                self.mark(range.start, range.end, mark, expand)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn new_string(&self, content: &str) -> Result<NString, String> {
+        let store = self
+            .store
+            .upgrade()
+            .ok_or("the text is not in a document")?;
+        let mut store = store.borrow_mut();
+        let id = store.next_id_range(content.len() as ClockTick).start_id();
+        let string = NString::new(id, content.to_string(), self.store.clone());
+        store.insert(string.clone());
+
+        Ok(string)
+    }
+
+    // delete the visible characters offset..offset + len, the strings at the edges are split
+    fn delete_at(&self, offset: u32, len: u32) {
+        self.thaw();
+        let end = offset + len;
+        let items: Vec<Type> = self.visible_item_iter().map(Type::from).collect();
+        let mut start = 0;
+        for item in items {
+            let (from, to) = (start, start + item.size());
+            start = to;
+            if to <= offset || from >= end {
+                continue;
+            }
+
+            let mut piece = item;
+            if from < offset {
+                piece = piece.split(offset - from).1;
+            }
+            let from = from.max(offset);
+            if to > end {
+                piece = piece.split(end - from).0;
+            }
+            piece.item_ref().delete(piece.size());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::doc::Doc;
+    use crate::state::ClientState;
+
+    use super::*;
+
+    #[test]
+    fn test_text_delta() {
+        let doc = Doc::default();
+        let text = doc.text();
+        doc.set("text", text.clone());
+        text.append(doc.string("hello world"));
+        text.mark(0, 5, Mark::Bold, Expand::After).unwrap();
+        doc.commit();
+
+        assert_eq!(
+            serde_json::to_value(text.to_delta()).unwrap(),
+            json!([
+                { "insert": "hello", "attributes": { "bold": true } },
+                { "insert": " world" },
+            ])
+        );
+
+        let ops: Vec<DeltaOp> = serde_json::from_value(json!([
+            { "retain": 5, "attributes": { "bold": null } },
+            { "insert": "!", "attributes": { "italic": true } },
+            { "retain": 1 },
+            { "delete": 5 },
+            { "insert": "there", "attributes": { "link": "https://nitro.dev" } },
+        ]))
+        .unwrap();
+        text.apply_delta(&ops).unwrap();
+        doc.commit();
+
+        let expected = json!([
+            { "insert": "hello" },
+            { "insert": "!", "attributes": { "italic": true } },
+            { "insert": " " },
+            { "insert": "there", "attributes": { "link": "https://nitro.dev" } },
+        ]);
+        assert_eq!(text.text_content(), "hello! there");
+        assert_eq!(serde_json::to_value(text.to_delta()).unwrap(), expected);
+
+        // nothing is applied when the delta does not fit the text
+        let ops = vec![
+            DeltaOp::Insert {
+                insert: "x".to_string(),
+                attributes: Map::new(),
+            },
+            DeltaOp::Delete { delete: 20 },
+        ];
+        assert!(text.apply_delta(&ops).is_err());
+        assert_eq!(text.text_content(), "hello! there");
+
+        let remote = Doc::new(doc.meta.clone());
+        remote.apply(&doc.diff(ClientState::default()));
+        let Some(Type::Text(remote_text)) = remote.get("text") else {
+            panic!("text not synced");
+        };
+        assert_eq!(
+            serde_json::to_value(remote_text.to_delta()).unwrap(),
+            expected
+        );
+    }
+}
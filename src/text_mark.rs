@@ -0,0 +1,320 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use crate::annotation::char_id;
+use crate::id::{IdRange, WithId};
+use crate::item::{Content, ItemIterator, Linked};
+use crate::mark::{Mark, MarkAnchor, MarkContent};
+use crate::nmark::NMark;
+use crate::ntext::NText;
+use crate::types::Type;
+
+/// Expand decides whether the text inserted at an edge of a marked range takes the mark,
+/// like the mark expansion of PeriText
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub enum Expand {
+    /// inserts at either edge stay unmarked, e.g. links
+    None,
+    /// inserts before the first marked character take the mark
+    Before,
+    /// inserts after the last marked character take the mark, e.g. bold or italic
+    #[default]
+    After,
+    Both,
+}
+
+impl Expand {
+    /// Usual expansion of the mark, links and ids do not expand
+    pub fn default_for(mark: &Mark) -> Self {
+        match mark {
+            Mark::Link(_) | Mark::Id(_) => Expand::None,
+            _ => Expand::After,
+        }
+    }
+}
+
+// positions of the characters and the gaps between them, deleted characters included.
+// the character at full index k is at 4k + 2, the gap before it at 4k + 1 and after it
+// at 4k + 3, a character inserted between the characters k - 1 and k lands at 4k.
+struct TextLayout {
+    // id range, full index of the first character and visibility of every item
+    items: Vec<(IdRange, i64, bool)>,
+    len: i64,
+}
+
+impl TextLayout {
+    fn new(text: &NText) -> Self {
+        text.thaw();
+        let mut items = vec![];
+        let mut len = 0;
+        for item in text.item_iter() {
+            let size = item.size();
+            if size > 0 {
+                items.push((item.id().range(size), len, item.is_visible()));
+            }
+            len += size as i64;
+        }
+
+        Self { items, len }
+    }
+
+    // full index of the visible character at the offset
+    fn full_index(&self, offset: u32) -> Option<i64> {
+        let mut start = 0;
+        for (range, full, visible) in &self.items {
+            if !visible {
+                continue;
+            }
+            let size = range.size();
+            if offset < start + size {
+                return Some(full + (offset - start) as i64);
+            }
+            start += size;
+        }
+
+        None
+    }
+
+    // position of a mark edge, None when the character is not known yet
+    fn anchor(&self, anchor: &MarkAnchor, is_end: bool) -> Option<i64> {
+        let Some(id) = &anchor.id else {
+            return Some(if is_end { 4 * self.len + 1 } else { -1 });
+        };
+        let (range, full, _) = self.items.iter().find(|(range, _, _)| range.contains(id))?;
+        let index = full + (id.clock - range.start) as i64;
+
+        Some(if anchor.after {
+            4 * index + 3
+        } else {
+            4 * index + 1
+        })
+    }
+
+    // positions of the first and the last character of the range, for a collapsed range
+    // the position a character inserted at the offset lands at, see NText::insert
+    fn span(&self, range: &Range<u32>, size: u32) -> Option<(i64, i64)> {
+        if range.start > range.end || range.end > size {
+            return None;
+        }
+
+        if range.is_empty() {
+            let position = match range.start {
+                0 => 0,
+                offset if offset >= size => 4 * self.len,
+                offset => 4 * self.full_index(offset)?,
+            };
+            return Some((position, position));
+        }
+
+        let first = self.full_index(range.start)?;
+        let last = self.full_index(range.end - 1)?;
+        Some((4 * first + 2, 4 * last + 2))
+    }
+}
+
+impl NText {
+    /// Mark the characters start..end, the offsets are the same as in `NText::insert`.
+    ///
+    /// The mark is anchored to the characters at its edges, so the characters inserted into
+    /// the range by any replica take the mark and splits of the strings do not affect it.
+    /// The expansion decides about the characters inserted at the edges.
+    pub fn mark(&self, start: u32, end: u32, mark: Mark, expand: Expand) -> Result<(), String> {
+        let size = self.size();
+        if start >= end || end > size {
+            return Err(format!(
+                "invalid mark range {}..{} of a text of size {}",
+                start, end, size
+            ));
+        }
+
+        let char_at = |offset: u32| {
+            char_id(self, offset).ok_or_else(|| format!("no character at offset {}", offset))
+        };
+        let start = match expand {
+            Expand::Before | Expand::Both if start == 0 => MarkAnchor::default(),
+            Expand::Before | Expand::Both => MarkAnchor::after(char_at(start - 1)?),
+            _ => MarkAnchor::before(char_at(start)?),
+        };
+        let end = match expand {
+            Expand::After | Expand::Both if end == size => MarkAnchor::default(),
+            Expand::After | Expand::Both => MarkAnchor::before(char_at(end)?),
+            _ => MarkAnchor::after(char_at(end - 1)?),
+        };
+
+        let store = self
+            .store
+            .upgrade()
+            .ok_or("the text is not in a document")?;
+        let id = store.borrow_mut().next_id();
+        let content = MarkContent::anchored(mark, start, end);
+        let mark = NMark::new(id, Content::Mark(content), self.store.clone());
+        mark.attach(&self.clone().into());
+
+        Ok(())
+    }
+
+    /// Marks covering every character of the range in name order. For a collapsed range
+    /// these are the marks a character inserted at the offset takes. Of the overlapping
+    /// marks with the same name the latest one wins.
+    pub fn marks_at(&self, range: Range<u32>) -> Vec<Mark> {
+        let layout = TextLayout::new(self);
+        match layout.span(&range, self.size()) {
+            Some((first, last)) => latest(&self.anchored_marks(&layout), first, last),
+            None => vec![],
+        }
+    }
+
+    // runs of the visible text with the same marks, in text order
+    pub(crate) fn mark_runs(&self) -> Vec<(Range<u32>, Vec<Mark>)> {
+        let layout = TextLayout::new(self);
+        let anchored = self.anchored_marks(&layout);
+        let mut runs: Vec<(Range<u32>, Vec<Mark>)> = vec![];
+        let mut offset = 0;
+        for (range, full, visible) in &layout.items {
+            if !visible {
+                continue;
+            }
+            for index in 0..range.size() as i64 {
+                let position = 4 * (full + index) + 2;
+                let marks = latest(&anchored, position, position);
+                match runs.last_mut() {
+                    Some((run, last)) if *last == marks => run.end += 1,
+                    _ => runs.push((offset..offset + 1, marks)),
+                }
+                offset += 1;
+            }
+        }
+
+        runs
+    }
+
+    // text marks with the positions of their edges and their order
+    fn anchored_marks(&self, layout: &TextLayout) -> Vec<(i64, i64, (u32, u32), Mark)> {
+        Type::from(self.clone())
+            .mark_items()
+            .iter()
+            .filter_map(|item| {
+                let Some(Content::Mark(content)) = item.as_mark().map(|m| m.content()) else {
+                    return None;
+                };
+                let (start, end) = content.anchors?;
+                let start = layout.anchor(&start, false)?;
+                let end = layout.anchor(&end, true)?;
+                let id = item.id();
+                Some((start, end, (id.clock, id.client), content.data))
+            })
+            .collect()
+    }
+}
+
+/// Mark clearing the marks with the name, e.g. a `bold: null` attribute of a delta
+pub(crate) fn removal(name: impl Into<String>) -> Mark {
+    Mark::Custom(name.into(), "null".to_string())
+}
+
+#[inline]
+pub(crate) fn is_removal(mark: &Mark) -> bool {
+    matches!(mark, Mark::Custom(_, json) if json == "null")
+}
+
+// latest mark by name covering the positions first to last, without the cleared names
+fn latest(anchored: &[(i64, i64, (u32, u32), Mark)], first: i64, last: i64) -> Vec<Mark> {
+    let mut marks: BTreeMap<String, ((u32, u32), Mark)> = BTreeMap::new();
+    for (start, end, order, mark) in anchored {
+        if *start >= first || last >= *end {
+            continue;
+        }
+
+        let key = mark.key();
+        if !marks.get(&key).is_some_and(|(latest, _)| latest > order) {
+            marks.insert(key, (*order, mark.clone()));
+        }
+    }
+
+    marks
+        .into_values()
+        .map(|(_, mark)| mark)
+        .filter(|mark| !is_removal(mark))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec_v1::EncoderV1;
+    use crate::decoder::{Decode, DecodeContext};
+    use crate::diff::Diff;
+    use crate::doc::Doc;
+    use crate::encoder::{Encode, EncodeContext, Encoder};
+    use crate::state::ClientState;
+
+    use super::*;
+
+    fn text_of(doc: &Doc) -> NText {
+        match doc.get("text") {
+            Some(Type::Text(text)) => text,
+            _ => panic!("no text"),
+        }
+    }
+
+    #[test]
+    fn test_text_marks_expand() {
+        let doc = Doc::default();
+        let text = doc.text();
+        doc.set("text", text.clone());
+        text.append(doc.string("hello world"));
+        doc.commit();
+
+        let link = Mark::Link("https://nitro.dev".to_string());
+        text.mark(0, 5, Mark::Bold, Expand::After).unwrap();
+        text.mark(6, 11, link.clone(), Expand::default_for(&link))
+            .unwrap();
+        doc.commit();
+        assert!(text.mark(3, 3, Mark::Italic, Expand::None).is_err());
+
+        assert_eq!(text.marks_at(1..3), vec![Mark::Bold]);
+        assert_eq!(text.marks_at(4..7), vec![]);
+        // typing after the bold word continues the bold, typing after the link does not
+        assert_eq!(text.marks_at(5..5), vec![Mark::Bold]);
+        assert_eq!(text.marks_at(0..0), vec![]);
+        assert_eq!(text.marks_at(11..11), vec![]);
+
+        // the marks sync through the encoded diff
+        let mut e = EncoderV1::new();
+        doc.diff(ClientState::default())
+            .encode(&mut e, &mut EncodeContext::default());
+        let diff = Diff::decode(&mut e.decoder(), &DecodeContext::default()).unwrap();
+        let remote = Doc::new(doc.meta.clone());
+        remote.apply(&diff);
+        remote.update_client();
+        let remote_text = text_of(&remote);
+        assert_eq!(remote_text.marks_at(7..9), vec![link.clone()]);
+
+        // concurrent inserts inside and at the edges of the marks
+        text.insert(5, doc.string("!!"));
+        text.insert(2, doc.string("y"));
+        doc.commit();
+        remote_text.insert(11, remote.string("s"));
+        remote_text.insert(8, remote.string("-"));
+        remote.commit();
+
+        doc.apply(&remote.diff(doc.state()));
+        remote.apply(&doc.diff(remote.state()));
+        assert_eq!(text.text_content(), remote_text.text_content());
+        assert_eq!(text.text_content(), "heyllo!! wo-rlds");
+        for t in [&text, &remote_text] {
+            assert_eq!(t.marks_at(0..8), vec![Mark::Bold]);
+            assert_eq!(t.marks_at(9..15), vec![link.clone()]);
+            assert_eq!(t.marks_at(15..16), vec![]);
+        }
+
+        // the latest mark with the same name wins
+        text.mark(0, 3, Mark::Color("red".into()), Expand::None)
+            .unwrap();
+        text.mark(1, 2, Mark::Color("blue".into()), Expand::None)
+            .unwrap();
+        assert_eq!(
+            text.marks_at(1..2),
+            vec![Mark::Bold, Mark::Color("blue".into())]
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use std::ops::Add;
+
+use hashbrown::HashMap;
+
+use crate::id::{ClockTick, Id, WithId};
+use crate::item::ItemIterator;
+use crate::ntext::NText;
+use crate::store::DocStore;
+
+/// OffsetEncoding is the unit a text offset is counted in
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub enum OffsetEncoding {
+    /// bytes of the utf-8 text, the unit of the text api
+    #[default]
+    Utf8,
+    /// utf-16 code units, the unit of javascript strings
+    Utf16,
+    /// unicode scalar values, the unit of `str::chars`
+    Scalar,
+}
+
+// code units of a run in every encoding, indexed by the encoding
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+struct Units([u32; 3]);
+
+impl Units {
+    fn of(content: &str) -> Self {
+        let mut units = Units::default();
+        content.chars().for_each(|c| units.push(c));
+        units
+    }
+
+    #[inline]
+    fn push(&mut self, c: char) {
+        self.0[0] += c.len_utf8() as u32;
+        self.0[1] += c.len_utf16() as u32;
+        self.0[2] += 1;
+    }
+
+    #[inline]
+    fn get(&self, encoding: OffsetEncoding) -> u32 {
+        self.0[encoding as usize]
+    }
+}
+
+impl Add for Units {
+    type Output = Units;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Units([
+            self.0[0] + rhs.0[0],
+            self.0[1] + rhs.0[1],
+            self.0[2] + rhs.0[2],
+        ])
+    }
+}
+
+/// OffsetIndex maps the offsets of a text between the encodings.
+///
+/// The code units of every run are counted once, the content of a run never changes.
+/// A lookup finds the run holding the offset and scans the content of that run only.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub(crate) struct OffsetIndex {
+    // start of every visible run with the run id, None for runs without string content
+    starts: Vec<(Units, Option<Id>)>,
+    size: Units,
+    // code units of the runs by run id and size, runs are split by edits
+    runs: HashMap<(Id, u32), Units>,
+    // store clock the index was built at
+    clock: Option<ClockTick>,
+}
+
+impl OffsetIndex {
+    #[inline]
+    fn is_valid(&self, clock: ClockTick) -> bool {
+        self.clock == Some(clock)
+    }
+
+    #[inline]
+    pub(crate) fn invalidate(&mut self) {
+        self.clock = None;
+    }
+
+    fn update(&mut self, text: &NText, clock: ClockTick) {
+        let mut runs = HashMap::new();
+        self.starts = vec![];
+        self.size = Units::default();
+
+        for item in text.visible_item_iter() {
+            let size = item.size();
+            let key = (item.id(), size);
+            let is_string = item.kind().is_string();
+            let units = self.runs.remove(&key).unwrap_or_else(|| match is_string {
+                true => Units::of(&item.text_content()),
+                false => Units([size; 3]),
+            });
+
+            self.starts.push((self.size, is_string.then(|| item.id())));
+            self.size = self.size + units;
+            runs.insert(key, units);
+        }
+
+        self.runs = runs;
+        self.clock = Some(clock);
+    }
+
+    // the offset in the target encoding, None past the end or inside a character
+    fn convert(
+        &self,
+        offset: u32,
+        from: OffsetEncoding,
+        to: OffsetEncoding,
+        content: impl Fn(usize, &Id) -> String,
+    ) -> Option<u32> {
+        let size = self.size.get(from);
+        if offset > size {
+            return None;
+        }
+        if from == to {
+            return Some(offset);
+        }
+        if offset == size {
+            return Some(self.size.get(to));
+        }
+
+        let run = self
+            .starts
+            .partition_point(|(start, _)| start.get(from) <= offset)
+            - 1;
+        let (start, id) = &self.starts[run];
+        let rest = offset - start.get(from);
+        let Some(id) = id else {
+            return Some(start.get(to) + rest);
+        };
+
+        let mut units = *start;
+        for c in content(run, id).chars() {
+            let at = units.get(from) - start.get(from);
+            if at >= rest {
+                return (at == rest).then(|| units.get(to));
+            }
+            units.push(c);
+        }
+
+        None
+    }
+}
+
+impl DocStore {
+    // remote diffs and rollbacks change texts without moving the clock forward
+    pub(crate) fn invalidate_offset_indexes(&mut self) {
+        self.offset_indexes
+            .values_mut()
+            .for_each(OffsetIndex::invalidate);
+    }
+}
+
+impl NText {
+    /// Convert an offset of the text between encodings, e.g. a utf-16 offset of a
+    /// javascript binding to the byte offset of the text api. Returns None for offsets
+    /// past the end of the text or inside a character.
+    pub fn convert_offset(
+        &self,
+        offset: u32,
+        from: OffsetEncoding,
+        to: OffsetEncoding,
+    ) -> Option<u32> {
+        self.thaw();
+        let Some(store) = self.store.upgrade() else {
+            let mut index = OffsetIndex::default();
+            index.update(self, 0);
+            return index.convert(offset, from, to, |run, _| self.run_content(run));
+        };
+
+        let (cached, clock) = {
+            let mut store = store.borrow_mut();
+            (store.offset_indexes.remove(&self.id()), store.clock)
+        };
+        let mut index = cached.unwrap_or_default();
+        if !index.is_valid(clock) {
+            index.update(self, clock);
+        }
+
+        let result = index.convert(offset, from, to, |run, id| {
+            let item = store.borrow().find(id);
+            match item {
+                Some(item) => item.item_ref().text_content(),
+                None => self.run_content(run),
+            }
+        });
+        store.borrow_mut().offset_indexes.insert(self.id(), index);
+
+        result
+    }
+
+    // content of the visible run at the position
+    fn run_content(&self, run: usize) -> String {
+        self.visible_item_iter()
+            .nth(run)
+            .map(|item| item.text_content())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doc::{CloneDeep, Doc};
+    use crate::state::ClientState;
+    use crate::Type;
+
+    use super::*;
+    use OffsetEncoding::*;
+
+    #[test]
+    fn test_convert_offset() {
+        let doc = Doc::default();
+        let text = doc.text();
+        doc.set("text", text.clone());
+        text.append(doc.string("aé"));
+        text.append(doc.string("😀b"));
+
+        // a: 1 byte, é: 2 bytes, 😀: 4 bytes and a surrogate pair
+        assert_eq!(text.convert_offset(3, Utf8, Utf16), Some(2));
+        assert_eq!(text.convert_offset(7, Utf8, Utf16), Some(4));
+        assert_eq!(text.convert_offset(4, Utf16, Utf8), Some(7));
+        assert_eq!(text.convert_offset(3, Scalar, Utf8), Some(7));
+        assert_eq!(text.convert_offset(8, Utf8, Scalar), Some(4));
+        assert_eq!(text.convert_offset(5, Utf16, Scalar), Some(4));
+
+        // inside a character or past the end
+        assert_eq!(text.convert_offset(2, Utf8, Utf16), None);
+        assert_eq!(text.convert_offset(3, Utf16, Utf8), None);
+        assert_eq!(text.convert_offset(9, Utf8, Utf16), None);
+
+        // a local edit splitting a run
+        text.insert(1, doc.string("ü"));
+        assert_eq!(text.convert_offset(5, Utf8, Utf16), Some(3));
+        doc.commit();
+
+        // a remote edit
+        let remote = doc.clone_deep();
+        remote.update_client();
+        remote.get("text").unwrap().insert(0, remote.string("😀"));
+        remote.commit();
+        doc.apply(&remote.diff(ClientState::default()));
+        assert_eq!(text.text_content(), "😀aüé😀b");
+        assert_eq!(text.convert_offset(7, Utf8, Utf16), Some(4));
+        assert_eq!(text.convert_offset(14, Utf8, Scalar), Some(6));
+    }
+
+    #[test]
+    fn test_convert_offset_after_backspace() {
+        let doc = Doc::default();
+        let text = doc.text();
+        doc.set("text", text.clone());
+        let chars: Vec<Type> = ["é", "b", "ü", "d"]
+            .map(|c| Type::from(doc.string(c)))
+            .into();
+        for c in &chars {
+            text.append(c.clone());
+        }
+        doc.commit();
+
+        chars[3].delete();
+        assert_eq!(text.convert_offset(5, Utf8, Utf16), Some(3));
+
+        // the backspace extends the pending delete without a new clock tick
+        chars[2].delete();
+        assert_eq!(text.convert_offset(5, Utf8, Utf16), None);
+        assert_eq!(text.convert_offset(3, Utf8, Utf16), Some(2));
+    }
+}
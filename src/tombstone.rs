@@ -0,0 +1,117 @@
+use crate::item::ItemIterator;
+use crate::nlist::NList;
+use crate::nmap::NMap;
+use crate::ntext::NText;
+
+// share of the raw length taken by tombstones, 0 for an empty container
+fn ratio(len: usize, raw_len: usize) -> f64 {
+    match raw_len {
+        0 => 0.0,
+        _ => (raw_len - len.min(raw_len)) as f64 / raw_len as f64,
+    }
+}
+
+impl NList {
+    /// Visible items of the list
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.size() as usize
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Items including the deleted items and the old places of moved items
+    pub fn raw_len(&self) -> usize {
+        self.item_ref().item_iter().count()
+    }
+
+    pub fn tombstone_ratio(&self) -> f64 {
+        ratio(self.len(), self.raw_len())
+    }
+}
+
+impl NText {
+    /// Visible characters of the text in bytes
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.size() as usize
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Characters including the deleted ones
+    pub fn raw_len(&self) -> usize {
+        self.thaw();
+        self.item_iter().map(|item| item.size() as usize).sum()
+    }
+
+    pub fn tombstone_ratio(&self) -> f64 {
+        ratio(self.len(), self.raw_len())
+    }
+}
+
+impl NMap {
+    /// Visible keys of the map
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.size() as usize
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Values including the deleted and overwritten ones
+    pub fn raw_len(&self) -> usize {
+        self.item_ref().item_iter().count()
+    }
+
+    pub fn tombstone_ratio(&self) -> f64 {
+        ratio(self.len(), self.raw_len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doc::Doc;
+
+    #[test]
+    fn test_visible_and_raw_len() {
+        let doc = Doc::default();
+        let list = doc.list();
+        doc.set("list", list.clone());
+        list.append(doc.atom("a"));
+        list.append(doc.atom("b"));
+        list.append(doc.atom("c"));
+        list.get(1u32).unwrap().delete();
+        assert_eq!((list.len(), list.raw_len()), (2, 3));
+        assert!((list.tombstone_ratio() - 1.0 / 3.0).abs() < 1e-9);
+
+        let text = doc.text();
+        doc.set("text", text.clone());
+        text.append(doc.string("hello"));
+        let world = doc.string(" world");
+        text.append(world.clone());
+        world.delete();
+        assert_eq!((text.len(), text.raw_len()), (5, 11));
+
+        let map = doc.map();
+        doc.set("map", map.clone());
+        map.set("a", doc.atom(1u32));
+        map.set("a", doc.atom(2u32));
+        map.set("b", doc.atom(3u32));
+        map.remove("b".into());
+        assert_eq!((map.len(), map.raw_len()), (1, 3));
+
+        let empty = doc.list();
+        assert!(empty.is_empty());
+        assert_eq!(empty.tombstone_ratio(), 0.0);
+    }
+}
@@ -1,110 +1,196 @@
-use crate::change::Change;
-use crate::natom::NAtom;
-use crate::nlist::NList;
-use crate::nmap::NMap;
-use crate::store::WeakStoreRef;
-use crate::{ClockTick, Content, Id, NString, NText, Type};
-use std::rc::Rc;
-
-// Transaction represents a transaction with the changes made to the Nitro document
-// Change represents the changes made to the Nitro document at local/remote client site
-// pub(crate) struct Transaction {
-//     store: WeakStoreRef,
-//     change: Change,
-// }
-//
-// impl Transaction {
-//     pub fn new(store: WeakStoreRef) -> Self {
-//         let store_ref = store.upgrade().unwrap();
-//         let store_ref = store_ref.borrow();
-//
-//         let client = store_ref.client;
-//         let tick = store_ref.current_tick();
-//         let change = Change::from_id(Id::new(client, tick).into());
-//
-//         Self { store, change }
-//     }
-//
-//     pub fn atom(&mut self, content: impl Into<Content>) -> NAtom {
-//         let atom = NAtom::new(self.next_id(), content.into(), self.store.clone());
-//         self.insert(atom.clone());
-//         self.update_tick();
-//
-//         atom
-//     }
-//
-//     pub fn list(&mut self) -> NList {
-//         let list = NList::new(self.next_id(), self.store.clone());
-//         self.insert(list.clone());
-//         self.update_tick();
-//
-//         list
-//     }
-//
-//     pub fn map(&mut self) -> NMap {
-//         let map = NMap::new(self.next_id(), self.store.clone());
-//         self.insert(map.clone());
-//         self.update_tick();
-//
-//         map
-//     }
-//
-//     pub fn text(&mut self) -> NText {
-//         let text = NText::new(self.next_id(), self.store.clone());
-//         self.insert(text.clone());
-//         self.update_tick();
-//
-//         text
-//     }
-//
-//     pub fn string(&mut self, value: impl Into<String>) -> NString {
-//         let content = value.into();
-//         let string = NString::new(self.next_id(), content, self.store.clone());
-//         self.insert(string.clone());
-//         self.update_tick();
-//
-//         string
-//     }
-//
-//     fn current_tick(&self) -> ClockTick {
-//         self.store.upgrade().unwrap().borrow().current_tick()
-//     }
-//
-//     fn update_tick(&mut self) {
-//         self.change.id.end = self.change.id.end.max(self.current_tick() - 1);
-//     }
-//
-//     fn next_id(&mut self) -> Id {
-//         self.store.upgrade().unwrap().borrow_mut().next_id()
-//     }
-//
-//     fn insert(&mut self, item: impl Into<Type>) {
-//         self.store.upgrade().unwrap().borrow_mut().insert(item);
-//     }
-//
-//     /// Commit the transaction to the store.
-//     pub fn commit(&mut self) {
-//         let store = self.store.upgrade().unwrap();
-//         store.borrow_mut().insert_change(self.change.id);
-//     }
-// }
-//
-// pub(crate) struct ChangeTx {
-//     pub(crate) store: WeakStoreRef,
-//     pub(crate) change: Change,
-// }
-//
-// impl ChangeTx {
-//     fn new(store: WeakStoreRef, change: Change) -> Self {
-//         Self { store, change }
-//     }
-//
-//     // apply the change items to the document store
-//     // the items should be applied in the tick order
-//     fn commit(&mut self) {
-//         let store_ref = self.store.upgrade().unwrap();
-//         let mut store = store_ref.borrow_mut();
-//     }
-//
-//     fn rollback(&mut self) {}
-// }
+use std::ops::Deref;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+
+use crate::bimapid::ClientId;
+use crate::doc::Doc;
+use crate::id::{ClockTick, Id};
+use crate::store::DocStore;
+
+/// ChangeMeta is the user metadata of a change committed by `Doc::transact`
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ChangeMeta {
+    /// origin tag of the change, see `Doc::commit_with_origin`
+    pub origin: Option<String>,
+    /// set by the caller, e.g. milliseconds since the epoch
+    pub timestamp: Option<u64>,
+    pub description: Option<String>,
+}
+
+impl ChangeMeta {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.origin.is_none() && self.timestamp.is_none() && self.description.is_none()
+    }
+}
+
+/// Transaction stages the edits of `Doc::transact`, the document is used through it
+/// like the document itself
+pub struct Transaction<'a> {
+    doc: &'a Doc,
+    meta: ChangeMeta,
+    aborted: bool,
+}
+
+impl<'a> Transaction<'a> {
+    pub fn set_origin(&mut self, origin: impl Into<String>) {
+        self.meta.origin = Some(origin.into());
+    }
+
+    pub fn set_timestamp(&mut self, timestamp: u64) {
+        self.meta.timestamp = Some(timestamp);
+    }
+
+    pub fn set_description(&mut self, description: impl Into<String>) {
+        self.meta.description = Some(description.into());
+    }
+
+    #[inline]
+    pub fn meta(&self) -> &ChangeMeta {
+        &self.meta
+    }
+
+    /// Roll the staged edits back when the transaction closure returns
+    #[inline]
+    pub fn abort(&mut self) {
+        self.aborted = true;
+    }
+
+    #[inline]
+    pub fn is_aborted(&self) -> bool {
+        self.aborted
+    }
+}
+
+impl Deref for Transaction<'_> {
+    type Target = Doc;
+
+    fn deref(&self) -> &Self::Target {
+        self.doc
+    }
+}
+
+impl DocStore {
+    // attach the metadata to the changes of the client starting at or after the clock
+    fn tag_meta(&mut self, client: ClientId, start: ClockTick, meta: ChangeMeta) {
+        for change in self.changes_since(client, start) {
+            self.change_meta.insert(change, meta.clone());
+        }
+    }
+}
+
+impl Doc {
+    /// Run the edits of `f` as a single change with the metadata set on the transaction.
+    ///
+    /// The pending edits are committed first, so the change holds the edits of the
+    /// transaction only. When the transaction is aborted or its change violates the
+    /// document schema the staged edits are rolled back and an error is returned. When `f`
+    /// panics the edits are rolled back and the panic is resumed.
+    pub fn transact<R>(&self, f: impl FnOnce(&mut Transaction) -> R) -> Result<R, String> {
+        self.try_commit().map_err(|errors| {
+            format!("pending change violates the schema: {}", errors.join(", "))
+        })?;
+
+        let mut tx = Transaction {
+            doc: self,
+            meta: ChangeMeta::default(),
+            aborted: false,
+        };
+        let result = match catch_unwind(AssertUnwindSafe(|| f(&mut tx))) {
+            Ok(result) => result,
+            Err(panic) => {
+                self.rollback();
+                resume_unwind(panic)
+            }
+        };
+
+        if tx.aborted {
+            self.rollback();
+            return Err("transaction aborted".to_string());
+        }
+
+        let (client, start) = {
+            let store = self.store.borrow();
+            (store.client, store.commited_clock)
+        };
+        let meta = tx.meta;
+        match &meta.origin {
+            Some(origin) => self.commit_with_origin(origin.clone()),
+            None => self.try_commit(),
+        }
+        .map_err(|errors| format!("transaction violates the schema: {}", errors.join(", ")))?;
+        if !meta.is_empty() {
+            self.store_mut("transact").tag_meta(client, start, meta);
+        }
+
+        Ok(result)
+    }
+
+    /// Metadata of the transaction change holding the id
+    pub fn change_meta(&self, id: &Id) -> Option<ChangeMeta> {
+        let store = self.store.borrow();
+        let change = store.changes.get(id)?;
+        store.change_meta.get(change).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::id::WithId;
+
+    use super::*;
+
+    #[test]
+    fn test_transact() {
+        let doc = Doc::default();
+        let list = doc.list();
+        doc.set("list", list.clone());
+        doc.commit();
+
+        let (a, b) = doc
+            .transact(|tx| {
+                tx.set_origin("import");
+                tx.set_timestamp(42);
+                tx.set_description("add items");
+
+                let a = tx.atom("a");
+                let b = tx.atom("b");
+                list.append(a.clone());
+                list.append(b.clone());
+                (a, b)
+            })
+            .unwrap();
+        assert_eq!(list.size(), 2);
+
+        // both edits are in the same change
+        {
+            let store = doc.store.borrow();
+            assert_eq!(store.changes.get(&a.id()), store.changes.get(&b.id()));
+        }
+        let meta = doc.change_meta(&b.id()).unwrap();
+        assert_eq!(meta.timestamp, Some(42));
+        assert_eq!(meta.description.as_deref(), Some("add items"));
+        assert_eq!(doc.origin_of(&a.id()).as_deref(), Some("import"));
+
+        let aborted = doc.transact(|tx| {
+            list.append(tx.atom("c"));
+            list.get(0u32).unwrap().delete();
+            tx.abort();
+        });
+        assert!(aborted.is_err());
+        assert_eq!(list.size(), 2);
+
+        let panicked = catch_unwind(AssertUnwindSafe(|| {
+            doc.transact(|tx| {
+                list.append(tx.atom("d"));
+                panic!("edit failed");
+            })
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(list.size(), 2);
+
+        doc.transact(|tx| list.append(tx.atom("e"))).unwrap();
+        assert_eq!(list.size(), 3);
+        assert!(doc.change_meta(&list.get(2u32).unwrap().id()).is_none());
+        assert!(doc.check_invariants().is_ok());
+    }
+}
@@ -0,0 +1,287 @@
+use crate::chunk::chunk_hash;
+use crate::codec_v1::{DecoderV1, EncoderV1};
+use crate::decoder::{Decode, DecodeContext, Decoder};
+use crate::diff::Diff;
+use crate::doc::{Doc, DocId};
+use crate::encoder::{Encode, EncodeContext, Encoder};
+use crate::state::ClientState;
+
+/// SnapshotRequest asks for the snapshot of a document, from the start or from where an
+/// interrupted download stopped
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct SnapshotRequest {
+    pub doc_id: DocId,
+    /// `snapshot:offset` token of the interrupted download
+    pub resume: Option<String>,
+}
+
+/// SnapshotChunk is a part of a snapshot download
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct SnapshotChunk {
+    /// hex sha1 of the whole snapshot, identifies the download
+    pub snapshot: String,
+    /// size of the whole snapshot
+    pub total: u64,
+    pub offset: u64,
+    pub data: Vec<u8>,
+    /// hex sha1 of the data
+    pub checksum: String,
+}
+
+impl Encode for SnapshotRequest {
+    fn encode<E: Encoder>(&self, e: &mut E, ctx: &mut EncodeContext) {
+        self.doc_id.encode(e, ctx);
+        self.resume.encode(e, ctx);
+    }
+}
+
+impl Decode for SnapshotRequest {
+    fn decode<D: Decoder>(d: &mut D, ctx: &DecodeContext) -> Result<SnapshotRequest, String> {
+        Ok(SnapshotRequest {
+            doc_id: DocId::decode(d, ctx)?,
+            resume: Option::<String>::decode(d, ctx)?,
+        })
+    }
+}
+
+impl Encode for SnapshotChunk {
+    fn encode<E: Encoder>(&self, e: &mut E, ctx: &mut EncodeContext) {
+        self.snapshot.encode(e, ctx);
+        e.u64(self.total);
+        e.u64(self.offset);
+        e.bytes(&self.data);
+        self.checksum.encode(e, ctx);
+    }
+}
+
+impl Decode for SnapshotChunk {
+    fn decode<D: Decoder>(d: &mut D, ctx: &DecodeContext) -> Result<SnapshotChunk, String> {
+        Ok(SnapshotChunk {
+            snapshot: String::decode(d, ctx)?,
+            total: d.u64()?,
+            offset: d.u64()?,
+            data: d.bytes()?,
+            checksum: String::decode(d, ctx)?,
+        })
+    }
+}
+
+/// SnapshotUpload serves the chunks of a document snapshot taken when the upload is created
+#[derive(Debug, Clone)]
+pub struct SnapshotUpload {
+    snapshot: String,
+    bytes: Vec<u8>,
+    chunk_size: usize,
+}
+
+impl SnapshotUpload {
+    pub fn new(doc: &Doc, chunk_size: usize) -> Self {
+        let mut e = EncoderV1::new();
+        doc.diff(ClientState::default())
+            .encode(&mut e, &mut EncodeContext::default());
+        let bytes = e.buffer();
+
+        SnapshotUpload {
+            snapshot: chunk_hash(&bytes),
+            bytes,
+            chunk_size: chunk_size.max(1),
+        }
+    }
+
+    #[inline]
+    pub fn snapshot(&self) -> &str {
+        &self.snapshot
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Offset to continue the download from, the start when the token belongs to another
+    /// snapshot, e.g. one taken before the document changed
+    pub fn resume_offset(&self, request: &SnapshotRequest) -> u64 {
+        request
+            .resume
+            .as_deref()
+            .and_then(|token| token.split_once(':'))
+            .filter(|(snapshot, _)| *snapshot == self.snapshot)
+            .and_then(|(_, offset)| offset.parse::<u64>().ok())
+            .filter(|offset| *offset <= self.bytes.len() as u64)
+            .unwrap_or(0)
+    }
+
+    /// Chunk starting at the offset, None past the end
+    pub fn chunk_at(&self, offset: u64) -> Option<SnapshotChunk> {
+        let start = offset as usize;
+        if start >= self.bytes.len() {
+            return None;
+        }
+
+        let end = (start + self.chunk_size).min(self.bytes.len());
+        let data = self.bytes[start..end].to_vec();
+        Some(SnapshotChunk {
+            snapshot: self.snapshot.clone(),
+            total: self.bytes.len() as u64,
+            offset,
+            checksum: chunk_hash(&data),
+            data,
+        })
+    }
+
+    /// All chunks the request still needs
+    pub fn chunks(&self, request: &SnapshotRequest) -> Vec<SnapshotChunk> {
+        let mut chunks = vec![];
+        let mut offset = self.resume_offset(request);
+        while let Some(chunk) = self.chunk_at(offset) {
+            offset += chunk.data.len() as u64;
+            chunks.push(chunk);
+        }
+
+        chunks
+    }
+}
+
+/// SnapshotDownload collects the chunks of a snapshot. The received bytes survive a
+/// dropped connection, the next request resumes from the last verified chunk.
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotDownload {
+    snapshot: Option<String>,
+    total: u64,
+    bytes: Vec<u8>,
+}
+
+impl SnapshotDownload {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn received(&self) -> u64 {
+        self.bytes.len() as u64
+    }
+
+    #[inline]
+    pub fn is_complete(&self) -> bool {
+        self.snapshot.is_some() && self.received() == self.total
+    }
+
+    /// Token of the received part, None before the first chunk
+    pub fn resume_token(&self) -> Option<String> {
+        let snapshot = self.snapshot.as_ref()?;
+        Some(format!("{}:{}", snapshot, self.received()))
+    }
+
+    /// Request for the rest of the snapshot
+    pub fn request(&self, doc_id: &DocId) -> SnapshotRequest {
+        SnapshotRequest {
+            doc_id: doc_id.clone(),
+            resume: self.resume_token(),
+        }
+    }
+
+    /// Add the next chunk, a chunk of another snapshot starting at 0 restarts the download.
+    /// Returns true when the snapshot is complete.
+    pub fn push(&mut self, chunk: &SnapshotChunk) -> Result<bool, String> {
+        if self.snapshot.as_ref() != Some(&chunk.snapshot) {
+            if chunk.offset != 0 {
+                return Err(format!(
+                    "chunk of snapshot {} does not start the download",
+                    chunk.snapshot
+                ));
+            }
+            *self = SnapshotDownload {
+                snapshot: Some(chunk.snapshot.clone()),
+                total: chunk.total,
+                bytes: Vec::with_capacity(chunk.total as usize),
+            };
+        }
+
+        if chunk.offset != self.received() {
+            return Err(format!(
+                "chunk at {} does not continue the download at {}",
+                chunk.offset,
+                self.received()
+            ));
+        }
+        if chunk_hash(&chunk.data) != chunk.checksum {
+            return Err(format!("corrupted chunk at {}", chunk.offset));
+        }
+        if self.received() + chunk.data.len() as u64 > self.total {
+            return Err(format!("chunk at {} overflows the snapshot", chunk.offset));
+        }
+
+        self.bytes.extend_from_slice(&chunk.data);
+        Ok(self.is_complete())
+    }
+
+    /// Build the document of the complete snapshot
+    pub fn finish(&self) -> Result<Doc, String> {
+        let snapshot = self.snapshot.as_ref().ok_or("no snapshot chunk received")?;
+        if !self.is_complete() {
+            return Err(format!(
+                "snapshot incomplete: {} of {} bytes",
+                self.received(),
+                self.total
+            ));
+        }
+        if chunk_hash(&self.bytes) != *snapshot {
+            return Err(format!("corrupted snapshot {}", snapshot));
+        }
+
+        let mut d = DecoderV1::try_new(self.bytes.clone())?;
+        let diff = Diff::decode(&mut d, &DecodeContext::default())?;
+        Doc::from(&diff).ok_or_else(|| "snapshot has no document root".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::frame::{Frame, FrameKind, FrameParser};
+    use crate::sync::equal_docs;
+
+    use super::*;
+
+    #[test]
+    fn test_resume_snapshot_download() {
+        let doc = Doc::default();
+        let list = doc.list();
+        doc.set("list", list.clone());
+        for i in 0..50 {
+            list.append(doc.atom(format!("item {}", i)));
+        }
+        doc.commit();
+
+        let upload = SnapshotUpload::new(&doc, 64);
+        let mut download = SnapshotDownload::new();
+        let doc_id = doc.id();
+
+        // the connection drops after a few chunks
+        let first = upload.chunks(&download.request(&doc_id));
+        assert!(first.len() > 3);
+        for chunk in &first[..3] {
+            let frame = Frame::snapshot_chunk(chunk);
+            assert!(!download.push(&frame.to_snapshot_chunk().unwrap()).unwrap());
+        }
+
+        let request = download.request(&doc_id);
+        let mut parser = FrameParser::new();
+        parser.push(&Frame::snapshot_request(&request).to_bytes());
+        let request = parser.next_frame().unwrap().unwrap();
+        assert_eq!(request.kind, FrameKind::SnapshotRequest);
+
+        let rest = upload.chunks(&request.to_snapshot_request().unwrap());
+        assert_eq!(rest.len(), first.len() - 3);
+        assert!(download.push(&first[4]).is_err());
+
+        let mut corrupted = rest[0].clone();
+        corrupted.data[0] ^= 0xff;
+        assert!(download.push(&corrupted).is_err());
+
+        for chunk in &rest {
+            download.push(chunk).unwrap();
+        }
+        assert!(download.is_complete());
+        assert!(equal_docs(&doc, &download.finish().unwrap()));
+    }
+}
@@ -0,0 +1,232 @@
+use serde_json::Value;
+
+use crate::doc::Doc;
+use crate::id::{Client, Id, WithId};
+use crate::item::{Content, ItemKind, Linked};
+use crate::types::Type;
+
+/// DeletedRoot is a deleted subtree whose parent is still alive
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeletedRoot {
+    /// id of the deleted item
+    pub id: Id,
+    pub kind: ItemKind,
+    /// id of the container the item was deleted from
+    pub parent: Id,
+    /// map key of the item, None for list children
+    pub field: Option<String>,
+    /// client that deleted the item
+    pub deleted_by: Option<Client>,
+    /// id of the delete operation
+    pub deleted_with: Id,
+    /// json content of the subtree at the time it was deleted
+    pub content: Value,
+}
+
+impl Doc {
+    /// Deleted subtrees that can be restored, the most recently deleted first.
+    /// Deleted strings are not listed, text edits are reverted with undo.
+    pub fn deleted_roots(&self) -> Vec<DeletedRoot> {
+        let deletes: Vec<(Id, Id)> = {
+            let store = self.store.borrow();
+            store
+                .deletes
+                .iter()
+                .flat_map(|(_, items)| items.iter().map(|(id, item)| (*id, item.target())))
+                .collect()
+        };
+
+        let mut roots: Vec<DeletedRoot> = deletes
+            .into_iter()
+            .filter_map(|(delete_id, target)| {
+                let item = self.find_by_id(&target)?;
+                let parent = item.parent()?;
+                if item.kind().is_string() || !item.is_deleted() || !is_alive(&parent) {
+                    return None;
+                }
+
+                let has_field = item.item_ref().borrow().data.field.is_some();
+                let field = has_field.then(|| item.field()).flatten();
+                Some(DeletedRoot {
+                    id: item.id(),
+                    kind: item.kind(),
+                    parent: parent.id(),
+                    field,
+                    deleted_by: self
+                        .store
+                        .borrow()
+                        .state
+                        .get_client(&delete_id.client)
+                        .cloned(),
+                    deleted_with: delete_id,
+                    content: item.to_json(),
+                })
+            })
+            .collect();
+
+        roots.sort_by(|a, b| b.deleted_with.clock.cmp(&a.deleted_with.clock));
+        roots
+    }
+
+    /// Re-insert a deleted subtree as new items at its prior location.
+    /// A list child is inserted after the closest visible left sibling,
+    /// a map child is restored only when the key is still free. Marks are not restored.
+    pub fn restore(&self, id: &Id) -> Result<Type, String> {
+        let item = self
+            .find_by_id(id)
+            .ok_or_else(|| format!("item {} not found", id))?;
+        if !item.is_deleted() {
+            return Err(format!("item {} is not deleted", id));
+        }
+
+        let parent = item
+            .parent()
+            .ok_or_else(|| format!("item {} has no parent", id))?;
+        if !is_alive(&parent) {
+            return Err(format!("parent of item {} is deleted", id));
+        }
+
+        let copy = self
+            .new_like(&item)
+            .ok_or_else(|| format!("{:?} can not be restored", item.kind()))?;
+
+        match parent.kind() {
+            ItemKind::Map => {
+                let field = item.field().unwrap_or_default();
+                if parent.get(field.clone()).is_some_and(|v| v.is_visible()) {
+                    return Err(format!("key {} is taken", field));
+                }
+                parent.set(field, copy.clone());
+            }
+            _ => {
+                let mut left = item.left();
+                while let Some(l) = &left {
+                    if l.is_visible() {
+                        break;
+                    }
+                    left = l.left();
+                }
+
+                match left {
+                    Some(left) => {
+                        left.insert_after(copy.clone());
+                        parent.on_insert(&copy);
+                    }
+                    None => parent.prepend(copy.clone()),
+                }
+            }
+        }
+
+        self.copy_children(&item, &copy);
+
+        Ok(copy)
+    }
+
+    // new detached item with the kind and content of the item
+    pub(crate) fn new_like(&self, item: &Type) -> Option<Type> {
+        let copy = match item.kind() {
+            ItemKind::Map => self.map().into(),
+            ItemKind::List => self.list().into(),
+            ItemKind::Text => self.text().into(),
+            ItemKind::PlaintText => self.plain_text().into(),
+            ItemKind::Atom => self.atom(item.content()).into(),
+            ItemKind::Counter => self.counter().into(),
+            ItemKind::String => match item.content() {
+                Content::String(s) => self.string(s).into(),
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        Some(copy)
+    }
+
+    // copy the visible children of the source into the attached copy
+    pub(crate) fn copy_children(&self, source: &Type, copy: &Type) {
+        match (source, copy) {
+            (Type::Map(source), Type::Map(copy)) => {
+                for key in source.keys() {
+                    let Some(child) = source.get(key.clone()) else {
+                        continue;
+                    };
+                    if let Some(child_copy) = self.new_like(&child) {
+                        copy.set(key, child_copy.clone());
+                        self.copy_children(&child, &child_copy);
+                    }
+                }
+            }
+            (Type::Counter(source), Type::Counter(copy)) => copy.increment(source.value()),
+            (Type::List(_), Type::List(_)) | (Type::Text(_), Type::Text(_)) => {
+                if let Type::Text(text) = source {
+                    text.thaw();
+                }
+                let children = source.item_ref().borrow().as_list();
+                for child in children {
+                    if let Some(child_copy) = self.new_like(&child) {
+                        copy.append(child_copy.clone());
+                        self.copy_children(&child, &child_copy);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// the item and all its ancestors are not deleted
+fn is_alive(item: &Type) -> bool {
+    let mut current = Some(item.clone());
+    while let Some(item) = current {
+        if item.is_deleted() {
+            return false;
+        }
+        current = item.parent();
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_restore_deleted_subtree() {
+        let doc = Doc::default();
+        let todos = doc.list();
+        doc.set("todos", todos.clone());
+
+        let first = doc.map();
+        todos.append(first.clone());
+        first.set("title", doc.atom("first"));
+
+        let second = doc.map();
+        todos.append(second.clone());
+        second.set("title", doc.atom("second"));
+        let tags = doc.list();
+        second.set("tags", tags.clone());
+        tags.append(doc.atom("red"));
+        doc.commit();
+
+        second.delete();
+        doc.commit();
+
+        let roots = doc.deleted_roots();
+        assert_eq!(roots.len(), 1);
+        assert_eq!(roots[0].id, second.id());
+        assert_eq!(roots[0].kind, ItemKind::Map);
+
+        let restored = doc.restore(&roots[0].id).unwrap();
+        doc.commit();
+
+        assert_ne!(restored.id(), second.id());
+        assert_eq!(todos.size(), 2);
+        assert_eq!(
+            todos.get(1u32).unwrap().to_json(),
+            json!({"title": "second", "tags": ["red"]})
+        );
+        assert!(doc.restore(&first.id()).is_err());
+    }
+}
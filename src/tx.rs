@@ -4,12 +4,13 @@ use std::collections::BTreeMap;
 use std::default::Default;
 use std::time::Duration;
 
+use crate::apply_stats::ApplyStats;
 use crate::bimapid::ClientId;
 use crate::crdt_yata::{integrate_yata, remove_yata};
 use crate::delete::DeleteItem;
 use crate::diff::Diff;
-use crate::id::WithId;
-use crate::item::{ItemData, ItemRef, Linked, StartEnd};
+use crate::id::{Id, WithId, WithIdRange};
+use crate::item::{Content, ItemData, ItemKind, ItemRef, Linked, StartEnd};
 use crate::print_yaml;
 use crate::queue_store::ClientQueueStore;
 use crate::store::{
@@ -33,6 +34,7 @@ pub(crate) struct Tx {
 
     elapsed: Duration,
     rollback: bool,
+    stats: ApplyStats,
 }
 
 impl Tx {
@@ -47,6 +49,7 @@ impl Tx {
             progress: Vec::default(),
             elapsed: Duration::default(),
             rollback: false,
+            stats: ApplyStats::default(),
         }
     }
 
@@ -69,6 +72,7 @@ impl Tx {
                 // println!("Time taken to apply: {:?}", now.elapsed());
                 Ok(())
             })
+            .and_then(|_| self.delete())
             .unwrap_or_else(|err| {
                 log::error!("Tx commit error: {}", err);
                 self.rollback();
@@ -171,16 +175,26 @@ impl Tx {
         }
 
         // now that all ready items are collected, collect the ready delete items
-        for (_, store) in self.pending.iter_delete_items() {
-            for (_, data) in store.iter() {
+        let mut integrated = Vec::new();
+        for (_, deletes) in self.pending.iter_delete_items() {
+            for (_, data) in deletes.iter() {
                 // FIXME: if the the target item is split or merged,
                 // the delete item should be split or merged before integration
                 let id = data.range().id();
-                if self.ready.contains(&id) || store.contains(&id) {
+                if store.deletes.contains(&data.id()) {
+                    integrated.push(data.id());
+                } else if self.ready.contains(&id) || store.contains(&id) {
                     self.ready.insert_delete(data.clone());
+                    integrated.push(data.id());
                 }
             }
         }
+        for id in integrated {
+            self.pending.remove_delete(&id);
+        }
+
+        self.stats.pending = self.pending.items.size() as usize;
+        self.stats.deleted = self.ready.delete_items.size() as usize;
 
         Ok(())
     }
@@ -196,17 +210,24 @@ impl Tx {
         let now = std::time::Instant::now();
         let mut times: Vec<Duration> = Vec::new();
         let client_map = self.store.upgrade().unwrap().borrow().state.clients.clone();
-        let store = self.store.upgrade().unwrap();
-        let mut store = store.borrow_mut();
+        let doc_store = self.store.upgrade().unwrap();
+        let mut store = doc_store.borrow_mut();
 
         while let Some(data) = self.ready.queue.pop_front() {
             let parent = {
                 if let Some(parent_id) = &data.parent_id {
                     store.find(parent_id)
                 } else if let Some(left_id) = &data.left_id {
-                    store.find(left_id).and_then(|item| item.parent())
+                    // the stored parent holds the child index of the container
+                    store
+                        .find(left_id)
+                        .and_then(|item| item.parent_id())
+                        .and_then(|id| store.find(&id))
                 } else if let Some(right_id) = &data.right_id {
-                    store.find(right_id).and_then(|item| item.parent())
+                    store
+                        .find(right_id)
+                        .and_then(|item| item.parent_id())
+                        .and_then(|id| store.find(&id))
                 } else {
                     None
                 }
@@ -214,11 +235,52 @@ impl Tx {
 
             let now = std::time::Instant::now();
             if let Some(parent) = parent {
+                // marks point to the marked item and stay out of its children
+                if data.kind == ItemKind::Mark {
+                    let item: Type = ItemRef::new(data.into(), self.store.clone()).into();
+                    item.set_parent(parent);
+                    store.insert(item);
+                    self.stats.integrated += 1;
+                    continue;
+                }
+
                 let mut left = data.left_id.as_ref().map(|id| store.find(id)).flatten();
-                let right = data.right_id.as_ref().map(|id| store.find(id)).flatten();
+                let mut right = data.right_id.as_ref().map(|id| store.find(id)).flatten();
+
+                // both origins point into a tombstone merged by DocStore::gc, a string run
+                // with both origins inside is split at the left origin instead
+                if let (Some(l), Some(r)) = (&left, &right) {
+                    let tombstone = matches!(l.item_ref().borrow().content, Content::Tombstone(_));
+                    if tombstone && l.id() == r.id() {
+                        right = l.right();
+                    }
+                }
 
                 // println!("integrating: {:?}", data.id);
 
+                // the origins point inside string runs, the runs are split at the origins.
+                // The split borrows the store, it is released meanwhile
+                let string_run =
+                    |item: &Type| matches!(item.item_ref().borrow().content, Content::String(_));
+                if let (Some(l), Some(left_id)) = (left.clone(), data.left_id) {
+                    if l.end_id() != left_id && l.range().contains(&left_id) && string_run(&l) {
+                        drop(store);
+                        left = Some(l.split(left_id.clock - l.id().clock + 1).0);
+                        store = doc_store.borrow_mut();
+                        // the right origin can be inside the split run
+                        right = data.right_id.as_ref().and_then(|id| store.find(id));
+                        self.stats.splits += 1;
+                    }
+                }
+                if let (Some(r), Some(right_id)) = (right.clone(), data.right_id) {
+                    if r.id() != right_id && r.range().contains(&right_id) && string_run(&r) {
+                        drop(store);
+                        right = Some(r.split(right_id.clock - r.id().clock).1);
+                        store = doc_store.borrow_mut();
+                        self.stats.splits += 1;
+                    }
+                }
+
                 let item: Type = ItemRef::new(data.into(), self.store.clone()).into();
 
                 let count = integrate_yata(
@@ -237,6 +299,7 @@ impl Tx {
 
                 // track integration progress
                 self.progress.push(item);
+                self.stats.integrated += 1;
 
                 // println!("integrated with count: {}", count);
             }
@@ -261,6 +324,53 @@ impl Tx {
         Ok(())
     }
 
+    /// Mark the targets of the ready deletes deleted, string runs are split at the range bounds
+    pub(crate) fn delete(&mut self) -> Result<(), String> {
+        let store = self.store.upgrade().unwrap();
+
+        for (_, deletes) in self.ready.delete_items.iter() {
+            for (_, data) in deletes.iter() {
+                let range = *data.range();
+                let mut clock = range.start;
+                while clock <= range.end {
+                    let found = store.borrow().find(&Id::new(range.client, clock));
+                    let Some(mut item) = found else {
+                        clock += 1;
+                        continue;
+                    };
+
+                    // the split borrows the store, it can not be held here
+                    if item.kind() == ItemKind::String {
+                        if item.id().clock < clock {
+                            item = item.split(clock - item.id().clock).1;
+                        }
+                        if item.id().clock + item.size() - 1 > range.end {
+                            item = item.split(range.end - clock + 1).0;
+                        }
+                    }
+
+                    item.item_ref().borrow_mut().make_deleted();
+                    clock = item.id().clock + item.size().max(1);
+                }
+
+                store.borrow_mut().insert_delete(data.clone());
+            }
+        }
+
+        Ok(())
+    }
+
+    // items integrated by the transaction in integration order
+    #[inline]
+    pub(crate) fn integrated(&self) -> &[Type] {
+        &self.progress
+    }
+
+    #[inline]
+    pub(crate) fn stats(&self) -> ApplyStats {
+        self.stats.clone()
+    }
+
     pub(crate) fn merge(&self) -> Result<(), String> {
         if let Some(store) = self.store.upgrade() {
             let mut store = store.borrow_mut();
@@ -316,6 +426,14 @@ impl Tx {
             }
         }
 
+        // proxies and movers find their target when they are inserted
+        if let (ItemKind::Proxy | ItemKind::Move, Content::Id(target)) = (&data.kind, &data.content)
+        {
+            if !(self.ready.contains(target) || store.contains(target)) {
+                return false;
+            }
+        }
+
         true
     }
 
@@ -5,17 +5,18 @@ use serde_json::Value;
 use std::cmp::Ordering;
 
 use crate::decoder::{Decode, DecodeContext, Decoder};
-use crate::delete::DeleteItem;
 use crate::doc::{Doc, DocMeta};
 use crate::encoder::{Encode, EncodeContext, Encoder};
 use crate::id::{Id, IdRange, Split, WithId, WithIdRange};
 use crate::item::{Content, ItemData, ItemKey, ItemKind, ItemRef, Linked, StartEnd, WithIndex};
 use crate::mark::Mark;
 use crate::natom::NAtom;
+use crate::ncounter::NCounter;
 use crate::nlist::NList;
 use crate::nmap::NMap;
 use crate::nmark::NMark;
 use crate::nmove::NMove;
+use crate::nproxy::NProxy;
 use crate::nstring::NString;
 use crate::ntext::NText;
 use crate::store::{StoreRef, WeakStoreRef};
@@ -24,13 +25,15 @@ use crate::{print_yaml, Client};
 /// Type is a wrapper around the different item types in the store.
 #[derive(Debug, Clone, Default)]
 pub enum Type {
-    List(NList),     // container
-    Map(NMap),       // container
-    Text(NText),     // container
-    String(NString), // elementary
-    Atom(NAtom),     // elementary
-    Move(NMove),     // elementary
-    Mark(NMark),     // elementary
+    List(NList),       // container
+    Map(NMap),         // container
+    Text(NText),       // container
+    String(NString),   // elementary
+    Atom(NAtom),       // elementary
+    Move(NMove),       // elementary
+    Mark(NMark),       // elementary
+    Counter(NCounter), // elementary
+    Proxy(NProxy),     // elementary
     #[default]
     Identity, // used for empty items
 }
@@ -128,6 +131,14 @@ impl Type {
         }
     }
 
+    #[inline]
+    pub(crate) fn as_counter(&self) -> Option<NCounter> {
+        match self {
+            Type::Counter(n) => Some(n.clone()),
+            _ => None,
+        }
+    }
+
     #[inline]
     pub(crate) fn as_doc(&self) -> Option<Doc> {
         match self {
@@ -250,10 +261,10 @@ impl Type {
         let next = self.right();
 
         item.set_parent_id(parent.as_ref().map(|p| p.id()));
-        item.set_left_id(Some(self.id()));
+        item.set_left_id(Some(self.end_id()));
         item.set_right_id(next.as_ref().map(|n| n.id()));
 
-        // item.set_parent(parent.clone());
+        item.set_parent(parent.clone());
         item.set_left(self.clone());
         item.set_right(next.clone());
 
@@ -272,7 +283,7 @@ impl Type {
         let prev = self.left();
 
         item.set_parent_id(parent.as_ref().map(|p| p.id()));
-        item.set_left_id(prev.as_ref().map(|p| p.id()));
+        item.set_left_id(prev.as_ref().map(|p| p.end_id()));
         item.set_right_id(Some(self.id()));
 
         item.set_parent(parent.clone());
@@ -299,6 +310,8 @@ impl Type {
             Type::Atom(n) => n.item_ref(),
             Type::Move(n) => n.item_ref(),
             Type::Mark(n) => n.item_ref(),
+            Type::Counter(n) => n.item_ref(),
+            Type::Proxy(n) => n.item_ref(),
             // Type::Doc(n) => n.root.item_ref(),
             Type::Identity => panic!("item_ref: not implemented"),
         }
@@ -380,7 +393,8 @@ impl Type {
         }
     }
 
-    pub(crate) fn add_mark(&self, mark: Mark) {
+    /// Add a mark to a map or a string
+    pub fn add_mark(&self, mark: Mark) {
         match self {
             // Type::List(n) => n.add_mark(mark),
             Type::Map(n) => n.add_mark(mark),
@@ -393,11 +407,66 @@ impl Type {
         }
     }
 
-    #[inline]
-    pub(crate) fn remove_mark(&self, mark: Mark) {
-        let id = self.store().upgrade().unwrap().borrow_mut().next_id();
-        // let marks = self.item_ref().borrow().get_marks();
-        let item = DeleteItem::new(id, self.range());
+    /// Visible marks of the item with the ids of the mark items, in id order
+    pub fn marks(&self) -> Vec<(Id, Mark)> {
+        self.mark_items()
+            .iter()
+            .filter_map(|item| Some((item.id(), item.as_mark()?.mark()?)))
+            .collect()
+    }
+
+    /// Remove the marks equal to the given mark
+    pub fn remove_mark(&self, mark: Mark) {
+        self.mark_items()
+            .iter()
+            .filter(|item| item.as_mark().and_then(|m| m.mark()).as_ref() == Some(&mark))
+            .for_each(|item| item.item_ref().delete(item.size()));
+    }
+
+    /// Remove a mark of the item by the id from `Type::marks`
+    pub fn remove_mark_by_id(&self, id: &Id) -> Result<(), String> {
+        let item = self
+            .mark_items()
+            .into_iter()
+            .find(|item| item.id() == *id)
+            .ok_or_else(|| format!("{} is not a mark of {}", id, self.id()))?;
+        item.item_ref().delete(item.size());
+
+        Ok(())
+    }
+
+    /// Remove every mark with the name, e.g. `bold` or a custom mark name, see `Mark::key`.
+    /// Returns the number of removed marks.
+    pub fn clear_marks(&self, name: &str) -> usize {
+        let items: Vec<Type> = self
+            .mark_items()
+            .into_iter()
+            .filter(|item| {
+                item.as_mark()
+                    .and_then(|m| m.mark())
+                    .is_some_and(|m| m.key() == name)
+            })
+            .collect();
+        items
+            .iter()
+            .for_each(|item| item.item_ref().delete(item.size()));
+
+        items.len()
+    }
+
+    // visible mark items pointing to the item
+    pub(crate) fn mark_items(&self) -> Vec<Type> {
+        let Some(store) = self.store().upgrade() else {
+            return vec![];
+        };
+        let id = self.id();
+        let store = store.borrow();
+        store
+            .marks
+            .iter()
+            .flat_map(|(_, marks)| marks.iter().map(|(_, item)| item.clone()))
+            .filter(|item| item.is_visible() && item.parent_id() == Some(id))
+            .collect()
     }
 
     #[inline]
@@ -410,6 +479,8 @@ impl Type {
             Type::Atom(n) => n.size(),
             Type::Move(n) => n.size(),
             Type::Mark(n) => n.size(),
+            Type::Counter(n) => n.size(),
+            Type::Proxy(n) => n.size(),
             _ => panic!("size: not implemented"),
         }
     }
@@ -421,6 +492,8 @@ impl Type {
             Type::Text(n) => n.content(),
             Type::Move(n) => n.content(),
             Type::Mark(n) => n.content(),
+            Type::Counter(n) => n.content(),
+            Type::Proxy(n) => n.content(),
             Type::List(n) => n.content(),
             Type::Map(n) => n.content(),
             _ => {
@@ -442,6 +515,19 @@ impl Type {
         }
     }
 
+    /// move the item under the key of the given map, e.g. to rename a key
+    pub fn move_to_key(&self, parent: impl Into<Type>, key: &str) {
+        let parent = parent.into();
+        match parent {
+            Type::Map(n) => n.move_to(key, self),
+            _ => panic!(
+                "move: not implemented for {:?} to parent type: {:?}",
+                self.kind(),
+                parent.kind()
+            ),
+        }
+    }
+
     /// move the item after the given item
     pub fn move_after(&self, before: &Type) {
         if let Some(parent) = before.parent() {
@@ -529,7 +615,11 @@ impl Type {
 
     #[inline]
     pub fn delete(&self) {
-        self.item_ref().delete(1);
+        match self {
+            // a string deletes all its characters
+            Type::String(n) => n.delete(),
+            _ => self.item_ref().delete(1),
+        }
     }
 
     #[inline]
@@ -577,6 +667,8 @@ impl Type {
             Type::Atom(n) => n.to_json(),
             Type::Move(n) => n.to_json(),
             Type::Mark(n) => n.to_json(),
+            Type::Counter(n) => n.to_json(),
+            Type::Proxy(n) => n.to_json(),
             // Type::Doc(n) => n.to_json(),
             Type::Identity => panic!("to_json: not implemented for identity"),
         }
@@ -642,14 +734,19 @@ impl Type {
                 n.on_insert(child)
             }
             Type::Map(n) => {}
+            // the deltas are summed on read
+            Type::Counter(_) => {}
             _ => panic!("on_insert: not implemented for {:?}", self.kind()),
         }
     }
 
+    // assign the runtime index from the neighbors, used by local inserts and remote integration
     pub(crate) fn add_frac_index(&self) {
         let index = match (self.left(), self.right()) {
+            // unordered neighbors are reindexed by the list after the insert, see NList::on_insert
             (Some(left), Some(right)) => {
-                FractionalIndex::new_between(&left.index(), &right.index()).unwrap()
+                FractionalIndex::new_between(&left.index(), &right.index())
+                    .unwrap_or_else(|| FractionalIndex::new_after(&left.index()))
             }
             (Some(left), None) => FractionalIndex::new_after(&left.index()),
             (None, Some(right)) => FractionalIndex::new_before(&right.index()),
@@ -719,6 +816,8 @@ impl Serialize for Type {
             Type::Atom(n) => n.serialize(serializer),
             Type::Mark(n) => n.serialize(serializer),
             Type::Move(n) => n.serialize(serializer),
+            Type::Counter(n) => n.serialize(serializer),
+            Type::Proxy(n) => n.serialize(serializer),
             _ => panic!("Type: serialize: not implemented for {:?}", self),
         }
     }
@@ -768,6 +867,8 @@ impl WithIdRange for Type {
             Type::Atom(n) => n.range(),
             Type::Move(n) => n.range(),
             Type::Mark(n) => n.range(),
+            Type::Counter(n) => n.range(),
+            Type::Proxy(n) => n.range(),
             // Type::Doc(n) => n.root.range(),
             Type::Identity => panic!("range: not implemented for identity"),
         }
@@ -825,17 +926,31 @@ impl From<NMark> for Type {
     }
 }
 
+impl From<NCounter> for Type {
+    fn from(n: NCounter) -> Self {
+        Self::Counter(n)
+    }
+}
+
+impl From<NProxy> for Type {
+    fn from(n: NProxy) -> Self {
+        Self::Proxy(n)
+    }
+}
+
 impl From<ItemRef> for Type {
     fn from(item: ItemRef) -> Self {
         let kind = item.borrow().kind.clone();
         match kind {
             ItemKind::List => Self::List(item.into()),
             ItemKind::Map => Self::Map(item.into()),
-            ItemKind::Text => Self::Text(item.into()),
+            ItemKind::Text | ItemKind::PlaintText => Self::Text(item.into()),
             ItemKind::String => Self::String(item.into()),
             ItemKind::Atom => Self::Atom(item.into()),
             ItemKind::Move => Self::Move(item.into()),
             ItemKind::Mark => Self::Mark(item.into()),
+            ItemKind::Counter => Self::Counter(item.into()),
+            ItemKind::Proxy => Self::Proxy(item.into()),
             _ => panic!("Type::from(ItemRef): not implemented"),
         }
     }
@@ -851,6 +966,8 @@ impl From<Type> for ItemRef {
             Type::Atom(n) => n.item_ref(),
             Type::Move(n) => n.item_ref(),
             Type::Mark(n) => n.item_ref(),
+            Type::Counter(n) => n.item_ref(),
+            Type::Proxy(n) => n.item_ref(),
             // Type::Doc(n) => n.root.item_ref(),
             Type::Identity => panic!("Type::into(ItemRef): not implemented"),
         }
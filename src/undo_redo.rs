@@ -0,0 +1,552 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::bimapid::ClientId;
+use crate::checkpoint::delete_item;
+use crate::doc::Doc;
+use crate::id::{ClockTick, Id, IdRange, WithId};
+use crate::item::{Content, ItemKind};
+use crate::nlist::NList;
+use crate::store::DocStore;
+use crate::types::Type;
+
+// root key prefix of the per user undo stacks
+const STACK_PREFIX: &str = "__undo:";
+
+// consecutive text edits within the timeout are undone together
+const CAPTURE_TIMEOUT: Duration = Duration::from_millis(500);
+
+// edits of the captured changes, the ranges of the inserted items and of the deleted items
+#[derive(Debug, Clone, Default)]
+struct StackItem {
+    inserted: Vec<IdRange>,
+    deleted: Vec<IdRange>,
+    // all the edits are string inserts and deletes
+    text: bool,
+}
+
+impl StackItem {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.inserted.is_empty() && self.deleted.is_empty()
+    }
+
+    fn merge(&mut self, other: StackItem) {
+        self.inserted.extend(other.inserted);
+        self.deleted.extend(other.deleted);
+        self.text &= other.text;
+    }
+}
+
+#[derive(Debug, Default)]
+struct UndoState {
+    // containers whose edits are captured, empty for the whole document
+    scope: Vec<Id>,
+    undo: Vec<StackItem>,
+    redo: Vec<StackItem>,
+    // local changes before the clock are captured
+    client: ClientId,
+    clock: ClockTick,
+    capture_timeout: Duration,
+    // time of the last capture, None after the capturing was stopped
+    last: Option<Instant>,
+    // undo and redo capture the reverting changes themselves
+    reverting: bool,
+}
+
+impl UndoState {
+    fn in_scope(&self, item: &Type) -> bool {
+        if self.scope.is_empty() {
+            return true;
+        }
+
+        let mut current = Some(item.clone());
+        while let Some(item) = current {
+            if self.scope.contains(&item.id()) {
+                return true;
+            }
+            current = item.parent();
+        }
+
+        false
+    }
+
+    // edits in scope of the local changes committed since the last collect
+    fn collect(&mut self, store: &DocStore) -> StackItem {
+        if store.client != self.client {
+            self.client = store.client;
+            self.clock = 0;
+        }
+
+        let mut step = StackItem {
+            text: true,
+            ..StackItem::default()
+        };
+        for change in store.changes_since(self.client, self.clock) {
+            let range = IdRange::from(change);
+            for item in store.items.get_by_range(range) {
+                if matches!(item.kind(), ItemKind::Move | ItemKind::Mark) || !self.in_scope(&item) {
+                    continue;
+                }
+                step.text &= item.kind().is_string();
+                step.inserted
+                    .push(item.id().range(item.item_ref().borrow().ticks()));
+            }
+
+            for delete in store.deletes.get_by_range(range) {
+                let Some(item) = store.find(&delete.target()) else {
+                    continue;
+                };
+                if !self.in_scope(&item) {
+                    continue;
+                }
+                step.text &= item.kind().is_string();
+                step.deleted.push(*delete.range());
+            }
+
+            self.clock = self.clock.max(change.end + 1);
+        }
+
+        step
+    }
+
+    // push the local edits on the undo stack, a new edit clears the redo stack
+    fn capture(&mut self, store: &DocStore, now: Instant) {
+        if self.reverting {
+            return;
+        }
+
+        let step = self.collect(store);
+        if step.is_empty() {
+            return;
+        }
+
+        self.redo.clear();
+        let recent = self
+            .last
+            .is_some_and(|last| now.duration_since(last) <= self.capture_timeout);
+        match self.undo.last_mut() {
+            Some(last) if recent && last.text && step.text => last.merge(step),
+            _ => self.undo.push(step),
+        }
+        self.last = Some(now);
+    }
+}
+
+/// UndoManager reverts the local edits of the containers in its scope.
+///
+/// The edits are captured when they are committed, changes applied from remote clients
+/// are never undone. Consecutive text edits within the capture timeout are undone as a
+/// single step. Undo deletes the inserted items and inserts copies of the deleted ones,
+/// the revert is committed as a regular change so it syncs like any other edit.
+pub struct UndoManager {
+    doc: Doc,
+    state: Rc<RefCell<UndoState>>,
+    token: u32,
+}
+
+impl UndoManager {
+    /// Capture the local edits inside the scope containers, an empty scope captures the
+    /// edits of the whole document
+    pub fn new(doc: &Doc, scope: Vec<Type>) -> Self {
+        let state = {
+            let store = doc.store.borrow();
+            UndoState {
+                scope: scope.iter().map(|item| item.id()).collect(),
+                client: store.client,
+                clock: store.commited_clock,
+                capture_timeout: CAPTURE_TIMEOUT,
+                ..UndoState::default()
+            }
+        };
+        let state = Rc::new(RefCell::new(state));
+
+        // the listener lives in the store, it holds the store weakly to not keep it alive
+        let store = Rc::downgrade(&doc.store);
+        let captured = state.clone();
+        let token = doc.observe_deep(move |event| {
+            if !event.local {
+                return;
+            }
+            let (Some(store), Ok(mut state)) = (store.upgrade(), captured.try_borrow_mut()) else {
+                return;
+            };
+            state.capture(&store.borrow(), Instant::now());
+        });
+
+        Self {
+            doc: doc.clone(),
+            state,
+            token,
+        }
+    }
+
+    /// Text edits committed within the timeout of the previous edit are undone together
+    pub fn with_capture_timeout(self, timeout: Duration) -> Self {
+        self.state.borrow_mut().capture_timeout = timeout;
+        self
+    }
+
+    /// Start a new undo step with the next edit, even within the capture timeout
+    pub fn stop_capturing(&self) {
+        self.state.borrow_mut().last = None;
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.capture();
+        !self.state.borrow().undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.capture();
+        !self.state.borrow().redo.is_empty()
+    }
+
+    /// Revert the latest undo step, pending edits are committed and captured first.
+    /// Returns false when there is nothing to undo.
+    pub fn undo(&self) -> bool {
+        self.capture();
+        let Some(step) = self.state.borrow_mut().undo.pop() else {
+            return false;
+        };
+
+        let reverted = self.revert(&step);
+        let mut state = self.state.borrow_mut();
+        if !reverted.is_empty() {
+            state.redo.push(reverted);
+        }
+
+        true
+    }
+
+    /// Revert the latest undo, returns false when there is nothing to redo
+    pub fn redo(&self) -> bool {
+        self.capture();
+        let Some(step) = self.state.borrow_mut().redo.pop() else {
+            return false;
+        };
+
+        let reverted = self.revert(&step);
+        let mut state = self.state.borrow_mut();
+        if !reverted.is_empty() {
+            state.undo.push(reverted);
+        }
+
+        true
+    }
+
+    // commit the pending edits and capture the local changes
+    fn capture(&self) {
+        self.doc.commit();
+        let store = self.doc.store.borrow();
+        self.state.borrow_mut().capture(&store, Instant::now());
+    }
+
+    // revert the edits of the step and commit, returns the edits of the revert
+    fn revert(&self, step: &StackItem) -> StackItem {
+        let (inserted, deleted) = {
+            let store = self.doc.store.borrow();
+            let items = |ranges: &[IdRange]| -> Vec<Type> {
+                ranges
+                    .iter()
+                    .flat_map(|range| store.items.get_by_range(*range))
+                    .collect()
+            };
+            (items(&step.inserted), items(&step.deleted))
+        };
+
+        self.state.borrow_mut().reverting = true;
+        for item in inserted {
+            if item.is_deleted() {
+                continue;
+            }
+            // children of a container inserted by the same step go with the container
+            let parent = item.parent().map(|p| p.id());
+            if parent.is_some_and(|p| step.inserted.iter().any(|range| range.contains(&p))) {
+                continue;
+            }
+            delete_item(&item);
+        }
+
+        for item in deleted {
+            if item.is_deleted() {
+                let _ = self.doc.restore(&item.id());
+            }
+        }
+        self.doc.commit();
+
+        let mut state = self.state.borrow_mut();
+        state.reverting = false;
+        // the next edit starts a new step
+        state.last = None;
+        let store = self.doc.store.borrow();
+        state.collect(&store)
+    }
+}
+
+impl Drop for UndoManager {
+    fn drop(&mut self) {
+        if let Ok(mut store) = self.doc.store.try_borrow_mut() {
+            store.emitter.remove_listener(self.token);
+        }
+    }
+}
+
+/// UndoStep describes a change reverted by `UserUndoManager::undo`
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct UndoStep {
+    /// `client/start/end` token of the reverted change
+    pub change: String,
+    /// number of inserted items deleted again
+    pub deleted: usize,
+    /// number of deleted items restored
+    pub restored: usize,
+}
+
+/// UserUndoManager keeps the undo stack of a user across all the devices of the user.
+///
+/// The stack is a list in the document, every device pushes its commits to the same list
+/// so the list order decides which change is the latest and an undo on one device can
+/// revert a change made on another one. Create the managers of the other devices after
+/// the first sync, otherwise each device starts its own stack under the same key.
+#[derive(Debug, Clone)]
+pub struct UserUndoManager {
+    doc: Doc,
+    user: String,
+    stack: NList,
+}
+
+impl UserUndoManager {
+    pub fn new(doc: &Doc, user: impl Into<String>) -> Result<Self, String> {
+        let user = user.into();
+        let key = format!("{}{}", STACK_PREFIX, user);
+        let stack = match doc.get(key.as_str()) {
+            Some(Type::List(list)) => list,
+            Some(other) => return Err(format!("{} is a {}, not a list", key, other.kind())),
+            None => {
+                let list = doc.list();
+                doc.set(key, list.clone());
+                doc.commit();
+                list
+            }
+        };
+
+        Ok(UserUndoManager {
+            doc: doc.clone(),
+            user,
+            stack,
+        })
+    }
+
+    #[inline]
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
+    /// Number of changes that can be undone
+    pub fn size(&self) -> usize {
+        self.stack.unique_items().len()
+    }
+
+    /// Commit the local edits and push the change on the user stack
+    pub fn commit(&self) {
+        let last_end = |doc: &Doc| {
+            let store = doc.store.borrow();
+            store
+                .changes
+                .id_store(&store.client)
+                .and_then(|changes| changes.last())
+                .map_or(0, |change| change.end)
+        };
+
+        let start = last_end(&self.doc) + 1;
+        self.doc.commit();
+        let end = last_end(&self.doc);
+        if end < start {
+            return;
+        }
+
+        let token = {
+            let store = self.doc.store.borrow();
+            match store.state.get_client(&store.client) {
+                Some(client) => format!("{}/{}/{}", client, start, end),
+                None => return,
+            }
+        };
+
+        // the stack entry is a change of its own and is never reverted
+        self.stack.append(self.doc.atom(token));
+        self.doc.commit();
+    }
+
+    /// Revert the latest change of the user made on any of the devices, the revert
+    /// is committed but not pushed. Moves and marks are not reverted.
+    pub fn undo(&self) -> Result<Option<UndoStep>, String> {
+        let Some(entry) = self.stack.unique_items().pop() else {
+            return Ok(None);
+        };
+        let Content::String(token) = entry.content() else {
+            return Err(format!("invalid undo entry {}", entry.id()));
+        };
+
+        let range = self.change_range(&token)?;
+        let (items, deletes) = {
+            let store = self.doc.store.borrow();
+            (
+                store.items.get_by_range(range),
+                store.deletes.get_by_range(range),
+            )
+        };
+
+        let mut step = UndoStep {
+            change: token,
+            ..UndoStep::default()
+        };
+
+        for item in items {
+            if item.is_deleted() || matches!(item.kind(), ItemKind::Move | ItemKind::Mark) {
+                continue;
+            }
+            // children of a container inserted by the same change go with the container
+            if item.parent().is_some_and(|p| range.contains(&p.id())) {
+                continue;
+            }
+
+            item.delete();
+            step.deleted += 1;
+        }
+
+        for delete in deletes {
+            if self.doc.restore(&delete.target()).is_ok() {
+                step.restored += 1;
+            }
+        }
+
+        entry.delete();
+        self.doc.commit();
+
+        Ok(Some(step))
+    }
+
+    // local id range of the change token, the client is written out to work on every device
+    fn change_range(&self, token: &str) -> Result<IdRange, String> {
+        let invalid = || format!("invalid undo entry {}", token);
+        let mut parts = token.rsplitn(3, '/');
+        let end = parts
+            .next()
+            .and_then(|s| s.parse::<ClockTick>().ok())
+            .ok_or_else(invalid)?;
+        let start = parts
+            .next()
+            .and_then(|s| s.parse::<ClockTick>().ok())
+            .ok_or_else(invalid)?;
+        let client = parts.next().ok_or_else(invalid)?;
+
+        let store = self.doc.store.borrow();
+        let client_id = store
+            .state
+            .clients()
+            .into_iter()
+            .find(|(c, _)| c.to_string() == client)
+            .map(|(_, id)| id)
+            .ok_or_else(|| format!("unknown client {}", client))?;
+
+        Ok(IdRange::new(client_id, start, end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doc::CloneDeep;
+    use crate::state::ClientState;
+    use crate::sync::equal_docs;
+
+    use super::*;
+
+    #[test]
+    fn test_scoped_undo_redo() {
+        let doc = Doc::default();
+        let text = doc.text();
+        doc.set("text", text.clone());
+        let list = doc.list();
+        doc.set("list", list.clone());
+        doc.commit();
+
+        let manager = UndoManager::new(&doc, vec![text.clone().into(), list.clone().into()]);
+        assert!(!manager.can_undo());
+
+        // consecutive text edits are a single step
+        text.append(doc.string("hello"));
+        doc.commit();
+        text.append(doc.string(" world"));
+        doc.commit();
+        assert!(manager.undo());
+        assert_eq!(text.text_content(), "");
+        assert!(manager.redo());
+        assert_eq!(text.text_content(), "hello world");
+        assert!(!manager.can_redo());
+
+        // edits outside the scope are not captured
+        list.append(doc.atom("a"));
+        doc.commit();
+        doc.set("title", doc.atom("outside"));
+        doc.commit();
+        assert!(manager.undo());
+        assert_eq!(list.size(), 0);
+        assert!(doc.get("title").is_some());
+
+        // remote edits are kept
+        let remote = doc.clone_deep();
+        remote.update_client();
+        remote.get("text").unwrap().append(remote.string("!"));
+        remote.commit();
+        doc.apply(&remote.diff(doc.state()));
+        assert!(manager.undo());
+        assert_eq!(text.text_content(), "!");
+        assert!(!manager.undo());
+
+        text.append(doc.string("a"));
+        doc.commit();
+        manager.stop_capturing();
+        text.append(doc.string("b"));
+        manager.undo();
+        assert_eq!(text.text_content(), "!a");
+    }
+
+    #[test]
+    fn test_user_undo_across_devices() {
+        let laptop = Doc::default();
+        let m1 = UserUndoManager::new(&laptop, "alice").unwrap();
+        let phone = laptop.clone_deep();
+        phone.update_client();
+        let m2 = UserUndoManager::new(&phone, "alice").unwrap();
+
+        laptop.set("title", laptop.atom("hello"));
+        m1.commit();
+        phone.apply(&laptop.diff(ClientState::default()));
+
+        // the phone reverts the edit made on the laptop
+        let step = m2.undo().unwrap().unwrap();
+        assert_eq!(step.deleted, 1);
+        assert!(phone.get("title").is_none());
+        laptop.apply(&phone.diff(ClientState::default()));
+        assert!(laptop.get("title").is_none());
+        assert_eq!(m1.size(), 0);
+
+        laptop.set("body", laptop.atom("text"));
+        m1.commit();
+        phone.apply(&laptop.diff(ClientState::default()));
+        phone.get("body").unwrap().delete();
+        m2.commit();
+        laptop.apply(&phone.diff(ClientState::default()));
+
+        let step = m1.undo().unwrap().unwrap();
+        assert_eq!(step.restored, 1);
+        assert_eq!(laptop.get("body").unwrap().to_json(), "text");
+
+        phone.apply(&laptop.diff(ClientState::default()));
+        assert!(equal_docs(&laptop, &phone));
+        assert_eq!(m2.size(), 1);
+    }
+}
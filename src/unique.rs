@@ -0,0 +1,202 @@
+use std::fmt::{Debug, Formatter};
+use std::rc::Rc;
+
+use hashbrown::HashMap;
+
+use crate::change::{ChangeId, ClientChangeId};
+use crate::id::{Id, WithId};
+use crate::nlist::NList;
+use crate::store::DocStore;
+use crate::types::Type;
+
+type UniqueKeyFn = Rc<dyn Fn(&Type) -> Option<String>>;
+
+/// UniqueKeys keeps the uniqueness key extractors of the lists acting as sets
+#[derive(Clone, Default)]
+pub(crate) struct UniqueKeys {
+    keys: HashMap<Id, UniqueKeyFn>,
+}
+
+impl Debug for UniqueKeys {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.keys.keys()).finish()
+    }
+}
+
+impl PartialEq for UniqueKeys {
+    fn eq(&self, other: &Self) -> bool {
+        self.keys.len() == other.keys.len() && self.keys.keys().all(|k| other.keys.contains_key(k))
+    }
+}
+
+impl Eq for UniqueKeys {}
+
+impl DocStore {
+    // position of the item in the change order, the same on every site.
+    // uncommitted items are ranked by their own id until the change is created
+    fn change_rank(&self, id: &Id) -> (ClientChangeId, u32) {
+        let change = self
+            .changes
+            .get(id)
+            .cloned()
+            .unwrap_or_else(|| ChangeId::from(id));
+        let client = self
+            .state
+            .get_client(&change.client)
+            .cloned()
+            .unwrap_or_default();
+
+        (
+            ClientChangeId::new(client, change.start, change.end),
+            id.clock,
+        )
+    }
+}
+
+impl NList {
+    /// Treat the list as a set, items with the same key are duplicates.
+    /// The item inserted by the earliest change wins, the others are hidden from
+    /// `size`, `get`, `content` and json output on every site and returned by `duplicates`.
+    /// Items without a key are always kept.
+    pub fn set_unique_key(&self, key: impl Fn(&Type) -> Option<String> + 'static) {
+        let store = self.item_ref().store.upgrade().unwrap();
+        store
+            .borrow_mut()
+            .unique
+            .keys
+            .insert(self.id(), Rc::new(key));
+    }
+
+    pub fn clear_unique_key(&self) {
+        let store = self.item_ref().store.upgrade().unwrap();
+        store.borrow_mut().unique.keys.remove(&self.id());
+    }
+
+    #[inline]
+    pub fn has_unique_key(&self) -> bool {
+        self.unique_key().is_some()
+    }
+
+    /// Visible items without the duplicates, in list order
+    pub fn unique_items(&self) -> Vec<Type> {
+        self.partition().0
+    }
+
+    /// Visible items hidden by the uniqueness key, in list order
+    pub fn duplicates(&self) -> Vec<Type> {
+        self.partition().1
+    }
+
+    /// Delete the duplicates, returns the number of deleted items
+    pub fn remove_duplicates(&self) -> usize {
+        let duplicates = self.duplicates();
+        for item in duplicates.iter() {
+            item.delete();
+        }
+
+        duplicates.len()
+    }
+
+    fn unique_key(&self) -> Option<UniqueKeyFn> {
+        let store = self.item_ref().store.upgrade()?;
+        // the list can be read while the store is updated, the key is ignored then
+        let store = store.try_borrow().ok()?;
+        store.unique.keys.get(&self.id()).cloned()
+    }
+
+    // split the visible items into winners and duplicates
+    fn partition(&self) -> (Vec<Type>, Vec<Type>) {
+        let items = self.borrow().as_list();
+        let Some(key) = self.unique_key() else {
+            return (items, vec![]);
+        };
+
+        // the key function may read the items, keep the store free while it runs
+        let keys: Vec<Option<String>> = items.iter().map(|item| key(item)).collect();
+
+        let store = self.item_ref().store.upgrade().unwrap();
+        let store = store.borrow();
+        let mut winners: HashMap<&str, (ClientChangeId, u32, Id)> = HashMap::new();
+        for (item, key) in items.iter().zip(keys.iter()) {
+            let Some(key) = key else {
+                continue;
+            };
+
+            let id = item.id();
+            let (change, clock) = store.change_rank(&id);
+            match winners.get(key.as_str()) {
+                Some((c, k, _)) if (c, k) <= (&change, &clock) => {}
+                _ => {
+                    winners.insert(key.as_str(), (change, clock, id));
+                }
+            }
+        }
+
+        let (unique, duplicates): (Vec<_>, Vec<_>) =
+            items.into_iter().zip(keys.iter()).partition(|(item, key)| {
+                key.as_ref()
+                    .map_or(true, |key| winners[key.as_str()].2 == item.id())
+            });
+
+        (
+            unique.into_iter().map(|(item, _)| item).collect(),
+            duplicates.into_iter().map(|(item, _)| item).collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::doc::{CloneDeep, Doc};
+    use crate::state::ClientState;
+    use crate::types::Type;
+
+    // "red:1" and "red:2" are the same tag
+    fn tag(item: &Type) -> Option<String> {
+        let json = item.to_json();
+        json.as_str()?.split(':').next().map(|s| s.to_string())
+    }
+
+    #[test]
+    fn test_concurrent_duplicates_converge() {
+        let d1 = Doc::default();
+        let tags = d1.list();
+        d1.set("tags", tags.clone());
+        d1.commit();
+
+        let d2 = d1.clone_deep();
+        d2.update_client();
+        let tags2 = d2.get("tags").unwrap().as_list().unwrap();
+
+        tags.set_unique_key(tag);
+        tags2.set_unique_key(tag);
+
+        tags.append(d1.atom("red:1"));
+        d1.commit();
+        tags2.append(d2.atom("red:2"));
+        tags2.append(d2.atom("blue:2"));
+        d2.commit();
+
+        d1.apply(&d2.diff(ClientState::default()));
+        d2.apply(&d1.diff(ClientState::default()));
+
+        assert_eq!(tags.size(), 2);
+        assert_eq!(tags.duplicates().len(), 1);
+        assert_eq!(tags.to_json(), tags2.to_json());
+        assert_eq!(
+            tags.duplicates()[0].to_json(),
+            tags2.duplicates()[0].to_json()
+        );
+
+        tags.clear_unique_key();
+        assert_eq!(tags.size(), 3);
+
+        tags.set_unique_key(tag);
+        assert_eq!(tags.remove_duplicates(), 1);
+        assert!(tags.duplicates().is_empty());
+        assert_eq!(tags.unique_items().len(), 2);
+        assert_ne!(tags.to_json(), json!([]));
+    }
+}
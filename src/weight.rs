@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+
+use crate::doc::Doc;
+use crate::id::{Id, WithId};
+use crate::item::{ItemData, ItemKind};
+use crate::sync::encoded_size;
+
+/// ContainerWeight is the encoded size of the direct children of a container
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct ContainerWeight {
+    /// content of the visible children
+    pub content: usize,
+    /// ids, origins and fields of the visible children
+    pub metadata: usize,
+    /// deleted children and the deletes targeting them
+    pub tombstones: usize,
+    /// number of direct children, deleted ones included
+    pub items: usize,
+}
+
+impl ContainerWeight {
+    #[inline]
+    pub fn total(&self) -> usize {
+        self.content + self.metadata + self.tombstones
+    }
+}
+
+/// WeightReport is the encoded size of a document by container
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct WeightReport {
+    pub containers: BTreeMap<Id, (ItemKind, ContainerWeight)>,
+    /// size of the items without a parent, e.g. the root
+    pub detached: usize,
+}
+
+impl WeightReport {
+    #[inline]
+    pub fn total(&self) -> usize {
+        self.detached
+            + self
+                .containers
+                .values()
+                .map(|(_, weight)| weight.total())
+                .sum::<usize>()
+    }
+
+    /// Containers by total weight, the heaviest first
+    pub fn heaviest(&self) -> Vec<(Id, ItemKind, ContainerWeight)> {
+        let mut containers: Vec<_> = self
+            .containers
+            .iter()
+            .map(|(id, (kind, weight))| (*id, *kind, *weight))
+            .collect();
+        containers.sort_by(|a, b| b.2.total().cmp(&a.2.total()).then(a.0.cmp(&b.0)));
+        containers
+    }
+}
+
+impl Doc {
+    /// Encoded size of every container split into content, metadata and tombstones, to
+    /// find the parts of a document that dominate its storage. Frozen texts are measured
+    /// without thawing them.
+    pub fn weight_report(&self) -> WeightReport {
+        let store = self.store.borrow();
+        let mut report = WeightReport::default();
+        let mut containers = BTreeMap::new();
+        let mut add = |report: &mut WeightReport, data: &ItemData, parent, deleted| {
+            if matches!(
+                data.kind,
+                ItemKind::Map | ItemKind::List | ItemKind::Text | ItemKind::PlaintText
+            ) {
+                containers.insert(data.id, data.kind);
+            }
+
+            let size = encoded_size(data);
+            let Some(parent) = parent else {
+                report.detached += size;
+                return;
+            };
+
+            let (_, weight) = report
+                .containers
+                .entry(parent)
+                .or_insert_with(|| (ItemKind::Map, ContainerWeight::default()));
+            weight.items += 1;
+            if deleted {
+                weight.tombstones += size;
+            } else {
+                let content = encoded_size(&data.content);
+                weight.content += content;
+                weight.metadata += size.saturating_sub(content);
+            }
+        };
+
+        for (_, items) in store.items.iter() {
+            for (_, item) in items.iter() {
+                let item = item.item_ref();
+                let item = item.borrow();
+                // the codec drops the parent id of items with a left origin, the link is kept
+                let parent = item.parent.as_ref().map(|p| p.id());
+                add(&mut report, &item.data, parent, item.is_deleted());
+            }
+        }
+        for (flags, data) in store.cold.flagged_items() {
+            add(&mut report, &data, data.parent_id, flags & 0x01 == 0x01);
+        }
+
+        // deletes are tombstones of the container of their target
+        for (_, deletes) in store.deletes.iter() {
+            for (_, delete) in deletes.iter() {
+                let target = store.find(&delete.target());
+                let parent = target.and_then(|item| item.parent()).map(|p| p.id());
+                let size = encoded_size(delete);
+                match parent.and_then(|parent| report.containers.get_mut(&parent)) {
+                    Some((_, weight)) => weight.tombstones += size,
+                    None => report.detached += size,
+                }
+            }
+        }
+
+        for (id, kind) in containers {
+            report
+                .containers
+                .entry(id)
+                .or_insert_with(|| (kind, ContainerWeight::default()))
+                .0 = kind;
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weight_report() {
+        let doc = Doc::default();
+        let text = doc.text();
+        doc.set("text", text.clone());
+        text.append(doc.string("a".repeat(1000)));
+        let list = doc.list();
+        doc.set("list", list.clone());
+        let removed = doc.atom("b".repeat(500));
+        list.append(removed.clone());
+        list.append(doc.atom("c"));
+        doc.commit();
+        removed.delete();
+        doc.commit();
+
+        let report = doc.weight_report();
+        let (kind, weight) = report.containers[&text.id()];
+        assert_eq!(kind, ItemKind::Text);
+        assert!(weight.content >= 1000);
+        assert_eq!(weight.tombstones, 0);
+
+        let (kind, weight) = report.containers[&list.id()];
+        assert_eq!(kind, ItemKind::List);
+        assert_eq!(weight.items, 2);
+        assert!(weight.tombstones >= 500);
+        assert!(weight.content < 100);
+
+        assert_eq!(report.heaviest()[0].0, text.id());
+        assert!(report.total() > 1500);
+    }
+}
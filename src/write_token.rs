@@ -0,0 +1,203 @@
+use std::fmt::{Display, Formatter};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+
+use crate::codec_v1::{DecoderV1, EncoderV1};
+use crate::decoder::{Decode, DecodeContext};
+use crate::doc::Doc;
+use crate::encoder::{Encode, EncodeContext, Encoder};
+use crate::id::{Id, WithId, WithTarget};
+use crate::state::ClientState;
+use crate::store::DocStore;
+use crate::transaction::Transaction;
+use crate::types::Type;
+
+/// WriteToken is the version of a document a stateless writer has read, see
+/// `Doc::apply_if_unchanged`.
+///
+/// The string form is the url safe base64 of the encoded version, it holds no path.
+/// A writer scopes the parsed token to the subtree it writes with `at`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct WriteToken {
+    version: ClientState,
+    // json pointer of the guarded subtree, the whole document when empty
+    path: String,
+}
+
+impl WriteToken {
+    /// Guard the subtree at the json pointer only, edits elsewhere do not conflict
+    pub fn at(mut self, path: impl Into<String>) -> Self {
+        self.path = path.into();
+        self
+    }
+
+    #[inline]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    #[inline]
+    pub fn version(&self) -> &ClientState {
+        &self.version
+    }
+
+    pub fn parse(token: &str) -> Result<Self, String> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(token)
+            .map_err(|e| format!("invalid write token: {}", e))?;
+
+        // the decoder panics on reads past the end of malformed tokens
+        let version = catch_unwind(AssertUnwindSafe(|| {
+            let mut d = DecoderV1::headless(bytes);
+            ClientState::decode(&mut d, &DecodeContext::default())
+        }))
+        .map_err(|_| "invalid write token".to_string())??;
+
+        Ok(Self {
+            version,
+            path: String::new(),
+        })
+    }
+}
+
+impl Display for WriteToken {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut e = EncoderV1::headless();
+        self.version.encode(&mut e, &mut EncodeContext::default());
+        f.write_str(&URL_SAFE_NO_PAD.encode(e.buffer()))
+    }
+}
+
+impl DocStore {
+    // some item of the subtree was inserted, moved or deleted after the version
+    fn changed_since(&self, root: &Type, version: &ClientState) -> bool {
+        let version = version.as_per(&self.state);
+        let unseen = |id: &Id| {
+            !version
+                .get(&id.client)
+                .is_some_and(|clock| id.clock <= *clock)
+        };
+        let in_subtree = |item: &Type| {
+            let mut current = Some(item.clone());
+            while let Some(item) = current {
+                if item.id() == root.id() {
+                    return true;
+                }
+                current = item.parent();
+            }
+            false
+        };
+
+        let items = self.items.iter().chain(self.marks.iter());
+        for (_, items) in items {
+            let changed = items
+                .iter()
+                .any(|(id, item)| unseen(id) && in_subtree(item));
+            if changed {
+                return true;
+            }
+        }
+
+        // a move changes the subtree it leaves and the subtree it enters
+        for (_, movers) in self.movers.iter() {
+            let changed = movers.iter().any(|(id, mover)| {
+                let target = mover.item_ref().get_target();
+                unseen(id) && (in_subtree(mover) || target.is_some_and(|t| in_subtree(&t)))
+            });
+            if changed {
+                return true;
+            }
+        }
+
+        self.deletes.iter().any(|(_, deletes)| {
+            deletes.iter().any(|(id, delete)| {
+                unseen(id)
+                    && self
+                        .find(&delete.target())
+                        .is_some_and(|item| in_subtree(&item))
+            })
+        })
+    }
+}
+
+impl Doc {
+    /// Token of the current version for a stateless writer, e.g. sent as an etag
+    pub fn write_token(&self) -> WriteToken {
+        WriteToken {
+            version: self.version(),
+            path: String::new(),
+        }
+    }
+
+    /// Run `f` as a transaction when the subtree guarded by the token has not changed
+    /// since the token was issued, like a compare-and-swap. Returns a conflict error
+    /// without running `f` otherwise, the writer reads again and retries.
+    pub fn apply_if_unchanged<R>(
+        &self,
+        token: &WriteToken,
+        f: impl FnOnce(&mut Transaction) -> R,
+    ) -> Result<R, String> {
+        let item = self.resolve_pointer(&token.path)?;
+        let root = item.item_ref().get_target().unwrap_or(item);
+        if self.store.borrow().changed_since(&root, &token.version) {
+            return Err(format!(
+                "write conflict: {} changed since the token was issued",
+                if token.path.is_empty() {
+                    "/"
+                } else {
+                    &token.path
+                }
+            ));
+        }
+
+        self.transact(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_if_unchanged() {
+        let doc = Doc::default();
+        let todos = doc.list();
+        doc.set("todos", todos.clone());
+        todos.append(doc.atom("write tests"));
+        let profile = doc.map();
+        doc.set("profile", profile.clone());
+        profile.set("name", doc.atom("alice"));
+        doc.commit();
+
+        let token = doc.write_token().to_string();
+        let parsed = WriteToken::parse(&token).unwrap();
+        assert_eq!(parsed, doc.write_token());
+
+        // another writer changes the profile
+        doc.transact(|tx| profile.set("name", tx.atom("bob")))
+            .unwrap();
+
+        // the todos are unchanged, the write goes through
+        let todos_token = WriteToken::parse(&token).unwrap().at("/todos");
+        doc.apply_if_unchanged(&todos_token, |tx| todos.append(tx.atom("ship")))
+            .unwrap();
+        assert_eq!(todos.size(), 2);
+
+        // the profile changed since the token was issued
+        let profile_token = WriteToken::parse(&token).unwrap().at("/profile");
+        let result = doc.apply_if_unchanged(&profile_token, |tx| {
+            profile.set("name", tx.atom("carol"));
+        });
+        assert!(result.unwrap_err().starts_with("write conflict"));
+        assert_eq!(profile.get("name").unwrap().to_json(), "bob");
+
+        // the whole document changed too
+        assert!(doc.apply_if_unchanged(&parsed, |_| ()).is_err());
+        let fresh = doc.write_token();
+        assert!(doc.apply_if_unchanged(&fresh, |_| ()).is_ok());
+
+        assert!(WriteToken::parse("not a token!").is_err());
+    }
+}
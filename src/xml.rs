@@ -0,0 +1,569 @@
+use std::collections::BTreeMap;
+
+use crate::doc::Doc;
+use crate::features::XML_TYPES;
+use crate::item::Content;
+use crate::nlist::NList;
+use crate::nmap::NMap;
+use crate::ntext::NText;
+use crate::types::Type;
+
+/// NXmlElement is an element of an xml tree, e.g. a node of a ProseMirror document.
+///
+/// The element is a map holding the tag name, a map of string attributes and a list of
+/// child elements and texts. An element without a tag is a fragment, it is written
+/// as its children only.
+#[derive(Clone, Debug)]
+pub struct NXmlElement {
+    doc: Doc,
+    map: NMap,
+}
+
+/// NXmlText is a text node of an xml tree
+#[derive(Clone, Debug)]
+pub struct NXmlText {
+    doc: Doc,
+    text: NText,
+}
+
+/// NXmlNode is a child of an xml element
+#[derive(Clone, Debug)]
+pub enum NXmlNode {
+    Element(NXmlElement),
+    Text(NXmlText),
+}
+
+impl NXmlElement {
+    pub fn tag(&self) -> String {
+        match self.map.get("tag").map(|tag| tag.content()) {
+            Some(Content::String(tag)) => tag,
+            _ => String::new(),
+        }
+    }
+
+    #[inline]
+    pub fn is_fragment(&self) -> bool {
+        self.tag().is_empty()
+    }
+
+    pub fn get_attribute(&self, name: &str) -> Option<String> {
+        match self.attrs()?.get(name)?.content() {
+            Content::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn set_attribute(&self, name: &str, value: &str) {
+        if self.get_attribute(name).as_deref() == Some(value) {
+            return;
+        }
+
+        let attrs = match self.attrs() {
+            Some(attrs) => attrs,
+            None => {
+                let attrs = self.doc.map();
+                self.map.set("attrs", attrs.clone());
+                attrs
+            }
+        };
+        attrs.set(name, self.doc.atom(value));
+    }
+
+    /// Remove the attribute, returns false when the element has no such attribute
+    pub fn remove_attribute(&self, name: &str) -> bool {
+        match self.attrs().and_then(|attrs| attrs.get(name)) {
+            Some(value) => {
+                value.delete();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Attributes sorted by name
+    pub fn attributes(&self) -> BTreeMap<String, String> {
+        let Some(attrs) = self.attrs() else {
+            return BTreeMap::new();
+        };
+
+        attrs
+            .keys()
+            .into_iter()
+            .filter_map(|name| self.get_attribute(&name).map(|value| (name, value)))
+            .collect()
+    }
+
+    /// Number of child nodes
+    pub fn len(&self) -> u32 {
+        self.children_list()
+            .map(|list| list.size())
+            .unwrap_or_default()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn children(&self) -> Vec<NXmlNode> {
+        let Some(list) = self.children_list() else {
+            return vec![];
+        };
+
+        let children = list.borrow().as_list();
+        children
+            .into_iter()
+            .filter_map(|child| NXmlNode::from_type(&self.doc, child))
+            .collect()
+    }
+
+    pub fn insert(&self, index: u32, node: impl Into<NXmlNode>) {
+        let node: Type = match node.into() {
+            NXmlNode::Element(element) => element.map.into(),
+            NXmlNode::Text(text) => text.text.into(),
+        };
+
+        match self.children_list() {
+            Some(list) => list.insert(index, node),
+            None => {
+                let list = self.doc.list();
+                self.map.set("children", list.clone());
+                list.append(node);
+            }
+        }
+    }
+
+    #[inline]
+    pub fn append(&self, node: impl Into<NXmlNode>) {
+        self.insert(self.len(), node)
+    }
+
+    /// Remove the child at the index, returns false when the index is out of bounds
+    pub fn remove(&self, index: u32) -> bool {
+        let child = self
+            .children_list()
+            .and_then(|list| list.borrow().as_list().get(index as usize).cloned());
+        match child {
+            Some(child) => {
+                child.delete();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Parse the xml and append the parsed nodes, nothing is appended when the xml is invalid.
+    /// Comments, declarations and whitespace only texts are skipped.
+    pub fn append_xml(&self, xml: &str) -> Result<(), String> {
+        let nodes = parse_xml(xml)?;
+        self.append_nodes(nodes);
+        Ok(())
+    }
+
+    fn append_nodes(&self, nodes: Vec<XmlNode>) {
+        for node in nodes {
+            match node {
+                XmlNode::Element(tag, attrs, children) => {
+                    let element = self.doc.xml_element(&tag);
+                    for (name, value) in attrs {
+                        element.set_attribute(&name, &value);
+                    }
+                    self.append(element.clone());
+                    element.append_nodes(children);
+                }
+                XmlNode::Text(text) => {
+                    let node = self.doc.xml_text();
+                    self.append(node.clone());
+                    node.push(&text);
+                }
+            }
+        }
+    }
+
+    pub fn to_xml_string(&self) -> String {
+        let mut xml = String::new();
+        self.write_xml(&mut xml);
+        xml
+    }
+
+    fn write_xml(&self, xml: &mut String) {
+        let tag = self.tag();
+        let children = self.children();
+        if !tag.is_empty() {
+            xml.push('<');
+            xml.push_str(&tag);
+            for (name, value) in self.attributes() {
+                xml.push_str(&format!(" {}=\"{}\"", name, escape(&value, true)));
+            }
+            if children.is_empty() {
+                xml.push_str("/>");
+                return;
+            }
+            xml.push('>');
+        }
+
+        for child in children {
+            match child {
+                NXmlNode::Element(element) => element.write_xml(xml),
+                NXmlNode::Text(text) => xml.push_str(&text.to_xml_string()),
+            }
+        }
+
+        if !tag.is_empty() {
+            xml.push_str(&format!("</{}>", tag));
+        }
+    }
+
+    fn attrs(&self) -> Option<NMap> {
+        match self.map.get("attrs") {
+            Some(Type::Map(attrs)) => Some(attrs),
+            _ => None,
+        }
+    }
+
+    fn children_list(&self) -> Option<NList> {
+        match self.map.get("children") {
+            Some(Type::List(list)) => Some(list),
+            _ => None,
+        }
+    }
+}
+
+impl NXmlText {
+    /// The underlying text, e.g. to mark a range
+    #[inline]
+    pub fn text(&self) -> &NText {
+        &self.text
+    }
+
+    pub fn insert(&self, offset: u32, value: &str) {
+        self.text.insert(offset, self.doc.string(value));
+    }
+
+    pub fn push(&self, value: &str) {
+        self.text.append(self.doc.string(value));
+    }
+
+    #[inline]
+    pub fn text_content(&self) -> String {
+        self.text.text_content()
+    }
+
+    pub fn to_xml_string(&self) -> String {
+        escape(&self.text_content(), false)
+    }
+}
+
+impl NXmlNode {
+    fn from_type(doc: &Doc, item: Type) -> Option<Self> {
+        match item {
+            Type::Map(map) => Some(NXmlNode::Element(NXmlElement {
+                doc: doc.clone(),
+                map,
+            })),
+            Type::Text(text) => Some(NXmlNode::Text(NXmlText {
+                doc: doc.clone(),
+                text,
+            })),
+            _ => None,
+        }
+    }
+
+    pub fn to_xml_string(&self) -> String {
+        match self {
+            NXmlNode::Element(element) => element.to_xml_string(),
+            NXmlNode::Text(text) => text.to_xml_string(),
+        }
+    }
+}
+
+impl From<NXmlElement> for NXmlNode {
+    fn from(element: NXmlElement) -> Self {
+        NXmlNode::Element(element)
+    }
+}
+
+impl From<NXmlText> for NXmlNode {
+    fn from(text: NXmlText) -> Self {
+        NXmlNode::Text(text)
+    }
+}
+
+impl Doc {
+    /// Create a new xml element, insert it into a parent element to attach it
+    pub fn xml_element(&self, tag: &str) -> NXmlElement {
+        self.use_feature(XML_TYPES);
+        let map = self.map();
+        map.set("tag", self.atom(tag));
+        map.set("attrs", self.map());
+        map.set("children", self.list());
+
+        NXmlElement {
+            doc: self.clone(),
+            map,
+        }
+    }
+
+    /// Create a new empty xml text
+    pub fn xml_text(&self) -> NXmlText {
+        self.use_feature(XML_TYPES);
+        NXmlText {
+            doc: self.clone(),
+            text: self.text(),
+        }
+    }
+
+    /// Xml fragment kept in the root map under the name, created when missing
+    pub fn xml_fragment(&self, name: &str) -> Result<NXmlElement, String> {
+        self.use_feature(XML_TYPES);
+        let map = match self.get(name) {
+            Some(Type::Map(map)) => map,
+            Some(other) => return Err(format!("{} is a {}, not a map", name, other.kind())),
+            None => {
+                // a fragment has no tag
+                let map = self.map();
+                map.set("children", self.list());
+                self.set(name, map.clone());
+                map
+            }
+        };
+
+        Ok(NXmlElement {
+            doc: self.clone(),
+            map,
+        })
+    }
+}
+
+// parsed xml before it is written into the document
+#[derive(Debug, Clone, PartialEq)]
+enum XmlNode {
+    Element(String, Vec<(String, String)>, Vec<XmlNode>),
+    Text(String),
+}
+
+fn parse_xml(xml: &str) -> Result<Vec<XmlNode>, String> {
+    XmlParser { src: xml, pos: 0 }.nodes(None)
+}
+
+struct XmlParser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> XmlParser<'a> {
+    #[inline]
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    // nodes until the closing tag of the parent or the end of the input
+    fn nodes(&mut self, parent: Option<&str>) -> Result<Vec<XmlNode>, String> {
+        let mut nodes = vec![];
+        loop {
+            let rest = self.rest();
+            if rest.is_empty() {
+                return match parent {
+                    Some(tag) => Err(format!("element <{}> is not closed", tag)),
+                    None => Ok(nodes),
+                };
+            }
+
+            if let Some(closing) = rest.strip_prefix("</") {
+                let end = closing
+                    .find('>')
+                    .ok_or_else(|| format!("closing tag at {} is not terminated", self.pos))?;
+                let tag = closing[..end].trim();
+                return match parent {
+                    Some(parent) if parent == tag => {
+                        self.pos += end + 3;
+                        Ok(nodes)
+                    }
+                    _ => Err(format!("unexpected closing tag </{}> at {}", tag, self.pos)),
+                };
+            }
+
+            if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<?") || rest.starts_with("<!") {
+                self.skip_past(">")?;
+            } else if rest.starts_with('<') {
+                nodes.push(self.element()?);
+            } else {
+                let end = rest.find('<').unwrap_or(rest.len());
+                let text = &rest[..end];
+                if !text.trim().is_empty() {
+                    nodes.push(XmlNode::Text(unescape(text)?));
+                }
+                self.pos += end;
+            }
+        }
+    }
+
+    fn element(&mut self) -> Result<XmlNode, String> {
+        self.pos += 1;
+        let tag = self.name()?;
+        let mut attrs = vec![];
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.starts_with("/>") {
+                self.pos += 2;
+                return Ok(XmlNode::Element(tag, attrs, vec![]));
+            }
+            if rest.starts_with('>') {
+                self.pos += 1;
+                let children = self.nodes(Some(&tag))?;
+                return Ok(XmlNode::Element(tag, attrs, children));
+            }
+
+            let name = self.name()?;
+            self.skip_whitespace();
+            if !self.rest().starts_with('=') {
+                return Err(format!("attribute {} of <{}> has no value", name, tag));
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+
+            let rest = self.rest();
+            let quote = rest
+                .chars()
+                .next()
+                .filter(|c| *c == '"' || *c == '\'')
+                .ok_or_else(|| format!("attribute {} of <{}> is not quoted", name, tag))?;
+            let end = rest[1..]
+                .find(quote)
+                .ok_or_else(|| format!("attribute {} of <{}> is not closed", name, tag))?;
+            attrs.push((name, unescape(&rest[1..end + 1])?));
+            self.pos += end + 2;
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let rest = self.rest();
+        let end = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '/' | '>' | '=' | '<'))
+            .unwrap_or(rest.len());
+        if end == 0 {
+            return Err(format!("expected a name at {}", self.pos));
+        }
+
+        self.pos += end;
+        Ok(rest[..end].to_string())
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn skip_past(&mut self, end: &str) -> Result<(), String> {
+        let at = self
+            .rest()
+            .find(end)
+            .ok_or_else(|| format!("markup at {} is not terminated", self.pos))?;
+        self.pos += at + end.len();
+        Ok(())
+    }
+}
+
+fn unescape(text: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find(';')
+            .ok_or_else(|| format!("entity in {:?} is not terminated", text))?;
+        let entity = &rest[start + 1..start + end];
+        let ch = match entity {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(|dec| dec.parse::<u32>()))
+                .and_then(|code| code.ok())
+                .and_then(char::from_u32)
+                .ok_or_else(|| format!("unknown entity &{};", entity))?,
+        };
+        out.push(ch);
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+
+    Ok(out)
+}
+
+fn escape(text: &str, attribute: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' if attribute => out.push_str("&quot;"),
+            ch => out.push(ch),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::doc::CloneDeep;
+
+    use super::*;
+
+    #[test]
+    fn test_xml_tree() {
+        let d1 = Doc::default();
+        let body = d1.xml_fragment("body").unwrap();
+        body.append_xml(
+            r#"<?xml version="1.0"?>
+            <p class='intro'>Hello <b>world</b> &amp; more</p>
+            <!-- a picture -->
+            <img src="a.png" alt="&quot;a&quot;"/>"#,
+        )
+        .unwrap();
+        d1.commit();
+
+        let xml = r#"<p class="intro">Hello <b>world</b> &amp; more</p><img alt="&quot;a&quot;" src="a.png"/>"#;
+        assert_eq!(body.to_xml_string(), xml);
+        assert_eq!(body.len(), 2);
+        let Some(NXmlNode::Element(p)) = body.children().into_iter().next() else {
+            panic!("paragraph not parsed");
+        };
+        assert_eq!(p.tag(), "p");
+        assert_eq!(p.get_attribute("class").as_deref(), Some("intro"));
+
+        // edits sync like any other type
+        let d2 = d1.clone_deep();
+        d2.update_client();
+        let remote = d2.xml_fragment("body").unwrap();
+        assert_eq!(remote.to_xml_string(), xml);
+
+        let quote = d2.xml_element("blockquote");
+        quote.set_attribute("cite", "x < y");
+        let text = d2.xml_text();
+        quote.append(text.clone());
+        text.push("said");
+        remote.insert(1, quote);
+        assert!(remote.remove(2));
+        d2.commit();
+
+        d1.apply(&d2.diff(d1.state()));
+        assert_eq!(
+            body.to_xml_string(),
+            r#"<p class="intro">Hello <b>world</b> &amp; more</p><blockquote cite="x &lt; y">said</blockquote>"#
+        );
+
+        assert!(body.append_xml("<p>open").is_err());
+        assert!(body.append_xml("<p></div>").is_err());
+        assert_eq!(body.len(), 2);
+    }
+}